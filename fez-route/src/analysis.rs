@@ -0,0 +1,181 @@
+//! what can be reached from a node, for both the room graph and value graphs made from it.
+//! edges are only followed if they pass the filter, like only edges with value or only edges without secret doors
+
+use crate::rooms::Node;
+use crate::units::Bits;
+use fixedbitset::FixedBitSet;
+use petgraph::stable_graph::{EdgeIndex, EdgeReference, NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences, NodeIndexable};
+use petgraph::Direction::{self, Incoming, Outgoing};
+use std::borrow::Borrow;
+
+/// every node reachable from `from` by following edges in `direction`, including `from`.
+/// `Incoming` finds every node that can reach `from` instead
+pub fn reachable<N, E>(
+    graph: &StableGraph<N, E>,
+    from: NodeIndex,
+    direction: Direction,
+    filter: impl Fn(EdgeReference<E>) -> bool,
+) -> FixedBitSet {
+    let mut visited = FixedBitSet::with_capacity(graph.node_bound());
+    visited.insert(from.index());
+    let mut stack = vec![from];
+    while let Some(n) = stack.pop() {
+        graph
+            .edges_directed(n, direction)
+            .filter(|e| filter(*e))
+            .for_each(|e| {
+                let next = if direction == Outgoing {
+                    e.target()
+                } else {
+                    e.source()
+                };
+                if !visited.put(next.index()) {
+                    stack.push(next);
+                }
+            });
+    }
+    visited
+}
+
+/// every node reachable from `from` and the bits collected at all of them
+pub fn reachable_bits<N: Borrow<Node>, E>(
+    graph: &StableGraph<N, E>,
+    from: NodeIndex,
+    filter: impl Fn(EdgeReference<E>) -> bool,
+) -> (FixedBitSet, Bits) {
+    let nodes = reachable(graph, from, Outgoing, filter);
+    let bits = nodes
+        .ones()
+        .map(|n| graph[NodeIndex::new(n)].borrow().bits)
+        .sum();
+    (nodes, bits)
+}
+
+/// keys that can be picked up at every node reachable from `from`, without spending any on locks
+pub fn reachable_keys<N: Borrow<Node>, E>(
+    graph: &StableGraph<N, E>,
+    from: NodeIndex,
+    filter: impl Fn(EdgeReference<E>) -> bool,
+) -> i32 {
+    reachable(graph, from, Outgoing, filter)
+        .ones()
+        .map(|n| graph[NodeIndex::new(n)].borrow().keys)
+        .sum()
+}
+
+/// the most bits any walk from `first` to `last` through each edge could collect,
+/// which are at the nodes on some way from `first` to its source or from its target to `last`.
+/// edges whose bound is short of the required bits can never be part of a route
+pub fn edge_bits_bounds<N: Borrow<Node>, E>(
+    graph: &StableGraph<N, E>,
+    first: NodeIndex,
+    last: NodeIndex,
+) -> Vec<(EdgeIndex, Bits)> {
+    let from_first = reachable(graph, first, Outgoing, |_| true);
+    let to_last = reachable(graph, last, Incoming, |_| true);
+    let before: Vec<Option<FixedBitSet>> = (0..graph.node_bound())
+        .map(|n| {
+            let n = NodeIndex::new(n);
+            graph.contains_node(n).then(|| {
+                let mut nodes = reachable(graph, n, Incoming, |_| true);
+                nodes.intersect_with(&from_first);
+                nodes
+            })
+        })
+        .collect();
+    let after: Vec<Option<FixedBitSet>> = (0..graph.node_bound())
+        .map(|n| {
+            let n = NodeIndex::new(n);
+            graph.contains_node(n).then(|| {
+                let mut nodes = reachable(graph, n, Outgoing, |_| true);
+                nodes.intersect_with(&to_last);
+                nodes
+            })
+        })
+        .collect();
+    graph
+        .edge_references()
+        .map(|e| {
+            let mut nodes = before[e.source().index()].clone().unwrap();
+            nodes.union_with(after[e.target().index()].as_ref().unwrap());
+            let bits = nodes
+                .ones()
+                .map(|n| graph[NodeIndex::new(n)].borrow().bits)
+                .sum();
+            (e.id(), bits)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, bits: i32, keys: i32) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            keys,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_filtered_edges_are_followed() {
+        let nodes = [
+            node("start", 0, 0),
+            node("key", 0, 1),
+            node("cube", 8, 0),
+            node("bit", 1, 1),
+        ];
+        let mut values = StableGraph::new();
+        let n: Vec<_> = nodes.iter().map(|n| values.add_node(n)).collect();
+        values.add_edge(n[0], n[1], 1.0);
+        values.add_edge(n[1], n[2], 0.0);
+        values.add_edge(n[2], n[3], 1.0);
+        values.add_edge(n[3], n[1], 1.0);
+
+        let (reached, bits) = reachable_bits(&values, n[0], |_| true);
+        assert_eq!(reached.ones().count(), 4);
+        assert_eq!(bits, Bits(9));
+        assert_eq!(reachable_keys(&values, n[0], |_| true), 2);
+
+        let with_value = |e: EdgeReference<f64>| *e.weight() > 0.0;
+        let (reached, bits) = reachable_bits(&values, n[0], with_value);
+        assert_eq!(reached.ones().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(bits, Bits(0));
+        assert_eq!(reachable_keys(&values, n[2], with_value), 2);
+
+        let reaching = reachable(&values, n[2], Incoming, with_value);
+        assert_eq!(reaching.ones().collect::<Vec<_>>(), [2]);
+        let reaching = reachable(&values, n[1], Incoming, with_value);
+        assert_eq!(reaching.ones().collect::<Vec<_>>(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn edges_only_see_bits_on_their_way() {
+        let nodes = [
+            node("start", 0, 0),
+            node("cube", 8, 0),
+            node("bit", 1, 0),
+            node("door", 0, 0),
+            node("end", 0, 0),
+        ];
+        let mut graph = StableGraph::new();
+        let n: Vec<_> = nodes.iter().map(|n| graph.add_node(n)).collect();
+        let to_cube = graph.add_edge(n[0], n[1], ());
+        graph.add_edge(n[1], n[4], ());
+        let to_bit = graph.add_edge(n[0], n[2], ());
+        let from_bit = graph.add_edge(n[2], n[3], ());
+        graph.add_edge(n[3], n[4], ());
+        let direct = graph.add_edge(n[0], n[4], ());
+
+        let bounds = edge_bits_bounds(&graph, n[0], n[4]);
+        let bound = |e| bounds.iter().find(|(b, _)| *b == e).unwrap().1;
+        assert_eq!(bound(to_cube), Bits(8));
+        assert_eq!(bound(to_bit), Bits(1));
+        assert_eq!(bound(from_bit), Bits(1));
+        assert_eq!(bound(direct), Bits(0));
+    }
+}
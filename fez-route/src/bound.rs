@@ -0,0 +1,101 @@
+use crate::common::cost;
+use crate::mincost::Network;
+use crate::rooms::{Edge, Node};
+use crate::units::{Bits, Frames};
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences, NodeIndexable};
+
+const ITERATIONS: usize = 100;
+
+/// lower bound on the route time, found by moving the required bits constraint into the objective
+/// with a multiplier and solving the remaining min cost flow problem for a sequence of multipliers
+pub fn lagrangian_bound(
+    graph: &StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    required_bits: Bits,
+) -> Frames {
+    // initial step size is scaled so that a single step changes costs by about one average edge
+    let total_cost: Frames = graph.edge_references().map(|e| cost(graph, e)).sum();
+    let total_bits: Bits = graph.node_weights().map(|n| n.bits).sum();
+    let step = if total_bits > Bits(0) {
+        f64::from(total_cost) / f64::from(total_bits) / graph.edge_count() as f64
+    } else {
+        0.0
+    };
+
+    let mut best = f64::NEG_INFINITY;
+    let mut multiplier = 0.0;
+    for i in 0..ITERATIONS {
+        let (flow_cost, flow_bits) = match shortest_flow(graph, first_node, last_node, multiplier) {
+            Some(flow) => flow,
+            None => {
+                warn!("no path from first node to last node");
+                return Frames(f64::INFINITY);
+            }
+        };
+        let bound = flow_cost + multiplier * f64::from(required_bits);
+        if bound > best {
+            best = bound;
+            trace!(
+                "lagrangian iteration {} multiplier {} bound {}",
+                i,
+                multiplier,
+                bound
+            );
+        }
+        let subgradient = f64::from(required_bits - flow_bits);
+        if subgradient == 0.0 {
+            // the relaxed flow collects exactly the required bits, so it can't be improved on
+            break;
+        }
+        multiplier = (multiplier + step * subgradient / (i + 1) as f64).max(0.0);
+    }
+    Frames(best)
+}
+
+/// min cost flow of one unit from the first node to the last node, where each node can be passed through once,
+/// which is the LP relaxation of just the flow and capacity constraints.
+/// the flow can include cycles that aren't connected to the path, so this is still only a relaxation.
+/// returns the penalized cost and collected bits of the flow
+fn shortest_flow(
+    graph: &StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    multiplier: f64,
+) -> Option<(f64, Bits)> {
+    // node n is split into 2n, where it is entered, and 2n+1, where it is left
+    let mut network = Network::new(graph.node_bound() * 2);
+    let passes: Vec<(usize, Bits)> = graph
+        .node_indices()
+        .map(|n| {
+            let bits = graph[n].bits;
+            let arc = network.add_arc(
+                n.index() * 2,
+                n.index() * 2 + 1,
+                1,
+                -multiplier * f64::from(bits),
+            );
+            (arc, bits)
+        })
+        .collect();
+    graph.edge_references().for_each(|e| {
+        network.add_arc(
+            e.source().index() * 2 + 1,
+            e.target().index() * 2,
+            1,
+            f64::from(cost(graph, e)),
+        );
+    });
+    network.add_supply(first_node.index() * 2 + 1, 1);
+    network.add_supply(last_node.index() * 2 + 1, -1);
+
+    let flow_cost = network.solve()?;
+    let bits = passes
+        .into_iter()
+        .filter(|(arc, _)| network.flow(*arc) > 0)
+        .map(|(_, bits)| bits)
+        .sum();
+    Some((flow_cost, bits))
+}
@@ -0,0 +1,98 @@
+use glpk::{Branch, TreeNode};
+use log::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+
+/// every subproblem glpk worked on and the edge it was split on, written as graphviz at the end of the solve,
+/// to see where a solve explodes and which branches actually moved the bound
+pub struct BranchTree {
+    path: PathBuf,
+    entries: Vec<Entry>,
+    /// the entry currently named by each glpk id
+    current: HashMap<c_int, usize>,
+}
+
+struct Entry {
+    id: c_int,
+    parent: Option<usize>,
+    level: c_int,
+    bound: f64,
+    branch: Option<(String, Branch)>,
+}
+
+impl BranchTree {
+    pub fn new(path: &Path) -> Self {
+        BranchTree {
+            path: path.to_owned(),
+            entries: Vec::new(),
+            current: HashMap::new(),
+        }
+    }
+
+    /// records the subproblem, unless it is already known
+    pub fn visit(&mut self, node: TreeNode) -> usize {
+        let parent = node.parent.and_then(|p| self.current.get(&p).copied());
+        if let Some(&entry) = self.current.get(&node.id) {
+            let known = &self.entries[entry];
+            if known.parent == parent && known.level == node.level {
+                return entry;
+            }
+        }
+        let entry = self.entries.len();
+        self.entries.push(Entry {
+            id: node.id,
+            parent,
+            level: node.level,
+            bound: node.bound,
+            branch: None,
+        });
+        self.current.insert(node.id, entry);
+        entry
+    }
+
+    pub fn branch(&mut self, node: TreeNode, var: String, branch: Branch) {
+        debug!(
+            "branching {:?} on {} at node {} (parent {:?}, level {}, bound {})",
+            branch, var, node.id, node.parent, node.level, node.bound
+        );
+        let entry = self.visit(node);
+        self.entries[entry].bound = node.bound;
+        self.entries[entry].branch = Some((var, branch));
+    }
+
+    pub fn save(&self) {
+        match self.try_save() {
+            Ok(()) => info!(
+                "saved {} branch tree nodes to {:?}",
+                self.entries.len(),
+                self.path
+            ),
+            Err(e) => error!("failed to save branch tree {:?}: {}", self.path, e),
+        }
+    }
+
+    fn try_save(&self) -> io::Result<()> {
+        let mut output = BufWriter::new(File::create(&self.path)?);
+        writeln!(output, "digraph {{")?;
+        writeln!(output, "node [shape=box]")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let branch = match &entry.branch {
+                Some((var, branch)) => format!("\\n{} {:?}", var, branch),
+                None => String::new(),
+            };
+            writeln!(
+                output,
+                "n{} [label=\"#{} level {}\\nbound {:.0}{}\"]",
+                i, entry.id, entry.level, entry.bound, branch
+            )?;
+            if let Some(parent) = entry.parent {
+                writeln!(output, "n{} -> n{}", parent, i)?;
+            }
+        }
+        writeln!(output, "}}")?;
+        output.flush()
+    }
+}
@@ -0,0 +1,356 @@
+//! compares the time the model predicts for each zone of a route with the splits of a real run of it,
+//! to find where the timing model is systematically fast or slow and by how much
+
+use crate::common::{long_load_edges, LONG_LOAD};
+use crate::heuristic::Route;
+use crate::options::Options;
+use crate::rooms::{Edge, Node};
+use crate::sheet::{self, fields};
+use crate::units::Frames;
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::StableGraph;
+use std::fs;
+use std::path::Path;
+
+const FRAMES_PER_SECOND: f64 = 60.0;
+
+/// what the predicted frames of a step are made of, which each get their own correction factor
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Category {
+    /// edges between nodes
+    Travel,
+    /// what is done at nodes, besides menuing
+    Actions,
+    Menus,
+    /// the first warp to each hub
+    LongLoads,
+}
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Travel,
+        Category::Actions,
+        Category::Menus,
+        Category::LongLoads,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Travel => "travel",
+            Category::Actions => "actions",
+            Category::Menus => "menus",
+            Category::LongLoads => "long loads",
+        }
+    }
+}
+
+/// the predicted frames of each category in a zone, next to the real frames of its splits
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneTimes {
+    pub zone: String,
+    /// in the order of `Category::ALL`
+    pub predicted: [Frames; 4],
+    /// none if the splits don't have the zone
+    pub actual: Option<Frames>,
+}
+impl ZoneTimes {
+    pub fn total(&self) -> Frames {
+        self.predicted.iter().sum()
+    }
+}
+
+/// predicted frames by zone and category, in the order the route first enters each zone.
+/// a step counts towards the zone of the node it reaches, and the first node takes no time
+pub fn predict(graph: &StableGraph<Node, Edge>, route: &Route) -> Vec<ZoneTimes> {
+    let long_loads = long_load_edges(graph, route.edges.iter().copied());
+    let mut zones: Vec<ZoneTimes> = Vec::new();
+    route.edges.iter().for_each(|e| {
+        let target = &graph[graph.edge_endpoints(*e).unwrap().1];
+        let i = zones
+            .iter()
+            .position(|zone| zone.zone == target.zone)
+            .unwrap_or_else(|| {
+                zones.push(ZoneTimes {
+                    zone: target.zone.clone(),
+                    predicted: [Frames(0.0); 4],
+                    actual: None,
+                });
+                zones.len() - 1
+            });
+        let predicted = &mut zones[i].predicted;
+        predicted[0] += graph[*e].time;
+        predicted[1] += target.time - target.menu;
+        predicted[2] += target.menu;
+        if long_loads.contains(e) {
+            predicted[3] += LONG_LOAD;
+        }
+    });
+    zones
+}
+
+/// real frames by zone from zone,time rows, where a zone with more than one split is their sum.
+/// times are seconds, or minutes and seconds like 1:02.5, or hours too. the header is optional
+pub fn parse_splits(csv: &str) -> Result<Vec<(String, Frames)>, String> {
+    let mut splits: Vec<(String, Frames)> = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        match fields(line).as_slice() {
+            [] => continue,
+            [first, ..] if i == 0 && first.trim().eq_ignore_ascii_case("zone") => continue,
+            [zone, time, ..] => {
+                let zone = zone.trim();
+                let time = parse_time(time.trim())
+                    .ok_or_else(|| format!("row {}: {} is not a time", i + 1, time.trim()))?;
+                match splits.iter_mut().find(|(z, _)| z == zone) {
+                    Some((_, total)) => *total += time,
+                    None => splits.push((zone.to_owned(), time)),
+                }
+            }
+            _ => return Err(format!("row {}: expected zone,time", i + 1)),
+        }
+    }
+    Ok(splits)
+}
+
+/// seconds, optionally after minutes and hours separated by colons
+fn parse_time(time: &str) -> Option<Frames> {
+    let seconds = time
+        .split(':')
+        .map(|part| part.parse::<f64>().ok().filter(|part| *part >= 0.0))
+        .try_fold(0.0, |total, part| Some(total * 60.0 + part?))?;
+    Some(Frames(seconds * FRAMES_PER_SECOND))
+}
+
+/// how much slower real runs are than predicted, overall and per category
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub zones: Vec<ZoneTimes>,
+    /// split zones the route never reaches
+    pub unknown: Vec<String>,
+    /// the one factor for every predicted frame that best fits the splits
+    pub global: Option<f64>,
+    /// a factor per category, none if the splits can't tell the categories apart
+    pub categories: Option<Vec<(Category, f64)>>,
+}
+
+/// fits real = factor * predicted by least squares over the zones with splits
+pub fn calibrate(mut zones: Vec<ZoneTimes>, splits: &[(String, Frames)]) -> Calibration {
+    zones.iter_mut().for_each(|zone| {
+        zone.actual = splits
+            .iter()
+            .find(|(z, _)| *z == zone.zone)
+            .map(|(_, time)| *time);
+    });
+    let unknown = splits
+        .iter()
+        .filter(|(z, _)| zones.iter().all(|zone| zone.zone != *z))
+        .map(|(z, _)| z.clone())
+        .collect();
+    let fitted: Vec<(Vec<f64>, f64)> = zones
+        .iter()
+        .filter_map(|zone| {
+            zone.actual.map(|actual| {
+                (
+                    zone.predicted.iter().map(|frames| frames.0).collect(),
+                    actual.0,
+                )
+            })
+        })
+        .collect();
+
+    let totals: Vec<(Vec<f64>, f64)> = fitted
+        .iter()
+        .map(|(predicted, actual)| (vec![predicted.iter().sum()], *actual))
+        .collect();
+    let global = least_squares(&totals).map(|factors| factors[0]);
+
+    // categories no zone predicts any time for can't be fit, and would make the system singular
+    let used: Vec<usize> = (0..Category::ALL.len())
+        .filter(|c| fitted.iter().any(|(predicted, _)| predicted[*c] > 0.0))
+        .collect();
+    let by_category: Vec<(Vec<f64>, f64)> = fitted
+        .iter()
+        .map(|(predicted, actual)| (used.iter().map(|c| predicted[*c]).collect(), *actual))
+        .collect();
+    let categories = least_squares(&by_category).map(|factors| {
+        used.iter()
+            .zip(factors)
+            .map(|(c, factor)| (Category::ALL[*c], factor))
+            .collect()
+    });
+
+    Calibration {
+        zones,
+        unknown,
+        global,
+        categories,
+    }
+}
+
+/// the factors minimizing the squared error of each row's actual against its predicted terms, by solving the normal
+/// equations. none if there are fewer rows than factors or the rows can't tell the factors apart
+fn least_squares(rows: &[(Vec<f64>, f64)]) -> Option<Vec<f64>> {
+    let n = rows.first()?.0.len();
+    if n == 0 || rows.len() < n {
+        return None;
+    }
+    // the augmented matrix of (AᵀA | Aᵀb)
+    let mut m: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| rows.iter().map(|(x, _)| x[i] * x[j]).sum())
+                .chain(std::iter::once(
+                    rows.iter().map(|(x, actual)| x[i] * actual).sum(),
+                ))
+                .collect()
+        })
+        .collect();
+    let scale = (0..n).map(|i| m[i][i]).fold(0.0, f64::max);
+    for col in 0..n {
+        let pivot = (col..n).max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))?;
+        if m[pivot][col].abs() <= 1e-9 * scale {
+            return None;
+        }
+        m.swap(col, pivot);
+        for row in 0..n {
+            if row != col {
+                let scale = m[row][col] / m[col][col];
+                let pivot_row = m[col].clone();
+                m[row]
+                    .iter_mut()
+                    .zip(pivot_row)
+                    .for_each(|(value, pivot)| *value -= scale * pivot);
+            }
+        }
+    }
+    Some((0..n).map(|i| m[i][n] / m[i][i]).collect())
+}
+
+/// prints how the splits of a run compare to the route sheet it followed. returns whether both could be read
+pub fn run(
+    graph: &StableGraph<Node, Edge>,
+    splits: &Path,
+    route: &Path,
+    options: &Options,
+) -> bool {
+    let route = match fs::read_to_string(route)
+        .map_err(|e| e.to_string())
+        .and_then(|csv| sheet::import(graph, &csv, options.required_bits))
+    {
+        Ok(route) => route,
+        Err(e) => {
+            error!("failed to import route sheet {:?}: {}", route, e);
+            return false;
+        }
+    };
+    let splits = match fs::read_to_string(splits)
+        .map_err(|e| e.to_string())
+        .and_then(|csv| parse_splits(&csv))
+    {
+        Ok(splits) => splits,
+        Err(e) => {
+            error!("failed to read splits {:?}: {}", splits, e);
+            return false;
+        }
+    };
+    report(&calibrate(predict(graph, &route), &splits))
+        .iter()
+        .for_each(|line| println!("{}", line));
+    true
+}
+
+/// a table of each zone's predicted and real time, then the suggested factors
+pub fn report(calibration: &Calibration) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<16} {:>10} {:>10} {:>8}",
+        "zone", "predicted", "actual", "ratio"
+    )];
+    calibration.zones.iter().for_each(|zone| {
+        let predicted = zone.total();
+        lines.push(match zone.actual {
+            Some(actual) => format!(
+                "{:<16} {:>10.0} {:>10.0} {:>8.3}",
+                zone.zone,
+                predicted,
+                actual,
+                actual.0 / predicted.0
+            ),
+            None => format!("{:<16} {:>10.0} {:>10}", zone.zone, predicted, "no split"),
+        });
+    });
+    if !calibration.unknown.is_empty() {
+        lines.push(format!(
+            "splits for zones the route doesn't reach: {}",
+            calibration.unknown.iter().join(", ")
+        ));
+    }
+    match calibration.global {
+        Some(global) => lines.push(format!(
+            "real runs take {:.3} times the predicted frames overall",
+            global
+        )),
+        None => lines.push("no zone has both a split and a predicted time".to_owned()),
+    }
+    match &calibration.categories {
+        Some(categories) => categories.iter().for_each(|(category, factor)| {
+            lines.push(format!("{:>8.3} for {}", factor, category.name()))
+        }),
+        None => lines.push(
+            "not enough zones with splits to tell the categories apart, so only the overall factor is suggested"
+                .to_owned(),
+        ),
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_parse_and_sum_by_zone() {
+        assert_eq!(
+            parse_splits("zone,time\nvillage,1:02.5\nsewer,10\n\nvillage,0:00.5\n"),
+            Ok(vec![
+                ("village".to_owned(), Frames(3780.0)),
+                ("sewer".to_owned(), Frames(600.0))
+            ])
+        );
+        assert_eq!(parse_time("1:00:01"), Some(Frames(3601.0 * 60.0)));
+        assert!(parse_splits("village,soon").is_err());
+        assert!(parse_splits("village").is_err());
+    }
+
+    #[test]
+    fn factors_fit_the_splits() {
+        let zone = |name: &str, travel: f64, actions: f64| ZoneTimes {
+            zone: name.to_owned(),
+            predicted: [Frames(travel), Frames(actions), Frames(0.0), Frames(0.0)],
+            actual: None,
+        };
+        // travel is 10% slow and actions are 50% slow
+        let zones = vec![
+            zone("a", 100.0, 0.0),
+            zone("b", 100.0, 100.0),
+            zone("c", 0.0, 200.0),
+        ];
+        let splits = [
+            ("a".to_owned(), Frames(110.0)),
+            ("b".to_owned(), Frames(260.0)),
+            ("c".to_owned(), Frames(300.0)),
+            ("d".to_owned(), Frames(1.0)),
+        ];
+        let calibration = calibrate(zones, &splits);
+        assert_eq!(calibration.unknown, ["d"]);
+        let categories = calibration.categories.unwrap();
+        assert_eq!(categories.len(), 2);
+        assert_eq!(categories[0].0, Category::Travel);
+        assert!((categories[0].1 - 1.1).abs() < 1e-9);
+        assert!((categories[1].1 - 1.5).abs() < 1e-9);
+        assert!(calibration.global.unwrap() > 1.1);
+
+        // one zone can't tell two categories apart
+        let calibration = calibrate(vec![zone("b", 100.0, 100.0)], &splits);
+        assert!((calibration.global.unwrap() - 1.3).abs() < 1e-9);
+        assert_eq!(calibration.categories, None);
+    }
+}
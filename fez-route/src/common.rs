@@ -1,86 +1,325 @@
-use crate::render::{Renderer, EXT};
-use crate::rooms::{Cost, Edge, Node};
+use crate::rooms::{Action, Edge, Node};
+use crate::units::{Bits, Frames};
+use fixedbitset::FixedBitSet;
 use itertools::Itertools;
 use log::*;
 use petgraph::stable_graph::{EdgeIndex, EdgeReference, NodeIndex, StableGraph};
 use petgraph::visit::{
     Dfs, DfsPostOrder, EdgeFiltered, EdgeRef, GraphBase, GraphRef, IntoEdgeReferences, IntoEdges,
     IntoEdgesDirected, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers,
-    IntoNodeReferences, NodeRef, VisitMap, Visitable, Walker,
+    IntoNodeReferences, NodeIndexable, NodeRef, VisitMap, Visitable, Walker,
 };
 use petgraph::Direction::{Incoming, Outgoing};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::iter::{self, Copied};
+use std::slice;
 
-#[derive(Clone, Debug, Default)]
+/// best known simple path from a node to the last node
+#[derive(Clone, Debug)]
 struct State<'g> {
-    edge: Option<EdgeReference<'g, f64>>,
-    bits: i32,
+    edges: Vec<EdgeReference<'g, f64>>,
+    visited: FixedBitSet,
+    /// oneof groups already used by the path
+    oneofs: Vec<&'g str>,
+    bits: Bits,
     weight: f64,
 }
+impl<'g> State<'g> {
+    fn better_than(&self, other: &State) -> bool {
+        self.bits
+            .cmp(&other.bits)
+            .then(self.weight.partial_cmp(&other.weight).unwrap())
+            == Ordering::Greater
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct HeuristicPath<'g> {
-    states: HashMap<NodeIndex, State<'g>>,
-    first: NodeIndex,
+    edges: Vec<EdgeReference<'g, f64>>,
+    visited: FixedBitSet,
+    bits: Bits,
+    weight: f64,
+}
+impl<'g> HeuristicPath<'g> {
+    /// no path was found, or the first node is the last node
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+    /// bits collected at every node on the path, including the first and last
+    pub fn bits(&self) -> Bits {
+        self.bits
+    }
+    /// total value of every edge on the path
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+    pub fn visits(&self, node: NodeIndex) -> bool {
+        self.visited.contains(node.index())
+    }
+    pub fn visited(&self) -> &FixedBitSet {
+        &self.visited
+    }
+    pub fn edge_ids(&self) -> impl Iterator<Item = EdgeIndex> + '_ {
+        self.edges.iter().map(|e| e.id())
+    }
+    /// frames spent following the path in the full graph, which the values were made from
+    pub fn time(&self, graph: &StableGraph<Node, Edge>) -> Frames {
+        self.edges
+            .iter()
+            .map(|e| graph[e.id()].time + graph[e.target()].time)
+            .sum()
+    }
 }
 impl<'p, 'g> IntoIterator for &'p HeuristicPath<'g> {
     type Item = EdgeReference<'g, f64>;
-    type IntoIter = HeuristicPathIter<'p, 'g>;
+    type IntoIter = Copied<slice::Iter<'p, EdgeReference<'g, f64>>>;
 
     fn into_iter(self) -> Self::IntoIter {
-        HeuristicPathIter {
-            states: &self.states,
-            node: self.first,
-        }
+        self.edges.iter().copied()
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct HeuristicPathIter<'p, 'g> {
-    states: &'p HashMap<NodeIndex, State<'g>>,
-    node: NodeIndex,
+/// frames spent taking an edge, including whatever is done at its target
+pub fn cost(graph: &StableGraph<Node, Edge>, e: EdgeReference<Edge>) -> Frames {
+    e.weight().time + graph[e.target()].time
 }
-impl<'p, 'g> Iterator for HeuristicPathIter<'p, 'g> {
-    type Item = EdgeReference<'g, f64>;
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(state) = self.states.get(&self.node) {
-            if let Some(edge) = state.edge {
-                self.node = edge.target();
-                return Some(edge);
-            }
-        }
-        None
-    }
+
+/// strats and detours are their own nodes, named after the node they are done at
+pub fn named_after(node: &Node, name: &str) -> bool {
+    node.name == name
+        || node
+            .name
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// frames for the long load the first time a route warps to each hub, later warps there skip it
+pub const LONG_LOAD: Frames = Frames(290.0);
+
+/// the hub an edge warps to, if it leaves a warp gate for another room
+pub fn warp_hub(graph: &StableGraph<Node, Edge>, e: EdgeIndex) -> Option<&str> {
+    let (source, target) = graph.edge_endpoints(e).unwrap();
+    let room = |n: NodeIndex| graph[n].name.split('.').next().unwrap();
+    (graph[source].action == Some(Action::Warp) && room(source) != room(target))
+        .then(|| room(target))
+}
+
+/// the edges of a path that first warp to each hub, which each take a long load
+pub fn long_load_edges(
+    graph: &StableGraph<Node, Edge>,
+    edges: impl IntoIterator<Item = EdgeIndex>,
+) -> HashSet<EdgeIndex> {
+    let mut hubs = HashSet::new();
+    edges
+        .into_iter()
+        .filter(|e| warp_hub(graph, *e).is_some_and(|hub| hubs.insert(hub)))
+        .collect()
 }
 
+/// the single node without incoming edges and the single node without outgoing edges
+pub fn endpoints<N, E>(graph: &StableGraph<N, E>) -> (NodeIndex, NodeIndex) {
+    let first_node = graph
+        .externals(Incoming)
+        .exactly_one()
+        .ok()
+        .expect("exactly one source node");
+    let last_node = graph
+        .externals(Outgoing)
+        .exactly_one()
+        .ok()
+        .expect("exactly one target node");
+    (first_node, last_node)
+}
+
+/// the simple path from the first node to the last node that collects the most bits, and then has the most value.
+/// visits each node at most once and at most one node of each oneof group, so it is something the MIP could actually choose.
+///
+/// paths are extended backwards from the last node until nothing improves,
+/// which handles cycles but isn't guaranteed to find the best path
 pub fn heuristic_path<'g>(
     values: &'g StableGraph<&'g Node, f64>,
     first: NodeIndex,
     last: NodeIndex,
 ) -> HeuristicPath<'g> {
+    let mut visited = FixedBitSet::with_capacity(values.node_bound());
+    visited.insert(last.index());
     let mut states = HashMap::<NodeIndex, State>::new();
-    states.insert(last, State::default());
-    DfsPostOrder::new(&values, first)
-        .iter(&values)
-        .for_each(|n| {
-            let n_bits = values[n].bits;
-            if let Some(state) = values
+    states.insert(
+        last,
+        State {
+            edges: Vec::new(),
+            visited,
+            oneofs: values[last].oneof.as_deref().into_iter().collect(),
+            bits: values[last].bits,
+            weight: 0.0,
+        },
+    );
+    let order: Vec<NodeIndex> = DfsPostOrder::new(&values, first).iter(&values).collect();
+    for _ in 0..order.len() {
+        let mut changed = false;
+        order.iter().filter(|n| **n != last).for_each(|&n| {
+            let node = values[n];
+            let oneof = node.oneof.as_deref();
+            let best = values
                 .edges(n)
-                .filter_map(|e| {
-                    states.get(&e.target()).map(|next| State {
-                        edge: Some(e),
-                        bits: n_bits + next.bits,
-                        weight: values[e.id()] + next.weight,
-                    })
+                .filter_map(|e| states.get(&e.target()).map(|next| (e, next)))
+                .filter(|(_, next)| {
+                    !next.visited.contains(n.index())
+                        && oneof.is_none_or(|group| !next.oneofs.contains(&group))
                 })
-                .max_by(|l, r| {
-                    l.bits
-                        .cmp(&r.bits)
-                        .then(l.weight.partial_cmp(&r.weight).unwrap())
-                })
-            {
-                states.insert(n, state);
+                .map(|(e, next)| (e, node.bits + next.bits, values[e.id()] + next.weight))
+                .max_by(|l, r| l.1.cmp(&r.1).then(l.2.partial_cmp(&r.2).unwrap()));
+            if let Some((e, bits, weight)) = best {
+                let next = &states[&e.target()];
+                let state = State {
+                    edges: iter::once(e).chain(next.edges.iter().copied()).collect(),
+                    visited: {
+                        let mut visited = next.visited.clone();
+                        visited.insert(n.index());
+                        visited
+                    },
+                    oneofs: next.oneofs.iter().copied().chain(oneof).collect(),
+                    bits,
+                    weight,
+                };
+                if states
+                    .get(&n)
+                    .is_none_or(|current| state.better_than(current))
+                {
+                    states.insert(n, state);
+                    changed = true;
+                }
             }
         });
-    HeuristicPath { states, first }
+        if !changed {
+            break;
+        }
+    }
+    match states.remove(&first) {
+        Some(state) => HeuristicPath {
+            edges: state.edges,
+            visited: state.visited,
+            bits: state.bits,
+            weight: state.weight,
+        },
+        None => HeuristicPath {
+            edges: Vec::new(),
+            visited: FixedBitSet::with_capacity(values.node_bound()),
+            bits: Bits(0),
+            weight: 0.0,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, bits: i32, oneof: Option<&str>) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            oneof: oneof.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    fn names(values: &StableGraph<&Node, f64>, path: &HeuristicPath) -> Vec<String> {
+        path.into_iter()
+            .map(|e| values[e.target()].name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn cycle_is_not_revisited() {
+        // a <-> b is a cycle that is only worth anything if b could be collected twice
+        let nodes = [
+            node("start", 0, None),
+            node("a", 1, None),
+            node("b", 8, None),
+            node("end", 0, None),
+        ];
+        let mut values = StableGraph::new();
+        let n: Vec<_> = nodes.iter().map(|n| values.add_node(n)).collect();
+        values.add_edge(n[0], n[1], 1.0);
+        values.add_edge(n[1], n[2], 1.0);
+        values.add_edge(n[2], n[1], 1.0);
+        values.add_edge(n[1], n[3], 1.0);
+        values.add_edge(n[2], n[3], 0.5);
+
+        let path = heuristic_path(&values, n[0], n[3]);
+        assert_eq!(names(&values, &path), ["a", "b", "end"]);
+        assert_eq!(path.bits(), Bits(9));
+        assert_eq!(path.weight(), 2.5);
+        assert!(path.visits(n[2]));
+    }
+
+    #[test]
+    fn only_one_oneof() {
+        let nodes = [
+            node("start", 0, None),
+            node("x", 8, Some("x")),
+            node("y", 8, Some("x")),
+            node("end", 0, None),
+        ];
+        let mut values = StableGraph::new();
+        let n: Vec<_> = nodes.iter().map(|n| values.add_node(n)).collect();
+        values.add_edge(n[0], n[1], 1.0);
+        values.add_edge(n[1], n[2], 1.0);
+        values.add_edge(n[2], n[1], 1.0);
+        values.add_edge(n[1], n[3], 1.0);
+        values.add_edge(n[2], n[3], 1.0);
+
+        let path = heuristic_path(&values, n[0], n[3]);
+        assert_eq!(names(&values, &path), ["x", "end"]);
+    }
+
+    #[test]
+    fn oneof_groups_are_separate() {
+        let nodes = [
+            node("start", 0, None),
+            node("x", 8, Some("x")),
+            node("y", 8, Some("y")),
+            node("end", 0, None),
+        ];
+        let mut values = StableGraph::new();
+        let n: Vec<_> = nodes.iter().map(|n| values.add_node(n)).collect();
+        values.add_edge(n[0], n[1], 1.0);
+        values.add_edge(n[1], n[2], 1.0);
+        values.add_edge(n[1], n[3], 1.0);
+        values.add_edge(n[2], n[3], 1.0);
+
+        let path = heuristic_path(&values, n[0], n[3]);
+        assert_eq!(names(&values, &path), ["x", "y", "end"]);
+    }
+
+    #[test]
+    fn one_long_load_per_hub() {
+        let warp = |name: &str| Node {
+            action: Some(Action::Warp),
+            ..node(name, 0, None)
+        };
+        let mut graph = StableGraph::new();
+        let gate = graph.add_node(warp("a.gate"));
+        let hub = graph.add_node(node("hub.gate", 0, None));
+        let back = graph.add_node(warp("hub.warp"));
+        let again = graph.add_node(warp("a.other_gate"));
+        let end = graph.add_node(node("hub.end", 0, None));
+        let edge = Edge { time: Frames(1.0) };
+        let first = graph.add_edge(gate, hub, edge.clone());
+        // walking to a warp gate in the same room isn't a warp
+        let walk = graph.add_edge(hub, back, edge.clone());
+        let away = graph.add_edge(back, again, edge.clone());
+        let second = graph.add_edge(again, end, edge);
+
+        assert_eq!(warp_hub(&graph, first), Some("hub"));
+        assert_eq!(warp_hub(&graph, walk), None);
+        assert_eq!(warp_hub(&graph, second), Some("hub"));
+        let loads = long_load_edges(&graph, [first, walk, away, second]);
+        assert_eq!(loads, HashSet::from([first, away]));
+    }
 }
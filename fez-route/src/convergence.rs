@@ -0,0 +1,136 @@
+use crate::units::Frames;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const WIDTH: usize = 60;
+const HEIGHT: usize = 12;
+
+/// a route that was the best known when it was found
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Incumbent {
+    /// since the solve started
+    pub seconds: f64,
+    pub time: Frames,
+    /// relative gap to the best bound as of the last tree status, none before glpk has an incumbent
+    pub gap: Option<f64>,
+    /// offered by the heuristics instead of found by glpk
+    pub heuristic: bool,
+}
+
+pub fn csv(history: &[Incumbent]) -> String {
+    let mut csv = "seconds,time,gap,heuristic\n".to_owned();
+    history.iter().for_each(|incumbent| {
+        csv += &format!(
+            "{:.3},{},{},{}\n",
+            incumbent.seconds,
+            incumbent.time,
+            incumbent
+                .gap
+                .map_or_else(String::new, |gap| gap.to_string()),
+            incumbent.heuristic
+        );
+    });
+    csv
+}
+
+/// the best time known at each moment of the solve, as a step plot from the first incumbent to the last
+pub fn plot(history: &[Incumbent]) -> Vec<String> {
+    let (first, last) = match (history.first(), history.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Vec::new(),
+    };
+    let worst = history.iter().map(|i| i.time.0).fold(f64::MIN, f64::max);
+    let best = history.iter().map(|i| i.time.0).fold(f64::MAX, f64::min);
+    let seconds = last.seconds.max(f64::EPSILON);
+    let mut rows = vec![vec![' '; WIDTH]; HEIGHT];
+    (0..WIDTH).for_each(|x| {
+        let at = seconds * x as f64 / (WIDTH - 1) as f64;
+        let time = history
+            .iter()
+            .take_while(|i| i.seconds <= at)
+            .last()
+            .unwrap_or(first)
+            .time
+            .0;
+        let y = if worst > best {
+            ((worst - time) / (worst - best) * (HEIGHT - 1) as f64).round() as usize
+        } else {
+            HEIGHT - 1
+        };
+        rows[y][x] = '*';
+    });
+    let label = |time: f64| format!("{:>9.0}", time);
+    rows.into_iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let axis = if i == 0 {
+                label(worst)
+            } else if i == HEIGHT - 1 {
+                label(best)
+            } else {
+                " ".repeat(9)
+            };
+            format!("{} |{}", axis, row.into_iter().collect::<String>())
+        })
+        .chain([
+            format!("{} +{}", " ".repeat(9), "-".repeat(WIDTH)),
+            format!(
+                "{} 0{:>width$.1}s",
+                " ".repeat(9),
+                last.seconds,
+                width = WIDTH - 1
+            ),
+        ])
+        .collect()
+}
+
+/// logs the plot, and writes the csv if there is a path for it
+pub fn report(history: &[Incumbent], path: Option<&Path>) {
+    if history.is_empty() {
+        return;
+    }
+    info!("{} incumbents, best time over the solve:", history.len());
+    plot(history).iter().for_each(|line| info!("{}", line));
+    if let Some(path) = path {
+        match fs::write(path, csv(history)) {
+            Ok(()) => info!("saved incumbent history to {:?}", path),
+            Err(e) => error!("failed to write incumbent history to {:?}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incumbent(seconds: f64, time: f64, gap: Option<f64>) -> Incumbent {
+        Incumbent {
+            seconds,
+            time: Frames(time),
+            gap,
+            heuristic: gap.is_none(),
+        }
+    }
+
+    #[test]
+    fn history_as_csv_and_plot() {
+        let history = [
+            incumbent(0.0, 1000.0, None),
+            incumbent(5.0, 900.0, Some(0.25)),
+            incumbent(10.0, 500.0, Some(0.0)),
+        ];
+        assert_eq!(
+            csv(&history),
+            "seconds,time,gap,heuristic\n0.000,1000,,true\n5.000,900,0.25,false\n10.000,500,0,false\n"
+        );
+        let lines = plot(&history);
+        assert_eq!(lines.len(), HEIGHT + 2);
+        assert!(lines[0].starts_with("     1000 |*"));
+        assert!(lines[HEIGHT - 1].starts_with("      500 | "));
+        assert!(lines[HEIGHT - 1].ends_with('*'));
+        assert!(lines[HEIGHT + 1].ends_with("10.0s"));
+        assert!(plot(&[]).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::opt::{cut_expr, VarRegistry};
+use crate::rooms::{Edge, Node};
+use crate::topology::{Header, Mismatch};
+use crate::units::Bits;
+use fixedbitset::FixedBitSet;
+use glpk::Expr;
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::visit::NodeIndexable;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// names of the nodes each cut must leave
+type Cuts = HashSet<Vec<String>>;
+
+/// connectivity cuts only depend on which nodes and edges exist, not on timings,
+/// so cuts found by one solve can be reused by any later solve of a graph with the same topology.
+///
+/// after the topology header, each cut is stored as the names of the nodes that must be left, one json list per line
+pub struct CutPool {
+    path: PathBuf,
+    header: Header,
+    exists: bool,
+    loaded: Cuts,
+    found: Vec<Vec<String>>,
+}
+impl CutPool {
+    /// a pool saved for a different graph is only warned about, its cuts are ignored and replaced when saving.
+    /// a pool with lines that can't be read, like one cut off while writing, keeps the cuts it can read and is rewritten
+    pub fn load(path: &Path, graph: &StableGraph<Node, Edge>) -> Self {
+        let (exists, loaded) = match read_cuts(path, graph) {
+            Ok(Ok((loaded, 0))) => (true, loaded),
+            Ok(Ok((loaded, skipped))) => {
+                warn!(
+                    "skipped {} unreadable lines of cut pool {:?}, it will be rewritten",
+                    skipped, path
+                );
+                (false, loaded)
+            }
+            Ok(Err(mismatch)) => {
+                warn!("ignoring the cut pool, {}", mismatch);
+                (false, HashSet::new())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (false, HashSet::new()),
+            Err(e) => {
+                error!(
+                    "failed to read cut pool {:?}, it will be rewritten: {}",
+                    path, e
+                );
+                (false, HashSet::new())
+            }
+        };
+        info!("loaded {} cuts from {:?}", loaded.len(), path);
+        CutPool {
+            path: path.to_owned(),
+            header: Header::new(graph),
+            exists,
+            loaded,
+            found: Vec::new(),
+        }
+    }
+
+    /// the loaded cuts that are still valid for the required bits
+    pub(crate) fn exprs(
+        &self,
+        graph: &StableGraph<Node, Edge>,
+        vars: &VarRegistry,
+        required_bits: Bits,
+    ) -> Vec<Expr> {
+        let indices: HashMap<&str, NodeIndex> = graph
+            .node_indices()
+            .map(|n| (graph[n].name.as_str(), n))
+            .collect();
+        self.loaded
+            .iter()
+            .filter_map(|names| {
+                let mut nodes = FixedBitSet::with_capacity(graph.node_bound());
+                let mut bits = Bits(0);
+                for name in names {
+                    let n = *indices.get(name.as_str())?;
+                    nodes.insert(n.index());
+                    bits += graph[n].bits;
+                }
+                if bits < required_bits {
+                    Some(cut_expr(graph, vars, &nodes))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn record(&mut self, graph: &StableGraph<Node, Edge>, connected_nodes: &FixedBitSet) {
+        let mut names: Vec<String> = connected_nodes
+            .ones()
+            .map(|n| graph[NodeIndex::new(n)].name.clone())
+            .collect();
+        names.sort();
+        if !self.loaded.contains(&names) && !self.found.contains(&names) {
+            self.found.push(names);
+        }
+    }
+
+    /// appends the newly found cuts to the pool, or starts it over with the loaded cuts if it couldn't be appended to
+    pub fn save(&self) {
+        if let Err(e) = self.try_save() {
+            error!("failed to write cut pool {:?}: {}", self.path, e);
+        } else {
+            info!("saved {} new cuts to {:?}", self.found.len(), self.path);
+        }
+    }
+
+    fn try_save(&self) -> io::Result<()> {
+        if let Some(folder) = self.path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        let mut file = if self.exists {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?
+        } else {
+            let mut file = File::create(&self.path)?;
+            writeln!(file, "{}", serde_json::to_string(&self.header)?)?;
+            self.loaded
+                .iter()
+                .try_for_each(|names| writeln!(file, "{}", serde_json::to_string(names)?))?;
+            file
+        };
+        self.found
+            .iter()
+            .try_for_each(|names| writeln!(file, "{}", serde_json::to_string(names)?))
+    }
+}
+
+/// the cuts, and how many lines couldn't be read
+fn read_cuts(
+    path: &Path,
+    graph: &StableGraph<Node, Edge>,
+) -> io::Result<Result<(Cuts, usize), Mismatch>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    if let Err(mismatch) = Header::verify(path, &header, graph) {
+        return Ok(Err(mismatch));
+    }
+    let mut cuts = HashSet::new();
+    let mut skipped = 0;
+    lines.for_each(
+        |line| match line.ok().and_then(|line| serde_json::from_str(&line).ok()) {
+            Some(names) => {
+                cuts.insert(names);
+            }
+            None => skipped += 1,
+        },
+    );
+    Ok(Ok((cuts, skipped)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_pools_are_rewritten() {
+        let mut graph = StableGraph::new();
+        ["a", "b"].iter().for_each(|name| {
+            graph.add_node(Node {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        });
+        let path = std::env::temp_dir().join(format!("cut-pool-{}.jsonl", std::process::id()));
+        fs::write(
+            &path,
+            format!(
+                "{}\n[\"a\"]\n[\"b",
+                serde_json::to_string(&Header::new(&graph)).unwrap()
+            ),
+        )
+        .unwrap();
+
+        let mut pool = CutPool::load(&path, &graph);
+        assert_eq!(pool.loaded.len(), 1);
+        let mut nodes = FixedBitSet::with_capacity(2);
+        nodes.insert(1);
+        pool.record(&graph, &nodes);
+        pool.save();
+        let (cuts, skipped) = read_cuts(&path, &graph).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(skipped, 0);
+        assert_eq!(cuts.len(), 2);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::common::cost;
+use crate::rooms::{Edge, Node};
+use fixedbitset::FixedBitSet;
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use petgraph::Direction::Outgoing;
+use std::os::raw::c_int;
+
+const EPS: f64 = 1e-6;
+
+/// how much each lazy connectivity cut moved the relaxation and how long it stayed tight, summarized at the end of the solve
+/// by the size and zone of the cut off nodes, to see which cuts are worth separating
+#[derive(Default)]
+pub struct CutStats {
+    cuts: Vec<CutRecord>,
+}
+
+struct CutRecord {
+    /// edges leaving the connected nodes, at least one of which has to be taken
+    crossing: Vec<EdgeIndex>,
+    size: usize,
+    /// the zone most of the connected nodes are in
+    zone: String,
+    /// the subproblem the cut was separated in
+    node: Option<c_int>,
+    /// objective of the relaxation the cut was separated from
+    before: f64,
+    /// objective of the relaxation solved again with the cut, unless the subproblem was dropped first
+    after: Option<f64>,
+    /// tree nodes created before the cut was added
+    added: c_int,
+    /// tree nodes created after the cut was added until a relaxation didn't need it
+    slack: Option<c_int>,
+}
+
+/// the cuts of one size and zone
+#[derive(Clone, Debug, PartialEq)]
+pub struct CutPattern {
+    /// the range of how many nodes were cut off, as a power of two
+    pub size: String,
+    pub zone: String,
+    pub cuts: usize,
+    /// cuts that raised the objective of the relaxation they were separated from
+    pub raised: usize,
+    /// total increase of the objective over every cut
+    pub gain: f64,
+    /// cuts that later became slack
+    pub slack: usize,
+    /// average tree nodes until a cut became slack, of the cuts that did
+    pub nodes_until_slack: f64,
+}
+
+impl CutStats {
+    /// checks the cuts against a solved relaxation, before any cut is separated from it
+    pub fn observe(
+        &mut self,
+        graph: &StableGraph<Node, Edge>,
+        values: &StableGraph<&Node, f64>,
+        node: Option<c_int>,
+        tree_nodes: c_int,
+    ) {
+        let value = objective(graph, values);
+        if let Some(last) = self.cuts.last_mut() {
+            if last.after.is_none() && last.node == node {
+                last.after = Some(value);
+            }
+        }
+        self.cuts
+            .iter_mut()
+            .filter(|cut| cut.slack.is_none())
+            .filter(|cut| {
+                let flow: f64 = cut
+                    .crossing
+                    .iter()
+                    .filter_map(|e| values.edge_weight(*e))
+                    .sum();
+                flow > 1.0 + EPS
+            })
+            .for_each(|cut| cut.slack = Some(tree_nodes - cut.added));
+    }
+
+    /// a cut separated from the relaxation
+    pub fn record(
+        &mut self,
+        graph: &StableGraph<Node, Edge>,
+        connected_nodes: &FixedBitSet,
+        values: &StableGraph<&Node, f64>,
+        node: Option<c_int>,
+        tree_nodes: c_int,
+    ) {
+        let crossing = connected_nodes
+            .ones()
+            .map(NodeIndex::new)
+            .flat_map(|n| {
+                graph
+                    .edges_directed(n, Outgoing)
+                    .filter(|e| !connected_nodes.contains(e.target().index()))
+                    .map(|e| e.id())
+            })
+            .collect();
+        let zone = connected_nodes
+            .ones()
+            .map(|n| graph[NodeIndex::new(n)].zone.as_str())
+            .counts()
+            .into_iter()
+            .max_by(|(l_zone, l), (r_zone, r)| l.cmp(r).then(r_zone.cmp(l_zone)))
+            .map_or_else(String::new, |(zone, _)| zone.to_owned());
+        self.cuts.push(CutRecord {
+            crossing,
+            size: connected_nodes.count_ones(..),
+            zone,
+            node,
+            before: objective(graph, values),
+            after: None,
+            added: tree_nodes,
+            slack: None,
+        });
+    }
+
+    /// the cuts grouped by size and zone, the ones that raised the objective most first
+    pub fn patterns(&self) -> Vec<CutPattern> {
+        self.cuts
+            .iter()
+            .into_group_map_by(|cut| (size_range(cut.size), cut.zone.clone()))
+            .into_iter()
+            .map(|((size, zone), cuts)| {
+                let gains: Vec<f64> = cuts
+                    .iter()
+                    .filter_map(|cut| cut.after.map(|after| after - cut.before))
+                    .collect();
+                let slack: Vec<c_int> = cuts.iter().filter_map(|cut| cut.slack).collect();
+                CutPattern {
+                    size,
+                    zone,
+                    cuts: cuts.len(),
+                    raised: gains.iter().filter(|gain| **gain > EPS).count(),
+                    gain: gains.iter().sum(),
+                    slack: slack.len(),
+                    nodes_until_slack: if slack.is_empty() {
+                        0.0
+                    } else {
+                        slack.iter().map(|nodes| f64::from(*nodes)).sum::<f64>()
+                            / slack.len() as f64
+                    },
+                }
+            })
+            .sorted_by(|l, r| {
+                r.gain
+                    .partial_cmp(&l.gain)
+                    .unwrap()
+                    .then_with(|| l.zone.cmp(&r.zone))
+                    .then_with(|| l.size.cmp(&r.size))
+            })
+            .collect()
+    }
+
+    pub fn log(&self) {
+        info!("{} lazy cuts by size and zone", self.cuts.len());
+        self.patterns().iter().for_each(|pattern| {
+            info!(
+                "{:>8} cuts of {:>7} nodes in {}: {} raised the objective by {:.1} frames, {} became slack after {:.1} tree nodes",
+                pattern.cuts,
+                pattern.size,
+                pattern.zone,
+                pattern.raised,
+                pattern.gain,
+                pattern.slack,
+                pattern.nodes_until_slack
+            )
+        });
+    }
+}
+
+/// the objective of a relaxation, which only the edges contribute to
+fn objective(graph: &StableGraph<Node, Edge>, values: &StableGraph<&Node, f64>) -> f64 {
+    graph
+        .edge_references()
+        .filter_map(|e| {
+            values
+                .edge_weight(e.id())
+                .map(|value| value * cost(graph, e).0)
+        })
+        .sum()
+}
+
+/// 1, 2-3, 4-7, ...
+fn size_range(size: usize) -> String {
+    let low = 1 << (usize::BITS - 1 - size.max(1).leading_zeros());
+    if low == 1 {
+        "1".to_owned()
+    } else {
+        format!("{}-{}", low, 2 * low - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Frames;
+
+    fn node(name: &str, zone: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            zone: zone.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cuts_are_grouped_by_size_and_zone() {
+        let mut graph = StableGraph::new();
+        let a = graph.add_node(node("a.start", "a"));
+        let b = graph.add_node(node("b.a", "b"));
+        let c = graph.add_node(node("b.end", "b"));
+        let edge = Edge { time: Frames(10.0) };
+        let ab = graph.add_edge(a, b, edge.clone());
+        let bc = graph.add_edge(b, c, edge.clone());
+        let ac = graph.add_edge(a, c, edge);
+
+        let mut connected = FixedBitSet::with_capacity(3);
+        connected.insert(a.index());
+        let mut values = graph.map(|_, n| n, |_, _| 0.0);
+        values.remove_edge(ab);
+        values.remove_edge(ac);
+        values[bc] = 1.0;
+
+        let mut stats = CutStats::default();
+        stats.observe(&graph, &values, Some(1), 1);
+        stats.record(&graph, &connected, &values, Some(1), 1);
+        // solved again with the cut
+        let mut values = graph.map(|_, n| n, |_, _| 0.5);
+        values[bc] = 1.0;
+        stats.observe(&graph, &values, Some(1), 1);
+        // a later relaxation doesn't need it
+        let values = graph.map(|_, n| n, |_, _| 1.0);
+        stats.observe(&graph, &values, Some(2), 4);
+
+        assert_eq!(
+            stats.patterns(),
+            vec![CutPattern {
+                size: "1".to_owned(),
+                zone: "a".to_owned(),
+                cuts: 1,
+                raised: 1,
+                gain: 10.0,
+                slack: 1,
+                nodes_until_slack: 3.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn size_ranges() {
+        assert_eq!(size_range(1), "1");
+        assert_eq!(size_range(2), "2-3");
+        assert_eq!(size_range(7), "4-7");
+        assert_eq!(size_range(8), "8-15");
+    }
+}
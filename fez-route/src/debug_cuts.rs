@@ -0,0 +1,190 @@
+//! every lazy cut written out as it is added, for debugging cuts that cut off too much or too little.
+//! each cut gets its expr as text, and connectivity cuts also get a small dot file of just the nodes
+//! connected to the first node and the edges leaving them, labeled with their values in the relaxation
+
+use crate::rooms::{Edge, Node};
+use fixedbitset::FixedBitSet;
+use glpk::{Bounds, Expr, Prob, VarRef};
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::Outgoing;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct CutDump {
+    folder: PathBuf,
+}
+
+impl CutDump {
+    /// none if the folder can't be made
+    pub fn new(folder: &Path) -> Option<Self> {
+        match fs::create_dir_all(folder) {
+            Ok(()) => Some(CutDump {
+                folder: folder.to_owned(),
+            }),
+            Err(e) => {
+                error!("failed to create cut folder {:?}: {}", folder, e);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn expr(&self, name: &str, problem: &Prob, expr: &Expr) {
+        self.write(
+            name,
+            "txt",
+            &expr_text(expr, |var| problem.get_var_name(var)),
+        );
+    }
+
+    pub(crate) fn component(
+        &self,
+        name: &str,
+        graph: &StableGraph<Node, Edge>,
+        values: &StableGraph<&Node, f64>,
+        connected: &FixedBitSet,
+    ) {
+        self.write(name, "dot", &component_dot(graph, values, connected));
+    }
+
+    fn write(&self, name: &str, ext: &str, contents: &str) {
+        let path = self.folder.join(format!("{}.{}", name, ext));
+        if let Err(e) = fs::write(&path, contents) {
+            warn!("failed to write cut {:?}: {}", path, e);
+        }
+    }
+}
+
+/// the expr like an LP file row, one term per line so long cuts stay readable
+fn expr_text(expr: &Expr, var_name: impl Fn(VarRef) -> String) -> String {
+    let mut text = format!("{}:\n", expr.name);
+    expr.terms.iter().for_each(|term| {
+        let coef = term.coef();
+        let sign = if coef < 0.0 { '-' } else { '+' };
+        writeln!(text, "  {} {} {}", sign, coef.abs(), var_name(term.var())).unwrap();
+    });
+    writeln!(
+        text,
+        "{}",
+        match expr.bounds {
+            Bounds::Free => "  free".to_owned(),
+            Bounds::Lower(lower) => format!("  >= {}", lower),
+            Bounds::Upper(upper) => format!("  <= {}", upper),
+            Bounds::Double(lower, upper) => format!("  >= {} and <= {}", lower, upper),
+            Bounds::Fixed(fixed) => format!("  = {}", fixed),
+        }
+    )
+    .unwrap();
+    text
+}
+
+/// the connected nodes in a cluster with the valued edges between them, and every edge leaving them dashed
+/// to the node it leads to. the crossing values sum to less than 1, which is what the cut forbids
+fn component_dot(
+    graph: &StableGraph<Node, Edge>,
+    values: &StableGraph<&Node, f64>,
+    connected: &FixedBitSet,
+) -> String {
+    let value = |e| values.edge_weight(e).copied().unwrap_or(0.0);
+    let nodes: Vec<NodeIndex> = connected.ones().map(NodeIndex::new).collect();
+    let edges: Vec<_> = nodes
+        .iter()
+        .flat_map(|n| graph.edges_directed(*n, Outgoing))
+        .collect();
+    let (crossing, inside): (Vec<_>, Vec<_>) = edges
+        .into_iter()
+        .partition(|e| !connected.contains(e.target().index()));
+    let crossing_total: f64 = crossing.iter().map(|e| value(e.id())).sum();
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph cut {{").unwrap();
+    writeln!(
+        dot,
+        "  label = \"{} crossing edges with {:.3} taken\"",
+        crossing.len(),
+        crossing_total
+    )
+    .unwrap();
+    writeln!(dot, "  subgraph \"cluster-connected\" {{").unwrap();
+    writeln!(dot, "    label = \"connected to the first node\"").unwrap();
+    nodes.iter().for_each(|n| {
+        let node = &graph[*n];
+        writeln!(
+            dot,
+            "    \"{}\" [ label = \"{}\\n{} bits\" ];",
+            node.name, node.name, node.bits
+        )
+        .unwrap();
+    });
+    writeln!(dot, "  }}").unwrap();
+    crossing
+        .iter()
+        .map(|e| e.target())
+        .sorted()
+        .dedup()
+        .for_each(|n| writeln!(dot, "  \"{}\" [ style = \"dashed\" ];", graph[n].name).unwrap());
+    inside.iter().filter(|e| value(e.id()) > 0.0).for_each(|e| {
+        writeln!(
+            dot,
+            "  \"{}\" -> \"{}\" [ label = \"{:.3}\" ];",
+            graph[e.source()].name,
+            graph[e.target()].name,
+            value(e.id())
+        )
+        .unwrap()
+    });
+    crossing.iter().for_each(|e| {
+        writeln!(
+            dot,
+            "  \"{}\" -> \"{}\" [ style = \"dashed\" label = \"{:.3}\" ];",
+            graph[e.source()].name,
+            graph[e.target()].name,
+            value(e.id())
+        )
+        .unwrap()
+    });
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Bits, Frames};
+
+    fn node(name: &str, bits: i32) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn components_show_their_crossing_edges() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("start", 0));
+        let a = graph.add_node(node("a", 1));
+        let b = graph.add_node(node("b", 8));
+        let edge = || Edge { time: Frames(1.0) };
+        graph.add_edge(start, a, edge());
+        graph.add_edge(a, start, edge());
+        graph.add_edge(a, b, edge());
+        let values = graph.filter_map(
+            |_, n| Some(n),
+            |e, _| (e.index() < 2).then(|| if e.index() == 0 { 1.0 } else { 0.5 }),
+        );
+        let mut connected = FixedBitSet::with_capacity(3);
+        connected.insert(start.index());
+        connected.insert(a.index());
+
+        let dot = component_dot(&graph, &values, &connected);
+        assert!(dot.contains("label = \"1 crossing edges with 0.000 taken\""));
+        assert!(dot.contains("  \"start\" -> \"a\" [ label = \"1.000\" ];"));
+        assert!(dot.contains("  \"a\" -> \"b\" [ style = \"dashed\" label = \"0.000\" ];"));
+        assert!(dot.contains("  \"b\" [ style = \"dashed\" ];"));
+    }
+}
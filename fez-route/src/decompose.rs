@@ -0,0 +1,189 @@
+//! nodes like the village exit or the memory core that every route between two parts of the map passes through.
+//! removing them splits the map into components the route can only enter and leave through them,
+//! which are nearly independent pieces of the problem
+
+use crate::rooms::{Edge, Node};
+use crate::units::Bits;
+use fixedbitset::FixedBitSet;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, NodeIndexable};
+use petgraph::Direction::Incoming;
+
+/// nodes left connected once the articulation points are removed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Component {
+    pub nodes: Vec<NodeIndex>,
+    /// the articulation points next to the component, which every route into it passes through
+    pub borders: Vec<NodeIndex>,
+    pub bits: Bits,
+    pub cubes: i32,
+    pub antis: i32,
+}
+
+impl Component {
+    /// the node the component is named after, its first one
+    pub fn name<'g>(&self, graph: &'g StableGraph<Node, Edge>) -> &'g str {
+        &graph[self.nodes[0]].name
+    }
+
+    /// edges from outside the component to a node in it
+    pub fn entries<'g>(
+        &'g self,
+        graph: &'g StableGraph<Node, Edge>,
+    ) -> impl Iterator<Item = EdgeIndex> + 'g {
+        self.nodes.iter().flat_map(move |n| {
+            graph
+                .edges_directed(*n, Incoming)
+                .filter(move |e| !self.nodes.contains(&e.source()))
+                .map(|e| e.id())
+        })
+    }
+}
+
+/// nodes whose removal disconnects the map, ignoring which way edges go, in index order
+pub fn articulation_points(graph: &StableGraph<Node, Edge>) -> Vec<NodeIndex> {
+    let bound = graph.node_bound();
+    let mut discovered: Vec<Option<usize>> = vec![None; bound];
+    let mut low = vec![0; bound];
+    let mut articulation = FixedBitSet::with_capacity(bound);
+    let mut time = 0;
+    for root in graph.node_indices() {
+        if discovered[root.index()].is_some() {
+            continue;
+        }
+        discovered[root.index()] = Some(time);
+        low[root.index()] = time;
+        time += 1;
+        let mut root_children = 0;
+        // depth first without recursion, since the map is deep enough to overflow the stack:
+        // each entry is a node, its parent, and its neighbors left to visit
+        let mut stack = vec![(root, root, neighbors(graph, root))];
+        while let Some((n, parent, rest)) = stack.last_mut() {
+            let (n, parent) = (*n, *parent);
+            match rest.pop() {
+                Some(m) => match discovered[m.index()] {
+                    None => {
+                        discovered[m.index()] = Some(time);
+                        low[m.index()] = time;
+                        time += 1;
+                        stack.push((m, n, neighbors(graph, m)));
+                    }
+                    Some(d) if m != parent => low[n.index()] = low[n.index()].min(d),
+                    Some(_) => {}
+                },
+                None => {
+                    stack.pop();
+                    if parent == n {
+                        continue;
+                    }
+                    low[parent.index()] = low[parent.index()].min(low[n.index()]);
+                    if parent == root {
+                        root_children += 1;
+                    } else if low[n.index()] >= discovered[parent.index()].unwrap() {
+                        articulation.insert(parent.index());
+                    }
+                }
+            }
+        }
+        if root_children > 1 {
+            articulation.insert(root.index());
+        }
+    }
+    articulation.ones().map(NodeIndex::new).collect()
+}
+
+/// every node an edge connects to `n` in either direction, once
+fn neighbors(graph: &StableGraph<Node, Edge>, n: NodeIndex) -> Vec<NodeIndex> {
+    let mut neighbors: Vec<NodeIndex> = graph.neighbors_undirected(n).filter(|m| *m != n).collect();
+    neighbors.sort();
+    neighbors.dedup();
+    neighbors
+}
+
+/// the components left once the articulation points are removed, largest first
+pub fn components(graph: &StableGraph<Node, Edge>, articulation: &[NodeIndex]) -> Vec<Component> {
+    let mut seen = FixedBitSet::with_capacity(graph.node_bound());
+    articulation.iter().for_each(|n| seen.insert(n.index()));
+    let mut components: Vec<Component> = graph
+        .node_indices()
+        .filter_map(|start| {
+            if seen.put(start.index()) {
+                return None;
+            }
+            let mut nodes = vec![start];
+            let mut borders = Vec::new();
+            let mut i = 0;
+            while i < nodes.len() {
+                neighbors(graph, nodes[i]).into_iter().for_each(|m| {
+                    if articulation.contains(&m) {
+                        if !borders.contains(&m) {
+                            borders.push(m);
+                        }
+                    } else if !seen.put(m.index()) {
+                        nodes.push(m);
+                    }
+                });
+                i += 1;
+            }
+            nodes.sort();
+            borders.sort();
+            Some(Component {
+                bits: nodes.iter().map(|n| graph[*n].bits).sum(),
+                cubes: nodes.iter().map(|n| graph[*n].cubes).sum(),
+                antis: nodes.iter().map(|n| graph[*n].antis).sum(),
+                nodes,
+                borders,
+            })
+        })
+        .collect();
+    components.sort_by_key(|component| std::cmp::Reverse(component.nodes.len()));
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Frames;
+
+    fn node(name: &str, bits: i32) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hubs_split_the_map() {
+        // a loop of a, b, and the hub, with c and d hanging off the hub
+        let mut graph = StableGraph::new();
+        let a = graph.add_node(node("a", 1));
+        let b = graph.add_node(node("b", 2));
+        let hub = graph.add_node(node("hub", 4));
+        let c = graph.add_node(node("c", 8));
+        let d = graph.add_node(node("d", 16));
+        let edge = || Edge { time: Frames(1.0) };
+        for (from, to) in [(a, b), (b, hub), (hub, a), (hub, c), (c, hub), (c, d)] {
+            graph.add_edge(from, to, edge());
+        }
+
+        let articulation = articulation_points(&graph);
+        assert_eq!(articulation, [hub, c]);
+        let components = components(&graph, &articulation);
+        assert_eq!(
+            components
+                .iter()
+                .map(|component| (
+                    component.nodes.clone(),
+                    component.borders.clone(),
+                    component.bits
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (vec![a, b], vec![hub], Bits(3)),
+                (vec![d], vec![c], Bits(16)),
+            ]
+        );
+        assert_eq!(components[1].entries(&graph).count(), 1);
+    }
+}
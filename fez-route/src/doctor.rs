@@ -0,0 +1,149 @@
+use crate::analysis::{reachable_bits, reachable_keys};
+use crate::common::endpoints;
+use crate::locks;
+use crate::options::{Options, RenderFormat};
+use crate::render::FOLDER;
+use crate::rooms;
+use glpk::Version;
+use std::fs;
+use std::panic;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// runs every check and prints a pass/fail summary, returns whether everything passed
+pub fn run(options: &Options) -> bool {
+    let mut checks = vec![
+        ("graphviz", graphviz(&options.render)),
+        ("glpk", glpk()),
+        ("render folder", render_folder(Path::new(FOLDER))),
+        ("rooms", rooms(&options.rooms, options)),
+    ];
+    if options.webhook.is_some() {
+        checks.push(("webhook", curl()));
+    }
+    checks.iter().for_each(|(name, result)| match result {
+        Ok(detail) => println!("pass  {:<14} {}", name, detail),
+        Err(reason) => println!("FAIL  {:<14} {}", name, reason),
+    });
+    let passed = checks.iter().filter(|(_, result)| result.is_ok()).count();
+    println!("{} of {} checks passed", passed, checks.len());
+    passed == checks.len()
+}
+
+/// renders are made by piping into fdp, which otherwise only fails with an error log per render
+fn graphviz(formats: &[RenderFormat]) -> Result<String, String> {
+    let output = Command::new("fdp")
+        .arg("-V")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| {
+            format!(
+                "couldn't run fdp, is graphviz installed and on the PATH? {}",
+                e
+            )
+        })?;
+    if !output.status.success() {
+        return Err(format!("fdp -V exited with {}", output.status));
+    }
+    // graphviz prints its version to stderr
+    let version = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+    formats
+        .iter()
+        .filter(|format| **format != RenderFormat::Jsonl)
+        .try_for_each(|format| {
+            let output = Command::new("fdp")
+                .arg(format!("-T{}", format.ext()))
+                .stdin(Stdio::null())
+                .output()
+                .map_err(|e| e.to_string())?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(format!("{} can't render {} files", version, format.ext()))
+            }
+        })?;
+    Ok(version)
+}
+
+/// webhook posts are sent by curl, which otherwise only fails with a warning per post
+fn curl() -> Result<String, String> {
+    let output = Command::new("curl")
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("couldn't run curl, is it installed and on the PATH? {}", e))?;
+    if !output.status.success() {
+        return Err(format!("curl --version exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned())
+}
+
+/// the glpk library is loaded at startup, so getting this far means it was found.
+/// still call into it to check its version against the bindings and list what it is missing.
+/// a different major version only might not line up with the bindings, so like the solve this doesn't fail for it
+fn glpk() -> Result<String, String> {
+    let version = glpk::version();
+    let linked = Version::parse(&version)
+        .ok_or_else(|| format!("couldn't parse glpk version {:?}", version))?;
+    let mut detail = format!("version {}", linked);
+    if !linked.has_proximity_search() {
+        detail += ", without proximity search";
+    }
+    if !linked.matches_bindings() {
+        detail += &format!(
+            ", which may not match the bindings from {}",
+            Version::BINDINGS
+        );
+    }
+    Ok(detail)
+}
+
+/// writes and removes a probe file, without clearing any existing renders
+fn render_folder(folder: &Path) -> Result<String, String> {
+    let probe = folder.join(".doctor");
+    fs::create_dir_all(folder)
+        .and_then(|()| fs::write(&probe, b""))
+        .and_then(|()| fs::remove_file(&probe))
+        .map(|()| format!("{:?} is writable", folder))
+        .map_err(|e| format!("{:?} is not writable: {}", folder, e))
+}
+
+/// loading panics on bad room data, so catch that instead of aborting the remaining checks
+fn rooms(path: &Path, options: &Options) -> Result<String, String> {
+    if !path.is_file() {
+        return Err(format!("{:?} does not exist", path));
+    }
+    let (nodes, edges, bits, keys, locks) = panic::catch_unwind(|| {
+        let graph = rooms::load(path, options);
+        let (first_node, last_node) = endpoints(&graph);
+        let (_, bits) = reachable_bits(&graph, first_node, |_| true);
+        let keys = reachable_keys(&graph, first_node, |_| true);
+        let locks = locks::problems(&graph, first_node, last_node);
+        (graph.node_count(), graph.edge_count(), bits, keys, locks)
+    })
+    .map_err(|e| {
+        let reason = e
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| e.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown error");
+        format!("failed to load {:?}: {}", path, reason)
+    })?;
+    if bits < options.required_bits {
+        return Err(format!(
+            "{:?} only has {} bits reachable from the first node, but {} are required",
+            path, bits, options.required_bits
+        ));
+    }
+    if !locks.is_empty() {
+        return Err(format!("{:?} has {}", path, locks.join(", ")));
+    }
+    Ok(format!(
+        "{:?} has {} nodes and {} edges, with {} bits and {} keys reachable",
+        path, nodes, edges, bits, keys
+    ))
+}
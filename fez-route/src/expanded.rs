@@ -0,0 +1,194 @@
+use crate::analysis::reachable;
+use crate::common::endpoints;
+use crate::heuristic::Route;
+use crate::opt::{self, Control};
+use crate::options::Options;
+use crate::rooms::{Edge, Node, RouteGraph};
+use crate::units::Bits;
+use log::*;
+use petgraph::graph::Graph;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::Incoming;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// experimental: a copy of each node for every level of bits a route could have collected when it gets there,
+/// so the required bits are part of the graph instead of a constraint and connectivity cuts.
+///
+/// each level is a bucket of bits, and a node's bits are rounded down to whole buckets,
+/// so with buckets bigger than 1 bit the routes are still feasible but the best one might be missed
+pub struct Expanded {
+    pub graph: RouteGraph,
+    /// the edge of the room graph each edge is a copy of
+    origins: Vec<EdgeIndex>,
+}
+
+impl Expanded {
+    /// none if the last node can't be reached with the required bits
+    pub fn new(graph: &RouteGraph, bucket: Bits, required_bits: Bits) -> Option<Self> {
+        assert!(
+            bucket > Bits(0),
+            "bits are expanded in buckets of at least 1 bit"
+        );
+        let (first_node, last_node) = endpoints(graph);
+        let top = (required_bits.0 + bucket.0 - 1) / bucket.0;
+
+        // only the copies reachable from the first node
+        let mut expanded = StableGraph::<Node, (Edge, EdgeIndex)>::new();
+        let mut copies = HashMap::<(NodeIndex, i32), NodeIndex>::new();
+        let mut stack = vec![(first_node, 0, copy(&mut expanded, graph, first_node, 0))];
+        while let Some((n, level, from)) = stack.pop() {
+            graph.edges(n).for_each(|e| {
+                let target = e.target();
+                let level = (level + graph[target].bits.0 / bucket.0).min(top);
+                if target == last_node && level < top {
+                    return;
+                }
+                let to = match copies.entry((target, level)) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(entry) => {
+                        let to = copy(&mut expanded, graph, target, level);
+                        stack.push((target, level, to));
+                        *entry.insert(to)
+                    }
+                };
+                expanded.add_edge(from, to, (e.weight().clone(), e.id()));
+            });
+        }
+
+        // and of those, only the copies that can still reach the last node with the required bits
+        let last = *copies.get(&(last_node, top))?;
+        let to_last = reachable(&expanded, last, Incoming, |_| true);
+        let expanded = Graph::from(expanded).filter_map(
+            |n, node| to_last.contains(n.index()).then(|| node.clone()),
+            |_, edge| Some(edge.clone()),
+        );
+        let origins = expanded.edge_weights().map(|(_, origin)| *origin).collect();
+        let expanded = expanded.map(|_, node| node.clone(), |_, (edge, _)| edge.clone());
+        info!(
+            "expanded {} nodes and {} edges into {} nodes and {} edges over {} levels of {} bits",
+            graph.node_count(),
+            graph.edge_count(),
+            expanded.node_count(),
+            expanded.edge_count(),
+            top + 1,
+            bucket
+        );
+        Some(Expanded {
+            graph: expanded.into(),
+            origins,
+        })
+    }
+
+    /// the same route through the room graph
+    pub fn route(&self, graph: &RouteGraph, route: &Route, required_bits: Bits) -> Route {
+        Route::from_path(
+            graph,
+            route
+                .edges
+                .iter()
+                .map(|e| self.origins[e.index()])
+                .collect(),
+            required_bits,
+        )
+    }
+}
+
+/// each copy is in the oneof group of its node, so only one level of each node is visited
+fn copy(
+    expanded: &mut StableGraph<Node, (Edge, EdgeIndex)>,
+    graph: &RouteGraph,
+    n: NodeIndex,
+    level: i32,
+) -> NodeIndex {
+    let node = &graph[n];
+    expanded.add_node(Node {
+        name: format!("{}@{}", node.name, level),
+        oneof: Some(node.oneof.clone().unwrap_or_else(|| node.name.clone())),
+        ..node.clone()
+    })
+}
+
+/// solves the expanded graph and maps the route back to the room graph
+pub fn optimize(
+    graph: &RouteGraph,
+    bucket: Bits,
+    options: &Options,
+    control: &Control,
+) -> Option<Route> {
+    let expanded = match Expanded::new(graph, bucket, options.required_bits) {
+        Some(expanded) => expanded,
+        None => {
+            error!("the last node can't be reached with the required bits in the expanded graph");
+            return None;
+        }
+    };
+    opt::optimize(&expanded.graph, options, control)
+        .map(|route| expanded.route(graph, &route, options.required_bits))
+}
+
+/// like `opt::dry_run`, but for the model of the expanded graph
+pub fn dry_run(graph: &RouteGraph, bucket: Bits, options: &Options) -> bool {
+    match Expanded::new(graph, bucket, options.required_bits) {
+        Some(expanded) => opt::dry_run(&expanded.graph, options),
+        None => {
+            error!("the last node can't be reached with the required bits in the expanded graph");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Frames;
+
+    fn node(name: &str, bits: i32) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn last_node_needs_the_required_bits() {
+        let mut graph = Graph::new();
+        let start = graph.add_node(node("start", 0));
+        let cube = graph.add_node(node("cube", 8));
+        let bit = graph.add_node(node("bit", 1));
+        let end = graph.add_node(node("end", 0));
+        let edge = Edge { time: Frames(1.0) };
+        graph.add_edge(start, end, edge.clone());
+        graph.add_edge(start, bit, edge.clone());
+        graph.add_edge(bit, end, edge.clone());
+        graph.add_edge(start, cube, edge.clone());
+        graph.add_edge(cube, end, edge);
+        let graph = RouteGraph::from(graph);
+
+        let expanded = Expanded::new(&graph, Bits(8), Bits(8)).unwrap();
+        let names: Vec<&str> = expanded
+            .graph
+            .node_weights()
+            .map(|n| n.name.as_str())
+            .collect();
+        // a single bit rounds down to nothing, so it can't lead to the end
+        assert_eq!(names, vec!["start@0", "cube@1", "end@1"]);
+        assert_eq!(
+            expanded.graph[NodeIndex::new(1)].oneof.as_deref(),
+            Some("cube")
+        );
+
+        let route = Route::from_path(
+            &expanded.graph,
+            expanded.graph.edge_indices().collect(),
+            Bits(8),
+        );
+        let route = expanded.route(&graph, &route, Bits(8));
+        assert!(route.feasible);
+        assert_eq!(route.bits, Bits(8));
+
+        assert!(Expanded::new(&graph, Bits(8), Bits(16)).is_none());
+    }
+}
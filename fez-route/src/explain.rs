@@ -0,0 +1,150 @@
+use crate::common::named_after;
+use crate::heuristic::Route;
+use crate::opt::{self, Control};
+use crate::options::Options;
+use crate::prune;
+use crate::rooms::{Edge, Node, RouteGraph};
+use crate::units::Bits;
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+
+/// why a node isn't on the best route: solves the route, then solves it again with the node forced in,
+/// starting from the best route with a detour through the node, and reports how many frames visiting it costs.
+/// strats and detours of the node count as visiting it, and the ones pruning removes are reported with why.
+/// takes the unpruned graph, and returns whether both solves found a route
+pub fn run(unpruned: &RouteGraph, name: &str, options: &Options) -> bool {
+    let named: Vec<NodeIndex> = unpruned
+        .node_indices()
+        .filter(|n| named_after(&unpruned[*n], name))
+        .collect();
+    if named.is_empty() {
+        error!("no node named {}", name);
+        return false;
+    }
+    let (graph, pruned) = if options.no_prune {
+        (unpruned.clone(), Vec::new())
+    } else {
+        prune::prune_with_reasons(unpruned.clone())
+    };
+    pruned
+        .iter()
+        .filter(|(n, _)| named.contains(n))
+        .for_each(|(n, reason)| info!("{} was pruned: {}", unpruned[*n].name, reason));
+    let graph = &graph;
+    let visit: Vec<NodeIndex> = graph
+        .node_indices()
+        .filter(|n| named_after(&graph[*n], name))
+        .collect();
+    if visit.is_empty() {
+        // pruning only removes nodes that no optimal route needs
+        return true;
+    }
+
+    let best = match opt::optimize(graph, options, &Control::default()) {
+        Some(route) => route,
+        None => {
+            error!("found no route to compare against");
+            return false;
+        }
+    };
+    if let Some(n) = visited(graph, &visit, &best) {
+        info!(
+            "{} is already on the best route of {} frames",
+            graph[n].name, best.time
+        );
+        return true;
+    }
+
+    let warm_start = detour(graph, &visit, &best, options.required_bits);
+    match opt::optimize_visiting(graph, options, &Control::default(), &visit, warm_start) {
+        Some(route) => {
+            info!(
+                "visiting {} costs {} frames, {} instead of {}",
+                graph[visited(graph, &visit, &route).unwrap()].name,
+                route.time - best.time,
+                route.time,
+                best.time
+            );
+            true
+        }
+        None => {
+            error!("found no route through {}", name);
+            false
+        }
+    }
+}
+
+fn visited(
+    graph: &StableGraph<Node, Edge>,
+    visit: &[NodeIndex],
+    route: &Route,
+) -> Option<NodeIndex> {
+    route
+        .edges
+        .iter()
+        .map(|e| graph.edge_endpoints(*e).unwrap().1)
+        .find(|n| visit.contains(n))
+}
+
+/// the cheapest feasible route that replaces one edge of the route with a step through one of the nodes
+fn detour(
+    graph: &StableGraph<Node, Edge>,
+    visit: &[NodeIndex],
+    route: &Route,
+    required_bits: Bits,
+) -> Option<Route> {
+    route
+        .edges
+        .iter()
+        .enumerate()
+        .flat_map(|(i, e)| {
+            let (source, target) = graph.edge_endpoints(*e).unwrap();
+            visit.iter().filter_map(move |n| {
+                let into = graph.find_edge(source, *n)?;
+                let out = graph.find_edge(*n, target)?;
+                let mut edges = route.edges.clone();
+                edges.splice(i..=i, [into, out]);
+                Some(Route::from_path(graph, edges, required_bits))
+            })
+        })
+        .filter(|route| route.feasible)
+        .min_by(|l, r| l.time.partial_cmp(&r.time).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Frames;
+
+    fn node(name: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detour_through_the_cheapest_node() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start"));
+        let end = graph.add_node(node("a.end"));
+        let slow = graph.add_node(node("a.chest.slow"));
+        let fast = graph.add_node(node("a.chest.fast"));
+        let edge = |time| Edge { time: Frames(time) };
+        let direct = graph.add_edge(start, end, edge(1.0));
+        graph.add_edge(start, slow, edge(5.0));
+        graph.add_edge(slow, end, edge(5.0));
+        graph.add_edge(start, fast, edge(2.0));
+        graph.add_edge(fast, end, edge(2.0));
+
+        let visit: Vec<NodeIndex> = graph
+            .node_indices()
+            .filter(|n| named_after(&graph[*n], "a.chest"))
+            .collect();
+        let best = Route::from_path(&graph, vec![direct], Bits(0));
+        assert_eq!(visited(&graph, &visit, &best), None);
+        let route = detour(&graph, &visit, &best, Bits(0)).unwrap();
+        assert_eq!(route.time, Frames(4.0));
+        assert_eq!(visited(&graph, &visit, &route), Some(fast));
+    }
+}
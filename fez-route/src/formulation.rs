@@ -0,0 +1,386 @@
+use crate::decompose::Component;
+use crate::opt::{
+    activation_exprs, capacity_exprs, collectable_exprs, component_bits_exprs, dominator_exprs,
+    flow_exprs, key_flow_exprs, lazy_required_bits_expr, level_exprs, long_load_exprs,
+    max_action_exprs, max_transitions_exprs, no_2_cycles, no_3_cycles, oneof_exprs,
+    required_bits_expr, requirement_exprs, VarRegistry,
+};
+use crate::options::Options;
+use crate::progress::ProgressBar;
+use crate::rooms::{Node, RouteGraph};
+use glpk::*;
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use std::collections::HashSet;
+use std::time::Instant;
+
+/// what every constraint family is built from
+pub(crate) struct Model<'m> {
+    pub graph: &'m RouteGraph,
+    pub vars: &'m VarRegistry,
+    pub first_node: NodeIndex,
+    pub last_node: NodeIndex,
+    pub options: &'m Options,
+}
+
+/// a group of related constraints of the model
+pub(crate) trait ConstraintFamily {
+    /// what `--disable-family` and `--enable-family` call it
+    fn name(&self) -> String;
+
+    /// families that only speed up the solve can be left out by default
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    /// the exprs added to the model before solving
+    fn build(&self, model: &Model) -> Vec<Expr>;
+
+    /// an expr the relaxed solution violates, added as a lazy cut while solving
+    fn separate(&self, _model: &Model, _values: &StableGraph<&Node, f64>) -> Option<Expr> {
+        None
+    }
+}
+
+/// the size of the model each family added, and how long it took to build
+#[derive(Clone, Debug)]
+pub(crate) struct FamilyStats {
+    pub name: String,
+    pub exprs: usize,
+    pub terms: usize,
+    pub seconds: f64,
+    /// the exprs it added, to check against the solution
+    pub refs: ExprRefs,
+}
+
+/// every enabled constraint family of the model
+pub(crate) struct Formulation {
+    families: Vec<Box<dyn ConstraintFamily>>,
+}
+
+impl Formulation {
+    /// every family of the model, enabled or not by the options
+    pub fn new(families: Vec<Box<dyn ConstraintFamily>>, options: &Options) -> Self {
+        options
+            .disable_family
+            .iter()
+            .chain(&options.enable_family)
+            .filter(|name| families.iter().all(|family| family.name() != **name))
+            .for_each(|name| {
+                warn!(
+                    "no constraint family is called {}, expected one of {}",
+                    name,
+                    families.iter().map(|family| family.name()).join(", ")
+                )
+            });
+        let families = families
+            .into_iter()
+            .filter(|family| {
+                let name = family.name();
+                !options.disable_family.contains(&name)
+                    && (family.enabled_by_default() || options.enable_family.contains(&name))
+            })
+            .collect();
+        Formulation { families }
+    }
+
+    pub fn enabled(&self, name: &str) -> bool {
+        self.families.iter().any(|family| family.name() == name)
+    }
+
+    /// adds the exprs of every family to the problem, a step of the progress bar each
+    pub fn build(
+        &self,
+        problem: &mut Problem,
+        model: &Model,
+        progress: &mut ProgressBar,
+    ) -> Vec<FamilyStats> {
+        progress.extend(self.families.len());
+        self.families
+            .iter()
+            .map(|family| {
+                let name = family.name();
+                progress.step(name.clone());
+                let start = Instant::now();
+                let exprs = family.build(model);
+                let (count, terms) = (exprs.len(), exprs.iter().map(|expr| expr.terms.len()).sum());
+                let seconds = start.elapsed().as_secs_f64();
+                let stats = FamilyStats {
+                    name,
+                    exprs: count,
+                    terms,
+                    seconds,
+                    refs: problem.add_exprs(exprs),
+                };
+                progress.suspend(|| {
+                    info!(
+                        "built {} with {} exprs and {} terms in {:.3}s",
+                        stats.name, stats.exprs, stats.terms, stats.seconds
+                    )
+                });
+                stats
+            })
+            .collect()
+    }
+
+    /// the first lazy cut any family has for the relaxed solution
+    pub fn separate(&self, model: &Model, values: &StableGraph<&Node, f64>) -> Option<Expr> {
+        self.families
+            .iter()
+            .find_map(|family| family.separate(model, values))
+    }
+}
+
+/// every edge taken is entered and left, except at the first and last nodes
+pub(crate) struct Flow;
+impl ConstraintFamily for Flow {
+    fn name(&self) -> String {
+        "flow".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        flow_exprs(model.graph, model.vars, model.first_node, model.last_node)
+    }
+}
+
+pub(crate) struct Capacity;
+impl ConstraintFamily for Capacity {
+    fn name(&self) -> String {
+        "capacity".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        capacity_exprs(model.graph, model.vars, model.first_node, model.last_node)
+    }
+}
+
+pub(crate) struct Dominator;
+impl ConstraintFamily for Dominator {
+    fn name(&self) -> String {
+        "dominator".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        dominator_exprs(
+            model.graph,
+            model.vars,
+            model.first_node,
+            &model.options.dominator_ignore,
+        )
+    }
+}
+
+pub(crate) struct No2Cycles;
+impl ConstraintFamily for No2Cycles {
+    fn name(&self) -> String {
+        "no-2-cycles".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        no_2_cycles(model.graph, model.vars)
+    }
+}
+
+/// many exprs for little gain, so only when asked for
+pub(crate) struct No3Cycles;
+impl ConstraintFamily for No3Cycles {
+    fn name(&self) -> String {
+        "no-3-cycles".to_owned()
+    }
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        no_3_cycles(model.graph, model.vars)
+    }
+}
+
+pub(crate) struct RequiredBits {
+    /// edges fixed to not be taken, which are left out
+    pub unusable: HashSet<EdgeIndex>,
+}
+impl ConstraintFamily for RequiredBits {
+    fn name(&self) -> String {
+        "required-bits".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        vec![required_bits_expr(
+            model.graph,
+            model.vars,
+            &self.unusable,
+            model.options.required_bits,
+        )]
+    }
+}
+
+/// the nodes connected to the first node have to have the required bits, which is only checked lazily
+pub(crate) struct Connectivity;
+impl ConstraintFamily for Connectivity {
+    fn name(&self) -> String {
+        "connectivity".to_owned()
+    }
+    fn build(&self, _model: &Model) -> Vec<Expr> {
+        Vec::new()
+    }
+    fn separate(&self, model: &Model, values: &StableGraph<&Node, f64>) -> Option<Expr> {
+        lazy_required_bits_expr(
+            model.graph,
+            model.vars,
+            model.first_node,
+            model.options.required_bits,
+            values,
+        )
+    }
+}
+
+pub(crate) struct Oneof;
+impl ConstraintFamily for Oneof {
+    fn name(&self) -> String {
+        "oneof".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        oneof_exprs(model.graph, model.vars)
+    }
+}
+
+pub(crate) struct MaxActions;
+impl ConstraintFamily for MaxActions {
+    fn name(&self) -> String {
+        "max-actions".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        max_action_exprs(model.graph, model.vars, model.options)
+    }
+}
+
+pub(crate) struct Requirements;
+impl ConstraintFamily for Requirements {
+    fn name(&self) -> String {
+        "requirements".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        requirement_exprs(model.graph, model.vars)
+    }
+}
+
+pub(crate) struct Collectables;
+impl ConstraintFamily for Collectables {
+    fn name(&self) -> String {
+        "collectables".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        collectable_exprs(model.graph, model.vars, model.options)
+    }
+}
+
+pub(crate) struct MaxTransitions;
+impl ConstraintFamily for MaxTransitions {
+    fn name(&self) -> String {
+        "max-transitions".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        max_transitions_exprs(model.graph, model.vars, model.options)
+    }
+}
+
+pub(crate) struct KeyFlow;
+impl ConstraintFamily for KeyFlow {
+    fn name(&self) -> String {
+        "key-flow".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        key_flow_exprs(model.graph, model.vars, model.first_node, model.last_node)
+    }
+}
+
+pub(crate) struct Phase {
+    pub resource: String,
+}
+impl ConstraintFamily for Phase {
+    fn name(&self) -> String {
+        format!("{}-phase", self.resource)
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        level_exprs(
+            model.graph,
+            model.vars,
+            &self.resource,
+            model.first_node,
+            model.last_node,
+        )
+    }
+}
+
+pub(crate) struct Activation {
+    pub state: String,
+}
+impl ConstraintFamily for Activation {
+    fn name(&self) -> String {
+        format!("{}-activation", self.state)
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        activation_exprs(
+            model.graph,
+            model.vars,
+            &self.state,
+            model.first_node,
+            model.last_node,
+        )
+    }
+}
+
+pub(crate) struct LongLoad {
+    /// which index the long load vars
+    pub hubs: Vec<String>,
+}
+impl ConstraintFamily for LongLoad {
+    fn name(&self) -> String {
+        "long-load".to_owned()
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        long_load_exprs(model.graph, model.vars, &self.hubs)
+    }
+}
+
+/// bits collected in each component the articulation points split off, which only speeds up the solve
+pub(crate) struct ComponentBits {
+    /// which index the component bits vars
+    pub components: Vec<Component>,
+}
+impl ConstraintFamily for ComponentBits {
+    fn name(&self) -> String {
+        "component-bits".to_owned()
+    }
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+    fn build(&self, model: &Model) -> Vec<Expr> {
+        component_bits_exprs(model.graph, model.vars, &self.components)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn options_toggle_families() {
+        let families = || -> Vec<Box<dyn ConstraintFamily>> {
+            vec![Box::new(Flow), Box::new(No2Cycles), Box::new(No3Cycles)]
+        };
+        let formulation = Formulation::new(families(), &Options::parse_from(["fez-route"]));
+        assert!(formulation.enabled("flow"));
+        assert!(formulation.enabled("no-2-cycles"));
+        assert!(!formulation.enabled("no-3-cycles"));
+
+        let options = Options::parse_from([
+            "fez-route",
+            "--disable-family",
+            "no-2-cycles",
+            "--enable-family",
+            "no-3-cycles",
+        ]);
+        let formulation = Formulation::new(families(), &options);
+        assert!(formulation.enabled("flow"));
+        assert!(!formulation.enabled("no-2-cycles"));
+        assert!(formulation.enabled("no-3-cycles"));
+    }
+}
@@ -0,0 +1,385 @@
+use crate::common::{endpoints, long_load_edges, LONG_LOAD};
+use crate::options::Options;
+use crate::render::route_sink;
+use crate::rooms::{Edge, Node};
+use crate::units::{Bits, Frames};
+use fixedbitset::FixedBitSet;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, NodeIndexable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::iter;
+
+/// frames charged per missing bit (or key, or extra oneof) so that infeasible routes are never preferred
+const PENALTY: f64 = 10_000.0;
+const START_TEMPERATURE: f64 = 2000.0;
+const END_TEMPERATURE: f64 = 1.0;
+
+/// a simple path from the first node to the last node
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub edges: Vec<EdgeIndex>,
+    pub time: Frames,
+    pub bits: Bits,
+    /// collects enough bits, never runs out of keys, visits at most one node of each oneof group,
+    /// never uses a phase after its resource has risen past it, never uses a node before its activation,
+    /// and meets every requirement
+    pub feasible: bool,
+}
+
+impl Route {
+    /// totals up an ordered list of edges from the first node to the last node
+    pub fn from_path(
+        graph: &StableGraph<Node, Edge>,
+        edges: Vec<EdgeIndex>,
+        required_bits: Bits,
+    ) -> Self {
+        Self::measure(graph, edges, required_bits).0
+    }
+
+    /// also returns the number of missing bits, missing keys, extra oneofs, phases used out of order,
+    /// nodes used before their activation, and nodes missing from requirements
+    fn measure(
+        graph: &StableGraph<Node, Edge>,
+        edges: Vec<EdgeIndex>,
+        required_bits: Bits,
+    ) -> (Self, i32) {
+        let mut time = Frames(0.0);
+        let mut bits = Bits(0);
+        let mut keys = 0;
+        let mut min_keys = 0;
+        let mut oneofs = HashMap::<&str, i32>::new();
+        let mut levels = HashMap::<&str, u32>::new();
+        let mut late_phases = 0;
+        let mut activated = HashSet::<&str>::new();
+        let mut early_activations = 0;
+        let mut met = HashMap::<&str, u32>::new();
+        let first = edges
+            .first()
+            .map(|e| &graph[graph.edge_endpoints(*e).unwrap().0]);
+        if let Some(phase) = first.and_then(|n| n.phase.as_ref()) {
+            levels.insert(&phase.resource, phase.level);
+        }
+        activated.extend(first.and_then(|n| n.activates.as_deref()));
+        edges.iter().for_each(|e| {
+            let target = &graph[graph.edge_endpoints(*e).unwrap().1];
+            time += graph[*e].time + target.time;
+            bits += target.bits;
+            keys += target.keys_minus_lock();
+            min_keys = min_keys.min(keys);
+            if let Some(group) = &target.oneof {
+                *oneofs.entry(group).or_default() += 1;
+            }
+            if let Some(phase) = &target.phase {
+                let level = levels.entry(&phase.resource).or_default();
+                if *level > phase.level {
+                    late_phases += 1;
+                } else {
+                    *level = phase.level;
+                }
+            }
+            if let Some(state) = &target.requires_activation {
+                if !activated.contains(state.as_str()) {
+                    early_activations += 1;
+                }
+            }
+            activated.extend(target.activates.as_deref());
+            target.requirements.iter().for_each(|requirement| {
+                *met.entry(&requirement.name).or_default() += 1;
+            });
+        });
+        time += LONG_LOAD * long_load_edges(graph, edges.iter().copied()).len() as f64;
+        let missing_bits = (required_bits - bits).max(Bits(0)).0;
+        let missing_keys = -min_keys;
+        let extra_oneofs: i32 = oneofs.values().map(|n| n - 1).sum();
+        let unmet_requirements: i32 = graph
+            .node_weights()
+            .flat_map(|n| &n.requirements)
+            .map(|requirement| (requirement.name.as_str(), requirement.at_least))
+            .collect::<HashMap<_, _>>()
+            .into_iter()
+            .map(|(name, at_least)| {
+                at_least.saturating_sub(met.get(name).copied().unwrap_or(0)) as i32
+            })
+            .sum();
+        let violations = missing_bits
+            + missing_keys
+            + extra_oneofs
+            + late_phases
+            + early_activations
+            + unmet_requirements;
+        let route = Route {
+            edges,
+            time,
+            bits,
+            feasible: violations == 0,
+        };
+        (route, violations)
+    }
+}
+
+/// runs the heuristic on its own, reporting and rendering the route it finds
+pub fn run(graph: &StableGraph<Node, Edge>, options: &Options) -> Option<Route> {
+    let (first_node, last_node) = endpoints(graph);
+    let route = match anneal(graph, first_node, last_node, options) {
+        Some(route) => route,
+        None => {
+            error!("heuristic found no route from first node to last node");
+            return None;
+        }
+    };
+    route.edges.iter().for_each(|e| {
+        let (source, target) = graph.edge_endpoints(*e).unwrap();
+        info!("{} -> {}", graph[source].name, graph[target].name);
+    });
+    if route.feasible {
+        info!("heuristic route {} frames, {} bits", route.time, route.bits);
+    } else {
+        warn!(
+            "heuristic route is infeasible, {} frames, {} bits",
+            route.time, route.bits
+        );
+    }
+    let mut sink = route_sink(graph, options);
+    if sink.enabled() {
+        sink.render_final(
+            "heuristic",
+            &route_graph(graph, &route),
+            first_node,
+            last_node,
+        );
+    }
+    Some(route)
+}
+
+/// value graph with 1.0 on each edge of the route, for rendering
+pub fn route_graph<'g>(
+    graph: &'g StableGraph<Node, Edge>,
+    route: &Route,
+) -> StableGraph<&'g Node, f64> {
+    let edges: HashSet<_> = route.edges.iter().copied().collect();
+    graph.filter_map(
+        |_, n| Some(n),
+        |i, _| if edges.contains(&i) { Some(1.0) } else { None },
+    )
+}
+
+/// simulated annealing over an ordered list of collectable waypoints,
+/// where consecutive waypoints are joined by the cheapest path through unvisited nodes
+pub fn anneal(
+    graph: &StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    options: &Options,
+) -> Option<Route> {
+    let router = Router::new(graph, first_node, last_node, options.required_bits);
+    let candidates: Vec<NodeIndex> = graph
+        .node_indices()
+        .filter(|n| *n != first_node && *n != last_node)
+        .filter(|n| graph[*n].bits > Bits(0) || graph[*n].keys > 0)
+        .collect();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let mut current = Vec::new();
+    let mut current_eval = router.evaluate(&current)?;
+    let mut best = current_eval.clone();
+    let cooling = (END_TEMPERATURE / START_TEMPERATURE)
+        .powf(1.0 / options.heuristic_iterations.max(1) as f64);
+    let mut temperature = START_TEMPERATURE;
+    for _ in 0..options.heuristic_iterations {
+        let next = mutate(&current, &candidates, &mut rng);
+        if let Some(next_eval) = router.evaluate(&next) {
+            let delta = next_eval.score - current_eval.score;
+            if delta <= 0.0 || rng.gen::<f64>() < (-delta / temperature).exp() {
+                current = next;
+                current_eval = next_eval;
+                if current_eval.score < best.score {
+                    best = current_eval.clone();
+                }
+            }
+        }
+        temperature *= cooling;
+    }
+    Some(best.route)
+}
+
+fn mutate(current: &[NodeIndex], candidates: &[NodeIndex], rng: &mut StdRng) -> Vec<NodeIndex> {
+    let mut next = current.to_vec();
+    let len = next.len();
+    match rng.gen_range(0..4) {
+        1 if len > 0 => {
+            next.remove(rng.gen_range(0..len));
+        }
+        2 if len > 1 => {
+            next.swap(rng.gen_range(0..len), rng.gen_range(0..len));
+        }
+        3 if len > 1 => {
+            let n = next.remove(rng.gen_range(0..len));
+            next.insert(rng.gen_range(0..len), n);
+        }
+        _ => {
+            if let Some(n) = candidates.get(rng.gen_range(0..candidates.len().max(1))) {
+                if !next.contains(n) {
+                    next.insert(rng.gen_range(0..=len), *n);
+                }
+            }
+        }
+    }
+    next
+}
+
+#[derive(Clone, Debug)]
+struct Evaluation {
+    route: Route,
+    score: f64,
+}
+
+struct Router<'g> {
+    graph: &'g StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    required_bits: Bits,
+    /// cheapest path trees from every node, see `cheapest`
+    trees: Vec<Vec<(f64, Option<EdgeIndex>)>>,
+}
+impl<'g> Router<'g> {
+    fn new(
+        graph: &'g StableGraph<Node, Edge>,
+        first_node: NodeIndex,
+        last_node: NodeIndex,
+        required_bits: Bits,
+    ) -> Self {
+        let empty = FixedBitSet::with_capacity(graph.node_bound());
+        let trees = (0..graph.node_bound())
+            .map(|n| cheapest(graph, NodeIndex::new(n), None, &empty))
+            .collect();
+        Router {
+            graph,
+            first_node,
+            last_node,
+            required_bits,
+            trees,
+        }
+    }
+
+    /// None if the last node can't be reached
+    fn evaluate(&self, waypoints: &[NodeIndex]) -> Option<Evaluation> {
+        let mut visited = FixedBitSet::with_capacity(self.graph.node_bound());
+        visited.insert(self.first_node.index());
+        let mut at = self.first_node;
+        let mut edges = Vec::new();
+        for &waypoint in waypoints.iter().chain(iter::once(&self.last_node)) {
+            if visited.contains(waypoint.index()) {
+                // already collected on the way to an earlier waypoint
+                continue;
+            }
+            match self.path(at, waypoint, &visited) {
+                Some(path) => {
+                    path.iter().for_each(|e| {
+                        visited.insert(self.graph.edge_endpoints(*e).unwrap().1.index());
+                    });
+                    edges.extend(path);
+                    at = waypoint;
+                }
+                None if waypoint == self.last_node => return None,
+                None => {}
+            }
+        }
+
+        let (route, violations) = Route::measure(self.graph, edges, self.required_bits);
+        Some(Evaluation {
+            score: f64::from(route.time) + PENALTY * violations as f64,
+            route,
+        })
+    }
+
+    /// cheapest path that avoids visited nodes, reusing the precomputed tree when it already does
+    fn path(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        visited: &FixedBitSet,
+    ) -> Option<Vec<EdgeIndex>> {
+        let tree = &self.trees[from.index()];
+        let path = tree_path(self.graph, tree, from, to)?;
+        if path
+            .iter()
+            .all(|e| !visited.contains(self.graph.edge_endpoints(*e).unwrap().1.index()))
+        {
+            return Some(path);
+        }
+        let tree = cheapest(self.graph, from, Some(to), visited);
+        tree_path(self.graph, &tree, from, to)
+    }
+}
+
+fn tree_path(
+    graph: &StableGraph<Node, Edge>,
+    tree: &[(f64, Option<EdgeIndex>)],
+    from: NodeIndex,
+    to: NodeIndex,
+) -> Option<Vec<EdgeIndex>> {
+    if !tree[to.index()].0.is_finite() {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut node = to;
+    while node != from {
+        let e = tree[node.index()].1?;
+        path.push(e);
+        node = graph.edge_endpoints(e).unwrap().0;
+    }
+    path.reverse();
+    Some(path)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct MinCost(f64, NodeIndex);
+impl Eq for MinCost {}
+impl PartialOrd for MinCost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for MinCost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.partial_cmp(&self.0).unwrap()
+    }
+}
+
+/// dijkstra from `from` that never enters a blocked node, stopping early once `to` is reached.
+/// returns the cost to and previous edge of each node
+fn cheapest(
+    graph: &StableGraph<Node, Edge>,
+    from: NodeIndex,
+    to: Option<NodeIndex>,
+    blocked: &FixedBitSet,
+) -> Vec<(f64, Option<EdgeIndex>)> {
+    let mut tree = vec![(f64::INFINITY, None); graph.node_bound()];
+    let mut heap = BinaryHeap::new();
+    tree[from.index()].0 = 0.0;
+    heap.push(MinCost(0.0, from));
+    while let Some(MinCost(cost, node)) = heap.pop() {
+        if Some(node) == to {
+            break;
+        }
+        if cost > tree[node.index()].0 {
+            continue;
+        }
+        graph.edges(node).for_each(|e| {
+            let target = e.target();
+            if blocked.contains(target.index()) {
+                return;
+            }
+            let next = cost + f64::from(e.weight().time + graph[target].time);
+            if next < tree[target.index()].0 {
+                tree[target.index()] = (next, Some(e.id()));
+                heap.push(MinCost(next, target));
+            }
+        });
+    }
+    tree
+}
@@ -0,0 +1,328 @@
+//! every run's options, room data, incumbents, and route, appended to a json lines file,
+//! so past runs can be listed and compared instead of kept as folders of renamed renders.
+//! runs are numbered by their line in the file, starting from 1
+
+use crate::common::endpoints;
+use crate::convergence::Incumbent;
+use crate::heuristic::Route;
+use crate::options::{Options, RunFilter};
+use crate::results::{rooms_hash, Stats};
+use crate::rooms::{Edge, Node};
+use crate::topology::{topology_hash, TopologyHash};
+use crate::units::{Bits, Frames};
+use clap::Parser;
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::StableGraph;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::iter;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// options that say where a run was read from or written to, rather than how it was solved
+const IGNORED: [&str; 3] = ["command", "config", "history"];
+
+/// one line of the history file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    /// seconds since the unix epoch
+    pub finished: u64,
+    pub version: String,
+    /// see [`rooms_hash`]
+    pub rooms_hash: Option<String>,
+    pub topology: TopologyHash,
+    /// after merging the config file with the command line, by field name
+    pub options: Map<String, Value>,
+    /// every incumbent of the solve, oldest first
+    pub incumbents: Vec<Incumbent>,
+    /// only for runs of the full solve
+    pub stats: Option<Stats>,
+    /// none if no route was found
+    pub route: Option<RunRoute>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRoute {
+    /// from the first node to the last node
+    pub nodes: Vec<String>,
+    pub time: Frames,
+    pub bits: Bits,
+    pub feasible: bool,
+}
+
+impl Run {
+    pub fn new(
+        graph: &StableGraph<Node, Edge>,
+        route: Option<&Route>,
+        options: &Options,
+        stats: Option<Stats>,
+        incumbents: Vec<Incumbent>,
+    ) -> Self {
+        let (first_node, _) = endpoints(graph);
+        Run {
+            finished: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            rooms_hash: rooms_hash(options),
+            topology: topology_hash(graph),
+            options: option_map(options),
+            incumbents,
+            stats,
+            route: route.map(|route| RunRoute {
+                nodes: iter::once(first_node)
+                    .chain(
+                        route
+                            .edges
+                            .iter()
+                            .map(|e| graph.edge_endpoints(*e).unwrap().1),
+                    )
+                    .map(|n| graph[n].name.clone())
+                    .collect(),
+                time: route.time,
+                bits: route.bits,
+                feasible: route.feasible,
+            }),
+        }
+    }
+}
+
+fn option_map(options: &Options) -> Map<String, Value> {
+    match serde_json::to_value(options) {
+        Ok(Value::Object(map)) => map,
+        _ => unreachable!("options serialize to an object"),
+    }
+}
+
+/// appends the run as one line, so an interrupted write can only lose that run
+pub fn record(path: &Path, run: &Run) {
+    let line = serde_json::to_string(run).unwrap();
+    match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+    {
+        Ok(()) => info!("recorded run in {:?}", path),
+        Err(e) => error!("failed to record run in {:?}: {}", path, e),
+    }
+}
+
+/// every run of the file with its number, skipping lines that can't be read
+pub fn load(path: &Path) -> io::Result<Vec<(usize, Run)>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| match serde_json::from_str(line) {
+            Ok(run) => Some((i + 1, run)),
+            Err(e) => {
+                warn!("skipping run {} of {:?}: {}", i + 1, path, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// lists the most recent runs that pass every filter, or compares two runs by number.
+/// returns whether the history could be read and the runs to compare exist
+pub fn run(options: &Options, filters: &[RunFilter], last: usize, diff: &[usize]) -> bool {
+    let runs = match load(&options.history) {
+        Ok(runs) => runs,
+        Err(e) => {
+            error!("failed to read history {:?}: {}", options.history, e);
+            return false;
+        }
+    };
+    if let [a, b] = diff {
+        let find = |number: usize| runs.iter().find(|(i, _)| *i == number).map(|(_, run)| run);
+        return match (find(*a), find(*b)) {
+            (Some(a), Some(b)) => {
+                print_diff(a, b);
+                true
+            }
+            _ => {
+                error!("no run {} or no run {} in {:?}", a, b, options.history);
+                false
+            }
+        };
+    }
+
+    let defaults = option_map(&Options::parse_from(["fez-route"]));
+    let matching: Vec<&(usize, Run)> = runs
+        .iter()
+        .filter(|(_, run)| filters.iter().all(|filter| matches(run, filter)))
+        .collect();
+    println!(" run  finished               time       gap  seconds  options");
+    matching[matching.len().saturating_sub(last)..]
+        .iter()
+        .for_each(|(i, run)| {
+            println!(
+                "{:>4}  {}  {:>9}  {:>8}  {:>7}  {}",
+                i,
+                date(run.finished),
+                run.route
+                    .as_ref()
+                    .map_or_else(|| "-".to_owned(), |route| format!("{:.0}", route.time)),
+                run.stats
+                    .as_ref()
+                    .and_then(|stats| stats.gap)
+                    .map_or_else(|| "-".to_owned(), |gap| format!("{:.2}%", gap * 100.0)),
+                run.stats
+                    .as_ref()
+                    .map_or_else(|| "-".to_owned(), |stats| format!("{:.1}", stats.seconds)),
+                changed_options(&defaults, &run.options).join(" ")
+            )
+        });
+    true
+}
+
+/// the option's value as it would be typed, with lists joined by commas
+fn option_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(option_value).join(","),
+        value => value.to_string(),
+    }
+}
+
+/// filters name options by their long names, which are the field names in kebab case
+fn matches(run: &Run, filter: &RunFilter) -> bool {
+    run.options
+        .get(&filter.option.replace('-', "_"))
+        .is_some_and(|value| option_value(value) == filter.value)
+}
+
+/// option=value for each option that isn't its default
+fn changed_options(defaults: &Map<String, Value>, options: &Map<String, Value>) -> Vec<String> {
+    options
+        .iter()
+        .filter(|(key, _)| !IGNORED.contains(&key.as_str()))
+        .filter(|(key, value)| defaults.get(*key) != Some(*value))
+        .map(|(key, value)| format!("{}={}", key.replace('_', "-"), option_value(value)))
+        .collect()
+}
+
+/// the options that changed, the nodes only one route visits, and how the time changed
+fn print_diff(a: &Run, b: &Run) {
+    if a.rooms_hash != b.rooms_hash {
+        println!("rooms {} -> {}", hash(&a.rooms_hash), hash(&b.rooms_hash));
+    }
+    a.options
+        .keys()
+        .chain(b.options.keys().filter(|key| !a.options.contains_key(*key)))
+        .filter(|key| !IGNORED.contains(&key.as_str()))
+        .for_each(|key| {
+            let (from, to) = (a.options.get(key), b.options.get(key));
+            if from != to {
+                println!(
+                    "{} {} -> {}",
+                    key.replace('_', "-"),
+                    from.map_or_else(|| "-".to_owned(), option_value),
+                    to.map_or_else(|| "-".to_owned(), option_value)
+                );
+            }
+        });
+    match (&a.route, &b.route) {
+        (Some(a), Some(b)) => {
+            a.nodes
+                .iter()
+                .filter(|n| !b.nodes.contains(n))
+                .for_each(|n| println!("- {}", n));
+            b.nodes
+                .iter()
+                .filter(|n| !a.nodes.contains(n))
+                .for_each(|n| println!("+ {}", n));
+            println!(
+                "{} frames, {:+} from {}",
+                b.time,
+                f64::from(b.time - a.time),
+                a.time
+            );
+        }
+        (None, Some(b)) => println!("only the second run found a route, of {} frames", b.time),
+        (Some(a), None) => println!("only the first run found a route, of {} frames", a.time),
+        (None, None) => println!("neither run found a route"),
+    }
+}
+
+fn hash(hash: &Option<String>) -> &str {
+    hash.as_deref().unwrap_or("unreadable")
+}
+
+/// utc, to the minute
+fn date(seconds: u64) -> String {
+    // days to a civil date, from Howard Hinnant's date algorithms
+    let days = (seconds / 86400) as i64 + 719468;
+    let minutes = seconds % 86400 / 60;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_filter_by_changed_options() {
+        let defaults = option_map(&Options::parse_from(["fez-route"]));
+        let options = option_map(&Options::parse_from([
+            "fez-route",
+            "--seed",
+            "3",
+            "--exclude-tag",
+            "glitch",
+            "--exclude-tag",
+            "clip",
+        ]));
+        assert_eq!(
+            changed_options(&defaults, &options),
+            ["exclude-tag=glitch,clip", "seed=3"]
+        );
+
+        let run = Run {
+            finished: 0,
+            version: String::new(),
+            rooms_hash: None,
+            topology: topology_hash(&StableGraph::new()),
+            options,
+            incumbents: Vec::new(),
+            stats: None,
+            route: None,
+        };
+        let filter = |s: &str| s.parse::<RunFilter>().unwrap();
+        assert!(matches(&run, &filter("seed=3")));
+        assert!(matches(&run, &filter("exclude-tag=glitch,clip")));
+        assert!(!matches(&run, &filter("seed=4")));
+        assert!(!matches(&run, &filter("sed=3")));
+    }
+
+    #[test]
+    fn dates_are_utc() {
+        assert_eq!(date(0), "1970-01-01 00:00");
+        assert_eq!(date(951_782_400), "2000-02-29 00:00");
+        assert_eq!(date(1_700_000_000), "2023-11-14 22:13");
+    }
+}
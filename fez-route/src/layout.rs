@@ -0,0 +1,137 @@
+use crate::options::Palette;
+use crate::render::map_dot;
+use crate::rooms::{Edge, Node};
+use log::*;
+use petgraph::stable_graph::StableGraph;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// where fdp placed every node of the full room graph, in points.
+///
+/// fdp lays out each render from scratch, so without pinning nodes successive renders move around
+/// and can't be compared by eye
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Layout {
+    positions: HashMap<String, (f64, f64)>,
+}
+
+impl Layout {
+    /// the cached layout if it places every node of the graph, otherwise a new layout from fdp that replaces it
+    pub fn load_or_seed(path: &Path, graph: &StableGraph<Node, Edge>) -> Option<Self> {
+        match read_layout(path) {
+            Ok(layout)
+                if graph
+                    .node_weights()
+                    .all(|n| layout.positions.contains_key(&n.name)) =>
+            {
+                info!(
+                    "loaded {} node positions from {:?}",
+                    layout.positions.len(),
+                    path
+                );
+                return Some(layout);
+            }
+            Ok(_) => info!("layout cache {:?} is missing nodes, laying out again", path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("ignoring unreadable layout cache {:?}: {}", path, e),
+        }
+        let layout = match seed(graph) {
+            Ok(layout) => layout,
+            Err(e) => {
+                error!("failed to lay out the room graph with fdp: {}", e);
+                return None;
+            }
+        };
+        match layout.try_save(path) {
+            Ok(()) => info!(
+                "saved {} node positions to {:?}",
+                layout.positions.len(),
+                path
+            ),
+            Err(e) => error!("failed to write layout cache {:?}: {}", path, e),
+        }
+        Some(layout)
+    }
+
+    pub fn position(&self, name: &str) -> Option<(f64, f64)> {
+        self.positions.get(name).copied()
+    }
+
+    fn try_save(&self, path: &Path) -> io::Result<()> {
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        serde_json::to_writer(File::create(path)?, self)?;
+        Ok(())
+    }
+}
+
+fn read_layout(path: &Path) -> io::Result<Layout> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// lays out the labeled map, so there is room for labels in every render
+fn seed(graph: &StableGraph<Node, Edge>) -> io::Result<Layout> {
+    let dot = map_dot(graph, Palette::Classic, true, None);
+    let mut child = Command::new("fdp")
+        .arg("-T")
+        .arg("plain")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    // taken so stdin is closed before the output is read
+    child.stdin.take().unwrap().write_all(&dot)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "fdp exited with {}",
+            output.status
+        )));
+    }
+    Ok(Layout {
+        positions: parse_plain(&String::from_utf8_lossy(&output.stdout)),
+    })
+}
+
+/// node centers from graphviz's plain output, which are in inches
+fn parse_plain(plain: &str) -> HashMap<String, (f64, f64)> {
+    plain
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("node ")?;
+            let (name, rest) = match rest.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"')?;
+                    (&quoted[..end], &quoted[end + 1..])
+                }
+                None => rest.split_once(' ')?,
+            };
+            let mut numbers = rest.split_whitespace().map(str::parse::<f64>);
+            let x = numbers.next()?.ok()?;
+            let y = numbers.next()?.ok()?;
+            Some((name.to_owned(), (x * 72.0, y * 72.0)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_node_positions() {
+        let plain = "graph 1 4.5 3\n\
+            node \"a.start\" 0.5 2.5 0.75 0.5 start solid ellipse white black\n\
+            node end 4 1.25 0.75 0.5 end solid ellipse white black\n\
+            edge \"a.start\" end 4 0.5 2.5 1 2 2 1.5 4 1.25 solid white\n\
+            stop\n";
+        let positions = parse_plain(plain);
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions["a.start"], (36.0, 180.0));
+        assert_eq!(positions["end"], (288.0, 90.0));
+    }
+}
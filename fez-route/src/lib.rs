@@ -0,0 +1,137 @@
+//! routing through the FEZ room data, shared by the command line solver and the wasm heuristic router.
+//! everything that needs glpk is behind the `solver` feature, which can't be built for wasm
+
+pub mod analysis;
+pub mod bound;
+#[cfg(feature = "solver")]
+pub mod branch_tree;
+pub mod calibrate;
+pub mod common;
+#[cfg(feature = "solver")]
+pub mod convergence;
+#[cfg(feature = "solver")]
+pub mod cut_pool;
+#[cfg(feature = "solver")]
+pub mod cut_stats;
+#[cfg(feature = "solver")]
+pub mod debug_cuts;
+pub mod decompose;
+#[cfg(feature = "solver")]
+pub mod doctor;
+#[cfg(feature = "solver")]
+pub mod expanded;
+#[cfg(feature = "solver")]
+pub mod explain;
+#[cfg(feature = "solver")]
+pub mod formulation;
+pub mod heuristic;
+#[cfg(feature = "solver")]
+pub mod history;
+pub mod layout;
+#[cfg(feature = "solver")]
+pub mod lns;
+pub mod locks;
+pub mod menu;
+pub mod mincost;
+#[cfg(feature = "solver")]
+pub mod opt;
+pub mod options;
+pub mod practice;
+pub mod progress;
+pub mod prune;
+pub mod render;
+pub mod results;
+pub mod rooms;
+pub mod sanity;
+#[cfg(feature = "solver")]
+pub mod serve;
+pub mod sheet;
+#[cfg(feature = "solver")]
+pub mod shell;
+#[cfg(feature = "solver")]
+pub mod tight;
+pub mod timing_cache;
+pub mod topology;
+#[cfg(feature = "solver")]
+pub mod tournament;
+#[cfg(feature = "solver")]
+pub mod tune;
+pub mod units;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+#[cfg(feature = "solver")]
+pub mod webhook;
+pub mod zones;
+
+use menu::MenuCosts;
+use options::Options;
+use rooms::RouteGraph;
+
+/// room data with known impossible and parallel edges removed, menu costs added, checked for broken times,
+/// and pruned unless disabled
+pub fn prepare(graph: RouteGraph, options: &Options) -> RouteGraph {
+    let mut graph = graph.into_graph();
+
+    graph.retain_edges(|g, e| {
+        let (source, target) = g.edge_endpoints(e).unwrap();
+        let source = g[source].name.as_str();
+        let target = g[target].name.as_str();
+
+        // the only edge were we definately don't have a key
+        if source == "villageville_3d.start" && target == "villageville_3d.boileroom" {
+            return false;
+        }
+
+        // lighthouse is a diode
+        if [
+            "lighthouse.anti",
+            "lighthouse.bit_1",
+            "lighthouse.zu_fork",
+            "lighthouse_house_a.lighthouse",
+            "nature_hub.lighthouse",
+        ]
+        .contains(&source)
+            && [
+                "lighthouse.bit_2",
+                "lighthouse.lighthouse_spin",
+                "lighthouse.water_tower",
+            ]
+            .contains(&target)
+        {
+            return false;
+        }
+
+        return true;
+    });
+
+    prune::remove_parallel_edges(&mut graph);
+
+    if let Some(path) = &options.menu_costs {
+        MenuCosts::load(path)
+            .unwrap_or_else(|e| panic!("failed to read menu costs {:?}: {}", path, e))
+            .apply(&mut graph);
+    }
+
+    let mut graph = RouteGraph::from(graph);
+    sanity::check(&graph);
+    let (first_node, last_node) = common::endpoints(&graph);
+    locks::check(&graph, first_node, last_node);
+    if !options.no_prune {
+        graph = prune::prune_dominated(graph);
+    }
+    graph
+}
+
+/// `prepare` without pruning, for routes that weren't found by the solver,
+/// which may go through nodes or take edges that no optimal route needs
+pub fn prepare_unpruned(graph: RouteGraph, options: &Options) -> RouteGraph {
+    prepare(
+        graph,
+        &Options {
+            no_prune: true,
+            ..options.clone()
+        },
+    )
+}
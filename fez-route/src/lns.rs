@@ -0,0 +1,190 @@
+use crate::common::warp_hub;
+use crate::formulation::{Formulation, Model};
+use crate::heuristic::Route;
+use crate::opt::{build_problem, resolve_options, value_graph, Control, Vars};
+use crate::options::Options;
+use crate::rooms::{Edge, Node, RouteGraph};
+use glpk::*;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const EPS: f64 = 1e-6;
+
+/// large neighborhood search around an incumbent route.
+///
+/// each iteration frees every edge touching a random contiguous segment of the route,
+/// fixes all other edges and long loads to their current value, and re-solves the much smaller MIP.
+/// the model is only built once, so cuts found by earlier iterations are kept.
+/// stopping the control stops the search with the best route so far
+pub fn improve(
+    graph: &RouteGraph,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    options: &Options,
+    control: &Control,
+    mut route: Route,
+) -> Route {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let (mut problem, vars) = build_problem(graph, first_node, last_node, options);
+    let mut cuts = LazyCuts::new(
+        Model {
+            graph,
+            vars: &vars.registry,
+            first_node,
+            last_node,
+            options,
+        },
+        &vars.formulation,
+    )
+    .stopped_by(&control.stop);
+    for i in 0..options.lns_iterations {
+        if control.stop.load(Ordering::Relaxed) {
+            info!(
+                "large neighborhood search was stopped after {} iterations",
+                i
+            );
+            break;
+        }
+        let len = route.edges.len();
+        let segment = options.lns_segment.min(len);
+        let start = rng.gen_range(0..=len - segment);
+        free_segment(
+            graph,
+            &mut problem,
+            &vars,
+            &route,
+            &route.edges[start..start + segment],
+        );
+        if let Err(e) = problem.optimize_mip(&resolve_options(options), &mut cuts) {
+            warn!("lns iteration {} failed: {:?}", i, e);
+            continue;
+        }
+
+        let chosen = vars.registry.edge_set(&problem.get_int_solution());
+        if let Some(path) = ordered_path(graph, first_node, last_node, &chosen) {
+            let next = Route::from_path(graph, path, options.required_bits);
+            if next.feasible && f64::from(route.time - next.time) > EPS {
+                info!(
+                    "lns iteration {} improved incumbent from {} to {} frames",
+                    i, route.time, next.time
+                );
+                route = next;
+            }
+        }
+    }
+    route
+}
+
+/// frees every edge touching the segment and the long loads of the hubs those edges warp to,
+/// and fixes every other edge and long load to the route, so only those count toward `--small-mip-vars`
+pub(crate) fn free_segment(
+    graph: &RouteGraph,
+    problem: &mut Problem,
+    vars: &Vars,
+    route: &Route,
+    segment: &[EdgeIndex],
+) {
+    let free = neighborhood(graph, segment);
+    let taken: HashSet<EdgeIndex> = route.edges.iter().copied().collect();
+    graph.edge_references().for_each(|e| {
+        let bounds = if vars.unusable.contains(&e.id()) {
+            Bounds::Fixed(0.0)
+        } else if free.contains(&e.id()) {
+            Bounds::Double(0.0, 1.0)
+        } else if taken.contains(&e.id()) {
+            Bounds::Fixed(1.0)
+        } else {
+            Bounds::Fixed(0.0)
+        };
+        problem.set_bounds(vars.registry.edge_var(e.id()), bounds);
+    });
+    let free_hubs: HashSet<&str> = free.iter().filter_map(|e| warp_hub(graph, *e)).collect();
+    let taken_hubs: HashSet<&str> = taken.iter().filter_map(|e| warp_hub(graph, *e)).collect();
+    vars.hubs.iter().enumerate().for_each(|(h, hub)| {
+        let bounds = if free_hubs.contains(hub.as_str()) {
+            Bounds::Double(0.0, 1.0)
+        } else if taken_hubs.contains(hub.as_str()) {
+            Bounds::Fixed(1.0)
+        } else {
+            Bounds::Fixed(0.0)
+        };
+        problem.set_bounds(vars.registry.long_load_var(h), bounds);
+    });
+}
+
+/// every edge touching the segment of the route, which an iteration leaves free
+pub(crate) fn neighborhood(graph: &RouteGraph, segment: &[EdgeIndex]) -> HashSet<EdgeIndex> {
+    let nodes: HashSet<NodeIndex> = segment
+        .iter()
+        .flat_map(|e| {
+            let (source, target) = graph.edge_endpoints(*e).unwrap();
+            [source, target]
+        })
+        .collect();
+    graph
+        .edge_references()
+        .filter(|e| nodes.contains(&e.source()) || nodes.contains(&e.target()))
+        .map(|e| e.id())
+        .collect()
+}
+
+/// follows the chosen edges from the first node to the last node
+pub(crate) fn ordered_path(
+    graph: &StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    chosen: &HashSet<EdgeIndex>,
+) -> Option<Vec<EdgeIndex>> {
+    let mut path = Vec::new();
+    let mut node = first_node;
+    while node != last_node {
+        let e = graph.edges(node).find(|e| chosen.contains(&e.id()))?;
+        path.push(e.id());
+        node = e.target();
+        if path.len() > chosen.len() {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+/// only the lazy cuts of the formulation, without any of the tracing or rendering of the full solve
+pub(crate) struct LazyCuts<'m> {
+    model: Model<'m>,
+    formulation: &'m Formulation,
+    stop: Option<&'m AtomicBool>,
+}
+impl<'m> LazyCuts<'m> {
+    pub(crate) fn new(model: Model<'m>, formulation: &'m Formulation) -> Self {
+        LazyCuts {
+            model,
+            formulation,
+            stop: None,
+        }
+    }
+
+    /// terminates the solve once the flag is set
+    pub(crate) fn stopped_by(self, stop: &'m AtomicBool) -> Self {
+        LazyCuts {
+            stop: Some(stop),
+            ..self
+        }
+    }
+}
+impl<'m> MipCallback for LazyCuts<'m> {
+    fn get_lazy_expr(&mut self, problem: &Prob) -> Option<Expr> {
+        self.formulation.separate(
+            &self.model,
+            &value_graph(self.model.graph, problem, self.model.vars),
+        )
+    }
+
+    fn terminate(&mut self) -> bool {
+        self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+}
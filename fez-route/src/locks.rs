@@ -0,0 +1,155 @@
+//! lock doors that no route could ever open, found before solving so a typo in the keys of the room data
+//! shows up as the doors it strands instead of the model mysteriously being infeasible.
+//! a door can only be opened if some way to it collects more keys before it than the doors it passes cost,
+//! so this only ever finds doors that are really impossible, not every door no good route opens
+
+use crate::analysis::reachable;
+use crate::rooms::{Cost, Edge, Node};
+use fixedbitset::FixedBitSet;
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::visit::{EdgeRef, NodeIndexable};
+use petgraph::Direction::Outgoing;
+use std::collections::VecDeque;
+
+/// every lock door that can never be opened, and the last node if it is only behind them
+pub fn problems(graph: &StableGraph<Node, Edge>, first: NodeIndex, last: NodeIndex) -> Vec<String> {
+    let locks: Vec<NodeIndex> = graph
+        .node_indices()
+        .filter(|n| *n != first && graph[*n].cost == Cost::Lock)
+        .collect();
+    let mut impossible = FixedBitSet::with_capacity(graph.node_bound());
+    let mut problems = Vec::new();
+
+    // each door that is impossible can make the doors behind it impossible too
+    loop {
+        let needed = doors_needed(graph, first, &impossible);
+        let found: Vec<(NodeIndex, i32, i32)> = locks
+            .iter()
+            .filter(|l| !impossible.contains(l.index()))
+            .filter_map(|&l| {
+                let needed = needed[l.index()]?;
+                let keys = reachable(graph, first, Outgoing, |e| {
+                    e.target() != l && !impossible.contains(e.target().index())
+                })
+                .ones()
+                .map(|n| graph[NodeIndex::new(n)].keys)
+                .sum();
+                (keys < needed).then_some((l, needed, keys))
+            })
+            .collect();
+        if found.is_empty() {
+            break;
+        }
+        found.into_iter().for_each(|(l, needed, keys)| {
+            impossible.insert(l.index());
+            problems.push(format!(
+                "lock door {} needs {} keys to reach and open, but only {} can be collected before it",
+                graph[l].name, needed, keys
+            ));
+        });
+    }
+
+    if impossible.count_ones(..) > 0 {
+        let needed = doors_needed(graph, first, &impossible);
+        let everything = reachable(graph, first, Outgoing, |_| true);
+        locks
+            .iter()
+            .chain([&last])
+            .filter(|n| {
+                !impossible.contains(n.index())
+                    && needed[n.index()].is_none()
+                    && everything.contains(n.index())
+            })
+            .for_each(|n| {
+                problems.push(format!(
+                    "{} {} is only reachable through lock doors that can never be opened",
+                    if *n == last { "last node" } else { "lock door" },
+                    graph[*n].name
+                ))
+            });
+    }
+    problems
+}
+
+/// the fewest lock doors, including itself, on any way from the first node to each node without going through an
+/// impossible door, or none if there is no way
+fn doors_needed(
+    graph: &StableGraph<Node, Edge>,
+    first: NodeIndex,
+    impossible: &FixedBitSet,
+) -> Vec<Option<i32>> {
+    let mut needed = vec![None; graph.node_bound()];
+    needed[first.index()] = Some(0);
+    // a door costs one and any other node nothing, so pushing doors to the back keeps the queue in order
+    let mut queue = VecDeque::from([first]);
+    while let Some(n) = queue.pop_front() {
+        let here = needed[n.index()].unwrap();
+        graph
+            .edges(n)
+            .filter(|e| !impossible.contains(e.target().index()))
+            .for_each(|e| {
+                let door = graph[e.target()].cost == Cost::Lock;
+                let there = here + door as i32;
+                if needed[e.target().index()].is_none_or(|current| there < current) {
+                    needed[e.target().index()] = Some(there);
+                    if door {
+                        queue.push_back(e.target());
+                    } else {
+                        queue.push_front(e.target());
+                    }
+                }
+            });
+    }
+    needed
+}
+
+/// logs every problem, since the solver would only say the model is infeasible
+pub fn check(graph: &StableGraph<Node, Edge>, first: NodeIndex, last: NodeIndex) {
+    problems(graph, first, last)
+        .iter()
+        .for_each(|problem| warn!("{}", problem));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Frames;
+
+    fn node(name: &str, keys: i32, cost: Cost) -> Node {
+        Node {
+            name: name.to_owned(),
+            keys,
+            cost,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn doors_need_keys_collected_before_them() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("start", 1, Cost::Free));
+        let a = graph.add_node(node("a", 0, Cost::Lock));
+        let b = graph.add_node(node("b", 0, Cost::Lock));
+        let key = graph.add_node(node("key", 2, Cost::Free));
+        let c = graph.add_node(node("c", 0, Cost::Lock));
+        let end = graph.add_node(node("end", 0, Cost::Free));
+        let edge = || Edge { time: Frames(1.0) };
+        graph.add_edge(start, a, edge());
+        graph.add_edge(a, b, edge());
+        graph.add_edge(b, key, edge());
+        graph.add_edge(key, c, edge());
+        graph.add_edge(c, end, edge());
+        assert_eq!(
+            problems(&graph, start, end),
+            [
+                "lock door b needs 2 keys to reach and open, but only 1 can be collected before it",
+                "lock door c is only reachable through lock doors that can never be opened",
+                "last node end is only reachable through lock doors that can never be opened"
+            ]
+        );
+
+        graph[a].keys = 1;
+        assert!(problems(&graph, start, end).is_empty());
+    }
+}
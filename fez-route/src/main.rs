@@ -1,53 +1,149 @@
-mod common;
-mod opt;
-mod render;
-mod rooms;
-
+use fez_route::history::{self, Run};
+use fez_route::opt::Control;
+use fez_route::options::{Command, Options};
+use fez_route::results::{RouteFile, Stats};
+use fez_route::rooms::{self, RouteGraph};
+use fez_route::{
+    calibrate, doctor, expanded, explain, heuristic, opt, practice, prepare, prepare_unpruned,
+    render, serve, sheet, shell, tournament, tune, verify, watch,
+};
 use simple_logger::SimpleLogger;
+use std::time::Instant;
 
 fn main() {
-    SimpleLogger::new().init().unwrap();
+    let options = Options::load();
+    options
+        .log
+        .iter()
+        .fold(SimpleLogger::new(), |logger, log| {
+            logger.with_module_level(&log.target, log.level)
+        })
+        .init()
+        .unwrap();
 
-    let mut graph = rooms::load("fez-route/rooms.json");
-
-    graph.retain_edges(|g, e| {
-        let (source, target) = g.edge_endpoints(e).unwrap();
-        let source = g[source].name.as_str();
-        let target = g[target].name.as_str();
-
-        // the only edge were we definately don't have a key
-        if source == "villageville_3d.start" && target == "villageville_3d.boileroom" {
-            return false;
-        }
-
-        // lighthouse is a diode
-        if [
-            "lighthouse.anti",
-            "lighthouse.bit_1",
-            "lighthouse.zu_fork",
-            "lighthouse_house_a.lighthouse",
-            "nature_hub.lighthouse",
-        ]
-        .contains(&source)
-            && [
-                "lighthouse.bit_2",
-                "lighthouse.lighthouse_spin",
-                "lighthouse.water_tower",
-            ]
-            .contains(&target)
-        {
-            return false;
-        }
-
-        return true;
-    });
-
-    opt::optimize(&graph.into(), 30 * 8);
+    match &options.command {
+        Some(Command::Doctor) => {
+            if !doctor::run(&options) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Serve { address }) => {
+            serve::run(address);
+            return;
+        }
+        Some(Command::Explain { node }) => {
+            if !explain::run(&load_unpruned_graph(&options), node, &options) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Shell) => {
+            if !shell::run(&load_graph(&options), &options) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Tournament { matrix, seconds }) => {
+            if !tournament::run(matrix, *seconds) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Calibrate { splits, sheet }) => {
+            if !calibrate::run(&load_unpruned_graph(&options), splits, sheet, &options) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Verify { sheet }) => {
+            if !verify::run(&load_unpruned_graph(&options), sheet, &options) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::History { filter, last, diff }) => {
+            if !history::run(&options, filter, *last, diff) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Tune { trials, seconds }) => {
+            if !tune::run(&options, *trials, *seconds) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    if options.watch {
+        watch::run(&options, load_graph);
+        return;
+    }
+    let graph = load_graph(&options);
+    if options.dry_run {
+        let written = match options.expand_bits {
+            Some(bucket) => expanded::dry_run(&graph, bucket, &options),
+            None => opt::dry_run(&graph, &options),
+        };
+        if !written {
+            std::process::exit(1);
+        }
+        return;
+    }
+    let start = Instant::now();
+    let (route, stats, incumbents) = if options.heuristic_only {
+        (heuristic::run(&graph, &options), None, Vec::new())
+    } else {
+        let control = Control::default();
+        let route = match options.expand_bits {
+            Some(bucket) => expanded::optimize(&graph, bucket, &options, &control),
+            None => opt::optimize(&graph, &options, &control),
+        };
+        let progress = control.progress.into_inner().unwrap();
+        let stats = Stats {
+            seconds: start.elapsed().as_secs_f64(),
+            tree_nodes: progress.nodes,
+            gap: progress.gap,
+            lazy_cuts: progress.lazy_cuts,
+        };
+        (route, Some(stats), progress.history)
+    };
+    if !options.no_history {
+        let run = Run::new(&graph, route.as_ref(), &options, stats.clone(), incumbents);
+        history::record(&options.history, &run);
+    }
+    if let Some(route) = route {
+        RouteFile::new(&graph, &route, &options, stats).save(&options.route_file);
+        if let Some(path) = &options.export_sheet {
+            sheet::save(path, &graph, &route);
+        }
+        if let Some(folder) = &options.practice {
+            practice::save(folder, &practice::segments(&graph, &route));
+        }
+        if let Some(folder) = &options.render_steps {
+            render::render_steps(&graph, &route, folder, &options);
+        }
+    }
+}
+
+fn load_graph(options: &Options) -> RouteGraph {
+    prepare(rooms::load(&options.rooms, options), options)
+}
+
+fn load_unpruned_graph(options: &Options) -> RouteGraph {
+    prepare_unpruned(rooms::load(&options.rooms, options), options)
 }
 
 #[test]
 fn load() {
+    use clap::Parser;
+
     SimpleLogger::new().init().unwrap();
 
-    rooms::load("../fez-route/rooms.json");
+    rooms::load(
+        "../fez-route/rooms.json",
+        &Options::parse_from(["fez-route"]),
+    );
 }
@@ -0,0 +1,114 @@
+use crate::common::named_after;
+use crate::rooms::{Action, Cost, Edge, Node};
+use crate::units::Frames;
+use log::*;
+use petgraph::graph::Graph;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// time spent in the map or inventory, like checking which doors are left or assembling artifacts,
+/// added to the nodes where it happens so it is part of the objective.
+///
+/// ```json
+/// { "nodes": { "villageville_3d.start": 90 }, "events": { "warp": 40, "lock": 20 } }
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MenuCosts {
+    /// frames by node name
+    #[serde(default)]
+    nodes: HashMap<String, f64>,
+    /// frames at every node where the event happens
+    #[serde(default)]
+    events: HashMap<MenuEvent, f64>,
+}
+
+/// something a route does that needs a menu each time
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum MenuEvent {
+    /// picking the destination of a warp gate
+    Warp,
+    Well,
+    /// opening a locked door
+    Lock,
+    /// changing a puzzle state
+    Activate,
+}
+impl MenuEvent {
+    fn happens_at(self, node: &Node) -> bool {
+        match self {
+            MenuEvent::Warp => node.action == Some(Action::Warp),
+            MenuEvent::Well => node.action == Some(Action::Well),
+            MenuEvent::Lock => node.cost == Cost::Lock,
+            MenuEvent::Activate => node.activates.is_some(),
+        }
+    }
+}
+
+impl MenuCosts {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// adds each node's menu time to its time. strats and detours are their own nodes,
+    /// so a cost by name also applies to every node named after it
+    pub fn apply(&self, graph: &mut Graph<Node, Edge>) {
+        self.nodes
+            .keys()
+            .filter(|name| !graph.node_weights().any(|node| named_after(node, name)))
+            .for_each(|name| warn!("menu cost for unknown node {}", name));
+        graph.node_weights_mut().for_each(|node| {
+            let frames: f64 = self
+                .nodes
+                .iter()
+                .filter(|(name, _)| named_after(node, name))
+                .map(|(_, frames)| frames)
+                .chain(
+                    self.events
+                        .iter()
+                        .filter(|(event, _)| event.happens_at(node))
+                        .map(|(_, frames)| frames),
+                )
+                .sum();
+            node.menu += Frames(frames);
+            node.time += Frames(frames);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, cost: Cost, action: Option<Action>) -> Node {
+        Node {
+            name: name.to_owned(),
+            cost,
+            action,
+            time: Frames(10.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn costs_are_added_by_name_and_event() {
+        let mut graph = Graph::new();
+        let door = graph.add_node(node("a.door", Cost::Lock, None));
+        let strat = graph.add_node(node("a.door.clip", Cost::Lock, None));
+        let warp = graph.add_node(node("a.warp", Cost::Free, Some(Action::Warp)));
+        let other = graph.add_node(node("a.doorway", Cost::Free, None));
+        let costs: MenuCosts = serde_json::from_str(
+            r#"{ "nodes": { "a.door": 30 }, "events": { "lock": 5, "warp": 40 } }"#,
+        )
+        .unwrap();
+        costs.apply(&mut graph);
+        assert_eq!(graph[door].menu, Frames(35.0));
+        assert_eq!(graph[strat].time, Frames(45.0));
+        assert_eq!(graph[warp].menu, Frames(40.0));
+        assert_eq!(graph[other].menu, Frames(0.0));
+    }
+}
@@ -0,0 +1,192 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+const EPS: f64 = 1e-9;
+
+/// min cost flow by successive shortest paths, for relaxations that only need flow and capacity constraints
+/// and are far cheaper to solve this way than as an LP.
+///
+/// arcs with negative cost are saturated before solving, so any costs are allowed, including negative cycles
+pub struct Network {
+    /// arcs come in pairs, so `a ^ 1` is the reverse of arc `a`
+    arcs: Vec<Arc>,
+    outgoing: Vec<Vec<usize>>,
+    supply: Vec<i64>,
+}
+
+struct Arc {
+    to: usize,
+    /// remaining capacity, which for a reverse arc is the flow on its forward arc
+    capacity: i64,
+    cost: f64,
+}
+
+impl Network {
+    pub fn new(nodes: usize) -> Self {
+        Network {
+            arcs: Vec::new(),
+            outgoing: vec![Vec::new(); nodes],
+            supply: vec![0; nodes],
+        }
+    }
+
+    /// returns the arc, to later look up its flow
+    pub fn add_arc(&mut self, from: usize, to: usize, capacity: i64, cost: f64) -> usize {
+        let arc = self.arcs.len();
+        self.arcs.push(Arc { to, capacity, cost });
+        self.arcs.push(Arc {
+            to: from,
+            capacity: 0,
+            cost: -cost,
+        });
+        self.outgoing[from].push(arc);
+        self.outgoing[to].push(arc ^ 1);
+        arc
+    }
+
+    /// positive where flow starts, negative where it ends
+    pub fn add_supply(&mut self, node: usize, supply: i64) {
+        self.supply[node] += supply;
+    }
+
+    pub fn flow(&self, arc: usize) -> i64 {
+        self.arcs[arc ^ 1].capacity
+    }
+
+    /// total cost of the cheapest flow meeting every supply, or none if they can't be met
+    pub fn solve(&mut self) -> Option<f64> {
+        let mut total = 0.0;
+        let mut excess = self.supply.clone();
+        // after this every arc with remaining capacity has a non negative cost
+        (0..self.arcs.len()).step_by(2).for_each(|a| {
+            let arc = &self.arcs[a];
+            if arc.cost < 0.0 && arc.capacity > 0 {
+                let (to, capacity) = (arc.to, arc.capacity);
+                let from = self.arcs[a ^ 1].to;
+                total += arc.cost * capacity as f64;
+                excess[from] -= capacity;
+                excess[to] += capacity;
+                self.arcs[a].capacity = 0;
+                self.arcs[a ^ 1].capacity += capacity;
+            }
+        });
+
+        let mut potential = vec![0.0; self.outgoing.len()];
+        while excess.iter().any(|e| *e > 0) {
+            let (dist, pred, sink) = self.shortest_paths(&excess, &potential)?;
+            let sink_dist = dist[sink];
+            potential
+                .iter_mut()
+                .zip(dist.iter())
+                .for_each(|(p, d)| *p += d.min(sink_dist));
+
+            let mut path = Vec::new();
+            let mut node = sink;
+            while let Some(arc) = pred[node] {
+                path.push(arc);
+                node = self.arcs[arc ^ 1].to;
+            }
+            let amount = path
+                .iter()
+                .map(|a| self.arcs[*a].capacity)
+                .chain([excess[node], -excess[sink]])
+                .min()
+                .unwrap();
+            path.into_iter().for_each(|a| {
+                total += self.arcs[a].cost * amount as f64;
+                self.arcs[a].capacity -= amount;
+                self.arcs[a ^ 1].capacity += amount;
+            });
+            excess[node] -= amount;
+            excess[sink] += amount;
+        }
+        Some(total)
+    }
+
+    /// dijkstra on reduced costs from every node with excess, stopping at the nearest node with a deficit
+    fn shortest_paths(
+        &self,
+        excess: &[i64],
+        potential: &[f64],
+    ) -> Option<(Vec<f64>, Vec<Option<usize>>, usize)> {
+        let nodes = self.outgoing.len();
+        let mut dist = vec![f64::INFINITY; nodes];
+        let mut pred = vec![None; nodes];
+        let mut done = vec![false; nodes];
+        let mut heap = BinaryHeap::new();
+        (0..nodes).filter(|n| excess[*n] > 0).for_each(|n| {
+            dist[n] = 0.0;
+            heap.push(MinDist(0.0, n));
+        });
+        while let Some(MinDist(d, node)) = heap.pop() {
+            if done[node] {
+                continue;
+            }
+            done[node] = true;
+            if excess[node] < 0 {
+                return Some((dist, pred, node));
+            }
+            self.outgoing[node].iter().for_each(|a| {
+                let arc = &self.arcs[*a];
+                if arc.capacity > 0 && !done[arc.to] {
+                    // reduced costs are only negative by rounding
+                    let reduced = (arc.cost + potential[node] - potential[arc.to]).max(0.0);
+                    let next = d + reduced;
+                    if next + EPS < dist[arc.to] {
+                        dist[arc.to] = next;
+                        pred[arc.to] = Some(*a);
+                        heap.push(MinDist(next, arc.to));
+                    }
+                }
+            });
+        }
+        None
+    }
+}
+
+struct MinDist(f64, usize);
+impl PartialEq for MinDist {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for MinDist {}
+impl PartialOrd for MinDist {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for MinDist {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_cycle_is_saturated() {
+        // 0 -> 1 -> 2 with a profitable cycle 1 -> 3 -> 1 on the side
+        let mut network = Network::new(4);
+        network.add_arc(0, 1, 1, 5.0);
+        network.add_arc(1, 2, 1, 5.0);
+        let cycle = network.add_arc(1, 3, 1, -4.0);
+        network.add_arc(3, 1, 1, 1.0);
+        network.add_arc(0, 2, 1, 20.0);
+        network.add_supply(0, 1);
+        network.add_supply(2, -1);
+        assert_eq!(network.solve(), Some(7.0));
+        assert_eq!(network.flow(cycle), 1);
+    }
+
+    #[test]
+    fn unmet_supply() {
+        let mut network = Network::new(2);
+        network.add_arc(0, 1, 1, 1.0);
+        network.add_supply(0, 2);
+        network.add_supply(1, -2);
+        assert_eq!(network.solve(), None);
+    }
+}
@@ -1,6 +1,31 @@
-use crate::common::heuristic_path;
-use crate::render::{Renderer, EXT};
-use crate::rooms::{Cost, Edge, Node};
+use crate::analysis::{edge_bits_bounds, reachable_bits};
+use crate::bound::lagrangian_bound;
+use crate::branch_tree::BranchTree;
+use crate::common::{cost, endpoints, heuristic_path, long_load_edges, warp_hub, LONG_LOAD};
+use crate::convergence::{self, Incumbent};
+use crate::cut_pool::CutPool;
+use crate::cut_stats::CutStats;
+use crate::debug_cuts::CutDump;
+use crate::decompose::{articulation_points, components, Component};
+use crate::formulation::{
+    Activation, Capacity, Collectables, ComponentBits, Connectivity, ConstraintFamily, Dominator,
+    FamilyStats, Flow, Formulation, KeyFlow, LongLoad, MaxActions, MaxTransitions, Model,
+    No2Cycles, No3Cycles, Oneof, Phase, RequiredBits, Requirements,
+};
+use crate::heuristic::{anneal, route_graph, Route};
+use crate::lns::{improve, ordered_path};
+use crate::options::{
+    BranchDirection, EdgeKind, GlpkBacktracking, GlpkBranching, GlpkCut, Options, RenderFormat,
+};
+use crate::progress::ProgressBar;
+use crate::prune::Folded;
+use crate::render::{route_sink, Cut, RouteSink, FOLDER};
+use crate::rooms::{Action, Edge, Node, RouteGraph};
+use crate::sheet;
+use crate::tight;
+use crate::units::{Bits, Frames};
+use crate::webhook::Webhook;
+use crate::zones::ZoneBounds;
 use fixedbitset::FixedBitSet;
 use glpk::*;
 use itertools::Itertools;
@@ -8,30 +33,85 @@ use log::*;
 use petgraph::algo::dominators;
 use petgraph::stable_graph::{EdgeIndex, EdgeReference, NodeIndex, StableGraph};
 use petgraph::visit::{
-    Dfs, DfsPostOrder, EdgeFiltered, EdgeRef, GraphBase, GraphRef, IntoEdgeReferences, IntoEdges,
-    IntoEdgesDirected, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers,
+    DfsPostOrder, EdgeFiltered, EdgeIndexable, EdgeRef, GraphBase, GraphRef, IntoEdgeReferences,
+    IntoEdges, IntoEdgesDirected, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers,
     IntoNodeReferences, NodeRef, VisitMap, Visitable, Walker,
 };
 use petgraph::Direction::{Incoming, Outgoing};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 const EPS: f64 = 1e-6;
 const TRACE_CUT: i32 = i32::MAX;
 const RENDER_CUT: i32 = i32::MAX;
 const TRACE_BRANCH: i32 = 100;
 const RENDER_BRANCH: i32 = 100;
+/// log target of what the callbacks report while glpk searches, so it can be filtered without losing
+/// the incumbents, which are logged under this module
+const CALLBACK: &str = "glpk::callback";
 
-impl Node {
-    fn keys_minus_lock(&self) -> i32 {
-        self.keys
-            + match self.cost {
-                Cost::Lock => -1,
-                _ => 0,
-            }
-    }
+/// lets another thread watch a solve and stop it early
+#[derive(Default)]
+pub struct Control {
+    pub stop: AtomicBool,
+    pub progress: Mutex<Progress>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Progress {
+    /// time of the best route found so far
+    pub incumbent: Option<Frames>,
+    /// relative gap between the incumbent and the best bound
+    pub gap: Option<f64>,
+    /// branch and bound tree nodes created so far
+    pub nodes: i32,
+    /// connectivity cuts added while solving
+    pub lazy_cuts: usize,
+    /// every incumbent so far, oldest first
+    pub history: Vec<Incumbent>,
+    /// the solve finished, proving its route optimal
+    pub proved: bool,
+}
+
+/// returns the best route, even if the solve was stopped or cut short by its limits before proving it optimal
+pub fn optimize(graph: &RouteGraph, options: &Options, control: &Control) -> Option<Route> {
+    let warm_start = options
+        .import_sheet
+        .as_ref()
+        .and_then(|path| sheet::load(graph, path, options.required_bits));
+    optimize_visiting(graph, options, control, &[], warm_start)
+}
+
+/// like `optimize`, but the route has to visit one of the nodes if any are given.
+/// a known route that does is used as the initial incumbent instead of the heuristic's.
+/// the solve works on the graph with collectables on direct paths folded, and its route is mapped back
+pub fn optimize_visiting(
+    graph: &RouteGraph,
+    options: &Options,
+    control: &Control,
+    visit: &[NodeIndex],
+    warm_start: Option<Route>,
+) -> Option<Route> {
+    let folded = Folded::new(graph, options);
+    let warm_start = warm_start.map(|route| folded.fold(graph, &route, options.required_bits));
+    solve(&folded.graph, options, control, visit, warm_start)
+        .map(|route| folded.unfold(graph, &route, options.required_bits))
 }
 
-/// although graph is a StableGraph, it must be initialized with fully dense node and edge indicies
-pub fn optimize(graph: &StableGraph<Node, Edge>, required_bits: i32) {
+fn solve(
+    graph: &RouteGraph,
+    options: &Options,
+    control: &Control,
+    visit: &[NodeIndex],
+    warm_start: Option<Route>,
+) -> Option<Route> {
+    let start = Instant::now();
+    let required_bits = options.required_bits;
     graph.externals(Incoming).for_each(|node| {
         info!("incoming: {}", graph[node].name);
     });
@@ -39,123 +119,230 @@ pub fn optimize(graph: &StableGraph<Node, Edge>, required_bits: i32) {
         info!("outgoing: {}", graph[node].name);
     });
 
-    graph
-        .node_indices()
-        .enumerate()
-        .filter(|(i, n)| *i != n.index())
-        .for_each(|(i, n)| {
-            panic!(
-                "graph node indicies were not dense, index {} has id {}",
-                i,
-                n.index()
-            )
-        });
-    graph
-        .edge_indices()
-        .enumerate()
-        .filter(|(i, e)| *i != e.index())
-        .for_each(|(i, e)| {
-            panic!(
-                "graph edge indicies were not dense, index {} has id {}",
-                i,
-                e.index()
-            )
-        });
+    let (first_node, last_node) = endpoints(graph);
 
-    let first_node = graph
-        .externals(Incoming)
-        .exactly_one()
-        .ok()
-        .expect("exactly one source node");
-    let last_node = graph
-        .externals(Outgoing)
-        .exactly_one()
-        .ok()
-        .expect("exactly one target node");
+    match Version::linked() {
+        Some(version) if version.matches_bindings() => info!("glpk {}", version),
+        _ => warn!(
+            "glpk {} may not match the bindings from {}, run `fez-route doctor` to check",
+            glpk::version(),
+            Version::BINDINGS
+        ),
+    }
 
-    let mut problem = Problem::new();
-    problem.set_name("FEZ any% route".to_owned());
-    problem.set_direction(Direction::Minimize);
+    if let Some(megabytes) = options.mem_limit {
+        glpk::set_mem_limit(megabytes);
+    }
 
-    // vars
-    // if an edge should be taken
-    let edges = problem.add_vars(edge_vars(graph));
-    // let keys = problem.add_vars(key_vars(graph));
-
-    // exprs
-    problem.add_exprs(flow_exprs(graph, edges, first_node, last_node));
-    problem.add_exprs(capacity_exprs(graph, edges, first_node, last_node));
-    problem.add_exprs(dominator_exprs(graph, edges, first_node));
-    problem.add_exprs(no_2_cycles(graph, edges));
-    // problem.add_exprs(no_3_cycles(graph, edges));
-    problem.add_expr(required_bits_expr(graph, edges, required_bits));
-    problem.add_expr(oneof_expr(graph, edges));
-    problem.add_expr(total_keys_expr(graph, edges));
-    // problem.add_exprs(order_keys_exprs(graph, edges, keys));
-    // problem.add_exprs(approx_water_lock_exprs(graph, edges));
+    info!(
+        "lagrangian lower bound: {}",
+        lagrangian_bound(graph, first_node, last_node, required_bits)
+    );
+
+    let (mut problem, vars) = build_problem(graph, first_node, last_node, options);
+    let cut_pool = options
+        .cut_pool
+        .as_ref()
+        .map(|path| CutPool::load(path, graph));
+    if let Some(cut_pool) = &cut_pool {
+        problem.add_exprs(cut_pool.exprs(graph, &vars.registry, required_bits));
+    }
+    if !visit.is_empty() {
+        problem.add_exprs(vec![visit_expr(graph, &vars.registry, visit)]);
+    }
 
     info!("built problem");
 
+    let zones = ZoneBounds::new(graph);
+    let incumbent = match warm_start {
+        Some(route) => {
+            let route =
+                Some(route).filter(|route| route.feasible && within_limits(graph, options, route));
+            if let Some(route) = &route {
+                info!("initial incumbent {} frames", route.time);
+            }
+            route
+        }
+        None => {
+            let incumbent = anneal(graph, first_node, last_node, options).filter(|route| {
+                route.feasible
+                    && within_limits(graph, options, route)
+                    && visits_any(graph, visit, route)
+            });
+            match &incumbent {
+                Some(route) => {
+                    info!("initial heuristic incumbent {} frames", route.time);
+                    zones.log_spend(graph, &route.edges);
+                }
+                None => warn!("heuristic found no feasible initial incumbent"),
+            }
+            incumbent
+        }
+    };
+    let incumbent = incumbent.filter(|route| visits_any(graph, visit, route));
+
     struct Closure<'g> {
-        graph: &'g StableGraph<Node, Edge>,
-        edges: VarRefs,
+        graph: &'g RouteGraph,
+        vars: Vars,
         first_node: NodeIndex,
         last_node: NodeIndex,
-        required_bits: i32,
+        required_bits: Bits,
+        options: &'g Options,
+        branch_direction: BranchDirection,
+        /// the route has to visit one of these, if there are any
+        visit: &'g [NodeIndex],
+        incumbent: Option<Route>,
+        /// glpk doesn't report when it takes a heuristic solution, so this is tracked separately
+        best: Option<Frames>,
+        /// glpk has an incumbent, from either a solved relaxation or a heuristic solution
+        integer_feasible: bool,
+        first_solution: bool,
+        cut_pool: Option<CutPool>,
+        cut_stats: Option<CutStats>,
+        debug_cuts: Option<CutDump>,
+        branch_tree: Option<BranchTree>,
+        zones: ZoneBounds,
+        /// the subproblem glpk is working on
+        node: Option<TreeNode>,
 
         render: i32,
         cut: i32,
         branch: i32,
         solve: i32,
-        renderer: Renderer,
+        /// tree nodes when memory was last logged
+        nodes: i32,
+        /// every tree node glpk has created
+        tree_nodes: i32,
+        sink: Box<dyn RouteSink + 'g>,
+        webhook: Option<Webhook>,
+        control: &'g Control,
+        start: Instant,
+    }
+    impl Closure<'_> {
+        /// where the render of this name is once it has been made, if it is made as a png
+        fn png(&self, name: &str) -> Option<PathBuf> {
+            (self.sink.enabled() && self.options.render.contains(&RenderFormat::Png))
+                .then(|| Path::new(FOLDER).join(format!("{}.png", name)))
+        }
+
+        fn record(&self, time: Frames, heuristic: bool) {
+            let mut progress = self.control.progress.lock().unwrap();
+            let gap = progress.gap;
+            progress.history.push(Incumbent {
+                seconds: self.start.elapsed().as_secs_f64(),
+                time,
+                gap,
+                heuristic,
+            });
+        }
     }
 
     let mut closure = Closure {
         graph,
-        edges,
+        vars,
         first_node,
         last_node,
         required_bits,
+        options,
+        branch_direction: options.branch_direction,
+        visit,
+        incumbent,
+        best: None,
+        integer_feasible: false,
+        first_solution: options.first_solution,
+        cut_pool,
+        cut_stats: options.cut_stats.then(CutStats::default),
+        debug_cuts: options.debug_cuts.as_deref().and_then(CutDump::new),
+        branch_tree: options.branch_tree.as_deref().map(BranchTree::new),
+        zones,
+        node: None,
 
         render: 0,
         cut: 0,
         branch: 0,
         solve: 0,
-        renderer: Renderer::new("rendered").unwrap(),
+        nodes: 0,
+        tree_nodes: 0,
+        sink: route_sink(graph, options),
+        webhook: options.webhook.clone().map(Webhook::new),
+        control,
+        start,
     };
 
     impl<'g> MipCallback for Closure<'g> {
         fn get_lazy_expr(&mut self, problem: &Prob) -> Option<Expr> {
-            let value_graph = value_graph(self.graph, problem, self.edges);
+            let value_graph = value_graph(self.graph, problem, &self.vars.registry);
+            let node = self.node.map(|node| node.id);
+            if let Some(cut_stats) = &mut self.cut_stats {
+                cut_stats.observe(self.graph, &value_graph, node, self.tree_nodes);
+            }
             // TODO or small disconnected cycle? near path? that was already branched on?
-            if let Some(expr) = lazy_required_bits_expr(
-                self.graph,
-                self.edges,
-                self.first_node,
-                self.required_bits,
-                &value_graph,
-            ) {
+            // connectivity cuts are separated here instead of by the formulation so they can be pooled and rendered
+            let connected_nodes = if self.vars.formulation.enabled("connectivity") {
+                lazy_required_bits_cut(self.first_node, self.required_bits, &value_graph)
+            } else {
+                None
+            };
+            if let Some(connected_nodes) = connected_nodes {
+                if let Some(cut_pool) = &mut self.cut_pool {
+                    cut_pool.record(self.graph, &connected_nodes);
+                }
+                if let Some(cut_stats) = &mut self.cut_stats {
+                    cut_stats.record(
+                        self.graph,
+                        &connected_nodes,
+                        &value_graph,
+                        node,
+                        self.tree_nodes,
+                    );
+                }
+                let expr = cut_expr(self.graph, &self.vars.registry, &connected_nodes);
                 self.cut += 1;
+                self.control.progress.lock().unwrap().lazy_cuts += 1;
+                if let Some(debug_cuts) = &self.debug_cuts {
+                    let name = format!("cut-{}-{}-{}", self.solve, self.branch, self.cut);
+                    debug_cuts.expr(&name, problem, &expr);
+                    debug_cuts.component(&name, self.graph, &value_graph, &connected_nodes);
+                }
                 if self.cut % TRACE_CUT == 0 {
-                    trace!("cut {}-{}-{}", self.solve, self.branch, self.cut);
+                    trace!(target: CALLBACK, "cut {}-{}-{}", self.solve, self.branch, self.cut);
                 }
                 if self.cut % RENDER_CUT == 0 {
                     self.render += 1;
-                    self.renderer.render(
-                        format!(
-                            "{}-cut-{}-{}-{}.{}",
-                            self.render, self.solve, self.branch, self.cut, EXT
+                    self.sink.render_cut(
+                        &format!(
+                            "{}-cut-{}-{}-{}",
+                            self.render, self.solve, self.branch, self.cut
                         ),
                         &value_graph,
                         self.first_node,
                         self.last_node,
+                        &Cut::new(self.graph, &connected_nodes),
                     );
                 }
                 Some(expr)
+            } else if let Some(expr) = self.vars.formulation.separate(
+                &Model {
+                    graph: self.graph,
+                    vars: &self.vars.registry,
+                    first_node: self.first_node,
+                    last_node: self.last_node,
+                    options: self.options,
+                },
+                &value_graph,
+            ) {
+                self.cut += 1;
+                self.control.progress.lock().unwrap().lazy_cuts += 1;
+                if let Some(debug_cuts) = &self.debug_cuts {
+                    let name = format!("cut-{}-{}-{}", self.solve, self.branch, self.cut);
+                    debug_cuts.expr(&name, problem, &expr);
+                }
+                Some(expr)
             } else {
                 self.branch += 1;
                 if self.branch % TRACE_BRANCH == 0 {
                     trace!(
+                        target: CALLBACK,
                         "solved relaxation {}-{}-{}",
                         self.solve,
                         self.branch,
@@ -164,10 +351,10 @@ pub fn optimize(graph: &StableGraph<Node, Edge>, required_bits: i32) {
                 }
                 if self.branch % RENDER_BRANCH == 0 {
                     self.render += 1;
-                    self.renderer.render(
-                        format!(
-                            "{}-branch-{}-{}-{}.{}",
-                            self.render, self.solve, self.branch, self.cut, EXT
+                    self.sink.render(
+                        &format!(
+                            "{}-branch-{}-{}-{}",
+                            self.render, self.solve, self.branch, self.cut
                         ),
                         &value_graph,
                         self.first_node,
@@ -179,117 +366,774 @@ pub fn optimize(graph: &StableGraph<Node, Edge>, required_bits: i32) {
             }
         }
 
-        // fn get_heuristic_solution(&mut self, problem: &Prob) -> Option<Solution> {
-        //     let value_graph = value_graph(self.graph, problem, self.edges);
-        //     let path = heuristic_path(&value_graph, self.first_node, self.last_node);
-        //     if path
-        //         .into_iter()
-        //         .fold(self.required_bits, |a, e| a - self.graph[e.target()].bits)
-        //         <= 0
-        //     {
-        //         let mut s = Solution::zeros(problem.num_vars());
-        //         path.into_iter().for_each(|e| {
-        //             s[self.edges.get(e.id().index())] = 1.0;
-        //         });
-        //         // info!("heuristic!");
-        //         // TODO track if heuristic is better on my own, new best solution doesn't report heuristic solutions
-        //         Some(s)
-        //     } else {
-        //         // info!("no heuristic");
-        //         None
-        //     }
-        // }
+        fn get_heuristic_solution(&mut self, problem: &Prob) -> Option<Solution> {
+            // the initial incumbent is only offered once, glpk keeps it if it is better than what it already has
+            let route = match self.incumbent.take() {
+                Some(route) => route,
+                None => {
+                    // a path is always connected, so it can't be cut off by a lazy expr glpk doesn't have yet
+                    let value_graph = value_graph(self.graph, problem, &self.vars.registry);
+                    let path = heuristic_path(&value_graph, self.first_node, self.last_node);
+                    if path.is_empty() || path.bits() < self.required_bits {
+                        return None;
+                    }
+                    Route::from_path(self.graph, path.edge_ids().collect(), self.required_bits)
+                }
+            };
+            if !route.feasible
+                || !within_limits(self.graph, self.options, &route)
+                || !visits_any(self.graph, self.visit, &route)
+                || self.best.is_some_and(|best| route.time >= best)
+            {
+                return None;
+            }
+            trace!(target: CALLBACK, "offering heuristic solution {} frames", route.time);
+            self.best = Some(route.time);
+            self.record(route.time, true);
+            Some(
+                self.vars
+                    .solution(self.graph, problem, self.first_node, &route),
+            )
+        }
 
         fn get_branch(&mut self, problem: &Prob) -> Option<(VarRef, Branch)> {
-            let value_graph = value_graph(self.graph, problem, self.edges);
+            let value_graph = value_graph(self.graph, problem, &self.vars.registry);
 
-            heuristic_path(&value_graph, self.first_node, self.last_node)
+            let (e, branch) = heuristic_path(&value_graph, self.first_node, self.last_node)
                 .into_iter()
                 .filter(|e| 1.0 - *e.weight() > EPS)
                 .map(|e| {
                     let score = (*e.weight() - 0.5).abs();
-                    (e.id(), score)
+                    (e, score)
                 })
                 .min_by(|l, r| l.1.partial_cmp(&r.1).unwrap())
-                .map(|(e, _)| (self.edges.get(e.index()), Branch::Up))
+                .map(|(e, _)| {
+                    let branch = match self.branch_direction {
+                        BranchDirection::Up => Branch::Up,
+                        BranchDirection::Down => Branch::Down,
+                        BranchDirection::Auto => Branch::Auto,
+                        BranchDirection::Deficit => {
+                            deficit_branch(&value_graph, self.first_node, self.required_bits, e)
+                        }
+                    };
+                    (e, branch)
+                })?;
+            if let (Some(tree), Some(node)) = (&mut self.branch_tree, self.node) {
+                let var = format!(
+                    "{}/to/{}",
+                    self.graph[e.source()].name,
+                    self.graph[e.target()].name
+                );
+                tree.branch(node, var, branch);
+            }
+            Some((self.vars.registry.edge_var(e.id()), branch))
         }
 
         fn new_best_solution(&mut self, problem: &Prob) {
             self.render += 1;
             self.solve += 1;
-            info!("new best solution {}-{}", self.solve, self.branch);
-            self.renderer.render(
-                format!(
-                    "{}-solution-{}-{}.{}",
-                    self.render, self.solve, self.branch, EXT
-                ),
-                &value_graph(self.graph, problem, self.edges),
-                self.first_node,
-                self.last_node,
+            let taken: Vec<_> = self
+                .graph
+                .edge_references()
+                .filter(|e| problem.get_int_value(self.vars.registry.edge_var(e.id())) > 0.5)
+                .collect();
+            let time = taken.iter().map(|e| cost(self.graph, *e)).sum::<Frames>()
+                + LONG_LOAD
+                    * long_load_edges(self.graph, taken.iter().map(|e| e.id())).len() as f64;
+            self.best = Some(time);
+            self.integer_feasible = true;
+            self.control.progress.lock().unwrap().incumbent = Some(time);
+            self.record(time, false);
+            info!(
+                "new best solution {}-{} {} frames, {} with the prologue",
+                self.solve,
+                self.branch,
+                time,
+                Frames(problem.get_mip_objective())
             );
+            let chosen: Vec<EdgeIndex> = self
+                .vars
+                .registry
+                .edge_set(&problem.get_int_solution())
+                .into_iter()
+                .sorted()
+                .collect();
+            self.zones.log_spend(self.graph, &chosen);
+            let name = format!("{}-solution-{}-{}", self.render, self.solve, self.branch);
+            if self.sink.enabled() {
+                self.sink.render(
+                    &name,
+                    &value_graph(self.graph, problem, &self.vars.registry),
+                    self.first_node,
+                    self.last_node,
+                );
+            }
+            let image = self.png(&name);
+            if let Some(webhook) = &mut self.webhook {
+                let gap = self.control.progress.lock().unwrap().gap;
+                let message = format!(
+                    "new best route of {} frames{} after {:.1} minutes",
+                    time,
+                    gap.map_or_else(String::new, |gap| format!(
+                        ", {:.2}% from the best bound",
+                        gap * 100.0
+                    )),
+                    self.start.elapsed().as_secs_f64() / 60.0
+                );
+                webhook.post(message, image);
+            }
             self.cut = 0;
             self.branch = 0;
         }
+
+        fn tree_status(&mut self, status: TreeStatus) {
+            self.node = status.node;
+            self.tree_nodes = status.total;
+            if let (Some(tree), Some(node)) = (&mut self.branch_tree, status.node) {
+                tree.visit(node);
+            }
+            if status.total / 1000 > self.nodes / 1000 {
+                self.nodes = status.total;
+                let mem = glpk::mem_usage();
+                info!(
+                    target: CALLBACK,
+                    "{} tree nodes, {} active, {} kept, {} MB used, {} MB peak",
+                    status.total,
+                    status.active,
+                    status.current,
+                    mem.bytes >> 20,
+                    mem.peak_bytes >> 20
+                );
+            }
+            let mut progress = self.control.progress.lock().unwrap();
+            progress.nodes = status.total;
+            progress.gap = (status.gap < f64::MAX).then_some(status.gap);
+            // glpk only has a gap once it has an incumbent, which also catches accepted heuristic solutions
+            self.integer_feasible |= status.gap < f64::MAX;
+        }
+
+        fn terminate(&mut self) -> bool {
+            self.control.stop.load(Ordering::Relaxed)
+                || (self.first_solution && self.integer_feasible)
+        }
+    }
+
+    let result = problem.optimize_mip(&optimize_options(options), &mut closure);
+    if let Some(cut_pool) = &closure.cut_pool {
+        cut_pool.save();
+    }
+    if let Some(tree) = &closure.branch_tree {
+        tree.save();
+    }
+    if let Some(cut_stats) = &closure.cut_stats {
+        cut_stats.log();
+    }
+    convergence::report(
+        &control.progress.lock().unwrap().history,
+        options.convergence.as_deref(),
+    );
+    // the large neighborhood search only looks for a better route than a solve that was cut short by its limits
+    let mut improvable = false;
+    match result {
+        Ok(()) => control.progress.lock().unwrap().proved = true,
+        Err(Error::Stopped) if closure.first_solution && closure.integer_feasible => {
+            info!("stopped at the first integer feasible solution");
+        }
+        Err(e @ (Error::Timeout | Error::HitMipGapLimit)) if closure.integer_feasible => {
+            info!(
+                "solve stopped at its {}, keeping the best route so far without proving it optimal",
+                if e == Error::Timeout {
+                    "time limit"
+                } else {
+                    "mip gap"
+                }
+            );
+            improvable = true;
+        }
+        Err(Error::Stopped) if closure.integer_feasible => {
+            warn!("solve was stopped, keeping the best route so far without proving it optimal");
+        }
+        // the panic stopped glpk, which keeps whatever incumbent it had so the best route so far is still rendered
+        Err(Error::CallbackPanicked(message)) if closure.integer_feasible => {
+            error!(
+                "a solve callback panicked, keeping the best route so far without proving it optimal: {}",
+                message
+            );
+        }
+        Err(e) if closure.integer_feasible => {
+            error!(
+                "solve failed with {:?}, keeping the best route so far without proving it optimal",
+                e
+            );
+        }
+        Err(Error::Stopped) => {
+            warn!("solve was stopped before any route was found");
+            if let Some(webhook) = &mut closure.webhook {
+                webhook.post("solve was stopped".to_owned(), None);
+            }
+            return None;
+        }
+        Err(Error::CallbackPanicked(message)) => {
+            error!(
+                "a solve callback panicked before any route was found: {}",
+                message
+            );
+            return None;
+        }
+        Err(e) => {
+            error!("solve failed with {:?} before any route was found", e);
+            return None;
+        }
     }
+    trace!("done!");
+    tight::log(&problem, &closure.vars.families);
 
-    problem.optimize_mip(&mut closure).unwrap();
+    let chosen = closure.vars.registry.edge_set(&problem.get_int_solution());
+    let path = ordered_path(graph, first_node, last_node, &chosen);
+    if path.is_none() {
+        error!("the best solution is not a single path from the first node to the last node");
+    }
+    let route = match path.map(|path| Route::from_path(graph, path, required_bits)) {
+        // the search re-solves without the nodes to visit, so it could wander off of them
+        Some(route) if improvable && options.lns_iterations > 0 && visit.is_empty() => Some(
+            improve(graph, first_node, last_node, options, control, route),
+        ),
+        route => route,
+    };
 
+    // after the large neighborhood search, so they show the route that is returned
     closure.render += 1;
-    closure.renderer.render(
-        format!("{}-BEST.{}", closure.render, EXT),
-        &value_graph_int(graph, &problem, edges),
-        closure.first_node,
-        closure.last_node,
+    let name = format!("{}-BEST", closure.render);
+    if closure.sink.enabled() {
+        let values = match &route {
+            Some(route) => route_graph(graph, route),
+            None => value_graph_int(graph, &problem, &closure.vars.registry),
+        };
+        closure
+            .sink
+            .render_final(&name, &values, closure.first_node, closure.last_node);
+    }
+    let image = closure.png(&name);
+    if let Some(webhook) = &mut closure.webhook {
+        let time = route.as_ref().map_or_else(
+            || Frames(problem.get_mip_objective()) - options.prologue_frames,
+            |route| route.time,
+        );
+        let message = format!(
+            "solve finished with a best route of {} frames after {:.1} minutes",
+            time,
+            closure.start.elapsed().as_secs_f64() / 60.0
+        );
+        webhook.post(message, image);
+    }
+    route
+}
+
+/// builds the full model, prints its size, and writes it as an LP file without solving it.
+/// returns whether the file was written
+pub fn dry_run(graph: &RouteGraph, options: &Options) -> bool {
+    let folded = Folded::new(graph, options);
+    let graph = &folded.graph;
+    let (first_node, last_node) = endpoints(graph);
+    let (problem, vars) = build_problem(graph, first_node, last_node, options);
+    println!(
+        "{} nodes and {} edges after pruning and folding",
+        graph.node_count(),
+        graph.edge_count()
     );
-    trace!("done!");
+    println!(
+        "{} vars, {} binary",
+        problem.num_vars(),
+        problem.num_binary_vars()
+    );
+    vars.registry
+        .blocks()
+        .iter()
+        .for_each(|(name, block)| println!("{:>8} vars {}", block.len(), name));
+    println!(
+        "{} exprs with {} terms",
+        problem.num_exprs(),
+        problem.num_terms()
+    );
+    if problem.get_objective_constant() != 0.0 {
+        println!(
+            "{} frames of prologue in the objective constant",
+            problem.get_objective_constant()
+        );
+    }
+    println!(
+        "{} edge vars fixed to zero by reachable bits",
+        vars.unusable.len()
+    );
+    vars.families.iter().for_each(|family| {
+        println!(
+            "{:>8} exprs {:>8} terms {:>8.3}s {}",
+            family.exprs, family.terms, family.seconds, family.name
+        )
+    });
+    let articulation = articulation_points(graph);
+    println!(
+        "{} articulation points: {}",
+        articulation.len(),
+        articulation.iter().map(|n| &graph[*n].name).join(", ")
+    );
+    components(graph, &articulation)
+        .iter()
+        .for_each(|component| {
+            println!(
+                "{:>8} nodes {:>8} bits {:>4} cubes {:>4} antis  {} through {}",
+                component.nodes.len(),
+                component.bits,
+                component.cubes,
+                component.antis,
+                component.name(graph),
+                component.borders.iter().map(|n| &graph[*n].name).join(", ")
+            )
+        });
+    match problem.write_lp(&options.lp_file) {
+        Ok(()) => {
+            println!("wrote {:?}", options.lp_file);
+            true
+        }
+        Err(e) => {
+            error!("failed to write {:?}: {:?}", options.lp_file, e);
+            false
+        }
+    }
 }
 
-fn value_graph<'g>(
-    graph: &'g StableGraph<Node, Edge>,
+/// every block of vars in the model by name, each checked to have one var for everything it is indexed by.
+/// the typed accessors keep a block from being indexed by another block's kind of index
+pub(crate) struct VarRegistry {
+    /// each block in the order it was added
+    blocks: Vec<(String, VarRefs)>,
+    /// if an edge should be taken
+    edges: VarMap<EdgeIndex>,
+    /// keys held while taking an edge, if key-flow is enabled
+    key_flows: Option<VarMap<EdgeIndex>>,
+    /// the level of each phase resource while taking an edge
+    levels: Vec<(String, VarMap<EdgeIndex>)>,
+    /// if each puzzle state has been activated while taking an edge
+    activations: Vec<(String, VarMap<EdgeIndex>)>,
+    /// if the route warps to each hub, paying its long load, by the hub's index
+    long_loads: VarRefs,
+    /// bits collected in each component, by the component's index
+    component_bits: VarRefs,
+}
+impl VarRegistry {
+    /// only makes the vars of enabled families, so no block is left out of every expr.
+    /// the resources, states, hubs, and components are those of the enabled families, and empty otherwise
+    fn new(
+        problem: &mut Problem,
+        graph: &StableGraph<Node, Edge>,
+        key_flow: bool,
+        resources: Vec<String>,
+        states: Vec<String>,
+        hubs: &[String],
+        components: &[Component],
+    ) -> Self {
+        // edge blocks are indexed by edge index, so the graph can't have holes left by removed edges
+        assert_eq!(
+            graph.edge_count(),
+            graph.edge_bound(),
+            "edge vars need edge indices without holes"
+        );
+        let mut blocks = Vec::new();
+        let mut add = |name: String, specs: Vec<Var>, expected: usize| {
+            assert_eq!(
+                specs.len(),
+                expected,
+                "var block {} has {} vars but is indexed by {}",
+                name,
+                specs.len(),
+                expected
+            );
+            let vars = problem.add_vars(specs);
+            if expected > 0 {
+                blocks.push((name, vars));
+            }
+            vars
+        };
+        let edge_count = graph.edge_count();
+        let by_edge = |vars| VarMap::new(vars, EdgeIndex::new, |e: EdgeIndex| e.index());
+        let edges = by_edge(add("edges".to_owned(), edge_vars(graph), edge_count));
+        let key_flows = key_flow.then(|| {
+            by_edge(add(
+                "key-flows".to_owned(),
+                key_flow_vars(graph),
+                edge_count,
+            ))
+        });
+        let levels = resources
+            .into_iter()
+            .map(|resource| {
+                let vars = add(
+                    format!("levels/{}", resource),
+                    level_vars(graph, &resource),
+                    edge_count,
+                );
+                (resource, by_edge(vars))
+            })
+            .collect();
+        let activations = states
+            .into_iter()
+            .map(|state| {
+                let vars = add(
+                    format!("activations/{}", state),
+                    activation_vars(graph, &state),
+                    edge_count,
+                );
+                (state, by_edge(vars))
+            })
+            .collect();
+        let long_loads = add("long-loads".to_owned(), long_load_vars(hubs), hubs.len());
+        let component_bits = add(
+            "component-bits".to_owned(),
+            component_bit_vars(graph, components),
+            components.len(),
+        );
+        VarRegistry {
+            blocks,
+            edges,
+            key_flows,
+            levels,
+            activations,
+            long_loads,
+            component_bits,
+        }
+    }
+
+    /// every block by name, in the order they were added
+    pub fn blocks(&self) -> &[(String, VarRefs)] {
+        &self.blocks
+    }
+    pub fn edge_var(&self, e: EdgeIndex) -> VarRef {
+        self.edges.get(e)
+    }
+    /// if key-flow is enabled, so every edge has a key flow var
+    pub fn has_key_flows(&self) -> bool {
+        self.key_flows.is_some()
+    }
+    pub fn key_flow_var(&self, e: EdgeIndex) -> VarRef {
+        self.key_flows
+            .as_ref()
+            .expect("no key flow vars, key-flow is disabled")
+            .get(e)
+    }
+    /// the phase resources with level vars, in the order they were added
+    pub fn phase_resources(&self) -> impl Iterator<Item = &str> {
+        self.levels.iter().map(|(resource, _)| resource.as_str())
+    }
+    pub fn level_var(&self, resource: &str, e: EdgeIndex) -> VarRef {
+        Self::find(&self.levels, "phase resource", resource).get(e)
+    }
+    /// the puzzle states with activation vars, in the order they were added
+    pub fn activation_states(&self) -> impl Iterator<Item = &str> {
+        self.activations.iter().map(|(state, _)| state.as_str())
+    }
+    pub fn active_var(&self, state: &str, e: EdgeIndex) -> VarRef {
+        Self::find(&self.activations, "puzzle state", state).get(e)
+    }
+    pub fn long_load_var(&self, hub: usize) -> VarRef {
+        self.long_loads.get(hub)
+    }
+    pub fn component_bits_var(&self, component: usize) -> VarRef {
+        self.component_bits.get(component)
+    }
+
+    fn find<'r>(
+        blocks: &'r [(String, VarMap<EdgeIndex>)],
+        what: &str,
+        name: &str,
+    ) -> &'r VarMap<EdgeIndex> {
+        blocks
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, vars)| vars)
+            .unwrap_or_else(|| panic!("no vars for {} {}", what, name))
+    }
+
+    /// the edges a solution takes, whose edge vars are binary
+    pub fn edge_set(&self, solution: &Solution) -> HashSet<EdgeIndex> {
+        solution
+            .nonzero()
+            .filter(|(_, value)| *value > 0.5)
+            .filter_map(|(var, _)| self.edges.position(var))
+            .collect()
+    }
+
+    /// a solution taking just the chosen edges, with every other var 0
+    pub fn edge_solution(
+        &self,
+        problem: &Prob,
+        chosen: impl IntoIterator<Item = EdgeIndex>,
+    ) -> Solution {
+        let mut solution = Solution::zeros(problem);
+        chosen
+            .into_iter()
+            .for_each(|e| solution[self.edge_var(e)] = 1.0);
+        solution
+    }
+}
+
+/// the vars of the full model
+pub(crate) struct Vars {
+    pub registry: VarRegistry,
+    /// the hubs a warp can lead to, which index the long load vars. empty if long-load is disabled
+    pub hubs: Vec<String>,
+    /// the components split off by articulation points that the route doesn't start in,
+    /// which index the component bits vars. empty if component-bits is disabled
+    pub components: Vec<Component>,
+    /// how much each family of constraints added, for reporting the size of the model
+    pub families: Vec<FamilyStats>,
+    /// the enabled constraint families, which also separate lazy cuts
+    pub formulation: Formulation,
+    /// edges fixed to not be taken, since no route through them can collect the required bits
+    pub unusable: HashSet<EdgeIndex>,
+}
+impl Vars {
+    /// sets every var to match a route, which has to be feasible
+    pub(crate) fn solution(
+        &self,
+        graph: &StableGraph<Node, Edge>,
+        problem: &Prob,
+        first_node: NodeIndex,
+        route: &Route,
+    ) -> Solution {
+        let vars = &self.registry;
+        let mut s = vars.edge_solution(problem, route.edges.iter().copied());
+        let mut held = graph[first_node].keys_minus_lock();
+        let mut levels: Vec<(&str, u32)> = vars
+            .phase_resources()
+            .map(|resource| {
+                let level = phase_level(&graph[first_node], resource).unwrap_or(0);
+                (resource, level)
+            })
+            .collect();
+        let mut active: Vec<(&str, bool)> = vars
+            .activation_states()
+            .map(|state| (state, graph[first_node].activates.as_deref() == Some(state)))
+            .collect();
+        route.edges.iter().for_each(|e| {
+            let target = &graph[graph.edge_endpoints(*e).unwrap().1];
+            if let Some(h) =
+                warp_hub(graph, *e).and_then(|hub| self.hubs.iter().position(|h| h == hub))
+            {
+                s[vars.long_load_var(h)] = 1.0;
+            }
+            if vars.has_key_flows() {
+                s[vars.key_flow_var(*e)] = held as f64;
+            }
+            held += target.keys_minus_lock();
+            levels.iter_mut().for_each(|(resource, level)| {
+                s[vars.level_var(resource, *e)] = *level as f64;
+                *level = phase_level(target, resource).unwrap_or(*level);
+            });
+            active.iter_mut().for_each(|(state, active)| {
+                s[vars.active_var(state, *e)] = if *active { 1.0 } else { 0.0 };
+                *active |= target.activates.as_deref() == Some(*state);
+            });
+            if let Some(c) = self.components.iter().position(|component| {
+                component
+                    .nodes
+                    .contains(&graph.edge_endpoints(*e).unwrap().1)
+            }) {
+                s[vars.component_bits_var(c)] += f64::from(target.bits);
+            }
+        });
+        s
+    }
+}
+
+/// builds the full model, with every edge free to be taken or not
+pub(crate) fn build_problem(
+    graph: &RouteGraph,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    options: &Options,
+) -> (Problem, Vars) {
+    let mut problem = Problem::new();
+    problem.set_name("FEZ any% route".to_owned());
+    problem.set_direction(Direction::Minimize);
+    problem.set_objective_constant(f64::from(options.prologue_frames));
+
+    // the graph passes before the families, which are counted once the formulation knows which are enabled
+    let mut progress = ProgressBar::new("building model", 3);
+    let pass = |progress: &mut ProgressBar, name: &str, start: Instant| {
+        progress.suspend(|| info!("{} took {:.3}s", name, start.elapsed().as_secs_f64()));
+    };
+
+    // the components split off by articulation points, besides the one the route starts in
+    progress.step("articulation points");
+    let start = Instant::now();
+    let components: Vec<Component> = components(graph, &articulation_points(graph))
+        .into_iter()
+        .filter(|component| component.bits > Bits(0) && !component.nodes.contains(&first_node))
+        .collect();
+    pass(&mut progress, "articulation points", start);
+
+    progress.step("unusable edges");
+    let start = Instant::now();
+    // cleared while it runs, since it logs the bits left
+    let unusable =
+        progress.suspend(|| unusable_edges(graph, first_node, last_node, options.required_bits));
+    pass(&mut progress, "unusable edges", start);
+
+    let hubs = warp_hubs(graph);
+    let resources = phase_resources(graph);
+    let states = activation_states(graph);
+    // exprs, by family
+    let mut families: Vec<Box<dyn ConstraintFamily>> = vec![
+        Box::new(Flow),
+        Box::new(Capacity),
+        Box::new(Dominator),
+        Box::new(No2Cycles),
+        Box::new(No3Cycles),
+        Box::new(RequiredBits {
+            unusable: unusable.clone(),
+        }),
+        Box::new(Connectivity),
+        Box::new(Oneof),
+        Box::new(Requirements),
+        Box::new(MaxActions),
+        Box::new(Collectables),
+        Box::new(MaxTransitions),
+        Box::new(KeyFlow),
+        Box::new(LongLoad { hubs: hubs.clone() }),
+        Box::new(ComponentBits {
+            components: components.clone(),
+        }),
+    ];
+    resources.iter().for_each(|resource| {
+        families.push(Box::new(Phase {
+            resource: resource.clone(),
+        }));
+    });
+    states.iter().for_each(|state| {
+        families.push(Box::new(Activation {
+            state: state.clone(),
+        }));
+    });
+    let formulation = Formulation::new(families, options);
+
+    progress.step("vars");
+    let start = Instant::now();
+    let enabled = |name: String| formulation.enabled(&name);
+    let resources: Vec<String> = resources
+        .into_iter()
+        .filter(|resource| enabled(format!("{}-phase", resource)))
+        .collect();
+    let states: Vec<String> = states
+        .into_iter()
+        .filter(|state| enabled(format!("{}-activation", state)))
+        .collect();
+    let hubs = if enabled("long-load".to_owned()) {
+        hubs
+    } else {
+        Vec::new()
+    };
+    let components = if enabled("component-bits".to_owned()) {
+        components
+    } else {
+        Vec::new()
+    };
+    let registry = VarRegistry::new(
+        &mut problem,
+        graph,
+        enabled("key-flow".to_owned()),
+        resources,
+        states,
+        &hubs,
+        &components,
+    );
+    unusable.iter().for_each(|e| {
+        problem.fix_var(registry.edge_var(*e), 0.0);
+    });
+    pass(&mut progress, "vars", start);
+
+    let families = formulation.build(
+        &mut problem,
+        &Model {
+            graph,
+            vars: &registry,
+            first_node,
+            last_node,
+            options,
+        },
+        &mut progress,
+    );
+
+    (
+        problem,
+        Vars {
+            registry,
+            hubs,
+            components,
+            families,
+            formulation,
+            unusable,
+        },
+    )
+}
+
+/// edges that can't be on any route with enough bits, logging how many bits can still be collected without them
+fn unusable_edges(
+    graph: &StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+    required_bits: Bits,
+) -> HashSet<EdgeIndex> {
+    let (unusable, usable): (Vec<_>, Vec<_>) = edge_bits_bounds(graph, first_node, last_node)
+        .into_iter()
+        .partition(|(_, bits)| *bits < required_bits);
+    let collectable: Bits = usable
+        .iter()
+        .map(|(e, _)| graph.edge_endpoints(*e).unwrap().1)
+        .unique()
+        .map(|n| graph[n].bits)
+        .sum();
+    info!(
+        "fixed {} of {} edges to not be taken, {} bits can still be collected",
+        unusable.len(),
+        graph.edge_count(),
+        collectable
+    );
+    if collectable < required_bits {
+        warn!(
+            "only {} bits can be collected, so no route has the {} required",
+            collectable, required_bits
+        );
+    }
+    unusable.into_iter().map(|(e, _)| e).collect()
+}
+
+pub(crate) fn value_graph<'g>(
+    graph: &'g RouteGraph,
     problem: &Prob,
-    edges: VarRefs,
+    vars: &VarRegistry,
 ) -> StableGraph<&'g Node, f64> {
-    let filtered = graph.filter_map(
+    graph.filter_map(
         |_, n| Some(n),
         |i, _| {
-            let value = problem.get_value(edges.get(i.index()));
+            let value = problem.get_value(vars.edge_var(i));
             if value > EPS {
                 Some(value)
             } else {
                 None
             }
         },
-    );
-
-    graph.edge_references().for_each(|g| {
-        if let Some((fs, ft)) = filtered.edge_endpoints(g.id()) {
-            let gs = g.source();
-            let gt = g.target();
-            if gs != fs || gt != ft {
-                panic!(
-                    "edge id {} changed from {}->{} to {}->{}",
-                    g.id().index(),
-                    gs.index(),
-                    gt.index(),
-                    fs.index(),
-                    ft.index()
-                );
-            }
-        }
-    });
-
-    filtered
+    )
 }
 
 fn value_graph_int<'g>(
-    graph: &'g StableGraph<Node, Edge>,
+    graph: &'g RouteGraph,
     problem: &Prob,
-    edges: VarRefs,
+    vars: &VarRegistry,
 ) -> StableGraph<&'g Node, f64> {
     graph.filter_map(
         |_, n| Some(n),
         |i, _| {
-            let value = problem.get_int_value(edges.get(i.index()));
+            let value = problem.get_int_value(vars.edge_var(i));
             if value > EPS {
                 Some(value)
             } else {
@@ -308,29 +1152,17 @@ fn edge_vars(graph: &StableGraph<Node, Edge>) -> Vec<Var> {
             let target = &graph[e.target()];
             Var {
                 name: format!("{}/to/{}", source.name, target.name),
-                kind: Kind::Int,
+                kind: Kind::Binary,
                 bounds: Bounds::Double(0.0, 1.0),
-                objective: edge.time + target.time,
+                objective: f64::from(edge.time + target.time),
             }
         })
         .collect()
 }
 
-fn key_vars(graph: &StableGraph<Node, Edge>) -> Vec<Var> {
-    graph
-        .node_references()
-        .map(|n| Var {
-            name: format!("{}/keys", n.weight().name),
-            kind: Kind::Float,
-            bounds: Bounds::Lower(0.0),
-            objective: 0.0,
-        })
-        .collect()
-}
-
-fn flow_exprs(
+pub(crate) fn flow_exprs(
     graph: &StableGraph<Node, Edge>,
-    edges: VarRefs,
+    vars: &VarRegistry,
     first_node: NodeIndex,
     last_node: NodeIndex,
 ) -> Vec<Expr> {
@@ -347,20 +1179,20 @@ fn flow_exprs(
             }),
             terms: graph
                 .edges_directed(n.id(), Incoming)
-                .map(|e| edges.get(e.id().index()) * -1.0)
+                .map(|e| vars.edge_var(e.id()) * -1.0)
                 .chain(
                     graph
                         .edges_directed(n.id(), Outgoing)
-                        .map(|e| edges.get(e.id().index()) * 1.0),
+                        .map(|e| vars.edge_var(e.id()) * 1.0),
                 )
                 .collect(),
         })
         .collect()
 }
 
-fn capacity_exprs(
+pub(crate) fn capacity_exprs(
     graph: &StableGraph<Node, Edge>,
-    edges: VarRefs,
+    vars: &VarRegistry,
     first_node: NodeIndex,
     last_node: NodeIndex,
 ) -> Vec<Expr> {
@@ -372,43 +1204,70 @@ fn capacity_exprs(
             bounds: Bounds::Upper(1.0),
             terms: graph
                 .edges_directed(n.id(), Incoming)
-                .map(|e| edges.get(e.id().index()) * 1.0)
+                .map(|e| vars.edge_var(e.id()) * 1.0)
                 .collect(),
         })
         .collect()
 }
 
-fn dominator_exprs(
+pub(crate) fn dominator_exprs(
     graph: &StableGraph<Node, Edge>,
-    edges: VarRefs,
+    vars: &VarRegistry,
     first_node: NodeIndex,
+    ignore: &[EdgeKind],
 ) -> Vec<Expr> {
-    let no_secret_doors = EdgeFiltered::from_fn(graph, |e| graph[e.target()].cost != Cost::Secret);
-    let dominators = dominators::simple_fast(&no_secret_doors, first_node);
-    graph
+    let kept = EdgeFiltered::from_fn(graph, |e| {
+        !ignore.iter().any(|kind| kind.matches(&graph[e.target()]))
+    });
+    let dominators = dominators::simple_fast(&kept, first_node);
+    // dominators of the whole graph, to check that ignoring edges didn't make up any
+    let whole = dominators::simple_fast(graph, first_node);
+    let mut made_up = 0;
+    let exprs = graph
         .node_references()
         .filter_map(|n| {
             dominators
                 .immediate_dominator(n.id())
                 .filter(|d| *d != first_node)
+                .inspect(|d| {
+                    if !whole
+                        .dominators(n.id())
+                        .is_some_and(|mut ds| ds.any(|x| x == *d))
+                    {
+                        made_up += 1;
+                        debug!(
+                            "{} only dominates {} without {:?} edges",
+                            graph[*d].name,
+                            n.weight().name,
+                            ignore
+                        );
+                    }
+                })
                 .map(|d| Expr {
                     name: format!("{}/dominator", n.weight().name),
                     bounds: Bounds::Upper(0.0),
                     terms: graph
                         .edges_directed(n.id(), Incoming)
-                        .map(|e| edges.get(e.id().index()) * 1.0)
+                        .map(|e| vars.edge_var(e.id()) * 1.0)
                         .chain(
                             graph
                                 .edges_directed(d.id(), Incoming)
-                                .map(|e| edges.get(e.id().index()) * -1.0),
+                                .map(|e| vars.edge_var(e.id()) * -1.0),
                         )
                         .collect(),
                 })
         })
-        .collect()
+        .collect();
+    if made_up > 0 {
+        warn!(
+            "{} dominators only hold without {:?} edges, so their constraints cut off routes that use those edges",
+            made_up, ignore
+        );
+    }
+    exprs
 }
 
-fn no_2_cycles(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Vec<Expr> {
+pub(crate) fn no_2_cycles(graph: &StableGraph<Node, Edge>, vars: &VarRegistry) -> Vec<Expr> {
     graph
         .edge_references()
         .filter(|e| e.source().index() < e.target().index())
@@ -420,7 +1279,7 @@ fn no_2_cycles(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Vec<Expr> {
                 graph[a.target()].name
             ),
             bounds: Bounds::Upper(1.0),
-            terms: vec![edges.get(a.id().index()) * 1.0, edges.get(b.index()) * 1.0],
+            terms: vec![vars.edge_var(a.id()) * 1.0, vars.edge_var(b) * 1.0],
         })
         .collect()
 }
@@ -429,7 +1288,7 @@ fn no_2_cycles(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Vec<Expr> {
 // but it would be stronger as at most 2 edges among each set of 3 nodes (6 edges)
 // which would generalize to 3 edges among 4 nodes, 4 edges among 5 nodes ect
 // but still not sure how much such conditions would help
-fn no_3_cycles(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Vec<Expr> {
+pub(crate) fn no_3_cycles(graph: &StableGraph<Node, Edge>, vars: &VarRegistry) -> Vec<Expr> {
     graph
         .node_references()
         .flat_map(|n| {
@@ -455,152 +1314,889 @@ fn no_3_cycles(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Vec<Expr> {
                     ),
                     bounds: Bounds::Upper(2.0),
                     terms: vec![
-                        edges.get(s.id().index()) * 1.0,
-                        edges.get(t.id().index()) * 1.0,
-                        edges.get(o.index()) * 1.0,
+                        vars.edge_var(s.id()) * 1.0,
+                        vars.edge_var(t.id()) * 1.0,
+                        vars.edge_var(o) * 1.0,
                     ],
                 })
         })
         .collect()
 }
 
-/// not bothering with other required_bits nodes yet since they shouldn't be violated based on timing data
-fn required_bits_expr(graph: &StableGraph<Node, Edge>, edges: VarRefs, required_bits: i32) -> Expr {
+/// not bothering with other required_bits nodes yet since they shouldn't be violated based on timing data.
+/// unusable edges are left out, so the bits only count what can still be collected
+pub(crate) fn required_bits_expr(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    unusable: &HashSet<EdgeIndex>,
+    required_bits: Bits,
+) -> Expr {
     Expr {
         name: "total_bits".to_owned(),
-        bounds: Bounds::Lower(required_bits as f64),
+        bounds: Bounds::Lower(f64::from(required_bits)),
         terms: graph
             .node_references()
-            .filter(|n| n.weight().bits != 0)
+            .filter(|n| n.weight().bits != Bits(0))
             .flat_map(|n| {
                 graph
                     .edges_directed(n.id(), Incoming)
-                    .map(move |e| edges.get(e.id().index()) * n.weight().bits as f64)
+                    .filter(|e| !unusable.contains(&e.id()))
+                    .map(move |e| vars.edge_var(e.id()) * f64::from(n.weight().bits))
             })
             .collect(),
     }
 }
 
-fn oneof_expr(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Expr {
-    Expr {
-        name: "oneof".to_owned(),
-        bounds: Bounds::Upper(1.0),
-        terms: graph
-            .node_references()
-            .filter(|n| n.weight().cost == Cost::Oneof)
-            .flat_map(|n| {
-                graph
-                    .edges_directed(n.id(), Incoming)
-                    .map(|e| edges.get(e.id().index()) * 1.0)
-            })
-            .collect(),
+pub(crate) fn oneof_exprs(graph: &StableGraph<Node, Edge>, vars: &VarRegistry) -> Vec<Expr> {
+    graph
+        .node_references()
+        .filter_map(|(n, node)| node.oneof.as_deref().map(|group| (group, n)))
+        .into_group_map()
+        .into_iter()
+        .sorted_by_key(|(group, _)| *group)
+        .map(|(group, nodes)| Expr {
+            name: format!("oneof/{}", group),
+            bounds: Bounds::Upper(1.0),
+            terms: nodes
+                .into_iter()
+                .flat_map(|n| {
+                    graph
+                        .edges_directed(n, Incoming)
+                        .map(|e| vars.edge_var(e.id()) * 1.0)
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// the generic cuts glpk is allowed to add, for a problem solved once
+pub(crate) fn optimize_options(options: &Options) -> OptimizeOptions {
+    let cut = |cut| !options.no_glpk_cuts && options.glpk_cuts.contains(&cut);
+    OptimizeOptions {
+        gomory: cut(GlpkCut::Gomory),
+        mir: cut(GlpkCut::Mir),
+        cover: cut(GlpkCut::Cover),
+        clique: cut(GlpkCut::Clique),
+        reuse_basis: false,
+        // the callbacks read and write vars and exprs by their refs, which the presolver renumbers
+        no_presolve: true,
+        branching: match options.glpk_branching {
+            GlpkBranching::First => Branching::FirstFractional,
+            GlpkBranching::Last => Branching::LastFractional,
+            GlpkBranching::Most => Branching::MostFractional,
+            GlpkBranching::Driebeck => Branching::DriebeckTomlin,
+            GlpkBranching::Pseudocost => Branching::Pseudocost,
+        },
+        backtracking: match options.glpk_backtracking {
+            GlpkBacktracking::Depth => Backtracking::DepthFirst,
+            GlpkBacktracking::Breadth => Backtracking::BreadthFirst,
+            GlpkBacktracking::Bound => Backtracking::BestBound,
+            GlpkBacktracking::Projection => Backtracking::BestProjection,
+        },
+        small_mip_vars: 0,
+        time_limit: options.time_limit.map(Duration::from_secs_f64),
+        mip_gap: options.mip_gap,
     }
 }
 
-fn total_keys_expr(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Expr {
+/// for a problem that is solved again after changing a few bounds or objectives,
+/// so each solve starts from the basis the last one left, and ones with only a few edges left free skip glpk's search
+pub(crate) fn resolve_options(options: &Options) -> OptimizeOptions {
+    OptimizeOptions {
+        reuse_basis: true,
+        small_mip_vars: options.small_mip_vars,
+        // the limits are for the full solve, the re-solves are small enough to finish
+        time_limit: None,
+        mip_gap: 0.0,
+        ..optimize_options(options)
+    }
+}
+
+/// the most times each limited action may be used
+fn action_limits(options: &Options) -> Vec<(Action, u32)> {
+    vec![
+        (Action::Warp, options.max_warps),
+        (Action::Well, options.max_wells),
+    ]
+    .into_iter()
+    .filter_map(|(action, max)| max.map(|max| (action, max)))
+    .collect()
+}
+
+/// true if there are no nodes to visit
+fn visits_any(graph: &StableGraph<Node, Edge>, visit: &[NodeIndex], route: &Route) -> bool {
+    visit.is_empty()
+        || route
+            .edges
+            .iter()
+            .any(|e| visit.contains(&graph.edge_endpoints(*e).unwrap().1))
+}
+
+/// at least one of the nodes is entered
+fn visit_expr(graph: &StableGraph<Node, Edge>, vars: &VarRegistry, visit: &[NodeIndex]) -> Expr {
     Expr {
-        name: "total_keys".to_owned(),
-        bounds: Bounds::Lower(0.0),
-        terms: graph
-            .node_references()
-            .filter(|n| n.weight().keys_minus_lock() != 0)
-            .flat_map(|n| {
-                graph
-                    .edges_directed(n.id(), Incoming)
-                    .map(move |e| edges.get(e.id().index()) * n.weight().keys_minus_lock() as f64)
-            })
+        name: "visit".to_owned(),
+        bounds: Bounds::Lower(1.0),
+        terms: visit
+            .iter()
+            .flat_map(|n| graph.edges_directed(*n, Incoming))
+            .map(|e| vars.edge_var(e.id()) * 1.0)
             .collect(),
     }
 }
 
-fn order_keys_exprs(
+/// the heuristics only know about bits, so their routes are checked against the other limits before being used
+pub(crate) fn within_limits(
+    graph: &StableGraph<Node, Edge>,
+    options: &Options,
+    route: &Route,
+) -> bool {
+    let targets = || {
+        route
+            .edges
+            .iter()
+            .map(|e| &graph[graph.edge_endpoints(*e).unwrap().1])
+    };
+    options
+        .max_transitions
+        .is_none_or(|max| route.edges.len() <= max as usize)
+        && collectable_limits(options)
+            .iter()
+            .all(|(_, count, min, max)| {
+                let total: i32 = targets().map(count).sum();
+                min.is_none_or(|min| total >= min as i32)
+                    && max.is_none_or(|max| total <= max as i32)
+            })
+        && action_limits(options).iter().all(|(action, max)| {
+            let used = route
+                .edges
+                .iter()
+                .filter(|e| graph[graph.edge_endpoints(**e).unwrap().1].action == Some(*action))
+                .count();
+            used <= *max as usize
+        })
+}
+
+/// at most the chosen number of each action, for runners who would rather not warp so often
+pub(crate) fn max_action_exprs(
     graph: &StableGraph<Node, Edge>,
-    edges: VarRefs,
-    keys: VarRefs,
-    first: NodeIndex,
+    vars: &VarRegistry,
+    options: &Options,
 ) -> Vec<Expr> {
-    let total_keys: i32 = graph.node_weights().map(|n| n.keys).sum();
-    // next <= prev + next_keys + total*(1-edge)
-    // next <= prev + next_keys + total - total*edge
-    // next - prev + total*edge <= total + next_keys
+    action_limits(options)
+        .into_iter()
+        .map(|(action, max)| Expr {
+            name: format!("max_{:?}s", action).to_lowercase(),
+            bounds: Bounds::Upper(max as f64),
+            terms: graph
+                .node_references()
+                .filter(|n| n.weight().action == Some(action))
+                .flat_map(|n| {
+                    graph
+                        .edges_directed(n.id(), Incoming)
+                        .map(|e| vars.edge_var(e.id()) * 1.0)
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// at least the required number of each requirement's nodes are entered
+pub(crate) fn requirement_exprs(graph: &StableGraph<Node, Edge>, vars: &VarRegistry) -> Vec<Expr> {
+    graph
+        .node_references()
+        .flat_map(|(n, node)| {
+            node.requirements
+                .iter()
+                .map(move |requirement| ((requirement.name.as_str(), requirement.at_least), n))
+        })
+        .into_group_map()
+        .into_iter()
+        .sorted_by_key(|((name, _), _)| *name)
+        .map(|((name, at_least), nodes)| Expr {
+            name: format!("requirement/{}", name),
+            bounds: Bounds::Lower(f64::from(at_least)),
+            terms: nodes
+                .into_iter()
+                .flat_map(|n| graph.edges_directed(n, Incoming))
+                .map(|e| vars.edge_var(e.id()) * 1.0)
+                .collect(),
+        })
+        .collect()
+}
+
+/// a cube type as its name, its count at a node, and the fewest and most the route may collect
+type CollectableLimit = (&'static str, fn(&Node) -> i32, Option<u32>, Option<u32>);
+
+/// the cube types a route is asked to collect a number of
+pub(crate) fn collectable_limits(options: &Options) -> Vec<CollectableLimit> {
+    let cubes: fn(&Node) -> i32 = |node| node.cubes;
+    let antis: fn(&Node) -> i32 = |node| node.antis;
+    vec![
+        ("cubes", cubes, options.required_cubes, None),
+        ("antis", antis, options.required_antis, options.max_antis),
+    ]
+    .into_iter()
+    .filter(|(_, _, min, max)| min.is_some() || max.is_some())
+    .collect()
+}
+
+/// whole cubes and anti cubes each counted on their own, since the bits lump them together
+pub(crate) fn collectable_exprs(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    options: &Options,
+) -> Vec<Expr> {
+    collectable_limits(options)
+        .into_iter()
+        .map(|(name, count, min, max)| Expr {
+            name: format!("total_{}", name),
+            bounds: match (min, max) {
+                (Some(min), Some(max)) => Bounds::Double(f64::from(min), f64::from(max)),
+                (Some(min), None) => Bounds::Lower(f64::from(min)),
+                (None, Some(max)) => Bounds::Upper(f64::from(max)),
+                (None, None) => unreachable!(),
+            },
+            terms: graph
+                .node_references()
+                .filter(|(_, node)| count(node) != 0)
+                .flat_map(|(n, node)| {
+                    graph
+                        .edges_directed(n, Incoming)
+                        .map(move |e| vars.edge_var(e.id()) * f64::from(count(node)))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// at most the chosen number of edges, for simpler routes
+pub(crate) fn max_transitions_exprs(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    options: &Options,
+) -> Vec<Expr> {
+    options
+        .max_transitions
+        .into_iter()
+        .map(|max| Expr {
+            name: "max_transitions".to_owned(),
+            bounds: Bounds::Upper(max as f64),
+            terms: graph
+                .edge_indices()
+                .map(|e| vars.edge_var(e) * 1.0)
+                .collect(),
+        })
+        .collect()
+}
+
+fn key_flow_vars(graph: &StableGraph<Node, Edge>) -> Vec<Var> {
     graph
         .edge_references()
-        .map(|e| Expr {
+        .map(|e| Var {
             name: format!(
                 "{}/to/{}/keys",
                 graph[e.source()].name,
                 graph[e.target()].name
             ),
-            terms: vec![
-                keys.get(e.target().index()) * 1.0,
-                keys.get(e.source().index()) * -1.0,
-                edges.get(e.id().index()) * total_keys as f64,
-            ],
-            bounds: Bounds::Upper((total_keys + graph[e.target()].keys_minus_lock()) as f64),
+            kind: Kind::Float,
+            bounds: Bounds::Lower(0.0),
+            objective: 0.0,
+        })
+        .collect()
+}
+
+/// keys held are carried along the taken edges, gaining keys and spending one at each lock,
+/// so a lock can only be opened with a key that was picked up earlier on the route
+pub(crate) fn key_flow_exprs(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+) -> Vec<Expr> {
+    let total_keys: i32 = graph.node_weights().map(|n| n.keys).sum();
+    let capacity = graph.edge_references().map(|e| Expr {
+        name: format!(
+            "{}/to/{}/key_capacity",
+            graph[e.source()].name,
+            graph[e.target()].name
+        ),
+        bounds: Bounds::Upper(0.0),
+        terms: vec![
+            vars.key_flow_var(e.id()) * 1.0,
+            vars.edge_var(e.id()) * -total_keys as f64,
+        ],
+    });
+    let conservation = graph.node_references().map(|n| {
+        let keys = n.weight().keys_minus_lock() as f64;
+        let held_in = graph.edges_directed(n.id(), Incoming).flat_map(|e| {
+            vec![
+                vars.key_flow_var(e.id()) * 1.0,
+                vars.edge_var(e.id()) * keys,
+            ]
+        });
+        let held_out = graph
+            .edges_directed(n.id(), Outgoing)
+            .map(|e| vars.key_flow_var(e.id()) * -1.0);
+        Expr {
+            name: format!("{}/key_flow", n.weight().name),
+            // the route always starts at the first node, and keys left at the end are just unused
+            bounds: if n.id() == first_node {
+                Bounds::Fixed(-keys)
+            } else if n.id() == last_node {
+                Bounds::Lower(0.0)
+            } else {
+                Bounds::Fixed(0.0)
+            },
+            terms: held_in.chain(held_out).collect(),
+        }
+    });
+    capacity.chain(conservation).collect()
+}
+
+fn phase_level(node: &Node, resource: &str) -> Option<u32> {
+    node.phase
+        .as_ref()
+        .filter(|phase| phase.resource == resource)
+        .map(|phase| phase.level)
+}
+
+fn phase_resources(graph: &StableGraph<Node, Edge>) -> Vec<String> {
+    graph
+        .node_weights()
+        .filter_map(|n| n.phase.as_ref())
+        .map(|phase| phase.resource.clone())
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+fn level_vars(graph: &StableGraph<Node, Edge>, resource: &str) -> Vec<Var> {
+    graph
+        .edge_references()
+        .map(|e| Var {
+            name: format!(
+                "{}/to/{}/{}",
+                graph[e.source()].name,
+                graph[e.target()].name,
+                resource
+            ),
+            kind: Kind::Float,
+            bounds: Bounds::Lower(0.0),
+            objective: 0.0,
+        })
+        .collect()
+}
+
+/// the level of a resource is carried along the taken edges and only rises.
+/// a node with a phase can only be entered at or below its level, and leaves at exactly its level,
+/// so once the sewer water has risen past a door that door can't be used again
+pub(crate) fn level_exprs(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    resource: &str,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+) -> Vec<Expr> {
+    let max_level = graph
+        .node_weights()
+        .filter_map(|n| phase_level(n, resource))
+        .max()
+        .unwrap_or(0) as f64;
+    let capacity = graph.edge_references().map(|e| Expr {
+        name: format!(
+            "{}/to/{}/{}_capacity",
+            graph[e.source()].name,
+            graph[e.target()].name,
+            resource
+        ),
+        bounds: Bounds::Upper(0.0),
+        terms: vec![
+            vars.level_var(resource, e.id()) * 1.0,
+            vars.edge_var(e.id()) * -max_level,
+        ],
+    });
+    let phases = graph.node_references().flat_map(|(n, node)| {
+        let level = phase_level(node, resource);
+        let held = |direction, coef| {
+            graph
+                .edges_directed(n, direction)
+                .map(move |e| vars.level_var(resource, e.id()) * coef)
+        };
+        let taken_in = |level: u32| {
+            graph
+                .edges_directed(n, Incoming)
+                .map(move |e| vars.edge_var(e.id()) * -(level as f64))
+        };
+        let name = |what| format!("{}/{}_{}", node.name, resource, what);
+        let mut exprs = Vec::new();
+        if n == first_node {
+            // the route starts here, so the level it leaves at is fixed
+            exprs.push(Expr {
+                name: name("flow"),
+                bounds: Bounds::Fixed(level.unwrap_or(0) as f64),
+                terms: held(Outgoing, 1.0).collect(),
+            });
+        } else if let Some(level) = level {
+            exprs.push(Expr {
+                name: name("before"),
+                bounds: Bounds::Upper(0.0),
+                terms: held(Incoming, 1.0).chain(taken_in(level)).collect(),
+            });
+            if n != last_node {
+                exprs.push(Expr {
+                    name: name("flow"),
+                    bounds: Bounds::Fixed(0.0),
+                    terms: held(Outgoing, 1.0).chain(taken_in(level)).collect(),
+                });
+            }
+        } else if n != last_node {
+            exprs.push(Expr {
+                name: name("flow"),
+                bounds: Bounds::Fixed(0.0),
+                terms: held(Outgoing, 1.0).chain(held(Incoming, -1.0)).collect(),
+            });
+        }
+        exprs
+    });
+    capacity.chain(phases).collect()
+}
+
+fn warp_hubs(graph: &StableGraph<Node, Edge>) -> Vec<String> {
+    graph
+        .edge_indices()
+        .filter_map(|e| warp_hub(graph, e))
+        .map(str::to_owned)
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+fn long_load_vars(hubs: &[String]) -> Vec<Var> {
+    hubs.iter()
+        .map(|hub| Var {
+            name: format!("{}/long_load", hub),
+            kind: Kind::Binary,
+            bounds: Bounds::Double(0.0, 1.0),
+            objective: f64::from(LONG_LOAD),
+        })
+        .collect()
+}
+
+/// a hub's long load is paid if any warp to it is taken, and only then,
+/// so warping to the same hub again doesn't pay it twice
+pub(crate) fn long_load_exprs(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    hubs: &[String],
+) -> Vec<Expr> {
+    let warps = graph
+        .edge_indices()
+        .filter_map(|e| warp_hub(graph, e).map(|hub| (hub, e)))
+        .into_group_map();
+    hubs.iter()
+        .enumerate()
+        .flat_map(|(h, hub)| {
+            let warps = &warps[hub.as_str()];
+            let paid = warps.iter().map(move |e| Expr {
+                name: format!(
+                    "{}/to/{}/long_load",
+                    graph[graph.edge_endpoints(*e).unwrap().0].name,
+                    graph[graph.edge_endpoints(*e).unwrap().1].name
+                ),
+                bounds: Bounds::Lower(0.0),
+                terms: vec![vars.long_load_var(h) * 1.0, vars.edge_var(*e) * -1.0],
+            });
+            let only_if_warped = Expr {
+                name: format!("{}/long_load", hub),
+                bounds: Bounds::Upper(0.0),
+                terms: iter::once(vars.long_load_var(h) * 1.0)
+                    .chain(warps.iter().map(|e| vars.edge_var(*e) * -1.0))
+                    .collect(),
+            };
+            paid.chain(iter::once(only_if_warped))
+        })
+        .collect()
+}
+
+fn component_bit_vars(graph: &StableGraph<Node, Edge>, components: &[Component]) -> Vec<Var> {
+    components
+        .iter()
+        .map(|component| Var {
+            name: format!("{}/component_bits", component.name(graph)),
+            kind: Kind::Float,
+            bounds: Bounds::Double(0.0, f64::from(component.bits)),
+            objective: 0.0,
+        })
+        .collect()
+}
+
+/// a component's var counts the bits of the nodes entered in it,
+/// and the route only collects any if it enters the component from one of its articulation points
+pub(crate) fn component_bits_exprs(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    components: &[Component],
+) -> Vec<Expr> {
+    components
+        .iter()
+        .enumerate()
+        .flat_map(|(c, component)| {
+            let collected = Expr {
+                name: format!("{}/component_bits", component.name(graph)),
+                bounds: Bounds::Fixed(0.0),
+                terms: iter::once(vars.component_bits_var(c) * 1.0)
+                    .chain(
+                        component
+                            .nodes
+                            .iter()
+                            .filter(|n| graph[**n].bits != Bits(0))
+                            .flat_map(|n| {
+                                let bits = f64::from(graph[*n].bits);
+                                graph
+                                    .edges_directed(*n, Incoming)
+                                    .map(move |e| vars.edge_var(e.id()) * -bits)
+                            }),
+                    )
+                    .collect(),
+            };
+            let entered = Expr {
+                name: format!("{}/component_entered", component.name(graph)),
+                bounds: Bounds::Upper(0.0),
+                terms: iter::once(vars.component_bits_var(c) * 1.0)
+                    .chain(
+                        component
+                            .entries(graph)
+                            .map(|e| vars.edge_var(e) * -f64::from(component.bits)),
+                    )
+                    .collect(),
+            };
+            vec![collected, entered]
+        })
+        .collect()
+}
+
+fn activation_states(graph: &StableGraph<Node, Edge>) -> Vec<String> {
+    graph
+        .node_weights()
+        .flat_map(|n| n.activates.iter().chain(&n.requires_activation))
+        .cloned()
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+fn activation_vars(graph: &StableGraph<Node, Edge>, state: &str) -> Vec<Var> {
+    graph
+        .edge_references()
+        .map(|e| Var {
+            name: format!(
+                "{}/to/{}/{}",
+                graph[e.source()].name,
+                graph[e.target()].name,
+                state
+            ),
+            kind: Kind::Float,
+            bounds: Bounds::Lower(0.0),
+            objective: 0.0,
         })
         .collect()
 }
 
-// fn approx_water_lock_exprs(graph: &StableGraph<Node, Edge>, edges: VarRefs) -> Vec<Expr> {
-//     graph
-//         .node_references()
-//         .filter(|n| n.weight().after_node != NodeIndex::end())
-//         .map(|n| Expr {
-//             name: format!(
-//                 "{}.after.{}",
-//                 n.weight().name,
-//                 graph[n.weight().after_node].name
-//             ),
-//             bounds: Bounds::Lower(0.0),
-//             terms: graph
-//                 .edges_directed(n.weight().after_node, Incoming)
-//                 .map(|e| edges.get(e.id().index()) * 1.0)
-//                 .chain(
-//                     graph
-//                         .edges_directed(n.id(), Incoming)
-//                         .map(|e| edges.get(e.id().index()) * -1.0),
-//                 )
-//                 .collect(),
-//         })
-//         .collect()
-// }
-
-fn lazy_required_bits_expr(
+/// if a puzzle state has been changed is carried along the taken edges,
+/// so a node that requires it can only be used after a node that activates it
+pub(crate) fn activation_exprs(
     graph: &StableGraph<Node, Edge>,
-    edges: VarRefs,
+    vars: &VarRegistry,
+    state: &str,
     first_node: NodeIndex,
-    required_bits: i32,
+    last_node: NodeIndex,
+) -> Vec<Expr> {
+    let capacity = graph.edge_references().map(|e| Expr {
+        name: format!(
+            "{}/to/{}/{}_capacity",
+            graph[e.source()].name,
+            graph[e.target()].name,
+            state
+        ),
+        bounds: Bounds::Upper(0.0),
+        terms: vec![
+            vars.active_var(state, e.id()) * 1.0,
+            vars.edge_var(e.id()) * -1.0,
+        ],
+    });
+    let nodes = graph.node_references().flat_map(|(n, node)| {
+        let activates = node.activates.as_deref() == Some(state);
+        let requires = node.requires_activation.as_deref() == Some(state);
+        let held = |direction, coef| {
+            graph
+                .edges_directed(n, direction)
+                .map(move |e| vars.active_var(state, e.id()) * coef)
+        };
+        let taken_in = || {
+            graph
+                .edges_directed(n, Incoming)
+                .map(|e| vars.edge_var(e.id()) * -1.0)
+        };
+        let name = |what| format!("{}/{}_{}", node.name, state, what);
+        let mut exprs = Vec::new();
+        if n == first_node {
+            // the route starts here, so nothing can have been activated before it
+            exprs.push(Expr {
+                name: name("flow"),
+                bounds: Bounds::Fixed(if activates { 1.0 } else { 0.0 }),
+                terms: held(Outgoing, 1.0).collect(),
+            });
+            return exprs;
+        }
+        if requires {
+            exprs.push(Expr {
+                name: name("required"),
+                bounds: Bounds::Lower(0.0),
+                terms: held(Incoming, 1.0).chain(taken_in()).collect(),
+            });
+        }
+        if n != last_node {
+            exprs.push(Expr {
+                name: name("flow"),
+                bounds: Bounds::Fixed(0.0),
+                terms: if activates {
+                    held(Outgoing, 1.0).chain(taken_in()).collect()
+                } else {
+                    held(Outgoing, 1.0).chain(held(Incoming, -1.0)).collect()
+                },
+            });
+        }
+        exprs
+    });
+    capacity.chain(nodes).collect()
+}
+
+pub(crate) fn lazy_required_bits_expr(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    first_node: NodeIndex,
+    required_bits: Bits,
     values: &StableGraph<&Node, f64>,
 ) -> Option<Expr> {
-    let (connected_nodes, connected_bits) = get_connected_nodes(values, first_node);
+    lazy_required_bits_cut(first_node, required_bits, values)
+        .map(|connected_nodes| cut_expr(graph, vars, &connected_nodes))
+}
+
+/// the nodes connected to the first node, if they don't have enough bits
+pub(crate) fn lazy_required_bits_cut(
+    first_node: NodeIndex,
+    required_bits: Bits,
+    values: &StableGraph<&Node, f64>,
+) -> Option<FixedBitSet> {
+    let (connected_nodes, connected_bits) = reachable_bits(values, first_node, |_| true);
     if connected_bits < required_bits {
-        Some(Expr {
-            name: format!("cut.{:?}", connected_nodes),
-            bounds: Bounds::Lower(1.0),
-            terms: connected_nodes
-                .ones()
-                .map(NodeIndex::new)
-                .flat_map(|n| {
-                    graph
-                        .edges_directed(n, Outgoing)
-                        .filter(|e| !connected_nodes.contains(e.target().index()))
-                        .map(|e| edges.get(e.id().index()) * 1.0)
-                })
-                .collect(),
-        })
+        Some(connected_nodes)
     } else {
         None
     }
 }
 
-fn get_connected_nodes(
+/// take the edge first if it collects new bits while the nodes connected to the first node are short of the required bits,
+/// otherwise leave it out first, since the bits don't need it and the route is shorter without it
+fn deficit_branch(
     values: &StableGraph<&Node, f64>,
     first_node: NodeIndex,
-) -> (FixedBitSet, i32) {
-    let mut connected_bits = 0;
-    let mut dfs = Dfs::new(&values, first_node);
-    while let Some(n) = dfs.next(values) {
-        connected_bits += values[n].bits;
+    required_bits: Bits,
+    e: EdgeReference<f64>,
+) -> Branch {
+    let (connected_nodes, connected_bits) = reachable_bits(values, first_node, |_| true);
+    let target = e.target();
+    if connected_bits < required_bits
+        && !connected_nodes.contains(target.index())
+        && values[target].bits > Bits(0)
+    {
+        Branch::Up
+    } else {
+        Branch::Down
+    }
+}
+
+/// at least one edge must leave the connected nodes
+pub(crate) fn cut_expr(
+    graph: &StableGraph<Node, Edge>,
+    vars: &VarRegistry,
+    connected_nodes: &FixedBitSet,
+) -> Expr {
+    Expr {
+        name: format!("cut.{:?}", connected_nodes),
+        bounds: Bounds::Lower(1.0),
+        terms: connected_nodes
+            .ones()
+            .map(NodeIndex::new)
+            .flat_map(|n| {
+                graph
+                    .edges_directed(n, Outgoing)
+                    .filter(|e| !connected_nodes.contains(e.target().index()))
+                    .map(|e| vars.edge_var(e.id()) * 1.0)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lns::{free_segment, LazyCuts};
+    use crate::{heuristic, prepare, rooms};
+    use clap::Parser;
+    use petgraph::Graph;
+
+    fn small_rooms(args: &[&str]) -> (RouteGraph, Options) {
+        let rooms = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/small_rooms.json"
+        );
+        let options = Options::parse_from(
+            ["fez-route", "--rooms", rooms, "--no-render"]
+                .iter()
+                .chain(args),
+        );
+        let graph = prepare(rooms::load(&options.rooms, &options), &options);
+        (graph, options)
+    }
+
+    fn block_names(args: &[&str]) -> Vec<String> {
+        let (graph, options) = small_rooms(args);
+        let (first_node, last_node) = endpoints(&graph);
+        let (_, vars) = build_problem(&graph, first_node, last_node, &options);
+        vars.registry
+            .blocks()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// records the edges of every new best solution, read by their vars on the callback's problem
+    struct ReadsEdges<'v> {
+        graph: &'v RouteGraph,
+        vars: &'v VarRegistry,
+        chosen: HashSet<EdgeIndex>,
+    }
+    impl MipCallback for ReadsEdges<'_> {
+        fn new_best_solution(&mut self, problem: &Prob) {
+            self.chosen = self
+                .graph
+                .edge_indices()
+                .filter(|e| problem.get_int_value(self.vars.edge_var(*e)) > 0.5)
+                .collect();
+        }
+    }
+
+    #[test]
+    fn callbacks_read_vars_past_unusable_edges() {
+        let node = |name: &str, bits| Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            ..Default::default()
+        };
+        let mut graph = Graph::new();
+        let start = graph.add_node(node("a.start", 0));
+        let empty = graph.add_node(node("a.empty", 0));
+        let bit = graph.add_node(node("a.bit", 1));
+        let end = graph.add_node(node("a.end", 0));
+        let edge = |time| Edge { time: Frames(time) };
+        // the unusable edges come first, so every later var would move if they were taken out of the model
+        graph.add_edge(start, empty, edge(1.0));
+        graph.add_edge(empty, end, edge(1.0));
+        let into = graph.add_edge(start, bit, edge(5.0));
+        let out = graph.add_edge(bit, end, edge(5.0));
+        let graph = RouteGraph::from(graph);
+        let options = Options::parse_from(["fez-route", "--required-bits", "1", "--no-render"]);
+
+        let (mut problem, vars) = build_problem(&graph, start, end, &options);
+        assert_eq!(vars.unusable.len(), 2);
+        let mut callback = ReadsEdges {
+            graph: &graph,
+            vars: &vars.registry,
+            chosen: HashSet::new(),
+        };
+        assert_eq!(
+            problem.optimize_mip(&optimize_options(&options), &mut callback),
+            Ok(())
+        );
+        let expected = HashSet::from([into, out]);
+        assert_eq!(callback.chosen, expected);
+
+        let route = optimize(&graph, &options, &Control::default()).expect("a route");
+        assert_eq!(route.edges.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn disabled_families_make_no_vars() {
+        let names = block_names(&[]);
+        assert!(names.contains(&"edges".to_owned()));
+        assert!(!names.contains(&"component-bits".to_owned()));
+        assert!(!block_names(&["--disable-family", "long-load"]).contains(&"long-loads".to_owned()));
+        assert!(names.contains(&"key-flows".to_owned()));
+        assert!(!block_names(&["--disable-family", "key-flow"]).contains(&"key-flows".to_owned()));
+    }
+
+    /// only passes on the lazy cuts and records the nodes of the search. glpk's search asks for heuristic solutions
+    /// and branches, which the plain branch and bound never does, so it panics if glpk's runs
+    struct PlainOnly<'m> {
+        cuts: LazyCuts<'m>,
+        nodes: Vec<TreeNode>,
+    }
+    impl MipCallback for PlainOnly<'_> {
+        fn get_lazy_expr(&mut self, problem: &Prob) -> Option<Expr> {
+            self.cuts.get_lazy_expr(problem)
+        }
+
+        fn get_heuristic_solution(&mut self, _: &Prob) -> Option<Solution> {
+            panic!("glpk's search asked for a heuristic solution")
+        }
+
+        fn get_branch(&mut self, _: &Prob) -> Option<(VarRef, Branch)> {
+            panic!("glpk's search asked for a branch")
+        }
+
+        fn tree_status(&mut self, status: TreeStatus) {
+            self.nodes.extend(status.node);
+        }
+    }
+
+    #[test]
+    fn default_lns_segments_use_the_plain_branch_and_bound() {
+        let (graph, options) = small_rooms(&["--required-bits", "16"]);
+        let (first_node, last_node) = endpoints(&graph);
+        let route = heuristic::run(&graph, &options).expect("a heuristic route");
+        let (mut problem, vars) = build_problem(&graph, first_node, last_node, &options);
+        route
+            .edges
+            .windows(options.lns_segment)
+            .for_each(|segment| {
+                free_segment(&graph, &mut problem, &vars, &route, segment);
+                let mut callback = PlainOnly {
+                    cuts: LazyCuts::new(
+                        Model {
+                            graph: &graph,
+                            vars: &vars.registry,
+                            first_node,
+                            last_node,
+                            options: &options,
+                        },
+                        &vars.formulation,
+                    ),
+                    nodes: Vec::new(),
+                };
+                assert_eq!(
+                    problem.optimize_mip(&resolve_options(&options), &mut callback),
+                    Ok(())
+                );
+                // the plain branch and bound reports its root before solving it, so without a bound,
+                // where glpk's root starts at the most negative finite bound
+                let root = callback
+                    .nodes
+                    .first()
+                    .expect("the search reported its root");
+                assert_eq!((root.id, root.parent), (1, None));
+                assert_eq!(root.bound, f64::NEG_INFINITY);
+            });
     }
-    (dfs.discovered, connected_bits)
 }
@@ -0,0 +1,608 @@
+use crate::rooms::{Action, Cost, Node};
+use crate::units::{Bits, Frames};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueSource};
+use log::LevelFilter;
+use serde::{Serialize, Serializer};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use toml::Value;
+
+/// find the fastest FEZ any% route
+#[derive(Parser, Debug, Clone, Serialize)]
+#[clap(version)]
+pub struct Options {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// toml file of options, by their long names, for anything not given on the command line
+    #[clap(long, default_value = "fez-route.toml")]
+    pub config: PathBuf,
+
+    /// room data to route through
+    #[clap(long, default_value = "fez-route/rooms.json")]
+    pub rooms: PathBuf,
+
+    /// bits needed to finish the route, 8 per cube
+    #[clap(long, default_value_t = Bits::CUBE * 30)]
+    pub required_bits: Bits,
+
+    /// fewest whole golden cubes the route has to collect, not counting cubes assembled from bits
+    #[clap(long)]
+    pub required_cubes: Option<u32>,
+
+    /// fewest anti cubes the route has to collect
+    #[clap(long)]
+    pub required_antis: Option<u32>,
+
+    /// most anti cubes the route may collect, for categories that cap them
+    #[clap(long)]
+    pub max_antis: Option<u32>,
+
+    /// frames a strat is assumed to cost per point of difficulty, trading route time against risk
+    #[clap(long, default_value_t = Frames(0.0))]
+    pub difficulty_frames: Frames,
+
+    /// route for the expected time of a real run instead of a TAS, adding the frames each trick with a success rate
+    /// is expected to lose to retries, (1 - p) / p times its retry cost
+    #[clap(long)]
+    pub expected_time: bool,
+
+    /// fixed frames before the first node, like the intro, which are reported in totals but not routed
+    #[clap(long, default_value_t = Frames(0.0))]
+    pub prologue_frames: Frames,
+
+    /// frames between a node without a position and any other node of its room, so rooms can be routed
+    /// while their positions are still being entered
+    #[clap(long, default_value_t = Frames(120.0))]
+    pub unpositioned_frames: Frames,
+
+    /// json file of menuing frames by node name and by event, like warps or locked doors, added to the route time
+    #[clap(long)]
+    pub menu_costs: Option<PathBuf>,
+
+    /// most warp gates the route may use
+    #[clap(long)]
+    pub max_warps: Option<u32>,
+
+    /// most wells the route may use
+    #[clap(long)]
+    pub max_wells: Option<u32>,
+
+    /// most edges the route may take, for simpler routes or to see what the time costs
+    #[clap(long)]
+    pub max_transitions: Option<u32>,
+
+    /// leave out nodes and strats with this tag in the room data, like glitch
+    #[clap(long)]
+    pub exclude_tag: Vec<String>,
+
+    /// frames added to every node and strat with a tag, as tag=frames, like precise=120
+    #[clap(long)]
+    pub tag_penalty: Vec<TagPenalty>,
+
+    /// leave out death warps, for categories that don't allow dying on purpose to respawn
+    #[clap(long)]
+    pub no_death_warps: bool,
+
+    /// keep clock gated nodes, for runs that change the system clock to reach them
+    #[clap(long)]
+    pub clock_gates: bool,
+
+    /// keep nodes that can never be part of an optimal route
+    #[clap(long)]
+    pub no_prune: bool,
+
+    /// don't render any graphs, for running without graphviz
+    #[clap(long)]
+    pub no_render: bool,
+
+    /// formats to render progress in, can be given more than once
+    #[clap(long, value_enum, default_value = "png")]
+    pub render: Vec<RenderFormat>,
+
+    /// label rendered nodes with the bits and keys they collect and edges with the frames they take
+    #[clap(long)]
+    pub render_labels: bool,
+
+    /// color rendered edges by how their value changed since the previous render instead of by their value
+    #[clap(long)]
+    pub render_diff: bool,
+
+    /// also render the room graph once, with doors colored by what they cost, as `map`
+    #[clap(long)]
+    pub render_map: bool,
+
+    /// split the final render into pages of this many rooms of the route, each continuing from the last,
+    /// as well as rendering it whole with where each page starts. 0 only renders it whole
+    #[clap(long, default_value = "12")]
+    pub render_page_rooms: usize,
+
+    /// folder to render the route into one step at a time, each cropped to the room the route is in
+    /// and showing the transition it takes next, for making route tutorials
+    #[clap(long)]
+    pub render_steps: Option<PathBuf>,
+
+    /// colors for rendered values, diffs, and doors
+    #[clap(long, value_enum, default_value = "classic")]
+    pub palette: Palette,
+
+    /// only run the standalone heuristic, without solving the MIP
+    #[clap(long)]
+    pub heuristic_only: bool,
+
+    /// keep running, and run the heuristic again whenever the room data changes
+    #[clap(long, requires = "heuristic-only")]
+    pub watch: bool,
+
+    /// build the model, report its size, and write it to the lp file without solving, to quickly check data changes
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// where the dry run writes the model, in CPLEX LP format
+    #[clap(long, default_value = "route.lp")]
+    pub lp_file: PathBuf,
+
+    /// stop at the first legal route instead of proving one optimal, to quickly check that constraints can be met
+    #[clap(long)]
+    pub first_solution: bool,
+
+    /// seconds the solve may search before it stops with the best route it has, without proving it optimal
+    #[clap(long)]
+    pub time_limit: Option<f64>,
+
+    /// relative gap between the best route and the bound at which the solve stops without proving the route optimal.
+    /// 0 only stops once it is proven
+    #[clap(long, default_value_t = 0.0)]
+    pub mip_gap: f64,
+
+    /// which side of the chosen edge to explore first when branching
+    #[clap(long, value_enum, default_value = "up")]
+    pub branch_direction: BranchDirection,
+
+    /// number of simulated annealing iterations for the heuristic
+    #[clap(long, default_value_t = 20_000)]
+    pub heuristic_iterations: usize,
+
+    /// number of large neighborhood search iterations run from the best route of a solve that stopped at
+    /// `--time-limit` or `--mip-gap`, which take as long as they need on top of the time limit
+    #[clap(long, default_value_t = 20)]
+    pub lns_iterations: usize,
+
+    /// number of consecutive route edges freed by each large neighborhood search iteration.
+    /// on the full map a segment of 4 frees at most 89 edges and long loads, so with the default
+    /// `--small-mip-vars` every iteration is solved by the plain branch and bound
+    #[clap(long, default_value_t = 4)]
+    pub lns_segment: usize,
+
+    /// re-solves with at most this many edges and long loads left free use a plain branch and bound instead of
+    /// glpk's search, which takes longer to set up than such a small search. 0 always uses glpk's.
+    /// a large neighborhood search iteration frees every edge touching its segment and the long loads of the hubs
+    /// they warp to, while the shell's re-solves leave every edge free and so always use glpk's
+    #[clap(long, default_value_t = 100)]
+    pub small_mip_vars: usize,
+
+    /// megabytes glpk may allocate before aborting, instead of slowly eating all memory on long solves
+    #[clap(long)]
+    pub mem_limit: Option<i32>,
+
+    /// file of node to node times within each room, only recomputed for rooms that changed since they were saved
+    #[clap(long)]
+    pub timing_cache: Option<PathBuf>,
+
+    /// file of where fdp placed every node of the room graph, made once and then pinned in every render
+    /// so renders line up with each other. laid out again once nodes are added
+    #[clap(long)]
+    pub layout_cache: Option<PathBuf>,
+
+    /// json object of short notes by node name, drawn next to their nodes in the final render to print as a route sheet
+    #[clap(long)]
+    pub notes: Option<PathBuf>,
+
+    /// where to write the finished route, with everything needed to reproduce it
+    #[clap(long, default_value = "route.json")]
+    pub route_file: PathBuf,
+
+    /// url to post each new best route to, with its render if rendering pngs, like a discord channel's webhook.
+    /// posted with curl, and left out of route and history files since it works like a password
+    #[clap(long)]
+    #[serde(skip)]
+    pub webhook: Option<String>,
+
+    /// json lines file every run is appended to, with its options, incumbents, and route, for the history subcommand
+    #[clap(long, default_value = "history.jsonl")]
+    pub history: PathBuf,
+
+    /// don't append this run to the history file
+    #[clap(long)]
+    pub no_history: bool,
+
+    /// csv of room,action,frames rows, like community route spreadsheets, whose route is the initial incumbent
+    /// instead of the heuristic's
+    #[clap(long)]
+    pub import_sheet: Option<PathBuf>,
+
+    /// where to also write the finished route as room,action,frames rows, for pasting into a route spreadsheet
+    #[clap(long)]
+    pub export_sheet: Option<PathBuf>,
+
+    /// folder to write a practice file to for each zone the route passes through,
+    /// with the state the route enters it in and the nodes it visits there
+    #[clap(long)]
+    pub practice: Option<PathBuf>,
+
+    /// experimental: solve a copy of the graph with a node for every level of bits collected, in buckets of this many bits,
+    /// instead of constraining the bits with connectivity cuts
+    #[clap(long)]
+    pub expand_bits: Option<Bits>,
+
+    /// constraint family to leave out of the model, such as no-2-cycles or connectivity
+    #[clap(long)]
+    pub disable_family: Vec<String>,
+
+    /// constraint family that is off by default to add to the model, such as no-3-cycles
+    #[clap(long)]
+    pub enable_family: Vec<String>,
+
+    /// edges into nodes of this kind are left out when finding dominators, can be given more than once.
+    /// a dominator found this way is only real if routes never need those edges to get around it,
+    /// so any that aren't dominators of the whole graph are warned about
+    #[clap(long, value_enum, default_value = "secret")]
+    pub dominator_ignore: Vec<EdgeKind>,
+
+    /// file of connectivity cuts found by previous solves of the same graph, which new cuts are added to.
+    /// a file saved for a different graph is ignored and replaced
+    #[clap(long)]
+    pub cut_pool: Option<PathBuf>,
+
+    /// generic cuts glpk adds to each relaxation, on top of the connectivity cuts.
+    /// clique cuts are on by default, since oneof groups and 2 cycles are sets of edges that can't be taken together
+    #[clap(long, value_enum, default_value = "clique")]
+    pub glpk_cuts: Vec<GlpkCut>,
+
+    /// don't let glpk add any generic cuts
+    #[clap(long)]
+    pub no_glpk_cuts: bool,
+
+    /// how glpk chooses the fractional edge to branch on when no other option does
+    #[clap(long, value_enum, default_value = "driebeck")]
+    pub glpk_branching: GlpkBranching,
+
+    /// which subproblem glpk solves next
+    #[clap(long, value_enum, default_value = "bound")]
+    pub glpk_backtracking: GlpkBacktracking,
+
+    /// log how much the lazy cuts of each size and zone raised the relaxation, and how long they stayed tight
+    #[clap(long)]
+    pub cut_stats: bool,
+
+    /// folder to write every lazy cut to as it is added, as the expr in text and,
+    /// for connectivity cuts, a dot file of the component and its crossing edges with their values
+    #[clap(long, value_name = "DIR")]
+    pub debug_cuts: Option<PathBuf>,
+
+    /// csv file of every incumbent's time, when it was found, and the gap then, for comparing how options converge
+    #[clap(long)]
+    pub convergence: Option<PathBuf>,
+
+    /// graphviz file of every branch and bound subproblem and the edge it was split on
+    #[clap(long)]
+    pub branch_tree: Option<PathBuf>,
+
+    /// random seed for the heuristic and large neighborhood search, so runs are reproducible
+    #[clap(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// most verbose level logged for a target and everything under it, as target=level, like glpk::callback=info.
+    /// targets are module paths like fez_route::opt or fez_route::rooms, and glpk::callback for the cuts
+    /// and relaxations of the solve, which leaves the incumbents of fez_route::opt alone
+    #[clap(long)]
+    pub log: Vec<LogLevel>,
+}
+
+impl Options {
+    /// the command line merged with the config file
+    pub fn load() -> Self {
+        Self::load_from(env::args_os())
+    }
+
+    /// options in the config file are passed as if they were flags before the command line,
+    /// but only when the command line doesn't already give them
+    pub fn load_from<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let matches = Self::command().get_matches_from(&args);
+        let options = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let config = match fs::read_to_string(&options.config) {
+            Ok(config) => config,
+            // the default config file is optional
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return options,
+            Err(e) => panic!("failed to read config {:?}: {}", options.config, e),
+        };
+        let table: toml::value::Table = toml::from_str(&config)
+            .unwrap_or_else(|e| panic!("failed to parse config {:?}: {}", options.config, e));
+
+        let command = Self::command();
+        let mut merged = args[..1].to_vec();
+        table.into_iter().for_each(|(key, value)| {
+            // ids are the kebab case long names
+            let id = key.replace('_', "-");
+            if !command.get_arguments().any(|arg| arg.get_id() == id) {
+                panic!("unknown option {} in config {:?}", key, options.config);
+            }
+            if matches.value_source(&id) != Some(ValueSource::CommandLine) {
+                merged.extend(config_args(&id, value));
+            }
+        });
+        merged.extend_from_slice(&args[1..]);
+        Self::parse_from(merged)
+    }
+}
+
+fn config_args(key: &str, value: Value) -> Vec<OsString> {
+    let flag = OsString::from(format!("--{}", key));
+    match value {
+        Value::Boolean(true) => vec![flag],
+        Value::Boolean(false) => vec![],
+        Value::String(s) => vec![flag, s.into()],
+        Value::Array(values) => values
+            .into_iter()
+            .flat_map(|value| config_args(key, value))
+            .collect(),
+        Value::Table(_) => panic!("config option {} can't be a table", key),
+        value => vec![flag, value.to_string().into()],
+    }
+}
+
+#[derive(Subcommand, Debug, Clone, Serialize)]
+pub enum Command {
+    /// check that graphviz, glpk, the render folder, and the room data are all usable, without routing
+    Doctor,
+    /// solve routing jobs submitted over http, one at a time
+    Serve {
+        /// address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+    /// solve the route, then solve it again with a node forced in, to report how many frames visiting it costs,
+    /// or why pruning removed it
+    Explain {
+        /// the node to visit, which may also be any of its strats or detours
+        #[clap(long)]
+        node: String,
+    },
+    /// solve, then try what-if queries on the solved model, like fixing an edge or changing a node's time,
+    /// each solved again starting from the current route
+    Shell,
+    /// solve with every combination of options in a matrix file, each for a limited time, and rank how far each got.
+    /// options before the subcommand apply to every entry
+    Tournament {
+        /// toml file of axes, each a list of command line options to try
+        #[clap(long, default_value = "tournament.toml")]
+        matrix: PathBuf,
+        /// seconds each entry may run before it is stopped
+        #[clap(long, default_value_t = 300)]
+        seconds: u64,
+    },
+    /// compare the time of each zone of a route sheet with the splits of a real run of it,
+    /// and suggest factors to correct the timing model by, overall and for travel, actions, menus, and long loads
+    Calibrate {
+        /// csv of zone,time rows, where times are seconds or like 1:02.5 and a zone split more than once is summed
+        #[clap(long)]
+        splits: PathBuf,
+        /// csv of room,action,frames rows of the route the run followed
+        #[clap(long)]
+        sheet: PathBuf,
+    },
+    /// check the route of a sheet against every rule of the model, printing where it is, how often it has entered
+    /// the room, and the keys, bits, and frames it has after every step. exits with an error if it breaks any rule
+    Verify {
+        /// csv of room,action,frames rows, like --export-sheet writes
+        #[clap(long)]
+        sheet: PathBuf,
+    },
+    /// list past runs from the history file, newest last, or compare two of them
+    History {
+        /// only runs with an option set to a value, as option=value by long name, like seed=3.
+        /// lists are matched joined by commas
+        #[clap(long)]
+        filter: Vec<RunFilter>,
+        /// most runs to list
+        #[clap(long, default_value_t = 20)]
+        last: usize,
+        /// the numbers of two runs to compare the options and routes of, instead of listing
+        #[clap(long, number_of_values = 2, value_names = &["FROM", "TO"])]
+        diff: Vec<usize>,
+    },
+    /// solve with random glpk branching, backtracking, and cut options, each for a limited time,
+    /// and save the ones that found the best route into the config file. options before the subcommand apply to every trial.
+    /// presolve isn't tuned, since the callbacks need the vars numbered as they were built
+    Tune {
+        /// random option sets to try after the current ones
+        #[clap(long, default_value_t = 10)]
+        trials: usize,
+        /// seconds each trial may run before it is stopped
+        #[clap(long, default_value_t = 60)]
+        seconds: u64,
+    },
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BranchDirection {
+    /// always take the edge first
+    Up,
+    /// always leave the edge out first
+    Down,
+    /// let glpk choose
+    Auto,
+    /// take the edge first only if it helps collect the missing bits
+    Deficit,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    /// graphviz images
+    Png,
+    /// graphviz vector images
+    Svg,
+    /// one json line of edge values per render
+    Jsonl,
+}
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    /// red through blue to green, with red, yellow, and blue doors
+    Classic,
+    /// dark purple through teal to yellow, readable with most color vision deficiencies
+    Viridis,
+    /// dark blue through gray to yellow, readable with red and green indistinguishable
+    Cividis,
+}
+
+/// what is at the target of an edge
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// a locked door
+    Lock,
+    /// a door that needs the water lowered
+    Water,
+    /// a secret door
+    Secret,
+    /// a warp gate
+    Warp,
+    /// a well
+    Well,
+}
+impl EdgeKind {
+    pub fn matches(self, target: &Node) -> bool {
+        match self {
+            EdgeKind::Lock => target.cost == Cost::Lock,
+            EdgeKind::Water => target.cost == Cost::Water,
+            EdgeKind::Secret => target.cost == Cost::Secret,
+            EdgeKind::Warp => target.action == Some(Action::Warp),
+            EdgeKind::Well => target.action == Some(Action::Well),
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlpkCut {
+    /// gomory mixed integer cuts, strong but dense and slow to separate
+    Gomory,
+    /// mixed integer rounding cuts
+    Mir,
+    /// cover cuts, from knapsack-like exprs
+    Cover,
+    /// clique cuts, from oneof groups and 2 cycles
+    Clique,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlpkBranching {
+    /// the first fractional edge
+    First,
+    /// the last fractional edge
+    Last,
+    /// the edge closest to one half
+    Most,
+    /// Driebeck and Tomlin's heuristic, glpk's default
+    Driebeck,
+    /// hybrid pseudocosts, which learn from earlier branches
+    Pseudocost,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlpkBacktracking {
+    /// the newest subproblem, which finds incumbents quickly
+    Depth,
+    /// the oldest subproblem
+    Breadth,
+    /// the subproblem with the best bound, glpk's default
+    Bound,
+    /// the subproblem with the best projection of its objective
+    Projection,
+}
+
+/// frames a route is charged for each node or strat with the tag
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TagPenalty {
+    pub tag: String,
+    pub frames: Frames,
+}
+impl FromStr for TagPenalty {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tag, frames) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected tag=frames, got {}", s))?;
+        Ok(TagPenalty {
+            tag: tag.to_owned(),
+            frames: frames.parse().map_err(|e| format!("{}: {}", frames, e))?,
+        })
+    }
+}
+
+/// an option of past runs that has to have a value
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RunFilter {
+    pub option: String,
+    pub value: String,
+}
+impl FromStr for RunFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (option, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected option=value, got {}", s))?;
+        Ok(RunFilter {
+            option: option.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+
+/// a level filter for one log target
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LogLevel {
+    pub target: String,
+    #[serde(serialize_with = "serialize_level")]
+    pub level: LevelFilter,
+}
+impl FromStr for LogLevel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, level) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected target=level, got {}", s))?;
+        Ok(LogLevel {
+            target: target.to_owned(),
+            level: level.parse().map_err(|e| format!("{}: {}", level, e))?,
+        })
+    }
+}
+
+fn serialize_level<S: Serializer>(level: &LevelFilter, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(level)
+}
+
+impl RenderFormat {
+    pub fn ext(self) -> &'static str {
+        match self {
+            RenderFormat::Png => "png",
+            RenderFormat::Svg => "svg",
+            RenderFormat::Jsonl => "jsonl",
+        }
+    }
+}
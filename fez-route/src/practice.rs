@@ -0,0 +1,161 @@
+use crate::common::{long_load_edges, LONG_LOAD};
+use crate::heuristic::Route;
+use crate::rooms::{Action, Edge, Node};
+use crate::units::{Bits, Frames};
+use log::*;
+use petgraph::stable_graph::StableGraph;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// what a practice save has to match where a segment starts
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EntryState {
+    /// frames into the route
+    pub time: Frames,
+    pub bits: Bits,
+    pub keys: i32,
+    /// warp gates used so far
+    pub warps: u32,
+    pub wells: u32,
+    /// the level each phase resource has risen to
+    pub phases: BTreeMap<String, u32>,
+    /// puzzle states changed earlier in the route
+    pub activated: BTreeSet<String>,
+}
+
+/// a stretch of the route inside one zone, from the edge that enters it until the route leaves it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Segment {
+    pub zone: String,
+    pub entry: EntryState,
+    pub nodes: Vec<String>,
+    /// frames spent in the segment, including the edge into it and its long load
+    pub time: Frames,
+}
+
+/// splits the route wherever it changes zone, so a zone the route comes back to gets another segment.
+/// like `Route`, the first node's bits and keys aren't counted
+pub fn segments(graph: &StableGraph<Node, Edge>, route: &Route) -> Vec<Segment> {
+    let first = match route.edges.first() {
+        Some(e) => &graph[graph.edge_endpoints(*e).unwrap().0],
+        None => return Vec::new(),
+    };
+    let mut state = EntryState::default();
+    state.phases.extend(
+        first
+            .phase
+            .iter()
+            .map(|phase| (phase.resource.clone(), phase.level)),
+    );
+    state.activated.extend(first.activates.clone());
+    let long_loads = long_load_edges(graph, route.edges.iter().copied());
+    let mut segments = vec![Segment {
+        zone: first.zone.clone(),
+        entry: state.clone(),
+        nodes: vec![first.name.clone()],
+        time: Frames(0.0),
+    }];
+    route.edges.iter().for_each(|e| {
+        let target = &graph[graph.edge_endpoints(*e).unwrap().1];
+        if segments.last().unwrap().zone != target.zone {
+            segments.push(Segment {
+                zone: target.zone.clone(),
+                entry: state.clone(),
+                nodes: Vec::new(),
+                time: Frames(0.0),
+            });
+        }
+        let mut time = graph[*e].time + target.time;
+        if long_loads.contains(e) {
+            time += LONG_LOAD;
+        }
+        let segment = segments.last_mut().unwrap();
+        segment.nodes.push(target.name.clone());
+        segment.time += time;
+
+        state.time += time;
+        state.bits += target.bits;
+        state.keys += target.keys_minus_lock();
+        match target.action {
+            Some(Action::Warp) => state.warps += 1,
+            Some(Action::Well) => state.wells += 1,
+            None => {}
+        }
+        if let Some(phase) = &target.phase {
+            let level = state.phases.entry(phase.resource.clone()).or_default();
+            *level = (*level).max(phase.level);
+        }
+        state.activated.extend(target.activates.clone());
+    });
+    segments
+}
+
+/// one json file per segment, numbered in route order
+pub fn save(folder: &Path, segments: &[Segment]) {
+    let result = fs::create_dir_all(folder).and_then(|()| {
+        segments.iter().enumerate().try_for_each(|(i, segment)| {
+            let path = folder.join(format!("{:02}-{}.json", i, segment.zone));
+            File::create(path).and_then(|file| {
+                serde_json::to_writer_pretty(file, segment).map_err(io::Error::from)
+            })
+        })
+    });
+    match result {
+        Ok(()) => info!("saved {} practice segments to {:?}", segments.len(), folder),
+        Err(e) => error!("failed to write practice segments to {:?}: {}", folder, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, zone: &str, bits: i32, keys: i32, action: Option<Action>) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            keys,
+            action,
+            zone: zone.to_owned(),
+            time: Frames(1.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn segments_start_where_the_zone_changes() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", "a", 0, 0, None));
+        let key = graph.add_node(node("a.key", "a", 0, 1, None));
+        let warp = graph.add_node(node("b.warp", "b", 3, 0, Some(Action::Warp)));
+        let end = graph.add_node(node("a.end", "a", 0, 0, None));
+        let edge = Edge { time: Frames(10.0) };
+        let edges = vec![
+            graph.add_edge(start, key, edge.clone()),
+            graph.add_edge(key, warp, edge.clone()),
+            graph.add_edge(warp, end, edge),
+        ];
+        let route = Route::from_path(&graph, edges, Bits(0));
+
+        let segments = segments(&graph, &route);
+        assert_eq!(
+            segments
+                .iter()
+                .map(|s| (s.zone.as_str(), s.nodes.len(), s.time))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a", 2, Frames(11.0)),
+                ("b", 1, Frames(11.0)),
+                // the first warp back to a pays its long load
+                ("a", 1, Frames(11.0) + LONG_LOAD)
+            ]
+        );
+        assert_eq!(segments[1].entry.time, Frames(11.0));
+        assert_eq!(segments[1].entry.keys, 1);
+        assert_eq!(segments[2].entry.bits, Bits(3));
+        assert_eq!(segments[2].entry.warps, 1);
+    }
+}
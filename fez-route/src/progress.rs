@@ -0,0 +1,103 @@
+//! a one line progress bar on stderr for slow steps that would otherwise look hung.
+//! it is only drawn when stderr is a terminal, so logs redirected to a file stay clean
+
+use std::io::{self, IsTerminal, Write};
+
+const WIDTH: usize = 30;
+
+pub struct ProgressBar {
+    label: &'static str,
+    total: usize,
+    done: usize,
+    /// what is being done now
+    current: String,
+    draw: bool,
+}
+
+impl ProgressBar {
+    pub fn new(label: &'static str, total: usize) -> Self {
+        ProgressBar {
+            label,
+            total,
+            done: 0,
+            current: String::new(),
+            draw: io::stderr().is_terminal(),
+        }
+    }
+
+    /// for steps that are only known once earlier ones are done
+    pub fn extend(&mut self, more: usize) {
+        self.total += more;
+    }
+
+    /// finishes the current step, if any, and starts the next
+    pub fn step(&mut self, current: impl Into<String>) {
+        if !self.current.is_empty() {
+            self.done += 1;
+        }
+        self.current = current.into();
+        self.redraw();
+    }
+
+    /// clears the bar while `f` runs, so what it logs isn't drawn over
+    pub fn suspend<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        self.clear();
+        let result = f();
+        self.redraw();
+        result
+    }
+
+    fn redraw(&self) {
+        if self.draw {
+            let mut stderr = io::stderr();
+            let _ = write!(
+                stderr,
+                "\r{}\x1b[K",
+                line(self.label, self.done, self.total, &self.current)
+            );
+            let _ = stderr.flush();
+        }
+    }
+
+    fn clear(&self) {
+        if self.draw {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+        }
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+fn line(label: &str, done: usize, total: usize, current: &str) -> String {
+    let filled = (done * WIDTH).checked_div(total).unwrap_or(0).min(WIDTH);
+    format!(
+        "{} [{}{}] {}/{} {}",
+        label,
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        done,
+        total,
+        current
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars_fill_with_steps() {
+        assert_eq!(
+            line("model", 3, 10, "flow"),
+            "model [#########                     ] 3/10 flow"
+        );
+        assert_eq!(
+            line("model", 0, 0, ""),
+            format!("model [{}] 0/0 ", " ".repeat(30))
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use crate::analysis::reachable;
+use crate::common::{cost, endpoints};
+use crate::heuristic::Route;
+use crate::options::Options;
+use crate::rooms::{Cost, Edge, Node, RouteGraph};
+use crate::units::Bits;
+use fixedbitset::FixedBitSet;
+use itertools::Itertools;
+use log::*;
+use petgraph::graph::Graph;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use std::collections::HashMap;
+
+/// removes nodes that can never be part of an optimal route, logging why each one was removed.
+///
+/// only dominance that is safe regardless of which other nodes the route uses is considered,
+/// so a collectable is never removed just because a different collectable is cheaper
+/// (the route might need both of them for enough bits)
+pub fn prune_dominated(graph: RouteGraph) -> RouteGraph {
+    prune_with_reasons(graph).0
+}
+
+/// like `prune_dominated`, also returning each removed node, by its index in the given graph, with why it was removed
+pub fn prune_with_reasons(graph: RouteGraph) -> (RouteGraph, Vec<(NodeIndex, String)>) {
+    let mut graph = graph.into_inner();
+    let (first_node, last_node) = endpoints(&graph);
+    let mut pruned = Vec::new();
+    while let Some((node, reason)) = find_dominated(&graph, first_node, last_node) {
+        info!("pruned {}: {}", graph[node].name, reason);
+        graph.remove_node(node);
+        pruned.push((node, reason));
+    }
+    info!("pruned {} dominated nodes", pruned.len());
+    (Graph::from(graph).into(), pruned)
+}
+
+/// removes direct edges that pass right by a collectable whose only ways in and out are the ends of the edge,
+/// when picking it up on the way takes no longer. the way through it is then the only way between them,
+/// so it is collected whenever the route goes that way instead of being a separate choice.
+/// a node can only be left once, so the route never needs both the direct edge and the way through
+pub fn fold_on_path(graph: &RouteGraph) -> Folded {
+    let mut folded = graph.clone().into_inner();
+    let mut ways = HashMap::new();
+    graph
+        .node_indices()
+        .filter_map(|n| on_path(graph, n).map(|direct| (direct, n)))
+        .for_each(|(direct, n)| {
+            let (source, target) = graph.edge_endpoints(direct).unwrap();
+            info!(
+                "folded {} into {} -> {}",
+                graph[n].name, graph[source].name, graph[target].name
+            );
+            ways.entry((source, target)).or_insert(n);
+        });
+    info!("folded {} collectables on direct paths", ways.len());
+    ways.keys().for_each(|(source, target)| {
+        let direct = folded.find_edge(*source, *target).unwrap();
+        folded.remove_edge(direct);
+    });
+    Folded {
+        graph: Graph::from(folded).into(),
+        ways,
+    }
+}
+
+/// the graph the solve works on, with the direct edges `fold_on_path` removed, and the same nodes as the graph
+/// it was made from. routes that weren't found by the solve, like imported sheets, may still take those edges,
+/// so routes are mapped between the two graphs
+pub struct Folded {
+    pub graph: RouteGraph,
+    /// the collectable that replaces each removed direct edge, by the ends of the edge
+    ways: HashMap<(NodeIndex, NodeIndex), NodeIndex>,
+}
+impl Folded {
+    /// folds unless pruning is disabled, or `--max-transitions` would count the extra edge the way through takes
+    pub fn new(graph: &RouteGraph, options: &Options) -> Self {
+        if options.no_prune || options.max_transitions.is_some() {
+            Folded {
+                graph: graph.clone(),
+                ways: HashMap::new(),
+            }
+        } else {
+            fold_on_path(graph)
+        }
+    }
+
+    /// a route of the original graph that goes through the collectable wherever it took a removed direct edge
+    pub fn fold(&self, original: &RouteGraph, route: &Route, required_bits: Bits) -> Route {
+        let edges = route
+            .edges
+            .iter()
+            .flat_map(|e| {
+                let (source, target) = original.edge_endpoints(*e).unwrap();
+                match self.ways.get(&(source, target)) {
+                    Some(n) => vec![(source, *n), (*n, target)],
+                    None => vec![(source, target)],
+                }
+            })
+            .map(|(source, target)| self.graph.find_edge(source, target).unwrap())
+            .collect();
+        Route::from_path(&self.graph, edges, required_bits)
+    }
+
+    /// a route of the folded graph as the same edges of the original
+    pub fn unfold(&self, original: &RouteGraph, route: &Route, required_bits: Bits) -> Route {
+        let edges = route
+            .edges
+            .iter()
+            .map(|e| {
+                let (source, target) = self.graph.edge_endpoints(*e).unwrap();
+                original.find_edge(source, target).unwrap()
+            })
+            .collect();
+        Route::from_path(original, edges, required_bits)
+    }
+}
+
+/// the direct edge that passes a collectable, which only has an edge in from its source and out to its target.
+/// collecting anti cubes can break `--max-antis`, so they are always a choice
+fn on_path(graph: &StableGraph<Node, Edge>, node: NodeIndex) -> Option<EdgeIndex> {
+    let n = &graph[node];
+    if (n.bits == Bits(0) && n.keys == 0)
+        || n.antis != 0
+        || n.keys < 0
+        || n.cost != Cost::Free
+        || n.oneof.is_some()
+        || n.action.is_some()
+        || n.phase.is_some()
+        || n.activates.is_some()
+        || n.requires_activation.is_some()
+        || !n.requirements.is_empty()
+    {
+        return None;
+    }
+    let into = graph.edges_directed(node, Incoming).exactly_one().ok()?;
+    let out = graph.edges_directed(node, Outgoing).exactly_one().ok()?;
+    if into.source() == out.target() {
+        return None;
+    }
+    let direct = graph.find_edge(into.source(), out.target())?;
+    (into.weight().time + n.time + out.weight().time <= graph[direct].time).then_some(direct)
+}
+
+/// keeps only the cheapest edge from each node to each other node, logging the others.
+/// edges only differ in time, so a slower parallel edge is never better and only makes symmetric optima
+pub fn remove_parallel_edges(graph: &mut Graph<Node, Edge>) {
+    let mut kept: Vec<(NodeIndex, NodeIndex, Edge)> = Vec::new();
+    let mut pairs = HashMap::<(NodeIndex, NodeIndex), usize>::new();
+    let mut removed = 0;
+    graph.edge_references().for_each(|e| {
+        let pair = (e.source(), e.target());
+        match pairs.get(&pair) {
+            Some(&k) => {
+                let (slower, faster) = if e.weight().time < kept[k].2.time {
+                    let slower = kept[k].2.time;
+                    kept[k].2 = e.weight().clone();
+                    (slower, e.weight().time)
+                } else {
+                    (e.weight().time, kept[k].2.time)
+                };
+                info!(
+                    "removed parallel edge {}/to/{}: {} frames, kept {} frames",
+                    graph[pair.0].name, graph[pair.1].name, slower, faster
+                );
+                removed += 1;
+            }
+            None => {
+                pairs.insert(pair, kept.len());
+                kept.push((pair.0, pair.1, e.weight().clone()));
+            }
+        }
+    });
+    info!("removed {} parallel edges", removed);
+    if removed > 0 {
+        graph.clear_edges();
+        kept.into_iter().for_each(|(source, target, edge)| {
+            graph.add_edge(source, target, edge);
+        });
+    }
+}
+
+fn find_dominated(
+    graph: &StableGraph<Node, Edge>,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+) -> Option<(NodeIndex, String)> {
+    let from_first = reachable(graph, first_node, Outgoing, |_| true);
+    let to_last = reachable(graph, last_node, Incoming, |_| true);
+    graph
+        .node_indices()
+        .filter(|n| *n != first_node && *n != last_node)
+        .find_map(|n| {
+            dead_end(&from_first, &to_last, n)
+                .or_else(|| shortcut(graph, n))
+                .or_else(|| dominated_oneof(graph, n))
+                .map(|reason| (n, reason))
+        })
+}
+
+/// nodes that can't be on any path from the first node to the last node
+fn dead_end(from_first: &FixedBitSet, to_last: &FixedBitSet, node: NodeIndex) -> Option<String> {
+    if !from_first.contains(node.index()) {
+        Some("unreachable from the first node".to_owned())
+    } else if !to_last.contains(node.index()) {
+        Some("can't reach the last node".to_owned())
+    } else {
+        None
+    }
+}
+
+/// nodes with nothing to collect, where every way through them has a direct edge that is at least as fast
+fn shortcut(graph: &StableGraph<Node, Edge>, node: NodeIndex) -> Option<String> {
+    let n = &graph[node];
+    if n.bits != Bits(0)
+        || n.keys != 0
+        || n.cost != Cost::Free
+        || n.oneof.is_some()
+        || n.phase.is_some()
+        || n.activates.is_some()
+        || n.requires_activation.is_some()
+        || !n.requirements.is_empty()
+    {
+        return None;
+    }
+    let shortcut = graph.edges_directed(node, Incoming).all(|i| {
+        graph
+            .edges_directed(node, Outgoing)
+            .filter(|o| o.target() != i.source())
+            .all(|o| {
+                graph.find_edge(i.source(), o.target()).is_some_and(|d| {
+                    graph[d].time + graph[o.target()].time <= cost(graph, i) + cost(graph, o)
+                })
+            })
+    });
+    if shortcut {
+        Some("nothing to collect and always skipped by a direct edge".to_owned())
+    } else {
+        None
+    }
+}
+
+/// oneof nodes where another node of the same group collects as much, is reachable from every source as fast,
+/// and reaches every target as fast. since only one of them can be used, the other is always at least as good
+fn dominated_oneof(graph: &StableGraph<Node, Edge>, node: NodeIndex) -> Option<String> {
+    let n = &graph[node];
+    n.oneof.as_ref()?;
+    graph
+        .node_indices()
+        .filter(|other| *other != node)
+        .find(|other| {
+            let o = &graph[*other];
+            o.oneof == n.oneof
+                && o.cost == n.cost
+                && o.action == n.action
+                && o.phase == n.phase
+                && o.activates == n.activates
+                && o.requires_activation == n.requires_activation
+                && o.requirements == n.requirements
+                && o.bits >= n.bits
+                && o.keys >= n.keys
+                && o.cubes >= n.cubes
+                && o.antis == n.antis
+                && graph
+                    .edges_directed(node, Incoming)
+                    .filter(|i| i.source() != *other)
+                    .all(|i| {
+                        graph
+                            .find_edge(i.source(), *other)
+                            .is_some_and(|d| graph[d].time + o.time <= cost(graph, i))
+                    })
+                && graph
+                    .edges_directed(node, Outgoing)
+                    .filter(|i| i.target() != *other)
+                    .all(|i| {
+                        graph
+                            .find_edge(*other, i.target())
+                            .is_some_and(|d| graph[d].time <= i.weight().time)
+                    })
+        })
+        .map(|other| format!("dominated by oneof {}", graph[other].name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rooms::Action;
+    use crate::units::Frames;
+    use petgraph::visit::IntoEdgeReferences;
+
+    fn node(name: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_the_cheapest_parallel_edge_is_kept() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(node("a"));
+        let b = graph.add_node(node("b"));
+        graph.add_edge(a, b, Edge { time: Frames(5.0) });
+        graph.add_edge(b, a, Edge { time: Frames(9.0) });
+        graph.add_edge(a, b, Edge { time: Frames(3.0) });
+        graph.add_edge(a, b, Edge { time: Frames(4.0) });
+        remove_parallel_edges(&mut graph);
+        let edges: Vec<_> = graph
+            .edge_references()
+            .map(|e| (e.source(), e.target(), e.weight().time))
+            .collect();
+        assert_eq!(edges, vec![(a, b, Frames(3.0)), (b, a, Frames(9.0))]);
+    }
+
+    /// the names left after pruning a plain oneof node and another member of its group, which has the same times
+    fn prune_oneof_pair(other: Node) -> Vec<String> {
+        let member = |n: Node| Node {
+            bits: Bits(1),
+            oneof: Some("a.cube".to_owned()),
+            ..n
+        };
+        let mut graph = Graph::new();
+        let start = graph.add_node(node("a.start"));
+        let plain = graph.add_node(member(node("a.plain")));
+        let other = graph.add_node(member(other));
+        let end = graph.add_node(node("a.end"));
+        IntoIterator::into_iter([plain, other]).for_each(|n| {
+            graph.add_edge(start, n, Edge { time: Frames(4.0) });
+            graph.add_edge(n, end, Edge { time: Frames(4.0) });
+        });
+        prune_dominated(graph.into())
+            .into_graph()
+            .node_weights()
+            .map(|n| n.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn slower_oneofs_are_pruned_with_why() {
+        let mut graph = Graph::new();
+        let start = graph.add_node(node("a.start"));
+        let fast = graph.add_node(Node {
+            time: Frames(1.0),
+            oneof: Some("a.cube".to_owned()),
+            ..node("a.fast")
+        });
+        let slow = graph.add_node(Node {
+            time: Frames(2.0),
+            oneof: Some("a.cube".to_owned()),
+            ..node("a.slow")
+        });
+        let end = graph.add_node(node("a.end"));
+        IntoIterator::into_iter([fast, slow]).for_each(|n| {
+            graph.add_edge(start, n, Edge { time: Frames(4.0) });
+            graph.add_edge(n, end, Edge { time: Frames(4.0) });
+        });
+
+        let (graph, pruned) = prune_with_reasons(graph.into());
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(pruned, [(slow, "dominated by oneof a.fast".to_owned())]);
+    }
+
+    #[test]
+    fn free_oneofs_are_not_dominated_by_locked_ones() {
+        let names = prune_oneof_pair(Node {
+            cost: Cost::Lock,
+            ..node("a.locked")
+        });
+        assert!(names.contains(&"a.plain".to_owned()));
+    }
+
+    #[test]
+    fn oneofs_are_not_dominated_by_ones_with_an_action() {
+        let names = prune_oneof_pair(Node {
+            action: Some(Action::Warp),
+            ..node("a.warp")
+        });
+        assert!(names.contains(&"a.plain".to_owned()));
+    }
+
+    #[test]
+    fn collectables_on_the_way_are_folded() {
+        let mut graph = Graph::new();
+        let start = graph.add_node(node("a.start"));
+        let bit = graph.add_node(Node {
+            bits: Bits(1),
+            time: Frames(2.0),
+            ..node("a.bit")
+        });
+        let far = graph.add_node(Node {
+            bits: Bits(1),
+            time: Frames(2.0),
+            ..node("a.far")
+        });
+        let end = graph.add_node(node("a.end"));
+        graph.add_edge(start, bit, Edge { time: Frames(4.0) });
+        graph.add_edge(bit, end, Edge { time: Frames(4.0) });
+        graph.add_edge(start, far, Edge { time: Frames(9.0) });
+        graph.add_edge(far, end, Edge { time: Frames(9.0) });
+        graph.add_edge(start, end, Edge { time: Frames(10.0) });
+
+        let graph = RouteGraph::from(graph);
+        let folded = fold_on_path(&graph);
+        let edges: Vec<_> = folded
+            .graph
+            .edge_references()
+            .map(|e| {
+                (
+                    graph[e.source()].name.as_str(),
+                    graph[e.target()].name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(edges.len(), 4);
+        assert!(!edges.contains(&("a.start", "a.end")));
+
+        // routes that weren't solved for can still take the direct edge, which goes by the bit once folded
+        let edge = |source, target| graph.find_edge(source, target).unwrap();
+        let direct = Route::from_path(&graph, vec![edge(start, end)], Bits(0));
+        let through = folded.fold(&graph, &direct, Bits(0));
+        assert_eq!((through.edges.len(), through.bits), (2, Bits(1)));
+        assert_eq!(
+            folded.unfold(&graph, &through, Bits(0)).edges,
+            [edge(start, bit), edge(bit, end)]
+        );
+    }
+}
@@ -1,21 +1,37 @@
 use crate::common::heuristic_path;
-use crate::rooms::Node;
+use crate::heuristic::{route_graph, Route};
+use crate::layout::Layout;
+use crate::options::{Options, Palette, RenderFormat};
+use crate::rooms::{Cost, Edge, Node};
+use crate::units::Bits;
+use fixedbitset::FixedBitSet;
 use itertools::Itertools;
 use log::*;
-use petgraph::stable_graph::{EdgeReference, NodeIndex, StableGraph};
+use petgraph::stable_graph::{EdgeIndex, EdgeReference, NodeIndex, StableGraph};
 use petgraph::visit::{
     DfsPostOrder, EdgeRef, GraphBase, GraphRef, IntoEdgeReferences, IntoEdges, IntoEdgesDirected,
-    IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, IntoNodeReferences, NodeRef,
-    VisitMap, Visitable, Walker,
+    IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, VisitMap, Visitable, Walker,
 };
 use petgraph::EdgeDirection::{Incoming, Outgoing};
-use std::collections::HashSet;
-use std::fs;
-use std::io::{self, Write};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub const EXT: &'static str = "png";
+/// cleared and refilled by every run
+pub const FOLDER: &str = "rendered";
+
+const EPS: f64 = 1e-6;
+
+/// far longer than fdp takes on the whole room graph, so a render taking this long is stuck and holding up the solve
+const FDP_TIMEOUT: Duration = Duration::from_secs(60);
+const FDP_POLL: Duration = Duration::from_millis(5);
 
 const RBG_COLOR_SCALE: &[ColorF] = &[
     (1.0, 0.0, 0.0),
@@ -25,17 +41,40 @@ const RBG_COLOR_SCALE: &[ColorF] = &[
     (0.0, 1.0, 0.0),
 ];
 
-fn color_scale(value: f64) -> ColorF {
+/// evenly spaced samples of matplotlib's viridis
+const VIRIDIS_COLOR_SCALE: &[ColorF] = &[
+    (0.267, 0.005, 0.329),
+    (0.231, 0.322, 0.545),
+    (0.129, 0.569, 0.549),
+    (0.369, 0.788, 0.384),
+    (0.992, 0.906, 0.145),
+];
+
+/// evenly spaced samples of matplotlib's cividis
+const CIVIDIS_COLOR_SCALE: &[ColorF] = &[
+    (0.000, 0.125, 0.302),
+    (0.255, 0.302, 0.420),
+    (0.486, 0.482, 0.471),
+    (0.737, 0.686, 0.435),
+    (0.996, 0.918, 0.220),
+];
+
+fn color_scale(palette: Palette, value: f64) -> ColorF {
+    let scale = match palette {
+        Palette::Classic => RBG_COLOR_SCALE,
+        Palette::Viridis => VIRIDIS_COLOR_SCALE,
+        Palette::Cividis => CIVIDIS_COLOR_SCALE,
+    };
     if value <= 0.0 {
-        RBG_COLOR_SCALE[0]
+        scale[0]
     } else {
-        let value = value * RBG_COLOR_SCALE.len() as f64;
+        let value = value * scale.len() as f64;
         let i = value.floor() as usize;
         let f = value.fract();
-        if i >= RBG_COLOR_SCALE.len() - 1 {
-            RBG_COLOR_SCALE[RBG_COLOR_SCALE.len() - 1]
+        if i >= scale.len() - 1 {
+            scale[scale.len() - 1]
         } else {
-            interp(RBG_COLOR_SCALE[i], RBG_COLOR_SCALE[i + 1], f)
+            interp(scale[i], scale[i + 1], f)
         }
     }
 }
@@ -56,82 +95,978 @@ fn as_byte(v: f64) -> u8 {
     (v * 255.0).ceil() as u8
 }
 
-pub struct Renderer {
+/// something that consumes value graphs as the optimizer makes progress
+pub trait RouteSink {
+    /// false if renders are thrown away, so callers can skip building graphs that are only used for rendering
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// `name` is unique within a run and has no extension, each sink adds its own
+    fn render(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    );
+
+    /// a render made while adding a lazy cut, which sinks that can show it draw it on top of the values
+    fn render_cut(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+        _cut: &Cut,
+    ) {
+        self.render(name, values, first, last);
+    }
+
+    /// the finished route, which sinks that can annotate it for printing do
+    fn render_final(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        self.render(name, values, first, last);
+    }
+
+    /// the room graph itself, with doors colored by what it takes to go through them.
+    /// only done once per run, so sinks that only care about progress can ignore it
+    fn render_map(&mut self, _name: &str, _graph: &StableGraph<Node, Edge>) {}
+}
+
+/// every `--render` format into the render folder, or nothing if `--no-render` is set or the folder can't be setup
+pub fn route_sink<'g>(
+    graph: &'g StableGraph<Node, Edge>,
+    options: &Options,
+) -> Box<dyn RouteSink + 'g> {
+    if options.no_render || options.render.is_empty() {
+        return Box::new(NoRender);
+    }
+    if let Err(e) = clear_folder(Path::new(FOLDER)) {
+        error!("failed to setup rendering into {:?}: {}", FOLDER, e);
+        warn!("rendering is disabled");
+        return Box::new(NoRender);
+    }
+    let layout = options
+        .layout_cache
+        .as_deref()
+        .and_then(|path| Layout::load_or_seed(path, graph));
+    let notes = match &options.notes {
+        Some(path) => match load_notes(path) {
+            Ok(notes) => {
+                notes
+                    .keys()
+                    .filter(|name| graph.node_weights().all(|n| &n.name != *name))
+                    .for_each(|name| warn!("{:?} has a note for unknown node {}", path, name));
+                notes
+            }
+            Err(e) => {
+                error!("failed to read notes {:?}: {}", path, e);
+                HashMap::new()
+            }
+        },
+        None => HashMap::new(),
+    };
+    let mut sink = Composite(
+        options
+            .render
+            .iter()
+            .filter_map(|format| -> Option<Box<dyn RouteSink + 'g>> {
+                match format {
+                    RenderFormat::Png | RenderFormat::Svg => Some(Box::new(
+                        Graphviz::new(
+                            FOLDER,
+                            *format,
+                            options.palette,
+                            options.render_labels.then_some(graph),
+                            options.render_diff,
+                        )
+                        .with_layout(layout.clone())
+                        .with_notes(notes.clone())
+                        .with_pages(options.render_page_rooms),
+                    )),
+                    RenderFormat::Jsonl => match JsonlEvents::new(Path::new(FOLDER)) {
+                        Ok(events) => Some(Box::new(events)),
+                        Err(e) => {
+                            error!("failed to create render events in {:?}: {}", FOLDER, e);
+                            None
+                        }
+                    },
+                }
+            })
+            .collect(),
+    );
+    if options.render_map {
+        sink.render_map("map", graph);
+    }
+    Box::new(Dedup::new(Box::new(sink)))
+}
+
+/// one render per step of the route into the folder, in the order the route takes them, each cropped to the room
+/// the route is in and showing the transition it takes next, like a slideshow for route tutorials.
+/// made in the first `--render` format that graphviz makes, or png
+pub fn render_steps(
+    graph: &StableGraph<Node, Edge>,
+    route: &Route,
+    folder: &Path,
+    options: &Options,
+) {
+    if let Err(e) = clear_folder(folder) {
+        error!("failed to setup rendering steps into {:?}: {}", folder, e);
+        return;
+    }
+    let format = options
+        .render
+        .iter()
+        .copied()
+        .find(|format| *format != RenderFormat::Jsonl)
+        .unwrap_or(RenderFormat::Png);
+    let layout = options
+        .layout_cache
+        .as_deref()
+        .and_then(|path| Layout::load_or_seed(path, graph));
+    let mut sink = Graphviz::new(
+        folder,
+        format,
+        options.palette,
+        options.render_labels.then_some(graph),
+        false,
+    )
+    .with_layout(layout);
+    let values = route_graph(graph, route);
+    let (first, last) = match (route.edges.first(), route.edges.last()) {
+        (Some(first), Some(last)) => (
+            graph.edge_endpoints(*first).unwrap().0,
+            graph.edge_endpoints(*last).unwrap().1,
+        ),
+        _ => return,
+    };
+    route
+        .edges
+        .iter()
+        .map(|e| (graph.edge_endpoints(*e).unwrap().0, Some(*e)))
+        .chain([(last, None)])
+        .enumerate()
+        .for_each(|(i, (focus, next))| {
+            let name = format!("{:03}-{}", i + 1, room(&graph[focus].name));
+            sink.render_focus(&name, &values, first, last, focus, next);
+        });
+    info!("rendered {} steps into {:?}", route.edges.len() + 1, folder);
+}
+
+/// short notes by node name, like how to line up a jump, for printing with the final route
+fn load_notes(path: &Path) -> io::Result<HashMap<String, String>> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+fn clear_folder(folder: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(folder) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        e => return e,
+    };
+    fs::create_dir_all(folder)?;
+    Ok(())
+}
+
+pub struct NoRender;
+impl RouteSink for NoRender {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    fn render(&mut self, _: &str, _: &StableGraph<&Node, f64>, _: NodeIndex, _: NodeIndex) {}
+}
+
+/// values closer than this are the same when deciding if a render would look like the previous one
+const DEDUP_QUANTUM: f64 = 1e-4;
+
+/// skips progress renders whose values are the same as the previous render's, like after a cut
+/// that didn't change which edges the relaxation uses, and says how many it skipped once it is dropped.
+/// the final render is never skipped
+pub struct Dedup<'g> {
+    inner: Box<dyn RouteSink + 'g>,
+    /// of the values of the last render
+    previous: Option<u64>,
+    skipped: usize,
+}
+impl<'g> Dedup<'g> {
+    pub fn new(inner: Box<dyn RouteSink + 'g>) -> Self {
+        Dedup {
+            inner,
+            previous: None,
+            skipped: 0,
+        }
+    }
+
+    /// whether the values differ from the previous render, which they then replace
+    fn changed(&mut self, values: &StableGraph<&Node, f64>) -> bool {
+        let hash = values_hash(values);
+        if self.previous == Some(hash) {
+            self.skipped += 1;
+            false
+        } else {
+            self.previous = Some(hash);
+            true
+        }
+    }
+}
+impl<'g> RouteSink for Dedup<'g> {
+    fn enabled(&self) -> bool {
+        self.inner.enabled()
+    }
+
+    fn render(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        if self.changed(values) {
+            self.inner.render(name, values, first, last);
+        }
+    }
+
+    fn render_cut(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+        cut: &Cut,
+    ) {
+        if self.changed(values) {
+            self.inner.render_cut(name, values, first, last, cut);
+        }
+    }
+
+    fn render_final(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        self.previous = Some(values_hash(values));
+        self.inner.render_final(name, values, first, last);
+    }
+
+    fn render_map(&mut self, name: &str, graph: &StableGraph<Node, Edge>) {
+        self.inner.render_map(name, graph);
+    }
+}
+/// of which edges have a value and what it is, rounded to `DEDUP_QUANTUM`
+fn values_hash(values: &StableGraph<&Node, f64>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    values
+        .edge_references()
+        .map(|e| (e.id(), (*e.weight() / DEDUP_QUANTUM).round() as i64))
+        .filter(|(_, value)| *value != 0)
+        .for_each(|edge| edge.hash(&mut hasher));
+    hasher.finish()
+}
+
+impl Drop for Dedup<'_> {
+    fn drop(&mut self) {
+        if self.skipped > 0 {
+            info!(
+                "skipped {} renders that were the same as the one before",
+                self.skipped
+            );
+        }
+    }
+}
+
+/// forwards every render to each enabled sink
+pub struct Composite<'g>(pub Vec<Box<dyn RouteSink + 'g>>);
+impl<'g> RouteSink for Composite<'g> {
+    fn enabled(&self) -> bool {
+        self.0.iter().any(|sink| sink.enabled())
+    }
+
+    fn render(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        self.0
+            .iter_mut()
+            .filter(|sink| sink.enabled())
+            .for_each(|sink| sink.render(name, values, first, last));
+    }
+
+    fn render_cut(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+        cut: &Cut,
+    ) {
+        self.0
+            .iter_mut()
+            .filter(|sink| sink.enabled())
+            .for_each(|sink| sink.render_cut(name, values, first, last, cut));
+    }
+
+    fn render_final(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        self.0
+            .iter_mut()
+            .filter(|sink| sink.enabled())
+            .for_each(|sink| sink.render_final(name, values, first, last));
+    }
+
+    fn render_map(&mut self, name: &str, graph: &StableGraph<Node, Edge>) {
+        self.0
+            .iter_mut()
+            .filter(|sink| sink.enabled())
+            .for_each(|sink| sink.render_map(name, graph));
+    }
+}
+
+/// a lazy cut as it is added: the nodes connected to the first node, which are short of the required bits,
+/// and every edge leaving them, at least one of which the cut requires
+pub struct Cut {
+    connected: FixedBitSet,
+    /// edge, source, and target
+    crossing: Vec<(EdgeIndex, NodeIndex, NodeIndex)>,
+}
+impl Cut {
+    pub fn new(graph: &StableGraph<Node, Edge>, connected: &FixedBitSet) -> Self {
+        Cut {
+            connected: connected.clone(),
+            crossing: connected
+                .ones()
+                .map(NodeIndex::new)
+                .flat_map(|n| graph.edges_directed(n, Outgoing))
+                .filter(|e| !connected.contains(e.target().index()))
+                .map(|e| (e.id(), e.source(), e.target()))
+                .collect(),
+        }
+    }
+}
+
+/// what is drawn on top of the values
+#[derive(Copy, Clone)]
+enum Overlay<'a> {
+    Nothing,
+    Cut(&'a Cut),
+    Notes,
+    /// cropped to the room of the node the route is at, with the transition it takes next
+    Focus(NodeIndex, Option<EdgeIndex>),
+    /// cropped to the rooms of one page, with notes and where it continues from and on
+    Page(&'a [Page], usize),
+    /// everything with notes, and where each page starts
+    Pages(&'a [Page]),
+}
+
+/// consecutive rooms of the route rendered as one image
+pub(crate) struct Page {
+    /// in the order the path visits them. the first is where the previous page ended,
+    /// and the last is where the next page starts, which is the only node drawn from its room
+    nodes: Vec<NodeIndex>,
+}
+
+/// the heuristic path split into pages that each visit at most `rooms` rooms, counting a room again when the path
+/// comes back to it. always at least one page, even if there is no path
+pub(crate) fn pages(
+    values: &StableGraph<&Node, f64>,
+    first: NodeIndex,
+    last: NodeIndex,
+    rooms: usize,
+) -> Vec<Page> {
+    let mut pages = vec![Page { nodes: vec![first] }];
+    let mut visited = 1;
+    heuristic_path(values, first, last)
+        .into_iter()
+        .for_each(|e| {
+            let page = pages.last_mut().unwrap();
+            page.nodes.push(e.target());
+            if room(&values[e.source()].name) != room(&values[e.target()].name) {
+                visited += 1;
+                if visited > rooms {
+                    pages.push(Page {
+                        nodes: vec![e.target()],
+                    });
+                    visited = 1;
+                }
+            }
+        });
+    if pages.len() > 1 && pages.last().unwrap().nodes.len() == 1 {
+        pages.pop();
+    }
+    pages
+}
+
+/// one image per render, made by piping into fdp
+pub struct Graphviz<'g> {
     folder: PathBuf,
+    format: RenderFormat,
+    palette: Palette,
+    /// when set, nodes are labeled with what they collect and edges with how many frames they take
+    labels: Option<&'g StableGraph<Node, Edge>>,
+    /// when diffing, the source, target, and value of each edge in the previous render
+    previous: Option<HashMap<EdgeIndex, (NodeIndex, NodeIndex, f64)>>,
+    /// where nodes are pinned, so every render lines up
+    layout: Option<Layout>,
+    /// drawn next to their nodes in the final render
+    notes: HashMap<String, String>,
+    /// rooms per page of the final render, or 0 to only render it whole
+    page_rooms: usize,
 }
-impl Renderer {
-    pub fn new(folder: impl Into<PathBuf>) -> Option<Self> {
-        let folder = folder.into();
-        if let Err(e) = Renderer::try_init(&folder) {
-            error!("failed to setup rendering into {:?}: {}", folder, e);
-            return None;
+impl<'g> Graphviz<'g> {
+    pub fn new(
+        folder: impl Into<PathBuf>,
+        format: RenderFormat,
+        palette: Palette,
+        labels: Option<&'g StableGraph<Node, Edge>>,
+        diff: bool,
+    ) -> Self {
+        Graphviz {
+            folder: folder.into(),
+            format,
+            palette,
+            labels,
+            previous: diff.then(HashMap::new),
+            layout: None,
+            notes: HashMap::new(),
+            page_rooms: 0,
         }
-        Some(Renderer { folder })
     }
 
-    fn try_init(folder: &Path) -> io::Result<()> {
-        match fs::remove_dir_all(folder) {
-            Ok(()) => {}
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
-            e => return e,
+    pub fn with_layout(mut self, layout: Option<Layout>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_notes(mut self, notes: HashMap<String, String>) -> Self {
+        self.notes = notes;
+        self
+    }
+
+    pub fn with_pages(mut self, page_rooms: usize) -> Self {
+        self.page_rooms = page_rooms;
+        self
+    }
+}
+impl<'g> RouteSink for Graphviz<'g> {
+    fn render(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        let dot = self.dot(values, first, last, Overlay::Nothing);
+        self.write(name, &dot);
+    }
+
+    fn render_cut(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+        cut: &Cut,
+    ) {
+        let dot = self.dot(values, first, last, Overlay::Cut(cut));
+        self.write(name, &dot);
+    }
+
+    fn render_final(
+        &mut self,
+        name: &str,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+    ) {
+        let pages = match self.page_rooms {
+            0 => Vec::new(),
+            rooms => pages(values, first, last, rooms),
         };
-        fs::create_dir_all(folder)?;
-        Ok(())
+        if pages.len() <= 1 {
+            let dot = self.dot(values, first, last, Overlay::Notes);
+            self.write(name, &dot);
+            return;
+        }
+        // every page is diffed against the same previous render
+        let previous = self.previous.clone();
+        let dot = self.dot(values, first, last, Overlay::Pages(&pages));
+        self.write(name, &dot);
+        (0..pages.len()).for_each(|k| {
+            self.previous.clone_from(&previous);
+            let dot = self.dot(values, first, last, Overlay::Page(&pages, k));
+            self.write(&format!("{}-page-{:02}", name, k + 1), &dot);
+        });
     }
 
-    pub fn render(
-        &self,
-        filename: String,
+    fn render_map(&mut self, name: &str, graph: &StableGraph<Node, Edge>) {
+        let dot = map_dot(
+            graph,
+            self.palette,
+            self.labels.is_some(),
+            self.layout.as_ref(),
+        );
+        self.write(name, &dot);
+    }
+}
+impl<'g> Graphviz<'g> {
+    /// one step of a route: only the room of `focus`, with it and where `next` leads outlined and `next` drawn heavy
+    pub fn render_focus(
+        &mut self,
+        name: &str,
         values: &StableGraph<&Node, f64>,
         first: NodeIndex,
         last: NodeIndex,
+        focus: NodeIndex,
+        next: Option<EdgeIndex>,
     ) {
+        let dot = self.dot(values, first, last, Overlay::Focus(focus, next));
+        self.write(name, &dot);
+    }
+
+    fn write(&self, name: &str, dot: &[u8]) {
+        let ext = self.format.ext();
+        let path = self.folder.join(format!("{}.{}", name, ext));
+        if let Err(e) = try_render(&path, ext, dot) {
+            error!("failed to generate graphviz at {:?}: {}", path, e);
+        }
+    }
+
+    /// what is piped into fdp, which also moves the diff on to this render
+    fn dot(
+        &mut self,
+        values: &StableGraph<&Node, f64>,
+        first: NodeIndex,
+        last: NodeIndex,
+        overlay: Overlay,
+    ) -> Vec<u8> {
+        let cut = match overlay {
+            Overlay::Cut(cut) => Some(cut),
+            _ => None,
+        };
         let heuristic: HashSet<_> = heuristic_path(values, first, last)
             .into_iter()
             .map(|e| e.id())
             .collect();
 
-        let graph = values.filter_map(
+        // each edge keeps its index in the full graph, and when diffing also gets its previous value.
+        // edges that vanished since the previous render are added back so they can be drawn too
+        let mut diffed = values.map(
+            |_, &n| n,
+            |i, &e| {
+                let previous = self
+                    .previous
+                    .as_ref()
+                    .map(|previous| previous.get(&i).map_or(0.0, |p| p.2));
+                (i, e, previous)
+            },
+        );
+        if let Some(previous) = &self.previous {
+            previous
+                .iter()
+                .filter(|(i, _)| values.edge_weight(**i).is_none())
+                .for_each(|(i, (source, target, value))| {
+                    diffed.add_edge(*source, *target, (*i, 0.0, Some(*value)));
+                });
+        }
+        // a cut is explained by the edges it requires one of, which all have no value yet
+        if let Some(cut) = cut {
+            let drawn: HashSet<EdgeIndex> = diffed.edge_weights().map(|e| e.0).collect();
+            cut.crossing
+                .iter()
+                .filter(|(i, _, _)| !drawn.contains(i))
+                .for_each(|(i, source, target)| {
+                    let previous = self.previous.as_ref().map(|_| 0.0);
+                    diffed.add_edge(*source, *target, (*i, 0.0, previous));
+                });
+        }
+        let cut_colors = cut_colors(self.palette);
+        let focus = match overlay {
+            Overlay::Focus(node, next) => {
+                let next = next.map(|next| (next, values.edge_endpoints(next).unwrap().1));
+                Some((node, room(&values[node].name), next))
+            }
+            _ => None,
+        };
+        let page = match overlay {
+            Overlay::Page(pages, k) => {
+                let nodes = &pages[k].nodes;
+                let continues = k + 1 < pages.len();
+                let rooms: HashSet<&str> = nodes[..nodes.len() - continues as usize]
+                    .iter()
+                    .map(|n| room(&values[*n].name))
+                    .collect();
+                Some((rooms, continues.then(|| *nodes.last().unwrap())))
+            }
+            _ => None,
+        };
+        let marker = |i: NodeIndex| match overlay {
+            Overlay::Page(pages, k) => {
+                if k > 0 && pages[k].nodes[0] == i {
+                    Some(format!("continued from page {}", k))
+                } else if k + 1 < pages.len() && pages[k].nodes.last() == Some(&i) {
+                    Some(format!("continues on page {}", k + 2))
+                } else {
+                    None
+                }
+            }
+            Overlay::Pages(pages) => pages
+                .iter()
+                .position(|page| page.nodes[0] == i)
+                .map(|k| format!("page {}", k + 1)),
+            _ => None,
+        };
+
+        let graph = diffed.filter_map(
             |i, &n| {
+                if let Some((node, focus_room, next)) = focus {
+                    let target = next.map(|(_, target)| target);
+                    return (room(&n.name) == focus_room || target == Some(i)).then(|| DotNode {
+                        name: n.name.as_str(),
+                        zone: n.zone.as_str(),
+                        badges: self.labels.map(|_| badges(n)).unwrap_or_default(),
+                        outline: (i == node || target == Some(i)).then(|| color(self.palette, 0.5)),
+                        note: None,
+                    });
+                }
+                if let Some((rooms, end)) = &page {
+                    if !rooms.contains(room(&n.name)) && *end != Some(i) {
+                        return None;
+                    }
+                }
                 if IntoIterator::into_iter([
-                    values.edges_directed(i, Outgoing),
-                    values.edges_directed(i, Incoming),
+                    diffed.edges_directed(i, Outgoing),
+                    diffed.edges_directed(i, Incoming),
                 ])
                 .any(|mut iter| iter.next().is_some())
                 {
-                    Some(n.name.as_str())
+                    let marker = marker(i);
+                    let outline = cut
+                        .and_then(|cut| {
+                            if cut.connected.contains(i.index()) {
+                                Some(cut_colors.connected)
+                            } else if values.neighbors_undirected(i).next().is_some() {
+                                Some(cut_colors.disconnected)
+                            } else {
+                                None
+                            }
+                        })
+                        .or_else(|| marker.is_some().then(|| color(self.palette, 0.5)));
+                    let note = match overlay {
+                        Overlay::Notes | Overlay::Page(..) | Overlay::Pages(_) => {
+                            self.notes.get(&n.name).cloned()
+                        }
+                        _ => None,
+                    };
+                    Some(DotNode {
+                        name: n.name.as_str(),
+                        zone: n.zone.as_str(),
+                        badges: self.labels.map(|_| badges(n)).unwrap_or_default(),
+                        outline,
+                        note: marker.into_iter().chain(note).reduce(|a, b| a + ", " + &b),
+                    })
                 } else {
                     None
                 }
             },
-            |i, &e| Some((color(e), heuristic.contains(&i))),
+            |_, &(i, e, previous)| {
+                let label = self
+                    .labels
+                    .map(|graph| {
+                        let target = graph.edge_endpoints(i).unwrap().1;
+                        format!("{:.0}", graph[i].time + graph[target].time)
+                    })
+                    .unwrap_or_default();
+                let crossing = cut.is_some_and(|cut| cut.crossing.iter().any(|c| c.0 == i));
+                let color = match previous {
+                    _ if crossing => cut_colors.crossing,
+                    Some(previous) => diff_color(self.palette, previous, e),
+                    None => color(self.palette, e),
+                };
+                let heavy = match focus {
+                    Some((_, _, next)) => next.is_some_and(|(next, _)| next == i),
+                    None => heuristic.contains(&i),
+                };
+                Some((color, heavy, label))
+            },
         );
+        if let Some(previous) = &mut self.previous {
+            *previous = values
+                .edge_references()
+                .map(|e| (e.id(), (e.source(), e.target(), *e.weight())))
+                .collect();
+        }
 
-        let path = self.folder.join(filename);
-        if let Err(e) = try_render(&path, &graph) {
-            error!("failed to generate graphviz at {:?}: {}", path, e);
+        let mut dot = Vec::new();
+        write_dot(&mut dot, &graph, self.layout.as_ref()).expect("writing to memory can't fail");
+        dot
+    }
+}
+
+/// every node and edge, labeled the same way as values are
+pub(crate) fn map_dot(
+    graph: &StableGraph<Node, Edge>,
+    palette: Palette,
+    labels: bool,
+    layout: Option<&Layout>,
+) -> Vec<u8> {
+    let dot_graph = graph.map(
+        |_, n| {
+            let color = (n.cost != Cost::Free).then(|| door_color(palette, n.cost));
+            let badges = if labels { badges(n) } else { String::new() };
+            DotNode {
+                name: n.name.as_str(),
+                zone: n.zone.as_str(),
+                badges,
+                outline: color,
+                note: None,
+            }
+        },
+        |i, e| {
+            let label = if labels {
+                let target = graph.edge_endpoints(i).unwrap().1;
+                format!("{:.0}", e.time + graph[target].time)
+            } else {
+                String::new()
+            };
+            ((0xff, 0xff, 0xff), false, label)
+        },
+    );
+    let mut dot = Vec::new();
+    write_dot(&mut dot, &dot_graph, layout).expect("writing to memory can't fail");
+    dot
+}
+
+/// a single render, as written by `JsonlEvents` and kept by `Memory`
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub name: String,
+    /// source name, target name, and value of each edge with a non zero value
+    pub edges: Vec<(String, String, f64)>,
+}
+impl Event {
+    fn new(name: &str, values: &StableGraph<&Node, f64>) -> Self {
+        Event {
+            name: name.to_owned(),
+            edges: values
+                .edge_references()
+                .filter(|e| *e.weight() > 0.0)
+                .map(|e| {
+                    (
+                        values[e.source()].name.clone(),
+                        values[e.target()].name.clone(),
+                        *e.weight(),
+                    )
+                })
+                .collect(),
         }
     }
 }
 
+/// every render appended to a single `events.jsonl`, for consumers that want the values rather than an image
+pub struct JsonlEvents {
+    path: PathBuf,
+    file: File,
+}
+impl JsonlEvents {
+    pub fn new(folder: &Path) -> io::Result<Self> {
+        let path = folder.join("events.jsonl");
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(JsonlEvents { path, file })
+    }
+}
+impl RouteSink for JsonlEvents {
+    fn render(&mut self, name: &str, values: &StableGraph<&Node, f64>, _: NodeIndex, _: NodeIndex) {
+        let event = Event::new(name, values);
+        let result = serde_json::to_string(&event)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file, "{}", line));
+        if let Err(e) = result {
+            error!("failed to write render event to {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// keeps every render, so tests can check what would have been rendered
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Memory(pub std::rc::Rc<std::cell::RefCell<Vec<Event>>>);
+#[cfg(test)]
+impl RouteSink for Memory {
+    fn render(&mut self, name: &str, values: &StableGraph<&Node, f64>, _: NodeIndex, _: NodeIndex) {
+        self.0.borrow_mut().push(Event::new(name, values));
+    }
+}
+
 type ColorF = (f64, f64, f64);
 type ColorU = (u8, u8, u8);
+/// edge color, whether it is on the heuristic path, and label
+type DotGraph<'a> = StableGraph<DotNode<'a>, (ColorU, bool, String)>;
+
+struct DotNode<'a> {
+    name: &'a str,
+    /// rooms of the same zone are nested in a cluster for it
+    zone: &'a str,
+    /// bits and keys, when labeling
+    badges: String,
+    outline: Option<ColorU>,
+    /// placed outside the node, so it doesn't change the layout
+    note: Option<String>,
+}
+
+fn color(palette: Palette, value: f64) -> ColorU {
+    as_bytes(color_scale(palette, value))
+}
+
+/// green if the edge appeared, red if it vanished, cyan if it increased,
+/// magenta if it decreased, and gray if it didn't change.
+/// other palettes use the ends of their scale instead, so appearing and vanishing differ in lightness
+fn diff_color(palette: Palette, previous: f64, current: f64) -> ColorU {
+    let colors = match palette {
+        Palette::Classic => [
+            (0x00, 0xff, 0x00),
+            (0xff, 0x00, 0x00),
+            (0x00, 0xff, 0xff),
+            (0xff, 0x00, 0xff),
+        ],
+        _ => [
+            color(palette, 1.0),
+            color(palette, 0.0),
+            color(palette, 0.6),
+            color(palette, 0.2),
+        ],
+    };
+    if previous <= EPS && current > EPS {
+        colors[0]
+    } else if previous > EPS && current <= EPS {
+        colors[1]
+    } else if current - previous > EPS {
+        colors[2]
+    } else if previous - current > EPS {
+        colors[3]
+    } else {
+        (0x60, 0x60, 0x60)
+    }
+}
+
+struct CutColors {
+    connected: ColorU,
+    disconnected: ColorU,
+    crossing: ColorU,
+}
+
+/// the two sides of the cut from far apart on the palette's scale,
+/// and white for the crossing edges, which have no value to be colored by
+fn cut_colors(palette: Palette) -> CutColors {
+    CutColors {
+        connected: color(palette, 1.0),
+        disconnected: color(palette, 0.2),
+        crossing: (0xff, 0xff, 0xff),
+    }
+}
+
+/// what it takes to go through a door, white if it is free
+pub fn door_color(palette: Palette, cost: Cost) -> ColorU {
+    match (palette, cost) {
+        (_, Cost::Free) => (0xff, 0xff, 0xff),
+        (Palette::Classic, Cost::Lock) => (0xff, 0xff, 0x00),
+        (Palette::Classic, Cost::Water) => (0x00, 0x00, 0xff),
+        (Palette::Classic, Cost::Secret) => (0xff, 0x00, 0x00),
+        (palette, Cost::Lock) => color(palette, 1.0),
+        (palette, Cost::Water) => color(palette, 0.2),
+        (palette, Cost::Secret) => color(palette, 0.5),
+    }
+}
+
+/// the room part of a node's name, which the node is clustered by
+fn room(name: &str) -> &str {
+    name.split('.').next().unwrap()
+}
 
-fn color(value: f64) -> ColorU {
-    as_bytes(color_scale(value))
+/// bits and keys collected at a node, shown under its name
+fn badges(node: &Node) -> String {
+    let mut badges = Vec::new();
+    if node.bits != Bits(0) {
+        badges.push(format!("{}b", node.bits));
+    }
+    if node.keys != 0 {
+        badges.push(format!("{}k", node.keys));
+    }
+    badges.join(" ")
 }
 
-fn try_render(path: &Path, graph: &StableGraph<&str, (ColorU, bool)>) -> io::Result<()> {
+/// fdp writes next to the render and the result is only moved into place once it has finished,
+/// so a run killed mid render leaves a stray temp file instead of a corrupt image
+fn try_render(path: &Path, ext: &str, dot: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let result = run_fdp(&temp, ext, dot).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        // fdp may not have gotten far enough to make it
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn run_fdp(path: &Path, ext: &str, dot: &[u8]) -> io::Result<()> {
     let mut child = Command::new("fdp")
         .arg("-T")
-        .arg(EXT)
+        .arg(ext)
         .arg("-o")
         .arg(path)
         .stdin(Stdio::piped())
         .spawn()?;
-    let mut output = child.stdin.as_ref().unwrap();
-    // let mut output = fs::File::create(path)?;
+    // taken so stdin is closed before waiting, so fdp sees the end of the graph.
+    // fdp is waited for even if it stopped reading, so it is never left a zombie
+    let written = child.stdin.take().unwrap().write_all(dot);
+    let status = wait_timeout(&mut child, FDP_TIMEOUT)?;
+    written?;
+    match status {
+        Some(status) if status.success() => Ok(()),
+        Some(status) => Err(io::Error::other(format!("fdp exited with {}", status))),
+        None => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("fdp took longer than {:?} and was killed", FDP_TIMEOUT),
+        )),
+    }
+}
+
+/// the exit status, or none if the child took too long and was killed
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            // killing fails if it just exited, which wait still reaps
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(FDP_POLL);
+    }
+}
 
+/// nodes are grouped into a cluster per room, nested in a cluster per zone unless the room is its own zone,
+/// only outlined in a color if they have one, and pinned where the layout put them
+fn write_dot(output: &mut impl Write, graph: &DotGraph, layout: Option<&Layout>) -> io::Result<()> {
     // TODO black background with white lines
     writeln!(output, "strict digraph {{")?;
     writeln!(
@@ -140,40 +1075,354 @@ fn try_render(path: &Path, graph: &StableGraph<&str, (ColorU, bool)>) -> io::Res
     )?;
     writeln!(output, "  node [ color = \"white\" fontcolor = \"white\" ]")?;
     writeln!(output, "  edge [ penwidth = 2 ]")?;
+    // notes are only worth printing if none of them are left out
+    if graph.node_weights().any(|n| n.note.is_some()) {
+        writeln!(output, "  graph [ forcelabels = true ]")?;
+    }
 
     graph
-        .node_references()
-        .sorted_by_key(|n| *n.weight())
-        .group_by(|n| n.weight().split('.').next().unwrap())
+        .node_weights()
+        .sorted_by_key(|n| (n.zone, n.name))
+        .group_by(|n| n.zone)
         .into_iter()
-        .try_for_each(|(k, mut g)| {
-            writeln!(output, "  subgraph \"cluster-{}\" {{", k)?;
-            writeln!(output, "    label = \"{}\"", k)?;
-            g.try_for_each(|(_, &n)| {
-                writeln!(
-                    output,
-                    "    \"{}\" [ label = \"{}\" ];",
-                    n,
-                    &n[(k.len() + 1)..]
-                )
-            })?;
-            writeln!(output, "  }}")
+        .try_for_each(|(zone, rooms)| {
+            let rooms: Vec<&DotNode> = rooms.collect();
+            let nested = rooms.iter().any(|n| room(n.name) != zone);
+            let indent = if nested { "    " } else { "  " };
+            if nested {
+                writeln!(output, "  subgraph \"cluster-zone-{}\" {{", zone)?;
+                writeln!(output, "    label = \"{}\"", zone)?;
+            }
+            rooms
+                .into_iter()
+                .group_by(|n| room(n.name))
+                .into_iter()
+                .try_for_each(|(k, mut g)| {
+                    writeln!(output, "{}subgraph \"cluster-{}\" {{", indent, k)?;
+                    writeln!(output, "{}  label = \"{}\"", indent, k)?;
+                    g.try_for_each(|node| {
+                        let n = node.name;
+                        let name = &n[(k.len() + 1)..];
+                        let label = if node.badges.is_empty() {
+                            name.to_owned()
+                        } else {
+                            format!("{}\\n{}", name, node.badges)
+                        };
+                        let mut attrs = format!("label = \"{}\"", label);
+                        if let Some((r, g, b)) = node.outline {
+                            attrs += &format!(" color = \"#{:02x}{:02x}{:02x}\"", r, g, b);
+                        }
+                        if let Some((x, y)) = layout.and_then(|layout| layout.position(n)) {
+                            attrs += &format!(" pos = \"{:.0},{:.0}!\"", x, y);
+                        }
+                        if let Some(note) = &node.note {
+                            attrs += &format!(" xlabel = \"{}\"", note.replace('"', "\\\""));
+                        }
+                        writeln!(output, "{}  \"{}\" [ {} ];", indent, n, attrs)
+                    })?;
+                    writeln!(output, "{}}}", indent)
+                })?;
+            if nested {
+                writeln!(output, "  }}")?;
+            }
+            io::Result::Ok(())
         })?;
 
     graph
         .edge_references()
-        .map(|e| (graph[e.source()], graph[e.target()], *e.weight()))
-        .try_for_each(|(s, t, ((r, g, b), h))| {
-            let w = if h { "3" } else { "1" };
+        .map(|e| (graph[e.source()].name, graph[e.target()].name, e.weight()))
+        .try_for_each(|(s, t, ((r, g, b), h, label))| {
+            let w = if *h { "3" } else { "1" };
             writeln!(
                 output,
-                "  \"{}\" -> \"{}\" [ color = \"#{:02x}{:02x}{:02x}\" penwidth = {}];",
-                s, t, r, g, b, w
+                "  \"{}\" -> \"{}\" [ color = \"#{:02x}{:02x}{:02x}\" penwidth = {} label = \"{}\" ];",
+                s, t, r, g, b, w, label
             )
         })?;
 
-    writeln!(output, "}}")?;
+    writeln!(output, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Frames;
 
-    child.wait()?;
-    Ok(())
+    /// in a zone of its own room, like rooms without one are
+    fn node(name: &str, bits: i32, keys: i32) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            keys,
+            zone: room(name).to_owned(),
+            time: Frames(12.0),
+            ..Default::default()
+        }
+    }
+
+    /// two rooms, with a path of whole edges through them and a few fractional edges off it
+    fn fixture() -> (StableGraph<Node, Edge>, Vec<f64>) {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", 0, 0));
+        let key = graph.add_node(node("a.key", 0, 1));
+        let door = graph.add_node(node("b.a", 0, 0));
+        let cube = graph.add_node(node("b.cube", 8, 0));
+        let end = graph.add_node(node("b.end", 0, 0));
+        let time = Frames(24.0);
+        graph.add_edge(start, key, Edge { time });
+        graph.add_edge(key, door, Edge { time });
+        graph.add_edge(start, door, Edge { time });
+        graph.add_edge(door, cube, Edge { time });
+        graph.add_edge(cube, end, Edge { time });
+        graph.add_edge(door, end, Edge { time });
+        (graph, vec![1.0, 1.0, 0.0, 0.75, 0.75, 0.25])
+    }
+
+    fn values<'g>(
+        graph: &'g StableGraph<Node, Edge>,
+        values: &[f64],
+    ) -> StableGraph<&'g Node, f64> {
+        graph.map(|_, n| n, |e, _| values[e.index()])
+    }
+
+    /// compares against a checked in file, or replaces it when `BLESS` is set
+    fn assert_golden(name: &str, actual: &[u8]) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name);
+        if std::env::var_os("BLESS").is_some() {
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read(&path).unwrap_or_else(|e| {
+            panic!(
+                "failed to read {:?}, run with BLESS=1 to create it: {}",
+                path, e
+            )
+        });
+        assert!(
+            expected == actual,
+            "{:?} changed, run with BLESS=1 if that was intended:\n{}",
+            path,
+            String::from_utf8_lossy(actual)
+        );
+    }
+
+    #[test]
+    fn value_graph_dot() {
+        let (graph, edges) = fixture();
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Classic, Some(&graph), false);
+        let (first, last) = crate::common::endpoints(&graph);
+        let dot = sink.dot(&values(&graph, &edges), first, last, Overlay::Nothing);
+        assert_golden("value_graph.dot", &dot);
+    }
+
+    #[test]
+    fn diff_dot() {
+        let (graph, edges) = fixture();
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Classic, None, true);
+        let (first, last) = crate::common::endpoints(&graph);
+        sink.dot(&values(&graph, &edges), first, last, Overlay::Nothing);
+        let dot = sink.dot(
+            &values(&graph, &[1.0, 0.5, 0.0, 1.0, 0.0, 1.0]),
+            first,
+            last,
+            Overlay::Nothing,
+        );
+        assert_golden("diff.dot", &dot);
+    }
+
+    #[test]
+    fn pages_split_between_rooms() {
+        let (graph, edges) = fixture();
+        let values = values(&graph, &edges);
+        let (first, last) = crate::common::endpoints(&graph);
+        let names = |pages: &[Page]| -> Vec<Vec<&str>> {
+            pages
+                .iter()
+                .map(|page| {
+                    page.nodes
+                        .iter()
+                        .map(|n| values[*n].name.as_str())
+                        .collect()
+                })
+                .collect()
+        };
+        assert_eq!(
+            names(&pages(&values, first, last, 1)),
+            [
+                vec!["a.start", "a.key", "b.a"],
+                vec!["b.a", "b.cube", "b.end"]
+            ]
+        );
+        assert_eq!(pages(&values, first, last, 2).len(), 1);
+
+        let pages = pages(&values, first, last, 1);
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Classic, None, false);
+        let dot = sink.dot(&values, first, last, Overlay::Page(&pages, 0));
+        assert_golden("page.dot", &dot);
+    }
+
+    #[test]
+    fn viridis_dot() {
+        let (graph, edges) = fixture();
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Viridis, None, false);
+        let (first, last) = crate::common::endpoints(&graph);
+        let dot = sink.dot(&values(&graph, &edges), first, last, Overlay::Nothing);
+        assert_golden("viridis.dot", &dot);
+    }
+
+    #[test]
+    fn cut_dot() {
+        let (graph, _) = fixture();
+        // only the key is connected to the start, and the rest of the values are a separate component
+        let edges = [1.0, 0.0, 0.0, 0.0, 0.75, 0.25];
+        let values = graph.filter_map(
+            |_, n| Some(n),
+            |e, _| Some(edges[e.index()]).filter(|v| *v > 0.0),
+        );
+        let (first, last) = crate::common::endpoints(&graph);
+        let mut connected = FixedBitSet::with_capacity(graph.node_count());
+        connected.extend([first.index(), 1]);
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Classic, None, false);
+        let dot = sink.dot(
+            &values,
+            first,
+            last,
+            Overlay::Cut(&Cut::new(&graph, &connected)),
+        );
+        assert_golden("cut.dot", &dot);
+    }
+
+    #[test]
+    fn notes_dot() {
+        let (graph, edges) = fixture();
+        let notes = [
+            ("a.key", "align camera \"left\""),
+            ("b.cube", "buffer jump"),
+            ("b.unused", "not in the graph"),
+        ];
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Classic, None, false)
+            .with_notes(
+                notes
+                    .iter()
+                    .map(|(n, s)| (n.to_string(), s.to_string()))
+                    .collect(),
+            );
+        let (first, last) = crate::common::endpoints(&graph);
+        let dot = sink.dot(&values(&graph, &edges), first, last, Overlay::Notes);
+        assert_golden("notes.dot", &dot);
+    }
+
+    #[test]
+    fn focus_dot() {
+        let (graph, _) = fixture();
+        let (first, last) = crate::common::endpoints(&graph);
+        let route = Route::from_path(
+            &graph,
+            graph
+                .edge_indices()
+                .filter(|e| [0, 1, 3, 4].contains(&e.index()))
+                .collect(),
+            Bits(0),
+        );
+        let key = graph.edge_endpoints(route.edges[1]).unwrap().0;
+        let mut sink = Graphviz::new("", RenderFormat::Svg, Palette::Classic, Some(&graph), false);
+        let dot = sink.dot(
+            &route_graph(&graph, &route),
+            first,
+            last,
+            Overlay::Focus(key, Some(route.edges[1])),
+        );
+        assert_golden("focus.dot", &dot);
+    }
+
+    #[test]
+    fn map_outlines_doors() {
+        let (mut graph, _) = fixture();
+        let door = graph
+            .node_indices()
+            .find(|n| graph[*n].name == "b.a")
+            .unwrap();
+        graph[door].cost = Cost::Lock;
+        let dot = map_dot(&graph, Palette::Classic, true, None);
+        assert_golden("map.dot", &dot);
+    }
+
+    #[test]
+    fn rooms_nest_in_their_zone() {
+        let (mut graph, _) = fixture();
+        graph
+            .node_weights_mut()
+            .for_each(|n| n.zone = "village".to_owned());
+        let dot = map_dot(&graph, Palette::Classic, false, None);
+        assert_golden("zones.dot", &dot);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stuck_children_are_killed() {
+        let mut quick = Command::new("true").spawn().unwrap();
+        assert!(wait_timeout(&mut quick, Duration::from_secs(10))
+            .unwrap()
+            .is_some_and(|status| status.success()));
+        let mut stuck = Command::new("sleep").arg("10").spawn().unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            wait_timeout(&mut stuck, Duration::from_millis(50)).unwrap(),
+            None
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // reaped, so there is nothing left to wait for
+        assert!(stuck.try_wait().unwrap().is_some());
+    }
+
+    #[test]
+    fn composite_forwards_to_enabled_sinks() {
+        let nodes: Vec<Node> = ["a.start", "a.end"]
+            .iter()
+            .map(|name| Node {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let mut values = StableGraph::new();
+        let first = values.add_node(&nodes[0]);
+        let last = values.add_node(&nodes[1]);
+        values.add_edge(first, last, 1.0);
+        values.add_edge(last, first, 0.0);
+
+        let memory = Memory::default();
+        let mut sink = Composite(vec![Box::new(NoRender), Box::new(memory.clone())]);
+        assert!(sink.enabled());
+        sink.render("1-BEST", &values, first, last);
+
+        let events = memory.0.borrow();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "1-BEST");
+        assert_eq!(
+            events[0].edges,
+            vec![("a.start".to_owned(), "a.end".to_owned(), 1.0)]
+        );
+    }
+
+    #[test]
+    fn dedup_skips_identical_values() {
+        let (graph, edges) = fixture();
+        let (first, last) = crate::common::endpoints(&graph);
+        let memory = Memory::default();
+        let mut sink = Dedup::new(Box::new(memory.clone()));
+        sink.render("1", &values(&graph, &edges), first, last);
+        // too small a change to see
+        let mut nudged = edges.clone();
+        nudged[3] += 1e-6;
+        sink.render("2", &values(&graph, &nudged), first, last);
+        nudged[3] = 0.5;
+        sink.render("3", &values(&graph, &nudged), first, last);
+        sink.render_final("4-BEST", &values(&graph, &nudged), first, last);
+        assert_eq!(sink.skipped, 1);
+
+        let names: Vec<String> = memory.0.borrow().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["1", "3", "4-BEST"]);
+    }
 }
@@ -0,0 +1,244 @@
+use crate::common::{endpoints, long_load_edges, LONG_LOAD};
+use crate::heuristic::Route;
+use crate::options::Options;
+use crate::rooms::{Edge, Node};
+use crate::topology::{fnv1a, topology_hash, TopologyHash};
+use crate::units::{Bits, Frames};
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::iter;
+use std::path::Path;
+
+/// everything needed to reproduce a route or compare it with one found months later,
+/// written once routing finishes
+#[derive(Serialize)]
+pub struct RouteFile<'a> {
+    pub version: &'static str,
+    /// see [`rooms_hash`]
+    pub rooms_hash: Option<String>,
+    pub topology: TopologyHash,
+    /// after merging the config file with the command line
+    pub options: &'a Options,
+    /// from the first node to the last node
+    pub nodes: Vec<&'a str>,
+    pub edges: Vec<RouteEdge<'a>>,
+    /// every node the route opens a menu at, which is already part of the edge times
+    pub menu: Vec<RouteMenu<'a>>,
+    /// totals per zone, in the order the route first enters them
+    pub zones: Vec<RouteZone<'a>>,
+    /// the objective, including any difficulty penalties, expected retries, and menuing
+    pub time: Frames,
+    /// fixed frames before the first node
+    pub prologue: Frames,
+    /// the time with the prologue
+    pub total: Frames,
+    pub bits: Bits,
+    pub feasible: bool,
+    /// only for routes from the full solve
+    pub stats: Option<Stats>,
+}
+
+#[derive(Serialize)]
+pub struct RouteMenu<'a> {
+    pub node: &'a str,
+    pub time: Frames,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RouteZone<'a> {
+    pub zone: &'a str,
+    /// nodes the route reaches in the zone, counting every return to it
+    pub nodes: usize,
+    pub bits: Bits,
+    /// of the edges into the zone's nodes, long loads included
+    pub time: Frames,
+}
+
+#[derive(Serialize)]
+pub struct RouteEdge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    /// including whatever is done at `to`, and the long load of the first warp to each hub
+    pub time: Frames,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub seconds: f64,
+    pub tree_nodes: i32,
+    pub gap: Option<f64>,
+    pub lazy_cuts: usize,
+}
+
+impl<'a> RouteFile<'a> {
+    pub fn new(
+        graph: &'a StableGraph<Node, Edge>,
+        route: &Route,
+        options: &'a Options,
+        stats: Option<Stats>,
+    ) -> Self {
+        let (first_node, _) = endpoints(graph);
+        let long_loads = long_load_edges(graph, route.edges.iter().copied());
+        let edges: Vec<RouteEdge> = route
+            .edges
+            .iter()
+            .map(|e| {
+                let (source, target) = graph.edge_endpoints(*e).unwrap();
+                let long_load = if long_loads.contains(e) {
+                    LONG_LOAD
+                } else {
+                    Frames(0.0)
+                };
+                RouteEdge {
+                    from: &graph[source].name,
+                    to: &graph[target].name,
+                    time: graph[*e].time + graph[target].time + long_load,
+                }
+            })
+            .collect();
+        let menu: Vec<RouteMenu> = route
+            .edges
+            .iter()
+            .map(|e| &graph[graph.edge_endpoints(*e).unwrap().1])
+            .filter(|node| node.menu > Frames(0.0))
+            .map(|node| RouteMenu {
+                node: &node.name,
+                time: node.menu,
+            })
+            .collect();
+        if !menu.is_empty() {
+            info!(
+                "{} frames of menuing at {} nodes",
+                menu.iter().map(|m| m.time).sum::<Frames>(),
+                menu.len()
+            );
+        }
+        // the first node counts towards its zone without taking any time
+        let zones = zone_totals(
+            graph,
+            iter::once((first_node, Frames(0.0))).chain(
+                route
+                    .edges
+                    .iter()
+                    .zip(&edges)
+                    .map(|(e, edge)| (graph.edge_endpoints(*e).unwrap().1, edge.time)),
+            ),
+        );
+        zones.iter().for_each(|zone| {
+            info!(
+                "{}: {} frames, {} nodes, {} bits",
+                zone.zone, zone.time, zone.nodes, zone.bits
+            )
+        });
+        RouteFile {
+            version: env!("CARGO_PKG_VERSION"),
+            rooms_hash: rooms_hash(options),
+            topology: topology_hash(graph),
+            options,
+            nodes: iter::once(graph[first_node].name.as_str())
+                .chain(edges.iter().map(|e| e.to))
+                .collect(),
+            edges,
+            menu,
+            zones,
+            time: route.time,
+            prologue: options.prologue_frames,
+            total: route.time + options.prologue_frames,
+            bits: route.bits,
+            feasible: route.feasible,
+            stats,
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        match File::create(path)
+            .and_then(|file| serde_json::to_writer_pretty(file, self).map_err(io::Error::from))
+        {
+            Ok(()) => info!("saved route to {:?}", path),
+            Err(e) => error!("failed to write route to {:?}: {}", path, e),
+        }
+    }
+}
+
+/// fnv-1a of the rooms file, or none if it couldn't be read.
+/// like the topology hash it is stable between builds, so routes and runs saved months apart can tell if the rooms changed
+pub fn rooms_hash(options: &Options) -> Option<String> {
+    fs::read_to_string(&options.rooms)
+        .ok()
+        .map(|s| format!("{:016x}", fnv1a(iter::once(s.as_str()))))
+}
+
+/// every step is a node the route reaches and the time it took to get there and do it
+fn zone_totals(
+    graph: &StableGraph<Node, Edge>,
+    steps: impl IntoIterator<Item = (NodeIndex, Frames)>,
+) -> Vec<RouteZone<'_>> {
+    let mut zones: Vec<RouteZone> = Vec::new();
+    steps.into_iter().for_each(|(n, time)| {
+        let node = &graph[n];
+        let i = zones
+            .iter()
+            .position(|z| z.zone == node.zone)
+            .unwrap_or_else(|| {
+                zones.push(RouteZone {
+                    zone: &node.zone,
+                    nodes: 0,
+                    bits: Bits(0),
+                    time: Frames(0.0),
+                });
+                zones.len() - 1
+            });
+        zones[i].nodes += 1;
+        zones[i].bits += node.bits;
+        zones[i].time += time;
+    });
+    zones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, zone: &str, bits: i32) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            zone: zone.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn zones_total_in_first_visit_order() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", "village", 0));
+        let chest = graph.add_node(node("b.chest", "nature", 8));
+        let door = graph.add_node(node("a.door", "village", 1));
+        let steps = [
+            (start, Frames(0.0)),
+            (chest, Frames(30.0)),
+            (door, Frames(20.0)),
+            (chest, Frames(5.0)),
+        ];
+        assert_eq!(
+            zone_totals(&graph, steps),
+            [
+                RouteZone {
+                    zone: "village",
+                    nodes: 2,
+                    bits: Bits(1),
+                    time: Frames(20.0),
+                },
+                RouteZone {
+                    zone: "nature",
+                    nodes: 2,
+                    bits: Bits(16),
+                    time: Frames(35.0),
+                },
+            ]
+        );
+    }
+}
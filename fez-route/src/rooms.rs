@@ -1,10 +1,18 @@
+use crate::common::named_after;
+use crate::options::Options;
+use crate::timing_cache::TimingCache;
+use crate::topology::fnv1a;
+use crate::units::{Bits, Frames};
 use itertools::Itertools;
 use log::*;
 use petgraph::graph::{Graph, NodeIndex};
+use petgraph::stable_graph::StableGraph;
 use serde::de::{Unexpected, Visitor};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
+use std::ops::Deref;
 use std::path::Path;
 
 /*
@@ -13,7 +21,7 @@ use std::path::Path;
 120 frames to open a secret
 +690 frames to warp (not including long load)
 +80 frames to enter a hole
-+290 frames to long load, not added yet
++290 frames to long load, once per hub a route warps to
 +460 frames to far load
 240 frames to use well (not including long load)
 300 frames to open any chest
@@ -28,12 +36,59 @@ grave dest:
 
 */
 
+/// the room data is either just the rooms, or the rooms along with requirements
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RoomData<'a> {
+    #[serde(borrow)]
+    rooms: Vec<Room<'a>>,
+    #[serde(default, borrow)]
+    requirements: Vec<RoomRequirement<'a>>,
+}
+
+/// at least some number of a set of nodes, like any 2 of the 3 skull artifacts
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct RoomRequirement<'a> {
+    name: &'a str,
+    at_least: u32,
+    /// {room}.{name}, where strats and detours of a node count as that node
+    #[serde(borrow)]
+    nodes: Vec<&'a str>,
+}
+
 #[derive(Deserialize, Debug, Default, Clone)]
 struct Room<'a> {
     name: &'a str,
+    /// the area of the world this room is part of. a room without one is a zone of its own
+    zone: Option<Zone>,
     nodes: Vec<RoomNode<'a>>,
 }
 
+/// the areas of the world, each with a warp gate hub
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Zone {
+    Village,
+    Nature,
+    Industrial,
+    Graveyard,
+    Sewer,
+    Zu,
+}
+impl Zone {
+    fn name(self) -> &'static str {
+        match self {
+            Zone::Village => "village",
+            Zone::Nature => "nature",
+            Zone::Industrial => "industrial",
+            Zone::Graveyard => "graveyard",
+            Zone::Sewer => "sewer",
+            Zone::Zu => "zu",
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Copy, Clone)]
 #[serde(rename_all = "lowercase")]
 enum Orientation {
@@ -109,9 +164,6 @@ pub enum Cost {
     Lock,
     Water,
     Secret,
-    // at the moment there is only a single oneof constraint
-    // so this doesn't need an arg
-    Oneof,
 }
 impl Default for Cost {
     fn default() -> Self {
@@ -119,12 +171,81 @@ impl Default for Cost {
     }
 }
 
+/// something done at a node that runners may want to do less often
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Warp,
+    Well,
+}
+
+/// a level that only ever rises during a run, like the sewer water.
+/// a node with a phase can only be used while the resource is at that level,
+/// and using it brings the resource up to that level
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub resource: String,
+    pub level: u32,
+}
+
+/// a requirement a node counts towards, which is met by visiting at least `at_least` of its nodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub name: String,
+    pub at_least: u32,
+}
+
+/// something outside of moving around that a node waits on
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+enum Gate {
+    /// only available at certain real times, which a run can only wait for by changing the system clock
+    Clock,
+    /// a fixed input sequence, like a QR code, that takes this many frames to enter
+    Input(f64),
+}
+
+/// dying on purpose after a node, like jumping into the void, to respawn at another node of the room
+/// when that is faster than walking back
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct DeathWarp<'a> {
+    /// the node of the same room the respawn is at, like the door the room was entered through
+    to: &'a str,
+    /// frames from the start of the fall until control returns at the respawn
+    time: f64,
+}
+
+/// an alternate way to collect a node, like a fast but risky trick
+#[derive(Deserialize, Debug, Clone)]
+struct Strat<'a> {
+    name: &'a str,
+    time: f64,
+    /// how hard the strat is to pull off, where 0 is safe
+    #[serde(default)]
+    difficulty: f64,
+    /// freeform labels like glitch or precise, for `--exclude-tag` and `--tag-penalty`
+    #[serde(default, borrow)]
+    tags: Vec<&'a str>,
+    /// replaces the node's, see `RoomNode::success_rate`
+    success_rate: Option<f64>,
+    /// replaces the node's, see `RoomNode::retry_cost`
+    retry_cost: Option<f64>,
+}
+impl Strat<'_> {
+    fn is_excluded(&self, options: &Options) -> bool {
+        is_excluded(&self.tags, options)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 struct RoomNode<'a> {
     name: &'a str,
     to: Option<&'a str>,
     // at: &'a str,
-    position: Position,
+    /// can be left out while the room is still being entered, see `--unpositioned-frames`
+    #[serde(default)]
+    position: Option<Position>,
     #[serde(default)]
     bit: i32,
     #[serde(default)]
@@ -137,12 +258,55 @@ struct RoomNode<'a> {
     time: RoomTime,
     #[serde(default)]
     cost: Cost,
-    #[serde(skip, default = "NodeIndex::end")]
-    index: NodeIndex,
+    /// at most one node of each named group can be visited
+    #[serde(default)]
+    oneof: Option<&'a str>,
+    /// replaces time with one mutually exclusive node per strat
+    #[serde(default, borrow)]
+    strats: Vec<Strat<'a>>,
+    gate: Option<Gate>,
+    action: Option<Action>,
+    phase: Option<Phase>,
+    /// a puzzle state this node changes, like ringing the bell
+    activates: Option<&'a str>,
+    /// a puzzle state that has to have been changed earlier in the route to use this node
+    requires_activation: Option<&'a str>,
+    /// a dead end that is only reached from this node of the same room,
+    /// so it is folded into that node as an out and back detour instead of being a node of its own
+    detour: Option<&'a str>,
+    /// freeform labels like glitch or damage-boost, which also apply to each strat
+    #[serde(default, borrow)]
+    tags: Vec<&'a str>,
+    /// how often a risky trick here works on the first try, for `--expected-time`
+    success_rate: Option<f64>,
+    /// unless `--no-death-warps`
+    #[serde(default, borrow)]
+    death_warps: Vec<DeathWarp<'a>>,
+    /// frames each failed try loses, which defaults to the time of the node or strat
+    retry_cost: Option<f64>,
+    /// one per strat, or just one without strats
+    #[serde(skip)]
+    indices: Vec<NodeIndex>,
 }
 impl RoomNode<'_> {
-    fn is_actual(&self) -> bool {
+    fn is_actual(&self, clock_gates: bool) -> bool {
         !matches!(self.time, RoomTime::Src)
+            && (clock_gates || !matches!(self.gate, Some(Gate::Clock)))
+    }
+    fn is_excluded(&self, options: &Options) -> bool {
+        is_excluded(&self.tags, options)
+    }
+    /// only collectables can be detours, anything else would need to be a node to be constrained
+    fn is_detour(&self) -> bool {
+        self.detour.is_some()
+            && self.to.is_none()
+            && self.cost == Cost::Free
+            && self.oneof.is_none()
+            && self.strats.is_empty()
+            && self.action.is_none()
+            && self.phase.is_none()
+            && self.activates.is_none()
+            && self.requires_activation.is_none()
     }
     fn is_source(&self) -> bool {
         !matches!(self.time, RoomTime::Src | RoomTime::End)
@@ -150,33 +314,101 @@ impl RoomNode<'_> {
     fn is_target(&self) -> bool {
         !matches!(self.time, RoomTime::Src | RoomTime::Start)
     }
-    fn get_time(&self) -> f64 {
+    fn get_time(&self) -> Frames {
         match self.time {
             // TODO we need something, so for now assume the time to go through a hole
             // all collectables should have an actual time
-            RoomTime::Unknown => 80.0,
-            RoomTime::Time(time) => time,
-            _ => 0.0,
+            RoomTime::Unknown => Frames(80.0),
+            RoomTime::Time(time) => Frames(time),
+            _ => Frames(0.0),
         }
     }
-    fn get_bits(&self) -> i32 {
-        self.bit + self.cube * 8 + self.anti * 8
+    fn get_gate_time(&self) -> Frames {
+        match self.gate {
+            Some(Gate::Input(time)) => Frames(time),
+            _ => Frames(0.0),
+        }
+    }
+    fn get_bits(&self) -> Bits {
+        Bits(self.bit) + Bits::CUBE * (self.cube + self.anti)
     }
 }
 
-#[derive(Debug, Clone)]
+fn is_excluded(tags: &[&str], options: &Options) -> bool {
+    tags.iter()
+        .any(|tag| options.exclude_tag.iter().any(|excluded| excluded == tag))
+}
+
+/// frames a trick is expected to lose to failed tries, (1 - p) / p retries on average,
+/// but only with `--expected-time`, since TAS routes never fail
+fn get_retry_time(
+    name: &str,
+    success_rate: Option<f64>,
+    retry_cost: Option<f64>,
+    time: Frames,
+    options: &Options,
+) -> Frames {
+    match success_rate {
+        Some(p) if !(p > 0.0 && p <= 1.0) => panic!(
+            "{} has a success rate of {}, which has to be above 0 and at most 1",
+            name, p
+        ),
+        Some(p) if options.expected_time => Frames(retry_cost.unwrap_or(time.0) * (1.0 - p) / p),
+        _ => Frames(0.0),
+    }
+}
+
+/// every penalty for a tag is added once per tag
+fn get_tag_time(tags: &[&str], options: &Options) -> Frames {
+    options
+        .tag_penalty
+        .iter()
+        .filter(|penalty| tags.contains(&penalty.tag.as_str()))
+        .map(|penalty| penalty.frames)
+        .sum()
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Node {
     /// {room}.{name}
     pub name: String,
-    pub bits: i32,
+    /// every collectable as bits, including the cubes and anti cubes
+    pub bits: Bits,
+    /// whole golden cubes
+    pub cubes: i32,
+    pub antis: i32,
     pub keys: i32,
     pub cost: Cost,
-    pub time: f64,
+    /// the group of mutually exclusive nodes this belongs to
+    pub oneof: Option<String>,
+    pub action: Option<Action>,
+    pub phase: Option<Phase>,
+    /// a puzzle state this node changes
+    pub activates: Option<String>,
+    /// a puzzle state that has to be changed earlier in the route to use this node
+    pub requires_activation: Option<String>,
+    /// every "at least k of these nodes" requirement this node counts towards
+    pub requirements: Vec<Requirement>,
+    pub zone: String,
+    /// including `menu`
+    pub time: Frames,
+    /// opening the map or inventory at this node, from `--menu-costs`
+    pub menu: Frames,
+}
+
+impl Node {
+    pub fn keys_minus_lock(&self) -> i32 {
+        self.keys
+            + match self.cost {
+                Cost::Lock => -1,
+                _ => 0,
+            }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Edge {
-    pub time: f64,
+    pub time: Frames,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -186,12 +418,101 @@ pub struct Distance {
     pub dz: f64,
 }
 
-pub fn load(path: impl AsRef<Path>) -> Graph<Node, Edge> {
+/// the room graph with dense node and edge indices, which the model relies on to give each edge a variable.
+///
+/// it is a `StableGraph` so that graphs mapped from it keep the same indices,
+/// but it can only be made from a `Graph` and can't be changed in place, so removing nodes can't leave holes
+#[derive(Clone, Debug)]
+pub struct RouteGraph(StableGraph<Node, Edge>);
+impl RouteGraph {
+    /// for changing the graph, which has to be made into a `RouteGraph` again through a `Graph`
+    pub fn into_inner(self) -> StableGraph<Node, Edge> {
+        self.0
+    }
+
+    pub fn into_graph(self) -> Graph<Node, Edge> {
+        self.0.into()
+    }
+}
+impl From<Graph<Node, Edge>> for RouteGraph {
+    fn from(graph: Graph<Node, Edge>) -> Self {
+        RouteGraph(graph.into())
+    }
+}
+impl Deref for RouteGraph {
+    type Target = StableGraph<Node, Edge>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub fn load(path: impl AsRef<Path>, options: &Options) -> RouteGraph {
     let mut s = String::new();
     File::open(path).unwrap().read_to_string(&mut s).unwrap();
-    let mut rooms: Vec<Room> = serde_json::from_str(&s).unwrap();
-    verify_unique_names(&rooms);
-    as_graph(&mut rooms)
+    parse(&s, options).unwrap()
+}
+
+/// rooms that aren't even valid json are an error, but other mistakes in them still panic
+pub fn parse(s: &str, options: &Options) -> serde_json::Result<RouteGraph> {
+    let mut data: RoomData = if s.trim_start().starts_with('{') {
+        serde_json::from_str(s)?
+    } else {
+        RoomData {
+            rooms: serde_json::from_str(s)?,
+            requirements: Vec::new(),
+        }
+    };
+    verify_unique_names(&data.rooms);
+    log_zoneless(&data.rooms);
+    let mut graph = as_graph(&mut data.rooms, options);
+    add_requirements(&mut graph, &data.requirements);
+    Ok(graph.into())
+}
+
+/// nodes that were left out, like clock gated ones, can't count towards a requirement
+fn add_requirements(graph: &mut Graph<Node, Edge>, requirements: &[RoomRequirement]) {
+    requirements.iter().for_each(|requirement| {
+        let mut members = 0;
+        requirement.nodes.iter().for_each(|name| {
+            let nodes: Vec<NodeIndex> = graph
+                .node_indices()
+                .filter(|n| named_after(&graph[*n], name))
+                .collect();
+            if nodes.is_empty() {
+                warn!(
+                    "requirement {} names {}, which isn't in the graph",
+                    requirement.name, name
+                );
+            } else {
+                members += 1;
+            }
+            nodes.into_iter().for_each(|n| {
+                graph[n].requirements.push(Requirement {
+                    name: requirement.name.to_owned(),
+                    at_least: requirement.at_least,
+                })
+            });
+        });
+        if members < requirement.at_least {
+            warn!(
+                "requirement {} needs {} nodes but only {} are in the graph",
+                requirement.name, requirement.at_least, members
+            );
+        }
+    });
+}
+
+/// rooms are only grouped into zones once every room has one entered
+fn log_zoneless(rooms: &[Room]) {
+    let zoneless = rooms.iter().filter(|room| room.zone.is_none()).count();
+    if zoneless > 0 {
+        info!(
+            "{} of {} rooms have no zone and are grouped on their own",
+            zoneless,
+            rooms.len()
+        );
+    }
 }
 
 fn verify_unique_names(rooms: &[Room]) {
@@ -215,48 +536,226 @@ fn verify_unique_inner_names(room: &Room) {
     });
 }
 
-fn as_graph(rooms: &mut [Room]) -> Graph<Node, Edge> {
+fn as_graph(rooms: &mut [Room], options: &Options) -> Graph<Node, Edge> {
     let mut graph = Graph::new();
     rooms
         .iter_mut()
-        .for_each(|room| add_room_nodes(&mut graph, room));
-    let global = global_timing(rooms);
+        .for_each(|room| add_room_nodes(&mut graph, room, options));
+    let global = global_timing(rooms, options);
+    let mut cache = options.timing_cache.as_deref().map(TimingCache::load);
+    let timings: Vec<Timing> = rooms
+        .iter()
+        .map(|room| room_timing(room, &global, cache.as_mut()))
+        .collect();
+    if let Some(cache) = &cache {
+        cache.save();
+    }
+    rooms
+        .iter_mut()
+        .zip(&timings)
+        .for_each(|(room, timing)| add_room_detours(&mut graph, room, timing, options));
     rooms
         .iter()
-        .for_each(|room| add_room_edges(&mut graph, rooms, room, &room_timing(room, &global)));
+        .for_each(|room| add_room_edges(&mut graph, rooms, &timings, room));
+    if !options.no_death_warps {
+        rooms
+            .iter()
+            .for_each(|room| add_death_warps(&mut graph, room));
+    }
     graph
 }
 
-fn add_room_nodes(graph: &mut Graph<Node, Edge>, room: &mut Room) {
+/// clock gated nodes are left out unless the run changes the system clock,
+/// and their doors lead nowhere, like a `src` node. so are nodes and strats with an excluded tag
+fn add_room_nodes(graph: &mut Graph<Node, Edge>, room: &mut Room, options: &Options) {
     let room_name = room.name;
+    let zone = match room.zone {
+        Some(zone) => zone.name(),
+        None => room.name,
+    };
     room.nodes
         .iter_mut()
-        .filter(|node| node.is_actual())
+        .filter(|node| node.is_actual(options.clock_gates) && !node.is_excluded(options))
         .for_each(|node| {
-            node.index = graph.add_node(Node {
-                name: format!("{}.{}", room_name, node.name),
-                bits: node.get_bits(),
-                keys: node.key,
-                cost: node.cost,
-                time: node.get_time(),
-            })
+            // folded into their parent by `add_room_detours`
+            if node.detour.is_some() {
+                return;
+            }
+            let name = format!("{}.{}", room_name, node.name);
+            if node.strats.is_empty() {
+                let retry = get_retry_time(
+                    &name,
+                    node.success_rate,
+                    node.retry_cost,
+                    node.get_time(),
+                    options,
+                );
+                node.indices = vec![graph.add_node(Node {
+                    name,
+                    bits: node.get_bits(),
+                    cubes: node.cube,
+                    antis: node.anti,
+                    keys: node.key,
+                    cost: node.cost,
+                    oneof: node.oneof.map(str::to_owned),
+                    action: node.action,
+                    phase: node.phase.clone(),
+                    activates: node.activates.map(str::to_owned),
+                    requires_activation: node.requires_activation.map(str::to_owned),
+                    requirements: Vec::new(),
+                    zone: zone.to_owned(),
+                    time: node.get_time()
+                        + node.get_gate_time()
+                        + get_tag_time(&node.tags, options)
+                        + retry,
+                    menu: Frames(0.0),
+                })];
+                return;
+            }
+            if node.oneof.is_some() {
+                panic!("{} can't have both strats and a oneof group", name);
+            }
+            // the strats use the node itself as their oneof group
+            node.indices = node
+                .strats
+                .iter()
+                .filter(|strat| !strat.is_excluded(options))
+                .map(|strat| {
+                    let tags: Vec<&str> = node
+                        .tags
+                        .iter()
+                        .chain(&strat.tags)
+                        .copied()
+                        .unique()
+                        .collect();
+                    graph.add_node(Node {
+                        name: format!("{}.{}", name, strat.name),
+                        bits: node.get_bits(),
+                        cubes: node.cube,
+                        antis: node.anti,
+                        keys: node.key,
+                        cost: node.cost,
+                        oneof: Some(name.clone()),
+                        action: node.action,
+                        phase: node.phase.clone(),
+                        activates: node.activates.map(str::to_owned),
+                        requires_activation: node.requires_activation.map(str::to_owned),
+                        requirements: Vec::new(),
+                        zone: zone.to_owned(),
+                        time: Frames(strat.time)
+                            + node.get_gate_time()
+                            + options.difficulty_frames * strat.difficulty
+                            + get_tag_time(&tags, options)
+                            + get_retry_time(
+                                &format!("{}.{}", name, strat.name),
+                                strat.success_rate.or(node.success_rate),
+                                strat.retry_cost.or(node.retry_cost),
+                                Frames(strat.time),
+                                options,
+                            ),
+                        menu: Frames(0.0),
+                    })
+                })
+                .collect();
         });
 }
 
-fn add_room_edges(graph: &mut Graph<Node, Edge>, rooms: &[Room], room: &Room, timing: &Timing) {
+/// each detour is another version of its parent node, which also collects the dead end.
+/// the versions share a oneof group with the parent, so the parent is still visited at most once.
+/// detours are left out like any other node, and a parent that was left out takes its detours with it
+fn add_room_detours(
+    graph: &mut Graph<Node, Edge>,
+    room: &mut Room,
+    timing: &Timing,
+    options: &Options,
+) {
+    let detours: Vec<(usize, usize)> = room
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.is_actual(options.clock_gates) && !node.is_excluded(options))
+        .filter_map(|(d, node)| node.detour.map(|parent| (d, parent)))
+        .map(|(d, parent)| {
+            let detour = &room.nodes[d];
+            if !detour.is_detour() {
+                panic!(
+                    "{}.{} is a detour, so it can only be something to collect",
+                    room.name, detour.name
+                );
+            }
+            let p = room
+                .nodes
+                .iter()
+                .position(|n| n.name == parent)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "failed to find node {}.{} for detour {}.{}",
+                        room.name, parent, room.name, detour.name
+                    )
+                });
+            if room.nodes[p].detour.is_some() {
+                panic!(
+                    "detour {}.{} is of {}.{}, which is a detour itself",
+                    room.name, detour.name, room.name, parent
+                );
+            }
+            (d, p)
+        })
+        .collect();
+    detours.into_iter().for_each(|(d, p)| {
+        let detour = &room.nodes[d];
+        let parent = &room.nodes[p];
+        let time = timing.get(parent.name, detour.name)
+            + detour.get_time()
+            + detour.get_gate_time()
+            + get_tag_time(&detour.tags, options)
+            + get_retry_time(
+                &format!("{}.{}", room.name, detour.name),
+                detour.success_rate,
+                detour.retry_cost,
+                detour.get_time(),
+                options,
+            )
+            + timing.get(detour.name, parent.name);
+        let indices: Vec<NodeIndex> = parent
+            .indices
+            .iter()
+            .map(|i| {
+                let group = graph[*i]
+                    .oneof
+                    .clone()
+                    .unwrap_or_else(|| graph[*i].name.clone());
+                graph[*i].oneof = Some(group.clone());
+                let mut node = graph[*i].clone();
+                node.name = format!("{}.{}", node.name, detour.name);
+                node.bits += detour.get_bits();
+                node.cubes += detour.cube;
+                node.antis += detour.anti;
+                node.keys += detour.key;
+                node.oneof = Some(group);
+                node.time += time;
+                graph.add_node(node)
+            })
+            .collect();
+        room.nodes[p].indices.extend(indices);
+    });
+}
+
+/// edges through a door are timed in the room the door leads to
+fn add_room_edges(graph: &mut Graph<Node, Edge>, rooms: &[Room], timings: &[Timing], room: &Room) {
     room.nodes
         .iter()
         .filter(|node| node.is_source())
         .for_each(|source| {
             let to_name = source.to.unwrap_or(source.name);
-            let to = rooms.iter().find(|r| r.name == to_name);
+            let to = rooms.iter().zip(timings).find(|(r, _)| r.name == to_name);
             if source.to.is_some() && to.is_none() {
                 panic!(
                     "failed to find room {} for door {}.{}",
                     to_name, room.name, source.name
                 );
             }
-            if let Some(to) = to {
+            if let Some((to, timing)) = to {
                 let at_name = if source.to.is_some() {
                     source.name
                 } else {
@@ -272,23 +771,20 @@ fn add_room_edges(graph: &mut Graph<Node, Edge>, rooms: &[Room], room: &Room, ti
                             to.name, at_name, room.name, source.name
                         );
                     });
-                add_edges(
-                    graph,
-                    source.index,
-                    at.name,
-                    at.position,
-                    to,
-                    at.index,
-                    timing,
-                );
+                add_edges(graph, &source.indices, at.name, to, &at.indices, timing);
             } else {
+                let timing = rooms
+                    .iter()
+                    .zip(timings)
+                    .find(|(r, _)| r.name == room.name)
+                    .map(|(_, timing)| timing)
+                    .unwrap();
                 add_edges(
                     graph,
-                    source.index,
+                    &source.indices,
                     source.name,
-                    source.position,
                     room,
-                    source.index,
+                    &source.indices,
                     timing,
                 );
             }
@@ -297,51 +793,367 @@ fn add_room_edges(graph: &mut Graph<Node, Edge>, rooms: &[Room], room: &Room, ti
 
 fn add_edges<'a>(
     graph: &mut Graph<Node, Edge>,
-    src_i: NodeIndex,
+    src_indices: &[NodeIndex],
     src_name: &'a str,
-    src_pos: Position,
     room: &Room,
-    exclude: NodeIndex,
+    exclude: &[NodeIndex],
     timing: &Timing,
 ) {
     room.nodes
         .iter()
         .filter(|node| node.is_target())
-        .filter(|node| node.index != exclude)
+        .filter(|node| node.indices != exclude)
         .for_each(|target| {
-            graph.add_edge(
-                src_i,
-                target.index,
-                Edge {
-                    time: timing.get(src_name, src_pos, target.name, target.position),
-                },
-            );
+            let time = timing.get(src_name, target.name);
+            src_indices
+                .iter()
+                .cartesian_product(&target.indices)
+                .for_each(|(src_i, target_i)| {
+                    graph.add_edge(*src_i, *target_i, Edge { time });
+                });
         });
 }
 
-struct GlobalTiming {}
+/// an edge from every version of the node to every version of where it respawns, which only takes the respawn time.
+/// the node's own edges back are usually still there, and the slower of the two is removed with the parallel edges
+fn add_death_warps(graph: &mut Graph<Node, Edge>, room: &Room) {
+    room.nodes.iter().for_each(|source| {
+        source.death_warps.iter().for_each(|warp| {
+            let target = room
+                .nodes
+                .iter()
+                .find(|n| n.name == warp.to)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "failed to find node {}.{} for death warp from {}.{}",
+                        room.name, warp.to, room.name, source.name
+                    )
+                });
+            if !target.is_target() {
+                panic!(
+                    "{}.{} can't be a respawn for a death warp, nothing can reach it",
+                    room.name, target.name
+                );
+            }
+            source
+                .indices
+                .iter()
+                .cartesian_product(&target.indices)
+                .for_each(|(src_i, target_i)| {
+                    graph.add_edge(
+                        *src_i,
+                        *target_i,
+                        Edge {
+                            time: Frames(warp.time),
+                        },
+                    );
+                });
+        });
+    });
+}
 
-struct Timing {}
+/// nodes without a position that are named in a warning, the rest are only counted
+const UNPOSITIONED_WARNINGS: usize = 5;
 
-fn global_timing(rooms: &[Room]) -> GlobalTiming {
-    GlobalTiming {}
+struct GlobalTiming {
+    /// between a node without a position and any other node of its room
+    unpositioned: Frames,
 }
 
-fn room_timing(room: &Room, global: &GlobalTiming) -> Timing {
-    Timing {}
+/// frames between any two nodes of a room, by name
+struct Timing {
+    times: HashMap<(String, String), Frames>,
+}
+
+fn global_timing(rooms: &[Room], options: &Options) -> GlobalTiming {
+    warn_unpositioned(rooms, options.unpositioned_frames);
+    GlobalTiming {
+        unpositioned: options.unpositioned_frames,
+    }
+}
+
+/// names the first few nodes without a position, so a room being entered doesn't bury every other warning
+fn warn_unpositioned(rooms: &[Room], frames: Frames) {
+    let unpositioned: Vec<String> = rooms
+        .iter()
+        .flat_map(|room| {
+            room.nodes
+                .iter()
+                .filter(|node| node.position.is_none())
+                .map(move |node| format!("{}.{}", room.name, node.name))
+        })
+        .collect();
+    if unpositioned.is_empty() {
+        return;
+    }
+    unpositioned
+        .iter()
+        .take(UNPOSITIONED_WARNINGS)
+        .for_each(|name| warn!("{} has no position", name));
+    warn!(
+        "{} nodes have no position and are {} frames from every other node of their room",
+        unpositioned.len(),
+        frames
+    );
+}
+
+fn room_timing(room: &Room, global: &GlobalTiming, cache: Option<&mut TimingCache>) -> Timing {
+    let compute = || room_times(room, global);
+    let times = match cache {
+        Some(cache) => cache.get_or_compute(room.name, room_hash(room, global), compute),
+        None => compute(),
+    };
+    Timing {
+        times: times
+            .into_iter()
+            .map(|(from, to, time)| ((from, to), time))
+            .collect(),
+    }
+}
+
+/// everything about a room that its times are computed from
+fn room_hash(room: &Room, global: &GlobalTiming) -> u64 {
+    let mut parts: Vec<String> = room
+        .nodes
+        .iter()
+        .map(|node| format!("{}:{:?}", node.name, node.position))
+        .collect();
+    if room.nodes.iter().any(|node| node.position.is_none()) {
+        parts.push(format!("unpositioned:{}", global.unpositioned));
+    }
+    fnv1a(parts.iter().map(String::as_str))
+}
+
+fn room_times(room: &Room, global: &GlobalTiming) -> Vec<(String, String, Frames)> {
+    room.nodes
+        .iter()
+        .cartesian_product(&room.nodes)
+        .map(|(src, target)| {
+            let time = match (&src.position, &target.position) {
+                (Some(src), Some(target)) => {
+                    let dx = (src.x - target.x).abs();
+                    let dy = (src.y - target.y).abs();
+                    let dz = (src.z - target.z).abs();
+                    Frames((dx.min(dz) + dy) * 12.0)
+                }
+                _ if src.name == target.name => Frames(0.0),
+                _ => global.unpositioned,
+            };
+            (src.name.to_owned(), target.name.to_owned(), time)
+        })
+        .collect()
 }
 
 impl Timing {
-    fn get(
-        &self,
-        src_name: &str,
-        src_pos: Position,
-        target_name: &str,
-        target_pos: Position,
-    ) -> f64 {
-        let dx = (src_pos.x - target_pos.x).abs();
-        let dy = (src_pos.y - target_pos.y).abs();
-        let dz = (src_pos.z - target_pos.z).abs();
-        (dx.min(dz) + dy) * 12.0
+    fn get(&self, src_name: &str, target_name: &str) -> Frames {
+        self.times[&(src_name.to_owned(), target_name.to_owned())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use petgraph::visit::EdgeRef;
+
+    const ROOM: &str = r#"[{
+        "name": "village",
+        "nodes": [
+            { "name": "start", "position": { "x": 0, "y": 0, "z": 0 }, "time": "start" },
+            { "name": "zip", "position": { "x": 1, "y": 0, "z": 0 }, "time": 20, "bit": 1, "tags": ["glitch"] },
+            {
+                "name": "chest", "position": { "x": 2, "y": 0, "z": 0 }, "bit": 8, "tags": ["precise"],
+                "strats": [
+                    { "name": "safe", "time": 300 },
+                    { "name": "clip", "time": 100, "tags": ["glitch", "precise"] }
+                ]
+            },
+            { "name": "end", "position": { "x": 3, "y": 0, "z": 0 }, "time": "end" }
+        ]
+    }]"#;
+
+    fn times(args: &[&str]) -> Vec<(String, Frames)> {
+        let options = Options::parse_from(["fez-route"].iter().chain(args));
+        parse(ROOM, &options)
+            .unwrap()
+            .node_weights()
+            .map(|node| (node.name.clone(), node.time))
+            .collect()
+    }
+
+    #[test]
+    fn tags_exclude_and_penalize() {
+        let name = |name: &str| name.to_owned();
+        assert_eq!(
+            times(&["--exclude-tag", "glitch", "--tag-penalty", "precise=60"]),
+            vec![
+                (name("village.start"), Frames(0.0)),
+                (name("village.chest.safe"), Frames(360.0)),
+                (name("village.end"), Frames(0.0)),
+            ]
+        );
+        // a tag on both the node and the strat is only charged once
+        assert_eq!(
+            times(&["--tag-penalty", "precise=60"])[3],
+            (name("village.chest.clip"), Frames(160.0))
+        );
+    }
+
+    #[test]
+    fn retries_are_expected_only_when_asked() {
+        let room = ROOM
+            .replace(
+                r#""time": 20, "bit": 1"#,
+                r#""time": 20, "bit": 1, "success_rate": 0.8"#,
+            )
+            .replace(
+                r#""time": 100, "tags""#,
+                r#""time": 100, "success_rate": 0.5, "retry_cost": 40, "tags""#,
+            );
+        let times = |args: &[&str]| -> Vec<Frames> {
+            let options = Options::parse_from(["fez-route"].iter().chain(args));
+            parse(&room, &options)
+                .unwrap()
+                .node_weights()
+                .map(|node| node.time)
+                .collect()
+        };
+        assert_eq!(
+            times(&[])[1..4],
+            [Frames(20.0), Frames(300.0), Frames(100.0)]
+        );
+        // a failed zip costs its own time again, a failed clip only the 40 frames to retry it
+        assert_eq!(
+            times(&["--expected-time"])[1..4],
+            [Frames(25.0), Frames(300.0), Frames(140.0)]
+        );
+    }
+
+    #[test]
+    fn death_warps_respawn_at_their_node() {
+        let room = ROOM.replace(
+            r#""bit": 8,"#,
+            r#""bit": 8, "death_warps": [{ "to": "zip", "time": 5 }],"#,
+        );
+        let warps = |args: &[&str]| -> Vec<Frames> {
+            let options = Options::parse_from(["fez-route"].iter().chain(args));
+            let graph = parse(&room, &options).unwrap();
+            let find = |name: &str| {
+                graph
+                    .node_indices()
+                    .find(|n| graph[*n].name == name)
+                    .unwrap()
+            };
+            let zip = find("village.zip");
+            graph
+                .edges(find("village.chest.clip"))
+                .filter(|e| e.target() == zip)
+                .map(|e| e.weight().time)
+                .collect()
+        };
+        // the walk back is still there, until it is removed as a parallel edge if it is slower
+        assert_eq!(warps(&[]), [Frames(5.0), Frames(0.0)]);
+        assert_eq!(warps(&["--no-death-warps"]), [Frames(0.0)]);
+    }
+
+    #[test]
+    fn detours_fold_into_their_parent() {
+        let with_detour = |detour: &str| {
+            ROOM.replace(
+                r#"{ "name": "end","#,
+                &format!(
+                    r#"{{ "name": "nook", "position": {{ "x": 1, "y": 1, "z": 0 }}, "time": 30, "bit": 2, {} }},
+                    {{ "name": "end","#,
+                    detour
+                ),
+            )
+        };
+        let names = |room: &str, args: &[&str]| -> Vec<String> {
+            let options = Options::parse_from(["fez-route"].iter().chain(args));
+            parse(room, &options)
+                .unwrap()
+                .node_weights()
+                .map(|node| node.name.clone())
+                .collect()
+        };
+        let room = with_detour(r#""detour": "zip""#);
+        assert!(names(&room, &[]).contains(&"village.zip.nook".to_owned()));
+        assert!(!names(&room, &["--exclude-tag", "glitch"])
+            .iter()
+            .any(|name| name.ends_with("nook")));
+
+        // clock gated detours are left out like any other clock gated node
+        let room = with_detour(r#""detour": "zip", "gate": "clock""#);
+        assert!(!names(&room, &[]).iter().any(|name| name.ends_with("nook")));
+        assert!(names(&room, &["--clock-gates"]).contains(&"village.zip.nook".to_owned()));
+    }
+
+    #[test]
+    #[should_panic(expected = "detour village.nook is of village.zip, which is a detour itself")]
+    fn detours_of_detours_are_rejected() {
+        let room = ROOM
+            .replace(r#""bit": 1, "tags""#, r#""bit": 1, "detour": "start", "tags""#)
+            .replace(
+                r#"{ "name": "end","#,
+                r#"{ "name": "nook", "position": { "x": 1, "y": 1, "z": 0 }, "time": 30, "bit": 2, "detour": "zip" },
+                { "name": "end","#,
+            );
+        parse(&room, &Options::parse_from(["fez-route"])).unwrap();
+    }
+
+    #[test]
+    fn requirements_apply_to_strats() {
+        let data = format!(
+            r#"{{ "rooms": {}, "requirements": [
+                {{ "name": "loot", "at_least": 2, "nodes": ["village.zip", "village.chest"] }}
+            ] }}"#,
+            ROOM
+        );
+        let graph = parse(&data, &Options::parse_from(["fez-route"])).unwrap();
+        let loot: Vec<&str> = graph
+            .node_weights()
+            .filter(|node| {
+                node.requirements
+                    == [Requirement {
+                        name: "loot".to_owned(),
+                        at_least: 2,
+                    }]
+            })
+            .map(|node| node.name.as_str())
+            .collect();
+        assert_eq!(
+            loot,
+            ["village.zip", "village.chest.safe", "village.chest.clip"]
+        );
+    }
+
+    #[test]
+    fn unpositioned_nodes_use_the_fallback_time() {
+        let rooms: Vec<Room> = serde_json::from_str(
+            r#"[{
+                "name": "village",
+                "nodes": [
+                    { "name": "start", "position": { "x": 0, "y": 0, "z": 0 } },
+                    { "name": "door", "position": { "x": 0, "y": 2, "z": 0 } },
+                    { "name": "new" }
+                ]
+            }]"#,
+        )
+        .unwrap();
+        let global = GlobalTiming {
+            unpositioned: Frames(90.0),
+        };
+        let times = room_times(&rooms[0], &global);
+        let time = |from: &str, to: &str| {
+            times
+                .iter()
+                .find(|(f, t, _)| f == from && t == to)
+                .unwrap()
+                .2
+        };
+        assert_eq!(time("start", "door"), Frames(24.0));
+        assert_eq!(time("start", "new"), Frames(90.0));
+        assert_eq!(time("new", "door"), Frames(90.0));
+        assert_eq!(time("new", "new"), Frames(0.0));
     }
 }
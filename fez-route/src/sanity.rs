@@ -0,0 +1,103 @@
+//! mistakes in the room data that the model would happily optimize around, like a typo giving an edge
+//! negative frames, or a position far enough out that its times overflow into infinities and NaN
+
+use crate::rooms::{Edge, Node};
+use crate::units::Frames;
+use petgraph::stable_graph::StableGraph;
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+
+/// everything wrong with the times and totals of the graph, naming the offending nodes and edges
+pub fn problems(graph: &StableGraph<Node, Edge>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let bad = |time: Frames| !time.0.is_finite() || time.0 < 0.0;
+
+    graph.node_weights().for_each(|node| {
+        if bad(node.time) {
+            problems.push(format!("node {} takes {} frames", node.name, node.time));
+        }
+        if bad(node.menu) {
+            problems.push(format!(
+                "node {} takes {} frames of menus",
+                node.name, node.menu
+            ));
+        }
+        [
+            ("bits", node.bits.0),
+            ("cubes", node.cubes),
+            ("anti cubes", node.antis),
+        ]
+        .iter()
+        .filter(|(_, count)| *count < 0)
+        .for_each(|(what, count)| {
+            problems.push(format!("node {} has {} {}", node.name, count, what))
+        });
+    });
+    graph.edge_references().for_each(|e| {
+        if bad(e.weight().time) {
+            problems.push(format!(
+                "edge {} -> {} takes {} frames",
+                graph[e.source()].name,
+                graph[e.target()].name,
+                e.weight().time
+            ));
+        }
+    });
+
+    // every time can be fine on its own and still add up to more than an f64 can hold
+    if problems.is_empty() {
+        let total = graph.node_weights().map(|node| node.time.0).sum::<f64>()
+            + graph.edge_weights().map(|edge| edge.time.0).sum::<f64>();
+        if !total.is_finite() {
+            problems.push(format!(
+                "every node and edge together take {} frames",
+                total
+            ));
+        }
+    }
+    problems
+}
+
+/// panics listing every problem, like the other mistakes in the room data
+pub fn check(graph: &StableGraph<Node, Edge>) {
+    let problems = problems(graph);
+    if !problems.is_empty() {
+        panic!("the room data is broken:\n{}", problems.join("\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, time: f64) -> Node {
+        Node {
+            name: name.to_owned(),
+            time: Frames(time),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn negative_and_nan_times_are_named() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", 0.0));
+        let end = graph.add_node(node("a.end", f64::NAN));
+        graph.add_edge(start, end, Edge { time: Frames(-5.0) });
+        assert_eq!(
+            problems(&graph),
+            [
+                "node a.end takes NaN frames",
+                "edge a.start -> a.end takes -5 frames"
+            ]
+        );
+
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", f64::MAX));
+        let end = graph.add_node(node("a.end", f64::MAX));
+        graph.add_edge(start, end, Edge { time: Frames(1.0) });
+        assert_eq!(
+            problems(&graph),
+            ["every node and edge together take inf frames"]
+        );
+    }
+}
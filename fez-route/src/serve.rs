@@ -0,0 +1,322 @@
+use crate::opt::{optimize, Control, Progress};
+use crate::options::Options;
+use crate::render::FOLDER;
+use crate::{prepare, rooms};
+use clap::{CommandFactory, FromArgMatches, ValueSource};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::iter;
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// larger bodies are refused without reading them, the whole room data is well under a megabyte
+const MAX_BODY: usize = 4 << 20;
+
+/// requests are handled one at a time, so a client that stops sending or receiving is dropped after this long
+/// instead of blocking the rest
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// options remote args can't set, since they read or write files on the server or make it send requests
+const REFUSED: [&str; 17] = [
+    "config",
+    "menu-costs",
+    "render-steps",
+    "lp-file",
+    "timing-cache",
+    "layout-cache",
+    "notes",
+    "route-file",
+    "webhook",
+    "history",
+    "import-sheet",
+    "export-sheet",
+    "practice",
+    "cut-pool",
+    "debug-cuts",
+    "convergence",
+    "branch-tree",
+];
+
+/// solves one routing job at a time for remote clients, over plain http.
+///
+/// - `POST /job` with `{"rooms": [...], "args": "--required-bits 200"}` starts a job
+/// - `GET /job` reports its state and progress
+/// - `GET /job/renders` lists what it has rendered so far, and `GET /job/renders/{name}` fetches one
+/// - `DELETE /job` stops it
+pub fn run(address: &str) {
+    let listener = TcpListener::bind(address)
+        .unwrap_or_else(|e| panic!("failed to listen on {}: {}", address, e));
+    info!("serving on {}", address);
+    let mut job = None;
+    listener.incoming().for_each(|stream| {
+        if let Err(e) = stream.and_then(|mut stream| handle(&mut stream, &mut job)) {
+            warn!("failed to handle request: {}", e);
+        }
+    });
+}
+
+struct Job {
+    id: u32,
+    control: Arc<Control>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    Running,
+    Done,
+    Stopped,
+    Failed,
+}
+
+#[derive(Deserialize)]
+struct Submit {
+    rooms: serde_json::Value,
+    /// command line options, where `--rooms` is ignored
+    #[serde(default)]
+    args: String,
+}
+
+#[derive(Serialize)]
+struct Status {
+    id: u32,
+    state: State,
+    progress: Progress,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+impl Response {
+    fn json(value: &impl Serialize) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap(),
+        }
+    }
+
+    fn error(status: &'static str, message: impl Into<String>) -> Self {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: message.into().into_bytes(),
+        }
+    }
+}
+
+fn handle(stream: &mut TcpStream, job: &mut Option<Job>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let response = match read_request(stream)? {
+        Ok(request) => {
+            let response = respond(&request, job);
+            debug!("{} {} {}", request.method, request.path, response.status);
+            response
+        }
+        Err(response) => response,
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)
+}
+
+/// the request, or the response refusing it if its body is too large
+fn read_request(stream: &mut TcpStream) -> io::Result<Result<Request, Response>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or_default().to_owned();
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if length > MAX_BODY {
+        warn!(
+            "refusing {} {} with a body of {} bytes",
+            method, path, length
+        );
+        return Ok(Err(Response::error(
+            "413 Payload Too Large",
+            format!("bodies are limited to {} bytes", MAX_BODY),
+        )));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request { method, path, body }))
+}
+
+fn respond(request: &Request, job: &mut Option<Job>) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/job") => submit(&request.body, job),
+        ("GET", "/job") => match job {
+            Some(job) => Response::json(&status(job)),
+            None => Response::error("404 Not Found", "no job has been submitted"),
+        },
+        ("DELETE", "/job") => match job {
+            Some(job) => {
+                job.control.stop.store(true, Ordering::Relaxed);
+                Response::json(&status(job))
+            }
+            None => Response::error("404 Not Found", "no job has been submitted"),
+        },
+        ("GET", "/job/renders") => match fs::read_dir(FOLDER) {
+            Ok(entries) => Response::json(
+                &entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .collect::<Vec<_>>(),
+            ),
+            Err(e) => Response::error("404 Not Found", e.to_string()),
+        },
+        ("GET", path) => match path.strip_prefix("/job/renders/") {
+            // only plain file names, so nothing outside the render folder can be read
+            Some(name) if !name.contains(['/', '\\']) && name != ".." => {
+                render(&Path::new(FOLDER).join(name))
+            }
+            _ => Response::error("404 Not Found", "unknown path"),
+        },
+        _ => Response::error("404 Not Found", "unknown path"),
+    }
+}
+
+fn status(job: &Job) -> Status {
+    Status {
+        id: job.id,
+        state: *job.state.lock().unwrap(),
+        progress: job.control.progress.lock().unwrap().clone(),
+    }
+}
+
+fn render(path: &Path) -> Response {
+    match fs::read(path) {
+        Ok(body) => Response {
+            status: "200 OK",
+            content_type: match path.extension().and_then(|e| e.to_str()) {
+                Some("png") => "image/png",
+                Some("svg") => "image/svg+xml",
+                Some("jsonl") => "application/x-ndjson",
+                _ => "application/octet-stream",
+            },
+            body,
+        },
+        Err(e) => Response::error("404 Not Found", e.to_string()),
+    }
+}
+
+/// the room data is checked before responding, but anything that goes wrong while solving only shows up as failed
+fn submit(body: &[u8], job: &mut Option<Job>) -> Response {
+    if let Some(job) = job {
+        if *job.state.lock().unwrap() == State::Running {
+            return Response::error("409 Conflict", format!("job {} is still running", job.id));
+        }
+    }
+    let submit: Submit = match serde_json::from_slice(body) {
+        Ok(submit) => submit,
+        Err(e) => return Response::error("400 Bad Request", e.to_string()),
+    };
+    let options = match parse_args(&submit.args) {
+        Ok(options) => options,
+        Err(message) => return Response::error("400 Bad Request", message),
+    };
+    let graph = match rooms::parse(&submit.rooms.to_string(), &options) {
+        Ok(graph) => graph,
+        Err(e) => return Response::error("400 Bad Request", e.to_string()),
+    };
+
+    let id = job.as_ref().map_or(1, |job| job.id + 1);
+    let control = Arc::new(Control::default());
+    let state = Arc::new(Mutex::new(State::Running));
+    *job = Some(Job {
+        id,
+        control: control.clone(),
+        state: state.clone(),
+    });
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            optimize(&prepare(graph, &options), &options, &control)
+        }));
+        *state.lock().unwrap() = match result {
+            Ok(_) if control.stop.load(Ordering::Relaxed) => State::Stopped,
+            Ok(Some(_)) => State::Done,
+            Ok(None) | Err(_) => State::Failed,
+        };
+        info!("job {} finished", id);
+    });
+    info!("started job {}", id);
+    Response::json(&status(job.as_ref().unwrap()))
+}
+
+/// only options that tune the solve, not ones that touch the server's files or network, or subcommands
+fn parse_args(args: &str) -> Result<Options, String> {
+    let matches = Options::command()
+        .try_get_matches_from(iter::once("fez-route").chain(args.split_whitespace()))
+        .map_err(|e| e.to_string())?;
+    if let Some((name, _)) = matches.subcommand() {
+        return Err(format!("subcommand {} can't be run remotely", name));
+    }
+    if let Some(id) = REFUSED
+        .iter()
+        .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+    {
+        return Err(format!("option --{} can't be set remotely", id));
+    }
+    Options::from_arg_matches(&matches).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refused_options_exist() {
+        let command = Options::command();
+        REFUSED.iter().for_each(|id| {
+            assert!(
+                command.get_arguments().any(|arg| arg.get_id() == *id),
+                "{}",
+                id
+            )
+        });
+    }
+
+    #[test]
+    fn remote_args_only_tune_the_solve() {
+        assert!(parse_args("--required-bits 16 --seed 3").is_ok());
+        assert!(parse_args("--cut-pool /etc/cron.d/pool").is_err());
+        assert!(parse_args("--webhook=http://example.com").is_err());
+        assert!(parse_args("doctor").is_err());
+    }
+}
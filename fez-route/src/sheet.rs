@@ -0,0 +1,285 @@
+//! routes as the csv rows community spreadsheets track them in, one row per room and action with the frames expected,
+//! so known routes can seed the solver and its routes can be pasted into a sheet for review
+
+use crate::common::{endpoints, long_load_edges, LONG_LOAD};
+use crate::heuristic::Route;
+use crate::rooms::{Edge, Node};
+use crate::topology::{topology_hash, Mismatch, TopologyHash};
+use crate::units::{Bits, Frames};
+use log::*;
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "room,action,frames";
+
+/// exported sheets end their header with the graph they were made for, which imports don't need
+const TOPOLOGY: &str = "topology ";
+
+/// expected frames further off than this from the room data are reported when importing
+const MISMATCH_FRAMES: f64 = 1.0;
+
+/// the row number and frames of a row that says what it expects
+type Expected = Option<(usize, Frames)>;
+
+/// one row per node, starting with the first node, which takes no frames.
+/// the frames of a row include the edge into it and the long load of the first warp to each hub
+pub fn export(graph: &StableGraph<Node, Edge>, route: &Route) -> String {
+    let (first_node, _) = endpoints(graph);
+    let long_loads = long_load_edges(graph, route.edges.iter().copied());
+    let mut csv = format!("{},{}{}\n", HEADER, TOPOLOGY, topology_hash(graph));
+    let mut row = |node: &Node, time: Frames| {
+        let (room, action) = split(&node.name);
+        csv += &format!("{},{},{}\n", quote(room), quote(action), time);
+    };
+    row(&graph[first_node], Frames(0.0));
+    route.edges.iter().for_each(|e| {
+        let target = graph.edge_endpoints(*e).unwrap().1;
+        let long_load = if long_loads.contains(e) {
+            LONG_LOAD
+        } else {
+            Frames(0.0)
+        };
+        row(
+            &graph[target],
+            graph[*e].time + graph[target].time + long_load,
+        );
+    });
+    csv
+}
+
+/// the route through the rows' nodes in order. the header and the first node's row are optional,
+/// and rows whose expected frames differ from the room data are only logged, since sheets are often out of date
+pub fn import(
+    graph: &StableGraph<Node, Edge>,
+    csv: &str,
+    required_bits: Bits,
+) -> Result<Route, String> {
+    let (nodes, expected) = rows(graph, csv)?;
+    let edges = nodes
+        .windows(2)
+        .map(|pair| {
+            graph.find_edge(pair[0], pair[1]).ok_or_else(|| {
+                format!(
+                    "no edge from {} to {}",
+                    graph[pair[0]].name, graph[pair[1]].name
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let long_loads = long_load_edges(graph, edges.iter().copied());
+    edges
+        .iter()
+        .zip(&expected[1..])
+        .filter_map(|(e, expected)| expected.map(|(row, frames)| (e, row, frames)))
+        .for_each(|(e, row, frames)| {
+            let target = graph.edge_endpoints(*e).unwrap().1;
+            let mut time = graph[*e].time + graph[target].time;
+            if long_loads.contains(e) {
+                time += LONG_LOAD;
+            }
+            if (time - frames).0.abs() > MISMATCH_FRAMES {
+                info!(
+                    "row {}: the sheet expects {} frames at {}, the room data takes {}",
+                    row, frames, graph[target].name, time
+                );
+            }
+        });
+    Ok(Route::from_path(graph, edges, required_bits))
+}
+
+/// the node of each row, starting with the first node, and the row number and frames each row expects if it says.
+/// the header and the first node's row are optional
+pub(crate) fn rows(
+    graph: &StableGraph<Node, Edge>,
+    csv: &str,
+) -> Result<(Vec<NodeIndex>, Vec<Expected>), String> {
+    let (first_node, _) = endpoints(graph);
+    let by_name: HashMap<&str, NodeIndex> = graph
+        .node_indices()
+        .map(|n| (graph[n].name.as_str(), n))
+        .collect();
+    let mut nodes = vec![first_node];
+    let mut expected = vec![None];
+    for (i, line) in csv.lines().enumerate() {
+        let fields = fields(line);
+        match fields.as_slice() {
+            [] => continue,
+            [first, ..] if i == 0 && first.eq_ignore_ascii_case("room") => continue,
+            [room, action, rest @ ..] => {
+                let name = format!("{}.{}", room.trim(), action.trim());
+                let n = *by_name
+                    .get(name.as_str())
+                    .ok_or_else(|| format!("row {}: no node named {}", i + 1, name))?;
+                let frames = match rest.first().map(|s| s.trim()) {
+                    None | Some("") => None,
+                    Some(frames) => Some(
+                        frames
+                            .parse::<Frames>()
+                            .map_err(|e| format!("row {}: {}: {}", i + 1, frames, e))?,
+                    ),
+                };
+                // the first node is what the route starts from, not a step of it
+                if n == first_node && nodes.len() == 1 {
+                    continue;
+                }
+                nodes.push(n);
+                expected.push(frames.map(|frames| (i + 1, frames)));
+            }
+            _ => return Err(format!("row {}: expected room,action,frames", i + 1)),
+        }
+    }
+    Ok((nodes, expected))
+}
+
+/// the route of a sheet file if it is feasible, otherwise none with the reason logged.
+/// a sheet exported for a different graph is ignored
+pub fn load(graph: &StableGraph<Node, Edge>, path: &Path, required_bits: Bits) -> Option<Route> {
+    let csv = fs::read_to_string(path);
+    if let Some(stale) = csv.as_ref().ok().and_then(|csv| mismatch(graph, path, csv)) {
+        warn!("ignoring the route sheet, {}", stale);
+        return None;
+    }
+    match csv
+        .map_err(|e| e.to_string())
+        .and_then(|csv| import(graph, &csv, required_bits))
+    {
+        Ok(route) if route.feasible => {
+            info!("imported a route of {} frames from {:?}", route.time, path);
+            Some(route)
+        }
+        Ok(route) => {
+            warn!(
+                "the route of {} frames imported from {:?} is not feasible",
+                route.time, path
+            );
+            None
+        }
+        Err(e) => {
+            error!("failed to import route sheet {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+pub fn save(path: &Path, graph: &StableGraph<Node, Edge>, route: &Route) {
+    match fs::write(path, export(graph, route)) {
+        Ok(()) => info!("saved route sheet to {:?}", path),
+        Err(e) => error!("failed to write route sheet to {:?}: {}", path, e),
+    }
+}
+
+/// the header of an exported sheet says which graph it was made for, other sheets can only be checked by name
+fn mismatch(graph: &StableGraph<Node, Edge>, path: &Path, csv: &str) -> Option<Mismatch> {
+    let header = fields(csv.lines().next()?);
+    let found = header
+        .get(3)?
+        .strip_prefix(TOPOLOGY)?
+        .parse::<TopologyHash>()
+        .ok()?;
+    let expected = topology_hash(graph);
+    (found != expected).then(|| Mismatch {
+        path: path.to_owned(),
+        expected,
+        found: Some(found),
+    })
+}
+
+/// the room part of a node's name, and the rest
+fn split(name: &str) -> (&str, &str) {
+    name.split_once('.').unwrap_or((name, ""))
+}
+
+/// quoted only if the field would otherwise be split or misread
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// the fields of a csv line, where quoted fields may contain commas and doubled quotes
+pub(crate) fn fields(line: &str) -> Vec<String> {
+    if line.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, time: f64) -> Node {
+        Node {
+            name: name.to_owned(),
+            time: Frames(time),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sheets_round_trip() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", 0.0));
+        let chest = graph.add_node(node("a.chest.jump", 10.0));
+        let end = graph.add_node(node("b.end", 0.0));
+        let edge = |time| Edge { time: Frames(time) };
+        let into = graph.add_edge(start, chest, edge(20.0));
+        let out = graph.add_edge(chest, end, edge(5.0));
+        graph.add_edge(start, end, edge(100.0));
+
+        let route = Route::from_path(&graph, vec![into, out], Bits(0));
+        let csv = export(&graph, &route);
+        assert_eq!(
+            csv,
+            format!(
+                "room,action,frames,topology {}\na,start,0\na,chest.jump,30\nb,end,5\n",
+                topology_hash(&graph)
+            )
+        );
+        assert!(mismatch(&graph, Path::new("route.csv"), &csv).is_none());
+        assert_eq!(import(&graph, &csv, Bits(0)).unwrap().edges, [into, out]);
+
+        // spreadsheets often leave out the header, the start, and the frames
+        let sheet = "\"a\",\"chest.jump\"\n\nb,end,\n";
+        assert_eq!(import(&graph, sheet, Bits(0)).unwrap().edges, [into, out]);
+        assert_eq!(
+            import(&graph, "b,end\na,chest.jump", Bits(0)).unwrap_err(),
+            "no edge from b.end to a.chest.jump"
+        );
+        assert_eq!(
+            import(&graph, "a,chest", Bits(0)).unwrap_err(),
+            "row 1: no node named a.chest"
+        );
+
+        graph.add_node(node("b.secret", 0.0));
+        assert!(mismatch(&graph, Path::new("route.csv"), &csv).is_some());
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(
+            fields(r#"a,"b,c","say ""hi""""#),
+            ["a", "b,c", "say \"hi\""]
+        );
+        assert_eq!(quote("b,c"), "\"b,c\"");
+        assert!(fields("  ").is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::common::endpoints;
+use crate::formulation::Model;
+use crate::heuristic::Route;
+use crate::lns::{ordered_path, LazyCuts};
+use crate::opt::{self, build_problem, resolve_options, within_limits, Control};
+use crate::options::Options;
+use crate::rooms::{Edge, Node, RouteGraph};
+use crate::units::Frames;
+use glpk::*;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::Incoming;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+const HELP: &str = "\
+fix <from> <to>      the route has to take the edge
+ban <from> <to>      the route can't take the edge
+free <from> <to>     undo a fix or ban
+time <node> <frames> change how many frames the node takes
+solve                solve again, starting from the current route if it still fits
+route                print the current route
+help                 print this
+quit                 leave the shell";
+
+/// one line typed into the shell
+#[derive(Clone, Debug, PartialEq)]
+enum Query {
+    Fix(String, String),
+    Ban(String, String),
+    Free(String, String),
+    Time(String, Frames),
+    Solve,
+    Route,
+    Help,
+    Quit,
+}
+
+impl FromStr for Query {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let edge = |make: fn(String, String) -> Query| match words[1..] {
+            [from, to] => Ok(make(from.to_owned(), to.to_owned())),
+            _ => Err(format!("{} takes the names of two nodes", words[0])),
+        };
+        match words.first().copied() {
+            Some("fix") => edge(Query::Fix),
+            Some("ban") => edge(Query::Ban),
+            Some("free") => edge(Query::Free),
+            Some("time") => match words[1..] {
+                [node, frames] => Ok(Query::Time(
+                    node.to_owned(),
+                    frames.parse().map_err(|e| format!("{}: {}", frames, e))?,
+                )),
+                _ => Err("time takes the name of a node and frames".to_owned()),
+            },
+            Some("solve") => Ok(Query::Solve),
+            Some("route") => Ok(Query::Route),
+            Some("help") => Ok(Query::Help),
+            Some("quit" | "exit") => Ok(Query::Quit),
+            Some(other) => Err(format!("unknown command {}, try help", other)),
+            None => Err("try help".to_owned()),
+        }
+    }
+}
+
+/// solves the route, then reads what-if queries from stdin until it ends.
+/// the model is built once and changed in place, like large neighborhood search does,
+/// so cuts found by earlier solves are kept. returns whether the first solve found a route
+pub fn run(graph: &RouteGraph, options: &Options) -> bool {
+    let mut route = match opt::optimize(graph, options, &Control::default()) {
+        Some(route) => route,
+        None => {
+            error!("found no route to explore from");
+            return false;
+        }
+    };
+    let (first_node, last_node) = endpoints(graph);
+    let (mut problem, vars) = build_problem(graph, first_node, last_node, options);
+    // node times are changed on a copy, so routes are measured with them
+    let mut times = graph.clone().into_inner();
+    let mut fixed = HashMap::<EdgeIndex, bool>::new();
+    println!(
+        "best route takes {} frames, type help for commands",
+        route.time
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return true,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let query = match line.parse::<Query>() {
+            Ok(query) => query,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let result = match query {
+            Query::Fix(from, to) => find_edge(&times, &from, &to).map(|e| {
+                fixed.insert(e, true);
+                problem.set_bounds(vars.registry.edge_var(e), Bounds::Fixed(1.0));
+            }),
+            Query::Ban(from, to) => find_edge(&times, &from, &to).map(|e| {
+                fixed.insert(e, false);
+                problem.set_bounds(vars.registry.edge_var(e), Bounds::Fixed(0.0));
+            }),
+            Query::Free(from, to) => find_edge(&times, &from, &to).map(|e| {
+                fixed.remove(&e);
+                let bounds = if vars.unusable.contains(&e) {
+                    Bounds::Fixed(0.0)
+                } else {
+                    Bounds::Double(0.0, 1.0)
+                };
+                problem.set_bounds(vars.registry.edge_var(e), bounds);
+            }),
+            Query::Time(name, frames) => find_node(&times, &name).map(|n| {
+                println!("{} took {} frames, now {}", name, times[n].time, frames);
+                times[n].time = frames;
+                let incoming: Vec<(EdgeIndex, Frames)> = times
+                    .edges_directed(n, Incoming)
+                    .map(|e| (e.id(), e.weight().time))
+                    .collect();
+                incoming.into_iter().for_each(|(e, time)| {
+                    problem.set_objective(vars.registry.edge_var(e), f64::from(time + frames))
+                });
+            }),
+            Query::Solve => {
+                let before = route.clone();
+                let warm_start =
+                    Route::from_path(&times, route.edges.clone(), options.required_bits);
+                let warm_start = Some(warm_start).filter(|route| {
+                    route.feasible
+                        && within_limits(&times, options, route)
+                        && fixed
+                            .iter()
+                            .all(|(e, taken)| route.edges.contains(e) == *taken)
+                });
+                let model = Model {
+                    graph,
+                    vars: &vars.registry,
+                    first_node,
+                    last_node,
+                    options,
+                };
+                let mut callback = Resolve {
+                    cuts: LazyCuts::new(model, &vars.formulation),
+                    warm_start: warm_start
+                        .map(|route| vars.solution(&times, &problem, first_node, &route)),
+                };
+                match problem.optimize_mip(&resolve_options(options), &mut callback) {
+                    Ok(()) => {
+                        let chosen = vars.registry.edge_set(&problem.get_int_solution());
+                        match ordered_path(&times, first_node, last_node, &chosen) {
+                            Some(path) => {
+                                route = Route::from_path(&times, path, options.required_bits);
+                                print_delta(&times, &before, &route);
+                                Ok(())
+                            }
+                            None => Err("the solution is not a single path".to_owned()),
+                        }
+                    }
+                    Err(e) => Err(format!("no route with these changes: {:?}", e)),
+                }
+            }
+            Query::Route => {
+                print_route(&times, &route);
+                Ok(())
+            }
+            Query::Help => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            Query::Quit => return true,
+        };
+        if let Err(e) = result {
+            println!("{}", e);
+        }
+    }
+}
+
+/// the lazy cuts of the formulation, and the current route offered once as the initial incumbent
+struct Resolve<'m> {
+    cuts: LazyCuts<'m>,
+    warm_start: Option<Solution>,
+}
+impl<'m> MipCallback for Resolve<'m> {
+    fn get_lazy_expr(&mut self, problem: &Prob) -> Option<Expr> {
+        self.cuts.get_lazy_expr(problem)
+    }
+
+    fn get_heuristic_solution(&mut self, _problem: &Prob) -> Option<Solution> {
+        self.warm_start.take()
+    }
+}
+
+fn find_node(graph: &StableGraph<Node, Edge>, name: &str) -> Result<NodeIndex, String> {
+    graph
+        .node_indices()
+        .find(|n| graph[*n].name == name)
+        .ok_or_else(|| format!("no node named {}", name))
+}
+
+fn find_edge(graph: &StableGraph<Node, Edge>, from: &str, to: &str) -> Result<EdgeIndex, String> {
+    graph
+        .find_edge(find_node(graph, from)?, find_node(graph, to)?)
+        .ok_or_else(|| format!("no edge from {} to {}", from, to))
+}
+
+fn edge_name(graph: &StableGraph<Node, Edge>, e: EdgeIndex) -> String {
+    let (source, target) = graph.edge_endpoints(e).unwrap();
+    format!("{} -> {}", graph[source].name, graph[target].name)
+}
+
+fn print_route(graph: &StableGraph<Node, Edge>, route: &Route) {
+    route
+        .edges
+        .iter()
+        .for_each(|e| println!("{}", edge_name(graph, *e)));
+    println!("{} frames, {} bits", route.time, route.bits);
+}
+
+/// the edges the new route no longer takes and the ones it takes instead, and how the time changed
+fn print_delta(graph: &StableGraph<Node, Edge>, before: &Route, after: &Route) {
+    before
+        .edges
+        .iter()
+        .filter(|e| !after.edges.contains(e))
+        .for_each(|e| println!("- {}", edge_name(graph, *e)));
+    after
+        .edges
+        .iter()
+        .filter(|e| !before.edges.contains(e))
+        .for_each(|e| println!("+ {}", edge_name(graph, *e)));
+    println!(
+        "{} frames, {:+} from {}",
+        after.time,
+        f64::from(after.time - before.time),
+        before.time
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_parse() {
+        assert_eq!(
+            "fix a.start a.end".parse(),
+            Ok(Query::Fix("a.start".to_owned(), "a.end".to_owned()))
+        );
+        assert_eq!(
+            " time  a.zip 20.5 ".parse(),
+            Ok(Query::Time("a.zip".to_owned(), Frames(20.5)))
+        );
+        assert_eq!("quit".parse(), Ok(Query::Quit));
+        assert!("ban a.start".parse::<Query>().is_err());
+        assert!("time a.zip fast".parse::<Query>().is_err());
+        assert!("warp".parse::<Query>().is_err());
+    }
+}
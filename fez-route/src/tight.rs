@@ -0,0 +1,123 @@
+//! which constraints the best route only just meets, like collecting exactly the required bits,
+//! to show what is limiting the route. equalities like flow are always met exactly, so they aren't counted
+
+use crate::formulation::FamilyStats;
+use glpk::{Bounds, Prob};
+use itertools::Itertools;
+use log::*;
+
+const EPS: f64 = 1e-6;
+/// names listed for each family at info, the rest are only logged at debug
+const LISTED: usize = 5;
+
+/// the inequalities of a family and the names of those at one of their bounds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tightness {
+    pub family: String,
+    pub inequalities: usize,
+    pub tight: Vec<String>,
+}
+
+/// evaluates every family's exprs against the best integer solution
+pub(crate) fn tightness(problem: &Prob, families: &[FamilyStats]) -> Vec<Tightness> {
+    families
+        .iter()
+        .map(|family| {
+            let mut inequalities = 0;
+            let tight = family
+                .refs
+                .iter()
+                .filter(|expr| {
+                    let bounds = problem.get_expr_bounds(*expr);
+                    let inequality = !matches!(bounds, Bounds::Fixed(_));
+                    if inequality {
+                        inequalities += 1;
+                    }
+                    inequality && is_tight(problem.get_int_expr_value(*expr), bounds)
+                })
+                .map(|expr| problem.get_expr_name(expr))
+                .collect();
+            Tightness {
+                family: family.name.clone(),
+                inequalities,
+                tight,
+            }
+        })
+        .collect()
+}
+
+/// if the value is at either of its bounds
+fn is_tight(value: f64, bounds: Bounds) -> bool {
+    let at = |bound: f64| (value - bound).abs() <= EPS * bound.abs().max(1.0);
+    match bounds {
+        Bounds::Free => false,
+        Bounds::Lower(lower) => at(lower),
+        Bounds::Upper(upper) => at(upper),
+        Bounds::Double(lower, upper) => at(lower) || at(upper),
+        Bounds::Fixed(fixed) => at(fixed),
+    }
+}
+
+/// a line for each family with inequalities, naming the first few that are tight
+pub fn report(tightness: &[Tightness]) -> Vec<String> {
+    tightness
+        .iter()
+        .filter(|family| family.inequalities > 0)
+        .map(|family| {
+            let mut line = format!(
+                "{}: {} of {} tight",
+                family.family,
+                family.tight.len(),
+                family.inequalities
+            );
+            if !family.tight.is_empty() {
+                line += &format!(", {}", family.tight.iter().take(LISTED).join(", "));
+            }
+            if family.tight.len() > LISTED {
+                line += &format!(" and {} more", family.tight.len() - LISTED);
+            }
+            line
+        })
+        .collect()
+}
+
+/// logs the report, and every tight name at debug
+pub(crate) fn log(problem: &Prob, families: &[FamilyStats]) {
+    let tightness = tightness(problem, families);
+    report(&tightness)
+        .iter()
+        .for_each(|line| info!("tight {}", line));
+    tightness.iter().for_each(|family| {
+        family
+            .tight
+            .iter()
+            .for_each(|name| debug!("tight {} {}", family.family, name))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bounds_that_are_met_are_tight() {
+        assert!(is_tight(400.0, Bounds::Lower(400.0)));
+        assert!(!is_tight(401.0, Bounds::Lower(400.0)));
+        assert!(is_tight(1.0 - 1e-9, Bounds::Upper(1.0)));
+        assert!(is_tight(0.0, Bounds::Double(0.0, 5.0)));
+        assert!(!is_tight(0.0, Bounds::Free));
+
+        let family = |tight: usize| Tightness {
+            family: "capacity".to_owned(),
+            inequalities: 10,
+            tight: (0..tight).map(|i| format!("n{}", i)).collect(),
+        };
+        assert_eq!(
+            report(&[family(0), family(7)]),
+            [
+                "capacity: 0 of 10 tight",
+                "capacity: 7 of 10 tight, n0, n1, n2, n3, n4 and 2 more"
+            ]
+        );
+    }
+}
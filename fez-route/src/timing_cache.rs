@@ -0,0 +1,102 @@
+use crate::units::Frames;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+/// node to node times within each room, saved so that only rooms whose data changed are timed again.
+///
+/// rooms are keyed by name and remember a hash of the data their times were made from,
+/// so an entry is replaced once that room changes and dropped once the room is gone
+pub struct TimingCache {
+    path: PathBuf,
+    loaded: HashMap<String, CachedRoom>,
+    used: HashMap<String, CachedRoom>,
+    computed: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedRoom {
+    #[serde(with = "crate::topology::hex")]
+    hash: u64,
+    /// from, to, frames
+    times: Vec<(String, String, f64)>,
+}
+
+impl TimingCache {
+    pub fn load(path: &Path) -> Self {
+        let loaded = match read_rooms(path) {
+            Ok(loaded) => loaded,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("ignoring unreadable timing cache {:?}: {}", path, e);
+                HashMap::new()
+            }
+        };
+        info!("loaded timings for {} rooms from {:?}", loaded.len(), path);
+        TimingCache {
+            path: path.to_owned(),
+            loaded,
+            used: HashMap::new(),
+            computed: 0,
+        }
+    }
+
+    /// the cached times for a room if its hash still matches, otherwise the newly computed times
+    pub fn get_or_compute(
+        &mut self,
+        room: &str,
+        hash: u64,
+        compute: impl FnOnce() -> Vec<(String, String, Frames)>,
+    ) -> Vec<(String, String, Frames)> {
+        let cached = match self.loaded.remove(room) {
+            Some(cached) if cached.hash == hash => cached,
+            _ => {
+                self.computed += 1;
+                CachedRoom {
+                    hash,
+                    times: compute()
+                        .into_iter()
+                        .map(|(from, to, time)| (from, to, f64::from(time)))
+                        .collect(),
+                }
+            }
+        };
+        let times = cached
+            .times
+            .iter()
+            .map(|(from, to, time)| (from.clone(), to.clone(), Frames(*time)))
+            .collect();
+        self.used.insert(room.to_owned(), cached);
+        times
+    }
+
+    /// rewrites the cache with only the rooms that were used, if anything changed
+    pub fn save(&self) {
+        if self.computed == 0 && self.loaded.is_empty() {
+            return;
+        }
+        if let Err(e) = self.try_save() {
+            error!("failed to write timing cache {:?}: {}", self.path, e);
+        } else {
+            info!(
+                "saved timings to {:?}, {} rooms were timed again",
+                self.path, self.computed
+            );
+        }
+    }
+
+    fn try_save(&self) -> io::Result<()> {
+        if let Some(folder) = self.path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        serde_json::to_writer(File::create(&self.path)?, &self.used)?;
+        Ok(())
+    }
+}
+
+fn read_rooms(path: &Path) -> io::Result<HashMap<String, CachedRoom>> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
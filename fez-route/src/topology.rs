@@ -0,0 +1,131 @@
+use crate::rooms::{Edge, Node};
+use petgraph::stable_graph::StableGraph;
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// stable hash of everything about a graph except its timings:
+/// node names and cost kinds, and which nodes are connected by edges
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TopologyHash(#[serde(with = "hex")] u64);
+impl fmt::Display for TopologyHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+impl FromStr for TopologyHash {
+    type Err = ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(TopologyHash)
+    }
+}
+
+/// u64 doesn't fit in a json number, so store it the same way it is displayed
+pub mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(hash: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:016x}", hash))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let s = <&str>::deserialize(deserializer)?;
+        u64::from_str_radix(s, 16).map_err(D::Error::custom)
+    }
+}
+
+/// uses fnv-1a, which unlike `DefaultHasher` is stable between builds
+pub fn topology_hash(graph: &StableGraph<Node, Edge>) -> TopologyHash {
+    let mut parts: Vec<String> = graph
+        .node_weights()
+        .map(|n| {
+            format!(
+                "{}:{:?}:{:?}:{:?}:{:?}:{:?}",
+                n.name, n.cost, n.oneof, n.phase, n.activates, n.requires_activation
+            )
+        })
+        .collect();
+    parts.extend(
+        graph
+            .edge_references()
+            .map(|e| format!("{}->{}", graph[e.source()].name, graph[e.target()].name)),
+    );
+    parts.sort();
+    TopologyHash(fnv1a(parts.iter().map(String::as_str)))
+}
+
+/// fnv-1a of each part followed by a 0 byte, so the parts can't run together
+pub fn fnv1a<'a>(parts: impl IntoIterator<Item = &'a str>) -> u64 {
+    parts.into_iter().fold(0xcbf29ce484222325, |hash, part| {
+        part.bytes()
+            .chain(std::iter::once(0))
+            .fold(hash, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(0x100000001b3)
+            })
+    })
+}
+
+/// first line of every saved artifact, recording which graph it was made for
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub topology: TopologyHash,
+}
+impl Header {
+    pub fn new(graph: &StableGraph<Node, Edge>) -> Self {
+        Header {
+            topology: topology_hash(graph),
+        }
+    }
+
+    /// parses and checks the first line of an artifact
+    pub fn verify(
+        path: &Path,
+        line: &str,
+        graph: &StableGraph<Node, Edge>,
+    ) -> Result<(), Mismatch> {
+        let expected = topology_hash(graph);
+        match serde_json::from_str::<Header>(line) {
+            Ok(header) if header.topology == expected => Ok(()),
+            Ok(header) => Err(Mismatch {
+                path: path.to_owned(),
+                expected,
+                found: Some(header.topology),
+            }),
+            Err(_) => Err(Mismatch {
+                path: path.to_owned(),
+                expected,
+                found: None,
+            }),
+        }
+    }
+}
+
+/// an artifact that was saved for a different graph than the one currently loaded
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub path: PathBuf,
+    pub expected: TopologyHash,
+    pub found: Option<TopologyHash>,
+}
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.found {
+            Some(found) => write!(
+                f,
+                "{:?} was made for graph {} but the current graph is {}, the room data has changed since it was saved",
+                self.path, found, self.expected
+            ),
+            None => write!(
+                f,
+                "{:?} has no topology header, so it can't be checked against the current graph {}",
+                self.path, self.expected
+            ),
+        }
+    }
+}
+impl std::error::Error for Mismatch {}
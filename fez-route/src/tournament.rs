@@ -0,0 +1,230 @@
+use crate::opt::{optimize, Control};
+use crate::options::Options;
+use crate::units::Frames;
+use crate::{prepare, rooms};
+use itertools::Itertools;
+use log::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// every combination of one value from each axis is an entry, and each value is extra command line options.
+/// an empty value keeps the defaults
+///
+/// ```toml
+/// branching = ["--branch-direction up", "--branch-direction deficit"]
+/// families = ["", "--disable-family dominator", "--enable-family no-3-cycles"]
+/// heuristic = ["", "--lns-iterations 0"]
+/// ```
+pub type Matrix = BTreeMap<String, Vec<String>>;
+
+/// how far one entry got within its time cap
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub args: Vec<String>,
+    /// time of the best route found
+    pub incumbent: Option<Frames>,
+    /// relative gap between the incumbent and the best bound, when it was stopped
+    pub gap: Option<f64>,
+    /// the incumbent was proven optimal
+    pub proved: bool,
+    pub tree_nodes: i32,
+    pub seconds: f64,
+}
+
+/// runs every entry of the matrix for at most the given seconds, with the options before the subcommand
+/// applying to all of them, then logs the entries from best to worst. returns whether the matrix could be read
+pub fn run(matrix: &Path, seconds: u64) -> bool {
+    let matrix: Matrix = match fs::read_to_string(matrix)
+        .map_err(|e| e.to_string())
+        .and_then(|matrix| toml::from_str(&matrix).map_err(|e| e.to_string()))
+    {
+        Ok(matrix) => matrix,
+        Err(e) => {
+            error!("failed to read tournament matrix {:?}: {}", matrix, e);
+            return false;
+        }
+    };
+    let base: Vec<OsString> = env::args_os()
+        .take_while(|arg| arg != "tournament")
+        .collect();
+    let entries = entries(&matrix);
+    info!(
+        "running {} entries for at most {} seconds each",
+        entries.len(),
+        seconds
+    );
+    let mut results: Vec<Entry> = entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, args)| {
+            info!("entry {}: {}", i + 1, describe(&args));
+            let mut options = Options::load_from(
+                base.iter().cloned().chain(
+                    args.iter()
+                        .flat_map(|arg| arg.split_whitespace())
+                        .map(OsString::from),
+                ),
+            );
+            // every entry would render over the others
+            options.no_render = true;
+            play(args, options, Duration::from_secs(seconds))
+        })
+        .collect();
+    rank(&mut results);
+    log_ranking(&results);
+    true
+}
+
+/// one line per entry, in the order they were ranked
+pub(crate) fn log_ranking(results: &[Entry]) {
+    info!("rank  incumbent       gap  proved  tree nodes  seconds  options");
+    results.iter().enumerate().for_each(|(i, entry)| {
+        info!(
+            "{:>4}  {:>9}  {:>8}  {:>6}  {:>10}  {:>7.1}  {}",
+            i + 1,
+            entry
+                .incumbent
+                .map_or_else(|| "-".to_owned(), |time| format!("{:.0}", time)),
+            entry
+                .gap
+                .map_or_else(|| "-".to_owned(), |gap| format!("{:.2}%", gap * 100.0)),
+            if entry.proved { "yes" } else { "no" },
+            entry.tree_nodes,
+            entry.seconds,
+            describe(&entry.args)
+        )
+    });
+}
+
+/// the values of every axis, combined
+fn entries(matrix: &Matrix) -> Vec<Vec<String>> {
+    if matrix.is_empty() {
+        return vec![Vec::new()];
+    }
+    matrix
+        .values()
+        .map(|values| values.iter().cloned())
+        .multi_cartesian_product()
+        .collect()
+}
+
+pub(crate) fn describe(args: &[String]) -> String {
+    let args = args.iter().filter(|arg| !arg.is_empty()).join(" ");
+    if args.is_empty() {
+        "defaults".to_owned()
+    } else {
+        args
+    }
+}
+
+/// solves on another thread, which is stopped once the time cap runs out
+pub(crate) fn play(args: Vec<String>, mut options: Options, cap: Duration) -> Entry {
+    // entries are compared, so none may start from the cuts an earlier one found
+    options.cut_pool = None;
+    let start = Instant::now();
+    let control = Arc::new(Control::default());
+    let (sender, receiver) = mpsc::channel();
+    let solver = {
+        let control = control.clone();
+        thread::spawn(move || {
+            let graph = prepare(rooms::load(&options.rooms, &options), &options);
+            let route = optimize(&graph, &options, &control);
+            // the receiver is gone if the tournament itself has stopped
+            let _ = sender.send(());
+            route
+        })
+    };
+    if receiver.recv_timeout(cap).is_err() {
+        control.stop.store(true, atomic::Ordering::Relaxed);
+    }
+    let route = solver.join().unwrap_or_else(|_| {
+        warn!("entry {} failed", describe(&args));
+        None
+    });
+    let progress = control.progress.lock().unwrap().clone();
+    let proved = progress.proved;
+    Entry {
+        args,
+        incumbent: route.map(|route| route.time).or(progress.incumbent),
+        gap: if proved { Some(0.0) } else { progress.gap },
+        proved,
+        tree_nodes: progress.nodes,
+        seconds: start.elapsed().as_secs_f64(),
+    }
+}
+
+/// the best incumbents first, then the proven ones, then the smallest gaps, then the fastest
+pub(crate) fn rank(entries: &mut [Entry]) {
+    fn missing_last<T: PartialOrd>(l: Option<T>, r: Option<T>) -> Ordering {
+        match (l, r) {
+            (Some(l), Some(r)) => l.partial_cmp(&r).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    entries.sort_by(|l, r| {
+        missing_last(l.incumbent, r.incumbent)
+            .then(r.proved.cmp(&l.proved))
+            .then(missing_last(l.gap, r.gap))
+            .then(l.seconds.partial_cmp(&r.seconds).unwrap_or(Ordering::Equal))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_combine_every_axis() {
+        let matrix: Matrix = toml::from_str(
+            r#"
+            branching = ["--branch-direction up", "--branch-direction deficit"]
+            families = ["", "--disable-family dominator"]
+            "#,
+        )
+        .unwrap();
+        let combined = entries(&matrix);
+        assert_eq!(combined.len(), 4);
+        assert_eq!(describe(&combined[0]), "--branch-direction up");
+        assert_eq!(
+            describe(&combined[3]),
+            "--branch-direction deficit --disable-family dominator"
+        );
+        assert_eq!(entries(&Matrix::new()), vec![Vec::<String>::new()]);
+    }
+
+    #[test]
+    fn better_incumbents_rank_first() {
+        let entry = |name: &str, incumbent: Option<f64>, proved, gap: Option<f64>| Entry {
+            args: vec![name.to_owned()],
+            incumbent: incumbent.map(Frames),
+            gap,
+            proved,
+            tree_nodes: 0,
+            seconds: 1.0,
+        };
+        let mut entries = vec![
+            entry("none", None, false, None),
+            entry("gap", Some(100.0), false, Some(0.1)),
+            entry("slow", Some(120.0), true, Some(0.0)),
+            entry("proved", Some(100.0), true, Some(0.0)),
+        ];
+        rank(&mut entries);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.args[0].as_str())
+                .collect::<Vec<_>>(),
+            ["proved", "gap", "slow", "none"]
+        );
+    }
+}
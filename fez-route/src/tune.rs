@@ -0,0 +1,246 @@
+//! a small random search over how glpk searches the tree, scored like a tournament entry by the best route
+//! found within a time cap, with the winner saved into the config file so later runs use it.
+//! glpk's presolver isn't one of the options tried, since it renumbers the vars and exprs the solve's callbacks
+//! refer to, so every trial solves without it
+
+use crate::options::{GlpkBacktracking, GlpkBranching, GlpkCut, Options};
+use crate::tournament::{describe, log_ranking, play, rank};
+use clap::ValueEnum;
+use log::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use toml::value::{Table, Value};
+
+/// config keys of the options a trial sets, which are replaced when saving one
+const KEYS: [&str; 4] = [
+    "glpk-branching",
+    "glpk-backtracking",
+    "glpk-cuts",
+    "no-glpk-cuts",
+];
+
+/// one set of glpk options to try
+#[derive(Clone, Debug, PartialEq)]
+struct Trial {
+    branching: GlpkBranching,
+    backtracking: GlpkBacktracking,
+    /// in the order glpk cuts are listed, so equal sets compare equal
+    cuts: Vec<GlpkCut>,
+}
+
+impl Trial {
+    fn current(options: &Options) -> Self {
+        Trial {
+            branching: options.glpk_branching,
+            backtracking: options.glpk_backtracking,
+            cuts: GlpkCut::value_variants()
+                .iter()
+                .copied()
+                .filter(|cut| !options.no_glpk_cuts && options.glpk_cuts.contains(cut))
+                .collect(),
+        }
+    }
+
+    fn random(rng: &mut StdRng) -> Self {
+        let branchings = GlpkBranching::value_variants();
+        let backtrackings = GlpkBacktracking::value_variants();
+        Trial {
+            branching: branchings[rng.gen_range(0..branchings.len())],
+            backtracking: backtrackings[rng.gen_range(0..backtrackings.len())],
+            cuts: GlpkCut::value_variants()
+                .iter()
+                .copied()
+                .filter(|_| rng.gen_bool(0.5))
+                .collect(),
+        }
+    }
+
+    /// the command line options of the trial, for describing it
+    fn args(&self) -> Vec<String> {
+        let cuts = if self.cuts.is_empty() {
+            "--no-glpk-cuts".to_owned()
+        } else {
+            self.cuts
+                .iter()
+                .map(|cut| format!("--glpk-cuts {}", name(*cut)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        vec![
+            format!("--glpk-branching {}", name(self.branching)),
+            format!("--glpk-backtracking {}", name(self.backtracking)),
+            cuts,
+        ]
+    }
+
+    fn apply(&self, options: &mut Options) {
+        options.glpk_branching = self.branching;
+        options.glpk_backtracking = self.backtracking;
+        options.glpk_cuts = self.cuts.clone();
+        options.no_glpk_cuts = self.cuts.is_empty();
+    }
+
+    /// replaces the options of any earlier trial in the config, whether written in kebab or snake case
+    fn configure(&self, config: &mut Table) {
+        let earlier: Vec<String> = config
+            .keys()
+            .filter(|key| KEYS.contains(&key.replace('_', "-").as_str()))
+            .cloned()
+            .collect();
+        earlier.iter().for_each(|key| {
+            config.remove(key);
+        });
+        let mut set = |key: &str, value: Value| config.insert(key.to_owned(), value);
+        set("glpk-branching", Value::String(name(self.branching)));
+        set("glpk-backtracking", Value::String(name(self.backtracking)));
+        if self.cuts.is_empty() {
+            set("no-glpk-cuts", Value::Boolean(true));
+        } else {
+            set(
+                "glpk-cuts",
+                Value::Array(
+                    self.cuts
+                        .iter()
+                        .map(|cut| Value::String(name(*cut)))
+                        .collect(),
+                ),
+            );
+        }
+    }
+}
+
+fn name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().unwrap().get_name().to_owned()
+}
+
+/// the current options followed by up to `count` different random ones
+fn trials(current: Trial, count: usize, seed: u64) -> Vec<Trial> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut trials = vec![current];
+    // there are only a few hundred sets of options, so stop drawing long before repeats are all that's left
+    for _ in 0..count * 20 {
+        if trials.len() > count {
+            break;
+        }
+        let trial = Trial::random(&mut rng);
+        if !trials.contains(&trial) {
+            trials.push(trial);
+        }
+    }
+    trials
+}
+
+/// runs the current glpk options and `count` random ones for at most the given seconds each,
+/// with the options before the subcommand applying to all of them, then saves the best into the config file.
+/// the config file is written back without its comments. returns whether any trial found a route
+pub fn run(options: &Options, count: usize, seconds: u64) -> bool {
+    let trials = trials(Trial::current(options), count, options.seed);
+    info!(
+        "running {} trials for at most {} seconds each",
+        trials.len(),
+        seconds
+    );
+    let mut results: Vec<_> = trials
+        .iter()
+        .enumerate()
+        .map(|(i, trial)| {
+            let args = trial.args();
+            info!("trial {}: {}", i + 1, describe(&args));
+            let mut options = options.clone();
+            trial.apply(&mut options);
+            // every trial would render over the others
+            options.no_render = true;
+            play(args, options, Duration::from_secs(seconds))
+        })
+        .collect();
+    rank(&mut results);
+    log_ranking(&results);
+
+    let best = &results[0];
+    if best.incumbent.is_none() {
+        error!("no trial found a route, so the config is left alone");
+        return false;
+    }
+    let trial = trials
+        .iter()
+        .find(|trial| trial.args() == best.args)
+        .unwrap();
+    if trial == &trials[0] {
+        info!("the current options did best, so the config is left alone");
+        return true;
+    }
+    save(&options.config, trial);
+    true
+}
+
+fn save(path: &Path, trial: &Trial) {
+    let mut config: Table = match fs::read_to_string(path) {
+        Ok(config) => match toml::from_str(&config) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("failed to parse config {:?}: {}", path, e);
+                return;
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Table::new(),
+        Err(e) => {
+            error!("failed to read config {:?}: {}", path, e);
+            return;
+        }
+    };
+    trial.configure(&mut config);
+    match fs::write(path, toml::to_string(&config).unwrap()) {
+        Ok(()) => info!("saved {} to {:?}", describe(&trial.args()), path),
+        Err(e) => error!("failed to write config {:?}: {}", path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trials_start_from_the_current_options_and_differ() {
+        let current = Trial {
+            branching: GlpkBranching::Driebeck,
+            backtracking: GlpkBacktracking::Bound,
+            cuts: vec![GlpkCut::Clique],
+        };
+        let trials = trials(current.clone(), 8, 0);
+        assert_eq!(trials.len(), 9);
+        assert_eq!(trials[0], current);
+        assert!(trials
+            .iter()
+            .enumerate()
+            .all(|(i, trial)| !trials[..i].contains(trial)));
+        assert_eq!(
+            describe(&current.args()),
+            "--glpk-branching driebeck --glpk-backtracking bound --glpk-cuts clique"
+        );
+    }
+
+    #[test]
+    fn saved_trials_replace_earlier_ones() {
+        let mut config: Table = toml::from_str(
+            r#"
+            seed = 3
+            glpk_cuts = ["gomory"]
+            "#,
+        )
+        .unwrap();
+        Trial {
+            branching: GlpkBranching::Pseudocost,
+            backtracking: GlpkBacktracking::Depth,
+            cuts: Vec::new(),
+        }
+        .configure(&mut config);
+        assert_eq!(
+            toml::to_string(&config).unwrap(),
+            "glpk-backtracking = \"depth\"\nglpk-branching = \"pseudocost\"\nno-glpk-cuts = true\nseed = 3\n"
+        );
+    }
+}
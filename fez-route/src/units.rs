@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::num::{ParseFloatError, ParseIntError};
+use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
+use std::str::FromStr;
+
+/// an amount of time, at 60 frames per second.
+/// only converted to a plain f64 when handed to GLPK
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Frames(pub f64);
+
+/// an amount of collectables, where a cube or anti cube is worth 8 bits.
+/// only converted to a plain f64 when handed to GLPK
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Bits(pub i32);
+
+impl Bits {
+    pub const CUBE: Bits = Bits(8);
+}
+
+impl From<Frames> for f64 {
+    fn from(frames: Frames) -> f64 {
+        frames.0
+    }
+}
+impl From<Bits> for f64 {
+    fn from(bits: Bits) -> f64 {
+        bits.0 as f64
+    }
+}
+
+impl fmt::Display for Frames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl fmt::Display for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl FromStr for Frames {
+    type Err = ParseFloatError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Frames)
+    }
+}
+impl FromStr for Bits {
+    type Err = ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Bits)
+    }
+}
+
+macro_rules! impl_arithmetic {
+    ($unit:ident, $inner:ty) => {
+        impl Add for $unit {
+            type Output = $unit;
+            fn add(self, rhs: $unit) -> $unit {
+                $unit(self.0 + rhs.0)
+            }
+        }
+        impl AddAssign for $unit {
+            fn add_assign(&mut self, rhs: $unit) {
+                self.0 += rhs.0;
+            }
+        }
+        impl Sub for $unit {
+            type Output = $unit;
+            fn sub(self, rhs: $unit) -> $unit {
+                $unit(self.0 - rhs.0)
+            }
+        }
+        impl SubAssign for $unit {
+            fn sub_assign(&mut self, rhs: $unit) {
+                self.0 -= rhs.0;
+            }
+        }
+        impl Mul<$inner> for $unit {
+            type Output = $unit;
+            fn mul(self, rhs: $inner) -> $unit {
+                $unit(self.0 * rhs)
+            }
+        }
+        impl Sum for $unit {
+            fn sum<I: Iterator<Item = $unit>>(iter: I) -> $unit {
+                iter.fold($unit::default(), Add::add)
+            }
+        }
+        impl<'a> Sum<&'a $unit> for $unit {
+            fn sum<I: Iterator<Item = &'a $unit>>(iter: I) -> $unit {
+                iter.copied().sum()
+            }
+        }
+    };
+}
+impl_arithmetic!(Frames, f64);
+impl_arithmetic!(Bits, i32);
@@ -0,0 +1,403 @@
+use crate::common::{endpoints, long_load_edges, LONG_LOAD};
+use crate::heuristic::Route;
+use crate::options::Options;
+use crate::rooms::{Action, Edge, Node};
+use crate::sheet;
+use crate::units::{Bits, Frames};
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// checks a route against every rule of the model independently of how it was found,
+/// returning every rule it breaks
+pub fn check_route(
+    graph: &StableGraph<Node, Edge>,
+    route: &Route,
+    options: &Options,
+) -> Result<(), Vec<String>> {
+    let (nodes, mut problems) = match path_nodes(graph, &route.edges) {
+        Ok(path) => path,
+        Err(problem) => return Err(vec![problem]),
+    };
+
+    let time = timeline(graph, &nodes, &route.edges).last().unwrap().time;
+    if (time - route.time).0.abs() > 1e-6 {
+        problems.push(format!("takes {} frames but claims {}", time, route.time));
+    }
+    let bits: Bits = nodes[1..].iter().map(|n| graph[*n].bits).sum();
+    if bits != route.bits {
+        problems.push(format!("collects {} bits but claims {}", bits, route.bits));
+    }
+
+    problems.extend(check_nodes(graph, &nodes, options));
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+/// every rule broken by visiting the nodes in order, starting from the first node
+fn check_nodes(
+    graph: &StableGraph<Node, Edge>,
+    nodes: &[NodeIndex],
+    options: &Options,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let visits = nodes.iter().counts();
+    nodes
+        .iter()
+        .unique()
+        .filter(|n| visits[n] > 1)
+        .for_each(|n| {
+            problems.push(format!(
+                "{} is visited {} times but can only be visited once",
+                graph[*n].name, visits[n]
+            ))
+        });
+
+    let bits: Bits = nodes[1..].iter().map(|n| graph[*n].bits).sum();
+    if bits < options.required_bits {
+        problems.push(format!(
+            "collects {} bits of the {} required",
+            bits, options.required_bits
+        ));
+    }
+
+    let cubes: i32 = nodes[1..].iter().map(|n| graph[*n].cubes).sum();
+    if options.required_cubes.is_some_and(|min| cubes < min as i32) {
+        problems.push(format!(
+            "collects {} whole cubes of the {} required",
+            cubes,
+            options.required_cubes.unwrap()
+        ));
+    }
+    let antis: i32 = nodes[1..].iter().map(|n| graph[*n].antis).sum();
+    if options.required_antis.is_some_and(|min| antis < min as i32) {
+        problems.push(format!(
+            "collects {} anti cubes of the {} required",
+            antis,
+            options.required_antis.unwrap()
+        ));
+    }
+    if options.max_antis.is_some_and(|max| antis > max as i32) {
+        problems.push(format!(
+            "collects {} anti cubes of at most {}",
+            antis,
+            options.max_antis.unwrap()
+        ));
+    }
+
+    let mut keys = graph[nodes[0]].keys_minus_lock();
+    nodes[1..].iter().for_each(|n| {
+        keys += graph[*n].keys_minus_lock();
+        if keys < 0 {
+            problems.push(format!("has no key for {}", graph[*n].name));
+        }
+    });
+
+    // the model only counts nodes that are entered, so the first node is left out like it is for bits
+    let mut oneofs = HashMap::<&str, &str>::new();
+    nodes[1..].iter().for_each(|n| {
+        if let Some(group) = &graph[*n].oneof {
+            if let Some(other) = oneofs.insert(group, &graph[*n].name) {
+                problems.push(format!(
+                    "visits both {} and {} of oneof {}",
+                    other, graph[*n].name, group
+                ));
+            }
+        }
+    });
+
+    [
+        (Action::Warp, options.max_warps),
+        (Action::Well, options.max_wells),
+    ]
+    .iter()
+    .filter_map(|(action, max)| max.map(|max| (action, max)))
+    .for_each(|(action, max)| {
+        let used = nodes[1..]
+            .iter()
+            .filter(|n| graph[**n].action == Some(*action))
+            .count();
+        if used > max as usize {
+            problems.push(format!("uses {} {:?}s of at most {}", used, action, max));
+        }
+    });
+    if let Some(max) = options.max_transitions {
+        if nodes.len() - 1 > max as usize {
+            problems.push(format!(
+                "takes {} edges of at most {}",
+                nodes.len() - 1,
+                max
+            ));
+        }
+    }
+
+    let mut levels = HashMap::<&str, u32>::new();
+    let mut activated = HashSet::<&str>::new();
+    nodes.iter().for_each(|n| {
+        let node = &graph[*n];
+        if let Some(phase) = &node.phase {
+            let level = levels.entry(&phase.resource).or_default();
+            if *level > phase.level {
+                problems.push(format!(
+                    "uses {} after {} has risen to {}",
+                    node.name, phase.resource, level
+                ));
+            }
+            *level = (*level).max(phase.level);
+        }
+        if let Some(state) = &node.requires_activation {
+            if !activated.contains(state.as_str()) {
+                problems.push(format!("uses {} before {} is activated", node.name, state));
+            }
+        }
+        activated.extend(node.activates.as_deref());
+    });
+
+    graph
+        .node_weights()
+        .flat_map(|n| &n.requirements)
+        .map(|requirement| (requirement.name.as_str(), requirement.at_least))
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .for_each(|(name, at_least)| {
+            let met = nodes[1..]
+                .iter()
+                .filter(|n| graph[**n].requirements.iter().any(|r| r.name == name))
+                .count();
+            if met < at_least as usize {
+                problems.push(format!(
+                    "visits {} of the {} nodes requirement {} needs",
+                    met, at_least, name
+                ));
+            }
+        });
+    problems
+}
+
+/// every node of the route in order, from the first node to the last node, and every edge taken backwards.
+/// an edge taken against its direction still moves the route along it, so the rest can be checked
+fn path_nodes(
+    graph: &StableGraph<Node, Edge>,
+    edges: &[EdgeIndex],
+) -> Result<(Vec<NodeIndex>, Vec<String>), String> {
+    let (first_node, last_node) = endpoints(graph);
+    let mut nodes = vec![first_node];
+    let mut backwards = Vec::new();
+    for e in edges {
+        let (source, target) = graph
+            .edge_endpoints(*e)
+            .ok_or_else(|| format!("edge {} isn't in the graph", e.index()))?;
+        let at = *nodes.last().unwrap();
+        if source == at {
+            nodes.push(target);
+        } else if target == at {
+            backwards.push(format!(
+                "goes from {} to {} against the edge from {} to {}",
+                graph[target].name, graph[source].name, graph[source].name, graph[target].name
+            ));
+            nodes.push(source);
+        } else {
+            return Err(format!(
+                "leaves {} but is at {}",
+                graph[source].name, graph[at].name
+            ));
+        }
+    }
+    if *nodes.last().unwrap() != last_node {
+        return Err(format!(
+            "ends at {} instead of {}",
+            graph[*nodes.last().unwrap()].name,
+            graph[last_node].name
+        ));
+    }
+    Ok((nodes, backwards))
+}
+
+/// where a route is after each of its steps, and what it has by then
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub node: NodeIndex,
+    /// how many times the route has entered the node's room, counting this time
+    pub entry: usize,
+    /// keys held, which is negative if a lock was opened without one
+    pub keys: i32,
+    pub bits: Bits,
+    pub time: Frames,
+}
+
+/// a step for the first node and each edge after it, counting bits, keys, and frames like the model does
+fn timeline(
+    graph: &StableGraph<Node, Edge>,
+    nodes: &[NodeIndex],
+    edges: &[EdgeIndex],
+) -> Vec<Step> {
+    let long_loads = long_load_edges(graph, edges.iter().copied());
+    let room = |n: NodeIndex| graph[n].name.split('.').next().unwrap();
+    let mut entries = HashMap::<&str, usize>::new();
+    entries.insert(room(nodes[0]), 1);
+    let mut steps = vec![Step {
+        node: nodes[0],
+        entry: 1,
+        keys: graph[nodes[0]].keys_minus_lock(),
+        bits: Bits(0),
+        time: Frames(0.0),
+    }];
+    nodes.windows(2).zip(edges).for_each(|(pair, e)| {
+        let previous = steps.last().unwrap();
+        let node = &graph[pair[1]];
+        let entry = entries.entry(room(pair[1])).or_default();
+        if room(pair[0]) != room(pair[1]) {
+            *entry += 1;
+        }
+        let mut time = previous.time + graph[*e].time + node.time;
+        if long_loads.contains(e) {
+            time += LONG_LOAD;
+        }
+        steps.push(Step {
+            node: pair[1],
+            entry: *entry,
+            keys: previous.keys + node.keys_minus_lock(),
+            bits: previous.bits + node.bits,
+            time,
+        });
+    });
+    steps
+}
+
+/// a row per step, then each room the route enters more than once
+pub fn report(graph: &StableGraph<Node, Edge>, steps: &[Step]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:>4} {:<40} {:>5} {:>4} {:>5} {:>10}",
+        "step", "node", "entry", "keys", "bits", "frames"
+    )];
+    steps.iter().enumerate().for_each(|(i, step)| {
+        lines.push(format!(
+            "{:>4} {:<40} {:>5} {:>4} {:>5} {:>10.1}",
+            i, graph[step.node].name, step.entry, step.keys, step.bits, step.time
+        ))
+    });
+    steps
+        .iter()
+        .map(|step| (graph[step.node].name.split('.').next().unwrap(), step.entry))
+        .into_grouping_map()
+        .max()
+        .into_iter()
+        .filter(|(_, entries)| *entries > 1)
+        .sorted()
+        .for_each(|(room, entries)| lines.push(format!("{} is entered {} times", room, entries)));
+    lines
+}
+
+/// checks the route of a sheet against every rule of the model and prints its timeline and every rule it breaks,
+/// returning whether it breaks none. steps the sheet takes against an edge's direction are rules it breaks too
+pub fn run(graph: &StableGraph<Node, Edge>, path: &Path, options: &Options) -> bool {
+    let nodes = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|csv| sheet::rows(graph, &csv))
+    {
+        Ok((nodes, _)) => nodes,
+        Err(e) => {
+            error!("failed to import route sheet {:?}: {}", path, e);
+            return false;
+        }
+    };
+    let edges = match nodes
+        .windows(2)
+        .map(|pair| {
+            graph
+                .find_edge(pair[0], pair[1])
+                .or_else(|| graph.find_edge(pair[1], pair[0]))
+                .ok_or_else(|| {
+                    format!(
+                        "no edge between {} and {}",
+                        graph[pair[0]].name, graph[pair[1]].name
+                    )
+                })
+        })
+        .collect::<Result<Vec<EdgeIndex>, _>>()
+    {
+        Ok(edges) => edges,
+        Err(e) => {
+            error!("failed to follow route sheet {:?}: {}", path, e);
+            return false;
+        }
+    };
+    let (nodes, mut problems) = match path_nodes(graph, &edges) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("failed to follow route sheet {:?}: {}", path, e);
+            return false;
+        }
+    };
+    problems.extend(check_nodes(graph, &nodes, options));
+
+    report(graph, &timeline(graph, &nodes, &edges))
+        .iter()
+        .for_each(|line| println!("{}", line));
+    if problems.is_empty() {
+        println!("{:?} breaks no rules", path);
+    } else {
+        println!("{:?} breaks {} rules:", path, problems.len());
+        problems
+            .iter()
+            .for_each(|problem| println!("  {}", problem));
+    }
+    problems.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rooms::Cost;
+
+    fn node(name: &str, keys: i32, cost: Cost) -> Node {
+        Node {
+            name: name.to_owned(),
+            keys,
+            cost,
+            time: Frames(1.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backwards_edges_are_followed_and_reported() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("a.start", 0, Cost::Free));
+        let key = graph.add_node(node("a.key", 1, Cost::Free));
+        let lock = graph.add_node(node("b.lock", 0, Cost::Lock));
+        let end = graph.add_node(node("a.end", 0, Cost::Free));
+        let edge = || Edge { time: Frames(2.0) };
+        let edges = vec![
+            graph.add_edge(start, key, edge()),
+            graph.add_edge(lock, key, edge()),
+            graph.add_edge(lock, end, edge()),
+        ];
+        graph.add_edge(start, lock, edge());
+        graph.add_edge(key, end, edge());
+
+        let (nodes, backwards) = path_nodes(&graph, &edges).unwrap();
+        assert_eq!(nodes, [start, key, lock, end]);
+        assert_eq!(
+            backwards,
+            ["goes from a.key to b.lock against the edge from b.lock to a.key"]
+        );
+        let steps = timeline(&graph, &nodes, &edges);
+        assert_eq!(
+            steps.iter().map(|s| (s.entry, s.keys)).collect_vec(),
+            [(1, 0), (1, 1), (1, 0), (2, 0)]
+        );
+        assert_eq!(steps.last().unwrap().time, Frames(9.0));
+        assert_eq!(
+            report(&graph, &steps).last().unwrap(),
+            "a is entered 2 times"
+        );
+    }
+}
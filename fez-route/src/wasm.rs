@@ -0,0 +1,116 @@
+use crate::common::endpoints;
+use crate::heuristic::{anneal, Route};
+use crate::options::Options;
+use crate::prepare;
+use crate::rooms::{self, RouteGraph};
+use clap::Parser;
+use petgraph::stable_graph::NodeIndex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::iter;
+use wasm_bindgen::prelude::*;
+
+/// loaded room data for a browser tool, with the same cost model as the command line.
+///
+/// routes are passed as json lists of node names, from the first node to the last node
+#[wasm_bindgen]
+pub struct Router {
+    graph: RouteGraph,
+    options: Options,
+    first_node: NodeIndex,
+    last_node: NodeIndex,
+}
+
+#[derive(Serialize)]
+struct Evaluation<'g> {
+    nodes: Vec<&'g str>,
+    time: f64,
+    bits: i32,
+    feasible: bool,
+}
+
+#[wasm_bindgen]
+impl Router {
+    /// `args` are command line options, such as "--required-bits 200 --seed 3"
+    #[wasm_bindgen(constructor)]
+    pub fn new(rooms: &str, args: &str) -> Result<Router, JsValue> {
+        let options =
+            Options::try_parse_from(iter::once("fez-route").chain(args.split_whitespace()))
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let graph = rooms::parse(rooms, &options).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let graph = prepare(graph, &options);
+        let (first_node, last_node) = endpoints(&graph);
+        Ok(Router {
+            graph,
+            options,
+            first_node,
+            last_node,
+        })
+    }
+
+    /// the route found by the heuristic, with its time and bits
+    pub fn heuristic_route(&self) -> Result<String, JsValue> {
+        let route = anneal(&self.graph, self.first_node, self.last_node, &self.options)
+            .ok_or_else(|| JsValue::from_str("no route from the first node to the last node"))?;
+        self.to_json(&route)
+    }
+
+    /// the time and bits of following the given nodes in order, where each must have an edge to the next
+    pub fn evaluate(&self, nodes: &str) -> Result<String, JsValue> {
+        let names: Vec<String> =
+            serde_json::from_str(nodes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let indices: HashMap<&str, NodeIndex> = self
+            .graph
+            .node_indices()
+            .map(|n| (self.graph[n].name.as_str(), n))
+            .collect();
+        let nodes = names
+            .iter()
+            .map(|name| {
+                indices
+                    .get(name.as_str())
+                    .copied()
+                    .ok_or_else(|| JsValue::from_str(&format!("no node named {}", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let edges = nodes
+            .windows(2)
+            .map(|pair| {
+                self.graph.find_edge(pair[0], pair[1]).ok_or_else(|| {
+                    JsValue::from_str(&format!(
+                        "no edge from {} to {}",
+                        self.graph[pair[0]].name, self.graph[pair[1]].name
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.to_json(&Route::from_path(
+            &self.graph,
+            edges,
+            self.options.required_bits,
+        ))
+    }
+
+    fn to_json(&self, route: &Route) -> Result<String, JsValue> {
+        let nodes = route
+            .edges
+            .first()
+            .map(|e| self.graph.edge_endpoints(*e).unwrap().0)
+            .into_iter()
+            .chain(
+                route
+                    .edges
+                    .iter()
+                    .map(|e| self.graph.edge_endpoints(*e).unwrap().1),
+            )
+            .map(|n| self.graph[n].name.as_str())
+            .collect();
+        serde_json::to_string(&Evaluation {
+            nodes,
+            time: f64::from(route.time),
+            bits: route.bits.0,
+            feasible: route.feasible,
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
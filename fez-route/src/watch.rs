@@ -0,0 +1,35 @@
+use crate::heuristic;
+use crate::options::Options;
+use crate::rooms::RouteGraph;
+use log::*;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL: Duration = Duration::from_millis(500);
+
+/// reruns the heuristic every time the rooms file is saved, for a quick loop while editing timings.
+/// bad room data only skips that version of the file, since loading it panics
+pub fn run(options: &Options, load: impl Fn(&Options) -> RouteGraph) {
+    let mut seen: Option<SystemTime> = None;
+    loop {
+        let modified = fs::metadata(&options.rooms)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_some() && modified != seen {
+            seen = modified;
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| heuristic::run(&load(options), options)));
+            if result.is_err() {
+                error!(
+                    "failed to route {:?}, waiting for it to change",
+                    options.rooms
+                );
+            } else {
+                info!("waiting for {:?} to change", options.rooms);
+            }
+        }
+        thread::sleep(POLL);
+    }
+}
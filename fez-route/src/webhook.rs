@@ -0,0 +1,116 @@
+//! messages about an overnight solve, posted to a webhook like a discord channel's so they can be checked from a phone.
+//! posted by curl, which handles https, on their own threads so a slow server never holds up the solve
+
+use log::*;
+use serde_json::json;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// seconds curl may take to post, so a server that doesn't answer can't keep the solver from exiting
+const MAX_SECONDS: u32 = 30;
+
+pub struct Webhook {
+    url: String,
+    /// posts still being sent, which are waited on when dropped so the last ones aren't lost on exit
+    posts: Vec<JoinHandle<()>>,
+}
+
+impl Webhook {
+    pub fn new(url: String) -> Self {
+        Webhook {
+            url,
+            posts: Vec::new(),
+        }
+    }
+
+    /// posts the message, with the image attached if it exists by the time it is sent
+    pub fn post(&mut self, message: String, image: Option<PathBuf>) {
+        let url = self.url.clone();
+        self.posts.retain(|post| !post.is_finished());
+        self.posts.push(thread::spawn(move || {
+            let args = curl_args(&url, &message, image.filter(|image| image.exists()));
+            match Command::new("curl")
+                .args(&args)
+                .stdin(Stdio::null())
+                .output()
+            {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => warn!(
+                    "webhook post failed with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => warn!("couldn't run curl to post to the webhook: {}", e),
+            }
+        }));
+    }
+}
+
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        self.posts.drain(..).for_each(|post| {
+            let _ = post.join();
+        });
+    }
+}
+
+/// a multipart form of the message as discord's `payload_json` and the image as a file.
+/// the message is a form string so curl doesn't read a leading `@` or `<` as a file,
+/// and the image's path is quoted so curl doesn't split it at a `;` or `,`
+fn curl_args(url: &str, message: &str, image: Option<PathBuf>) -> Vec<String> {
+    let mut args = vec![
+        "--silent".to_owned(),
+        "--show-error".to_owned(),
+        "--fail".to_owned(),
+        "--max-time".to_owned(),
+        MAX_SECONDS.to_string(),
+        "--form-string".to_owned(),
+        format!("payload_json={}", json!({ "content": message })),
+    ];
+    if let Some(image) = image {
+        args.push("--form".to_owned());
+        let path = image.display().to_string();
+        args.push(format!(
+            "files[0]=@\"{}\"",
+            path.replace('\\', "\\\\").replace('"', "\\\"")
+        ));
+    }
+    args.push(url.to_owned());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_form_strings() {
+        assert_eq!(
+            curl_args(
+                "https://example.com/hook",
+                "@best \"route\"",
+                Some(PathBuf::from("rendered/3-BEST.png"))
+            )[5..],
+            [
+                "--form-string",
+                r#"payload_json={"content":"@best \"route\""}"#,
+                "--form",
+                r#"files[0]=@"rendered/3-BEST.png""#,
+                "https://example.com/hook"
+            ]
+        );
+    }
+
+    #[test]
+    fn image_paths_are_quoted() {
+        assert_eq!(
+            curl_args(
+                "https://example.com/hook",
+                "best",
+                Some(PathBuf::from(r#"runs/a;b,"c"\3-BEST.png"#))
+            )[8],
+            r#"files[0]=@"runs/a;b,\"c\"\\3-BEST.png""#
+        );
+    }
+}
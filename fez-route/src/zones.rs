@@ -0,0 +1,145 @@
+use crate::common::cost;
+use crate::rooms::{Edge, Node};
+use crate::units::{Bits, Frames};
+use itertools::Itertools;
+use log::*;
+use petgraph::stable_graph::{EdgeIndex, StableGraph};
+use petgraph::Direction::Incoming;
+use std::collections::HashMap;
+
+/// lower bounds on the frames a route has to spend inside each zone to collect some number of its bits,
+/// to point out where a route is slower than it needs to be
+pub struct ZoneBounds {
+    /// the bits of each node in the zone and the cheapest way into it, cheapest per bit first
+    zones: HashMap<String, Vec<(Bits, Frames)>>,
+}
+
+/// frames a route spent in a zone compared to what the bits it collected there could cost
+#[derive(Clone, Debug)]
+pub struct ZoneSpend {
+    pub zone: String,
+    pub bits: Bits,
+    pub frames: Frames,
+    pub bound: Frames,
+}
+impl ZoneSpend {
+    pub fn over(&self) -> Frames {
+        self.frames - self.bound
+    }
+}
+
+impl ZoneBounds {
+    pub fn new(graph: &StableGraph<Node, Edge>) -> Self {
+        let zones = graph
+            .node_indices()
+            .filter(|n| graph[*n].bits > Bits(0))
+            .filter_map(|n| {
+                let entry = graph
+                    .edges_directed(n, Incoming)
+                    .map(|e| cost(graph, e))
+                    .min_by(|l, r| l.partial_cmp(r).unwrap())?;
+                Some((graph[n].zone.clone(), (graph[n].bits, entry)))
+            })
+            .into_group_map()
+            .into_iter()
+            .map(|(zone, mut nodes)| {
+                nodes.sort_by(|(l_bits, l_entry), (r_bits, r_entry)| {
+                    (l_entry.0 / l_bits.0 as f64)
+                        .partial_cmp(&(r_entry.0 / r_bits.0 as f64))
+                        .unwrap()
+                });
+                (zone, nodes)
+            })
+            .collect();
+        ZoneBounds { zones }
+    }
+
+    /// the cheapest nodes are entered first, and the last one only partly,
+    /// which is the LP relaxation of picking which nodes of the zone to collect
+    pub fn bound(&self, zone: &str, bits: Bits) -> Frames {
+        let mut needed = bits;
+        let mut frames = Frames(0.0);
+        for (node_bits, entry) in self.zones.get(zone).into_iter().flatten() {
+            if needed <= Bits(0) {
+                break;
+            }
+            let taken = needed.min(*node_bits);
+            frames += *entry * (taken.0 as f64 / node_bits.0 as f64);
+            needed -= taken;
+        }
+        frames
+    }
+
+    /// every zone the route collects bits in, the most overspent first
+    pub fn spend(&self, graph: &StableGraph<Node, Edge>, edges: &[EdgeIndex]) -> Vec<ZoneSpend> {
+        let mut spent = HashMap::<&str, (Bits, Frames)>::new();
+        edges.iter().for_each(|e| {
+            let target = &graph[graph.edge_endpoints(*e).unwrap().1];
+            let zone = spent.entry(&target.zone).or_default();
+            zone.0 += target.bits;
+            zone.1 += graph[*e].time + target.time;
+        });
+        spent
+            .into_iter()
+            .filter(|(_, (bits, _))| *bits > Bits(0))
+            .map(|(zone, (bits, frames))| ZoneSpend {
+                zone: zone.to_owned(),
+                bits,
+                frames,
+                bound: self.bound(zone, bits),
+            })
+            .sorted_by(|l, r| r.over().partial_cmp(&l.over()).unwrap())
+            .collect()
+    }
+
+    /// logs the zones the route overspends in the most
+    pub fn log_spend(&self, graph: &StableGraph<Node, Edge>, edges: &[EdgeIndex]) {
+        self.spend(graph, edges)
+            .into_iter()
+            .take(5)
+            .filter(|spend| spend.over() > Frames(0.0))
+            .for_each(|spend| {
+                info!(
+                    "zone {} spends {} frames on {} bits, {} over its bound of {}",
+                    spend.zone,
+                    spend.frames,
+                    spend.bits,
+                    spend.over(),
+                    spend.bound
+                )
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, bits: i32, time: f64) -> Node {
+        Node {
+            name: name.to_owned(),
+            bits: Bits(bits),
+            zone: "zone".to_owned(),
+            time: Frames(time),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cheapest_bits_first() {
+        let mut graph = StableGraph::new();
+        let start = graph.add_node(node("start", 0, 0.0));
+        let cube = graph.add_node(node("cube", 8, 80.0));
+        let bit = graph.add_node(node("bit", 1, 12.0));
+        let time = Frames(0.0);
+        graph.add_edge(start, cube, Edge { time });
+        graph.add_edge(start, bit, Edge { time });
+        graph.add_edge(cube, bit, Edge { time });
+
+        let bounds = ZoneBounds::new(&graph);
+        assert_eq!(bounds.bound("zone", Bits(4)), Frames(40.0));
+        assert_eq!(bounds.bound("zone", Bits(9)), Frames(92.0));
+        assert_eq!(bounds.bound("zone", Bits(20)), Frames(92.0));
+        assert_eq!(bounds.bound("other", Bits(1)), Frames(0.0));
+    }
+}
@@ -0,0 +1,90 @@
+//! loads, solves, and verifies a trimmed map small enough for glpk to solve in moments.
+//! the optimal times were found by enumerating every simple path through the map
+
+use clap::Parser;
+use fez_route::opt::{optimize, Control};
+use fez_route::options::Options;
+use fez_route::units::{Bits, Frames};
+use fez_route::{prepare, prepare_unpruned, rooms, verify};
+use std::fs;
+
+fn solve(required_bits: &str) -> (Frames, Bits) {
+    solve_with(&["--required-bits", required_bits])
+}
+
+fn solve_with(args: &[&str]) -> (Frames, Bits) {
+    let rooms = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/small_rooms.json"
+    );
+    let options = Options::parse_from(
+        ["fez-route", "--rooms", rooms, "--no-render"]
+            .iter()
+            .chain(args),
+    );
+    let graph = prepare(rooms::load(&options.rooms, &options), &options);
+    let route = optimize(&graph, &options, &Control::default()).expect("a route");
+    if let Err(problems) = verify::check_route(&graph, &route, &options) {
+        panic!("best route is invalid: {:?}", problems);
+    }
+    (route.time, route.bits)
+}
+
+#[test]
+fn two_cubes() {
+    assert_eq!(solve("16"), (Frames(719.0), Bits(16)));
+}
+
+#[test]
+fn three_cubes() {
+    assert_eq!(solve("24"), (Frames(1075.0), Bits(24)));
+}
+
+#[test]
+fn routes_cut_short_by_the_mip_gap_are_searched_around() {
+    // any incumbent is within a gap of 1, so the solve stops at its first and the large neighborhood search
+    // takes over. a short heuristic leaves that first incumbent far from optimal
+    let gapped = |lns_iterations| {
+        solve_with(&[
+            "--required-bits",
+            "24",
+            "--mip-gap",
+            "1",
+            "--heuristic-iterations",
+            "10",
+            "--lns-segment",
+            "12",
+            "--lns-iterations",
+            lns_iterations,
+        ])
+    };
+    let (first, _) = gapped("0");
+    assert!(first > Frames(1075.0));
+    assert_eq!(gapped("10"), (Frames(1075.0), Bits(24)));
+}
+
+#[test]
+fn routes_through_pruned_nodes_verify() {
+    // the climb strat is slower than the jump strat of the same cube, so pruning removes it
+    let rooms = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/strat_rooms.json"
+    );
+    let options = Options::parse_from([
+        "fez-route",
+        "--rooms",
+        rooms,
+        "--no-render",
+        "--required-bits",
+        "8",
+    ]);
+    let pruned = prepare(rooms::load(&options.rooms, &options), &options);
+    assert!(!pruned
+        .node_weights()
+        .any(|n| n.name == "village.cube.climb"));
+
+    let path = std::env::temp_dir().join(format!("climb-{}.csv", std::process::id()));
+    fs::write(&path, "village,cube.climb\nvillage,end\n").unwrap();
+    let graph = prepare_unpruned(rooms::load(&options.rooms, &options), &options);
+    assert!(verify::run(&graph, &path, &options));
+}
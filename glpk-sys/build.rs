@@ -1,26 +1,32 @@
 extern crate bindgen;
+#[cfg(feature = "vendored")]
+extern crate cc;
+extern crate pkg_config;
 
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 
 fn main() {
-    // copy the pre-built glpk binary to the output
     let out_path = env::var("OUT_DIR").unwrap();
-    for ext in &["def", "dll", "exp", "lib", "pdb"] {
-        fs::copy(
-            format!("glpk/glpk_4_65.{}", ext),
-            format!("{}/glpk_4_65.{}", out_path, ext),
-        )
-        .expect("fs::copy failed");
+    if cfg!(all(feature = "vendored", feature = "system")) {
+        panic!("the vendored and system features pick different glpk libraries, only enable one");
     }
-
-    // Tell cargo to tell rustc to link the pre-build glpk binary
-    println!("cargo:rustc-link-search=native={}", out_path);
-    println!("cargo:rustc-link-lib=dylib=glpk_4_65");
+    let header = if cfg!(feature = "vendored") {
+        build_vendored()
+    } else if cfg!(feature = "system") {
+        link_system();
+        PathBuf::from("glpk.h")
+    } else if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+        link_prebuilt(&out_path);
+        PathBuf::from("glpk.h")
+    } else {
+        link_system();
+        PathBuf::from("glpk.h")
+    };
 
     // Tell cargo to invalidate the built crate whenever the wrapper changes
-    println!("cargo:rerun-if-changed=glpk.h");
+    println!("cargo:rerun-if-changed={}", header.display());
 
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
@@ -28,7 +34,7 @@ fn main() {
     let bindings = bindgen::Builder::default()
         // The input header we would like to generate
         // bindings for.
-        .header("glpk.h")
+        .header(header.to_str().expect("glpk.h path isn't utf-8"))
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
@@ -42,3 +48,76 @@ fn main() {
         .write_to_file(PathBuf::from(out_path).join("bindings.rs"))
         .expect("Couldn't write bindings!");
 }
+
+/// the pre-built glpk binary is only for windows
+fn link_prebuilt(out_path: &str) {
+    // copy the pre-built glpk binary to the output
+    for ext in &["def", "dll", "exp", "lib", "pdb"] {
+        fs::copy(
+            format!("glpk/glpk_4_65.{}", ext),
+            format!("{}/glpk_4_65.{}", out_path, ext),
+        )
+        .expect("fs::copy failed");
+    }
+
+    // Tell cargo to tell rustc to link the pre-build glpk binary
+    println!("cargo:rustc-link-search=native={}", out_path);
+    println!("cargo:rustc-link-lib=dylib=glpk_4_65");
+}
+
+/// without a feature, the pre-built binary is used on windows and the installed glpk everywhere else.
+///
+/// the installed glpk is found through pkg-config, such as libglpk-dev or homebrew's glpk,
+/// but most packages don't ship a pkg-config file, so fall back to the linker's search path.
+/// the bindings still come from the 4.65 glpk.h, fez-route checks the linked version at runtime
+fn link_system() {
+    if pkg_config::probe_library("glpk").is_err() {
+        println!("cargo:rustc-link-lib=dylib=glpk");
+    }
+}
+
+/// compiles glpk from an extracted release, such as glpk-4.65.tar.gz from ftp.gnu.org/gnu/glpk,
+/// found at $GLPK_SRC_DIR or glpk-sys/glpk-src, and returns its glpk.h so the bindings match it
+#[cfg(feature = "vendored")]
+fn build_vendored() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=GLPK_SRC_DIR");
+    let root = env::var_os("GLPK_SRC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("glpk-src"));
+    let src = root.join("src");
+    if !src.join("glpk.h").is_file() {
+        panic!(
+            "the vendored feature needs the glpk source extracted to {:?}, or GLPK_SRC_DIR set to it",
+            root
+        );
+    }
+
+    // every source folder is also an include folder, like glpk's own makefile
+    let mut dirs = vec![src.clone()];
+    let mut files = Vec::new();
+    let mut i = 0;
+    while i < dirs.len() {
+        for entry in fs::read_dir(&dirs[i]).expect("couldn't read the glpk source") {
+            let path = entry.expect("couldn't read the glpk source").path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "c") {
+                files.push(path);
+            }
+        }
+        i += 1;
+    }
+
+    cc::Build::new()
+        .includes(&dirs)
+        .files(&files)
+        // glpk has plenty of warnings that aren't ours to fix
+        .warnings(false)
+        .compile("glpk");
+    src.join("glpk.h")
+}
+
+#[cfg(not(feature = "vendored"))]
+fn build_vendored() -> PathBuf {
+    unreachable!("only called with the vendored feature")
+}
@@ -1,13 +1,23 @@
 use glpk_sys::*;
-use std::ffi::CString;
+use std::any::Any;
+use std::cell::RefCell;
+#[cfg(debug_assertions)]
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::marker::PhantomData;
 use std::mem::MaybeUninit;
-use std::ops::{Deref, DerefMut, Index, IndexMut, Mul};
-use std::os::raw::{c_int, c_uint, c_void};
+use std::ops::{Deref, DerefMut, Index, IndexMut, Mul, Range};
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::time::Duration;
 
 use log::*;
 
 #[derive(Copy, Clone, Debug)]
-pub struct VarRef(c_int);
+pub struct VarRef(c_int, Generation);
 impl Mul<f64> for VarRef {
     type Output = Term;
     fn mul(self, coef: f64) -> Term {
@@ -17,11 +27,20 @@ impl Mul<f64> for VarRef {
 
 #[derive(Copy, Clone, Debug)]
 pub struct Term(VarRef, f64);
+impl Term {
+    pub fn var(&self) -> VarRef {
+        self.0
+    }
+    pub fn coef(&self) -> f64 {
+        self.1
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct VarRefs {
     first: c_int,
     len: c_int,
+    generation: Generation,
 }
 impl VarRefs {
     pub fn get(&self, index: usize) -> VarRef {
@@ -31,10 +50,207 @@ impl VarRefs {
             index,
             self.len
         );
-        VarRef(self.first + index as c_int)
+        VarRef(self.first + index as c_int, self.generation)
     }
     pub fn iter(&self) -> impl Iterator<Item = VarRef> {
-        (self.first..self.first + self.len).map(VarRef)
+        let generation = self.generation;
+        (self.first..self.first + self.len).map(move |i| VarRef(i, generation))
+    }
+    pub fn len(&self) -> usize {
+        self.len.from_glp()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// the vars at the indices of the range, which `get` then indexes from the range's start
+    pub fn slice(&self, range: Range<usize>) -> VarRefs {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range {:?} out of bounds {}",
+            range,
+            self.len
+        );
+        VarRefs {
+            first: self.first + range.start as c_int,
+            len: (range.end - range.start) as c_int,
+            generation: self.generation,
+        }
+    }
+    /// the index `var` would be gotten by, or none if it isn't one of these vars
+    pub fn position(&self, var: VarRef) -> Option<usize> {
+        var.1.check(self.generation, "var", var.0);
+        (self.first..self.first + self.len)
+            .contains(&var.0)
+            .then(|| (var.0 - self.first) as usize)
+    }
+}
+
+/// a block of vars with one var for each index of another numbering, like the edges of a graph,
+/// so blocks for different things can't be indexed by the wrong kind of index
+#[derive(Copy, Clone, Debug)]
+pub struct VarMap<I> {
+    vars: VarRefs,
+    from_index: fn(usize) -> I,
+    to_index: fn(I) -> usize,
+}
+impl<I> VarMap<I> {
+    pub fn new(vars: VarRefs, from_index: fn(usize) -> I, to_index: fn(I) -> usize) -> Self {
+        VarMap {
+            vars,
+            from_index,
+            to_index,
+        }
+    }
+    pub fn vars(&self) -> VarRefs {
+        self.vars
+    }
+    pub fn get(&self, index: I) -> VarRef {
+        self.vars.get((self.to_index)(index))
+    }
+    /// the index the var is for, or none if it isn't in the block
+    pub fn position(&self, var: VarRef) -> Option<I> {
+        self.vars.position(var).map(self.from_index)
+    }
+    /// every index of the block with its var
+    pub fn iter(&self) -> impl Iterator<Item = (I, VarRef)> + '_ {
+        self.vars
+            .iter()
+            .enumerate()
+            .map(move |(i, var)| ((self.from_index)(i), var))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExprRef(c_int, Generation);
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExprRefs {
+    first: c_int,
+    len: c_int,
+    generation: Generation,
+}
+impl ExprRefs {
+    pub fn get(&self, index: usize) -> ExprRef {
+        debug_assert!(
+            index < self.len as usize,
+            "index {} out of bounds {}",
+            index,
+            self.len
+        );
+        ExprRef(self.first + index as c_int, self.generation)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = ExprRef> {
+        let generation = self.generation;
+        (self.first..self.first + self.len).map(move |i| ExprRef(i, generation))
+    }
+}
+
+/// which deletion a problem is on, so refs from before a deletion or from another problem can be caught.
+/// only tracked in debug builds, in release builds it is zero sized and never checked
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Generation(#[cfg(debug_assertions)] u32);
+
+#[cfg(debug_assertions)]
+thread_local! {
+    /// the last generation handed out, and the current generation of each live problem by address
+    static GENERATIONS: RefCell<(u32, HashMap<usize, Generation>)> = RefCell::new((0, HashMap::new()));
+}
+
+#[cfg(debug_assertions)]
+impl Generation {
+    /// a generation no other problem has had
+    fn fresh() -> Self {
+        GENERATIONS.with(|generations| {
+            let (last, _) = &mut *generations.borrow_mut();
+            *last += 1;
+            Generation(*last)
+        })
+    }
+    fn of(prob: &Prob) -> Self {
+        GENERATIONS.with(|generations| {
+            *generations
+                .borrow()
+                .1
+                .get(&(prob.as_ptr() as usize))
+                .expect("problem was not created by Problem::new, like a presolved one")
+        })
+    }
+    fn set(prob: &Prob, generation: Generation) {
+        GENERATIONS.with(|generations| {
+            generations
+                .borrow_mut()
+                .1
+                .insert(prob.as_ptr() as usize, generation)
+        });
+    }
+    fn forget(prob: &Prob) {
+        GENERATIONS
+            .with(|generations| generations.borrow_mut().1.remove(&(prob.as_ptr() as usize)));
+    }
+    fn check(self, current: Generation, what: &str, index: c_int) {
+        assert!(
+            self == current,
+            "{} {} is from generation {} but the problem is on generation {}, \
+             refs can't be used with a different problem or after a deletion unless they go through the renumbering",
+            what,
+            index,
+            self.0,
+            current.0
+        );
+    }
+}
+
+#[cfg(not(debug_assertions))]
+impl Generation {
+    fn fresh() -> Self {
+        Generation()
+    }
+    fn of(_: &Prob) -> Self {
+        Generation()
+    }
+    fn set(_: &Prob, _: Generation) {}
+    fn forget(_: &Prob) {}
+    fn check(self, _: Generation, _: &str, _: c_int) {}
+}
+
+/// glpk keeps vars and exprs densely numbered, so deleting some moves every later one down.
+/// this maps refs from before the deletion to after it
+#[derive(Clone, Debug)]
+pub struct Renumbering<T> {
+    /// sorted
+    deleted: Vec<c_int>,
+    from: Generation,
+    to: Generation,
+    marker: PhantomData<T>,
+}
+impl<T> Renumbering<T> {
+    fn new(deleted: Vec<c_int>, from: Generation, to: Generation) -> Self {
+        Renumbering {
+            deleted,
+            from,
+            to,
+            marker: PhantomData,
+        }
+    }
+    fn shift(&self, index: c_int) -> Option<c_int> {
+        match self.deleted.binary_search(&index) {
+            Ok(_) => None,
+            Err(before) => Some(index - before as c_int),
+        }
+    }
+}
+impl Renumbering<VarRef> {
+    /// none if the var was deleted
+    pub fn get(&self, var: VarRef) -> Option<VarRef> {
+        var.1.check(self.from, "var", var.0);
+        self.shift(var.0).map(|i| VarRef(i, self.to))
+    }
+}
+impl Renumbering<ExprRef> {
+    /// none if the expr was deleted
+    pub fn get(&self, expr: ExprRef) -> Option<ExprRef> {
+        expr.1.check(self.from, "expr", expr.0);
+        self.shift(expr.0).map(|i| ExprRef(i, self.to))
     }
 }
 
@@ -52,6 +268,16 @@ impl IntoGlp for String {
         CString::new(self).expect("CString::new failed")
     }
 }
+impl IntoGlp for bool {
+    type Output = c_int;
+    fn into_glp(self) -> Self::Output {
+        if self {
+            GLP_ON as c_int
+        } else {
+            GLP_OFF as c_int
+        }
+    }
+}
 impl IntoGlp for usize {
     type Output = c_int;
     fn into_glp(self) -> Self::Output {
@@ -66,21 +292,75 @@ impl FromGlp for c_int {
         self as usize
     }
 }
-impl IntoGlp for Vec<Term> {
-    type Output = (c_int, Vec<c_int>, Vec<f64>);
-    fn into_glp(self) -> Self::Output {
-        let len = self.len().into_glp();
-        let mut vars = Vec::with_capacity(self.len() + 1);
-        let mut coeffs = Vec::with_capacity(self.len() + 1);
-        // GLPK doesn't believe in 0 indicies for some reason
-        vars.push(0);
-        coeffs.push(0.0);
-        for Term(var, coef) in self.into_iter() {
-            vars.push(var.0);
-            coeffs.push(coef);
-        }
-        (len, vars, coeffs)
+
+/// the longest name glpk accepts for a problem, var, or expr, in bytes
+const MAX_NAME_LEN: usize = 255;
+
+/// names longer than glpk accepts, like a cut named after every node it connects, are cut short and end in
+/// `~` and a hash of the whole name instead. the same name is always mangled the same way,
+/// so `find_var` still finds it, and different names stay different
+fn mangle_name(name: String) -> CString {
+    if name.len() <= MAX_NAME_LEN {
+        return name.into_glp();
+    }
+    let hash = format!("~{:016x}", fnv1a(name.as_bytes()));
+    let mut end = MAX_NAME_LEN - hash.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &name[..end], hash).into_glp()
+}
+
+/// glpk gives unnamed vars and exprs a null name
+fn name_from_glp(name: *const c_char) -> String {
+    if name.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// 64 bit FNV-1a, which is stable across runs and platforms unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+thread_local! {
+    /// reused by every `init_expr`, since GLPK copies the terms out before returning
+    static SCRATCH: RefCell<(Vec<c_int>, Vec<f64>)> = const { RefCell::new((Vec::new(), Vec::new())) };
+}
+
+/// clears and fills the buffers with terms in the layout GLPK expects, returning the number of terms
+fn fill_terms(
+    terms: impl IntoIterator<Item = Term>,
+    generation: Generation,
+    vars: &mut Vec<c_int>,
+    coeffs: &mut Vec<f64>,
+) -> c_int {
+    vars.clear();
+    coeffs.clear();
+    // GLPK doesn't believe in 0 indicies for some reason
+    vars.push(0);
+    coeffs.push(0.0);
+    for Term(var, coef) in terms {
+        var.1.check(generation, "var", var.0);
+        vars.push(var.0);
+        coeffs.push(coef);
     }
+    (vars.len() - 1).into_glp()
+}
+
+/// sorted and deduplicated, with a leading 0 since that is how glpk takes lists of indices.
+/// just the 0 if there are none
+fn sorted_indices(indices: impl Iterator<Item = c_int>) -> Vec<c_int> {
+    let mut sorted: Vec<c_int> = std::iter::once(0).chain(indices).collect();
+    sorted.sort_unstable();
+    sorted.dedup();
+    sorted
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -102,6 +382,29 @@ impl IntoGlp for Direction {
 pub enum Kind {
     Float,
     Int,
+    /// an int that is either 0 or 1, which glpk has extra preprocessing for
+    Binary,
+}
+impl Kind {
+    /// glpk only rejects bad bounds once solving, without saying which var, so they are checked up front instead
+    #[allow(clippy::float_cmp)]
+    fn allows(self, bounds: Bounds) -> bool {
+        let integral = |value: f64| value.fract() == 0.0;
+        match (self, bounds) {
+            (_, Bounds::Double(lower, upper)) if lower > upper => false,
+            (Self::Float, _) => true,
+            (Self::Int, Bounds::Free) => true,
+            (Self::Int, Bounds::Lower(value) | Bounds::Upper(value) | Bounds::Fixed(value)) => {
+                integral(value)
+            }
+            (Self::Int, Bounds::Double(lower, upper)) => integral(lower) && integral(upper),
+            (Self::Binary, Bounds::Fixed(value)) => value == 0.0 || value == 1.0,
+            (Self::Binary, Bounds::Double(lower, upper)) => {
+                Self::Int.allows(bounds) && lower >= 0.0 && upper <= 1.0
+            }
+            (Self::Binary, _) => false,
+        }
+    }
 }
 impl IntoGlp for Kind {
     type Output = c_int;
@@ -109,6 +412,7 @@ impl IntoGlp for Kind {
         match self {
             Self::Float => GLP_CV as c_int,
             Self::Int => GLP_IV as c_int,
+            Self::Binary => GLP_BV as c_int,
         }
     }
 }
@@ -133,6 +437,69 @@ impl IntoGlp for Bounds {
         }
     }
 }
+impl FromGlp for (c_int, f64, f64) {
+    type Output = Bounds;
+    fn from_glp(self) -> Self::Output {
+        let (bounds, lower, upper) = self;
+        match bounds as c_uint {
+            GLP_FR => Bounds::Free,
+            GLP_LO => Bounds::Lower(lower),
+            GLP_UP => Bounds::Upper(upper),
+            GLP_DB => Bounds::Double(lower, upper),
+            GLP_FX => Bounds::Fixed(lower),
+            _ => panic!("unknown glpk bounds type {}", bounds),
+        }
+    }
+}
+/// where a var or expr is in a simplex basis
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Basic,
+    /// non-basic at its lower bound
+    AtLower,
+    /// non-basic at its upper bound
+    AtUpper,
+    /// non-basic and free
+    Free,
+    /// non-basic and fixed
+    Fixed,
+}
+impl IntoGlp for Status {
+    type Output = c_int;
+    fn into_glp(self) -> Self::Output {
+        match self {
+            Self::Basic => GLP_BS as c_int,
+            Self::AtLower => GLP_NL as c_int,
+            Self::AtUpper => GLP_NU as c_int,
+            Self::Free => GLP_NF as c_int,
+            Self::Fixed => GLP_NS as c_int,
+        }
+    }
+}
+impl Status {
+    fn from_glp(status: c_int) -> Self {
+        match status as c_uint {
+            GLP_BS => Self::Basic,
+            GLP_NL => Self::AtLower,
+            GLP_NU => Self::AtUpper,
+            GLP_NF => Self::Free,
+            GLP_NS => Self::Fixed,
+            _ => panic!("unknown glpk basis status {}", status),
+        }
+    }
+}
+
+/// a starting basis glpk can construct, instead of keeping the one left by the last solve
+#[derive(Copy, Clone, Debug)]
+pub enum Basis {
+    /// every expr basic and every var non-basic, which is always valid but far from optimal
+    Standard,
+    /// glpk's own crash basis, which tries to make it triangular
+    Advanced,
+    /// Bixby's crash basis
+    Bixby,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum LoggingLevel {
     Off,
@@ -160,19 +527,30 @@ pub struct Var {
     pub objective: f64,
 }
 
-pub struct Expr {
+/// `terms` is only iterated once when the expr is added, so it can be any iterator of terms, not just a `Vec`
+pub struct Expr<T = Vec<Term>> {
     pub name: String,
     pub bounds: Bounds,
-    pub terms: Vec<Term>,
+    pub terms: T,
+}
+impl<T: IntoIterator<Item = Term>> Expr<T> {
+    /// keeps the iterator as is, so adding the expr never collects the terms
+    pub fn from_terms(name: String, bounds: Bounds, terms: T) -> Self {
+        Expr {
+            name,
+            bounds,
+            terms,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Error {
     /// double must have correct order, integers must have integer bounds
     InvalidBounds,
     /// only if no presolver
     NoInitSolution,
-    /// only if presolver
+    /// only if presolver, or if the branch and bound for small problems found no integer solution
     NotPrimalFeasible,
     /// only if presolver, aka unbounded solution
     NotDualFeasible,
@@ -182,8 +560,19 @@ pub enum Error {
     HitMipGapLimit,
     /// time limit was reached
     Timeout,
+    /// the basis doesn't have exactly one basic var or expr per expr
+    InvalidBasis,
+    /// the basis matrix is singular or too ill-conditioned to factorize
+    SingularBasis,
     /// stopped by the callback
     Stopped,
+    /// the callback panicked with this message, which stopped the search since it can't unwind through glpk.
+    /// the best solution so far is kept
+    CallbackPanicked(String),
+    /// glpk couldn't write a file
+    WriteFailed,
+    /// glpk couldn't read or parse a file
+    ReadFailed,
     /// glpk didn't follow docs
     Unknown,
 }
@@ -198,7 +587,35 @@ pub enum Reason<'p> {
 pub struct Problem(*mut Prob);
 impl Problem {
     pub fn new() -> Self {
-        Problem(unsafe { glp_create_prob() } as *mut Prob)
+        let problem = Problem(unsafe { glp_create_prob() } as *mut Prob);
+        Generation::set(&problem, Generation::fresh());
+        problem
+    }
+    /// a model in CPLEX LP format, such as one written by `write_lp`.
+    /// its vars and exprs are found with `vars`, `exprs`, and `find_var`
+    pub fn from_lp_file(path: &Path) -> Result<Self, Error> {
+        let problem = Self::new();
+        let path = path.to_string_lossy().into_owned().into_glp();
+        match unsafe { glp_read_lp(problem.as_ptr(), ptr::null(), path.as_ptr()) } {
+            0 => Ok(problem),
+            _ => Err(Error::ReadFailed),
+        }
+    }
+    /// a model in free MPS format
+    pub fn from_mps_file(path: &Path) -> Result<Self, Error> {
+        let problem = Self::new();
+        let path = path.to_string_lossy().into_owned().into_glp();
+        match unsafe {
+            glp_read_mps(
+                problem.as_ptr(),
+                GLP_MPS_FILE as c_int,
+                ptr::null(),
+                path.as_ptr(),
+            )
+        } {
+            0 => Ok(problem),
+            _ => Err(Error::ReadFailed),
+        }
     }
 }
 impl Default for Problem {
@@ -216,11 +633,14 @@ impl Clone for Problem {
                 GLP_ON as c_int,
             )
         };
+        // the copy has the same vars and exprs, so refs are valid for both until one of them deletes some
+        Generation::set(&clone, Generation::of(self));
         clone
     }
 }
 impl Drop for Problem {
     fn drop(&mut self) {
+        Generation::forget(self);
         unsafe { glp_delete_prob(self.0 as *mut glp_prob) }
     }
 }
@@ -253,8 +673,18 @@ impl Prob {
     fn as_ptr(&self) -> *mut glp_prob {
         self as *const Prob as *mut glp_prob
     }
+    /// the glpk index of a var, panicking in debug builds if the ref is stale or from another problem
+    fn col(&self, var: VarRef) -> c_int {
+        var.1.check(Generation::of(self), "var", var.0);
+        var.0
+    }
+    /// the glpk index of an expr, panicking in debug builds if the ref is stale or from another problem
+    fn row(&self, expr: ExprRef) -> c_int {
+        expr.1.check(Generation::of(self), "expr", expr.0);
+        expr.0
+    }
     pub fn set_name(&mut self, name: String) {
-        unsafe { glp_set_prob_name(self.as_ptr(), name.into_glp().as_ptr()) }
+        unsafe { glp_set_prob_name(self.as_ptr(), mangle_name(name).as_ptr()) }
     }
     pub fn set_direction(&mut self, dir: Direction) {
         unsafe { glp_set_obj_dir(self.as_ptr(), dir.into_glp()) };
@@ -263,6 +693,49 @@ impl Prob {
     pub fn num_vars(&self) -> usize {
         unsafe { glp_get_num_cols(self.as_ptr()) }.from_glp()
     }
+    pub fn num_binary_vars(&self) -> usize {
+        unsafe { glp_get_num_bin(self.as_ptr()) }.from_glp()
+    }
+    pub fn num_exprs(&self) -> usize {
+        unsafe { glp_get_num_rows(self.as_ptr()) }.from_glp()
+    }
+    /// non zero coefficients over every expr
+    pub fn num_terms(&self) -> usize {
+        unsafe { glp_get_num_nz(self.as_ptr()) }.from_glp()
+    }
+    /// in CPLEX LP format, which most solvers can read
+    pub fn write_lp(&self, path: &Path) -> Result<(), Error> {
+        let path = path.to_string_lossy().into_owned().into_glp();
+        match unsafe { glp_write_lp(self.as_ptr(), ptr::null(), path.as_ptr()) } {
+            0 => Ok(()),
+            _ => Err(Error::WriteFailed),
+        }
+    }
+    /// every var, in the order they were added
+    pub fn vars(&self) -> VarRefs {
+        VarRefs {
+            first: 1,
+            len: unsafe { glp_get_num_cols(self.as_ptr()) },
+            generation: Generation::of(self),
+        }
+    }
+    /// every expr, in the order they were added
+    pub fn exprs(&self) -> ExprRefs {
+        ExprRefs {
+            first: 1,
+            len: unsafe { glp_get_num_rows(self.as_ptr()) },
+            generation: Generation::of(self),
+        }
+    }
+    /// mostly for models read from a file, where the vars are only known by name
+    pub fn find_var(&mut self, name: &str) -> Option<VarRef> {
+        let name = mangle_name(name.to_owned());
+        let col = unsafe {
+            glp_create_index(self.as_ptr());
+            glp_find_col(self.as_ptr(), name.as_ptr())
+        };
+        (col != 0).then(|| VarRef(col, Generation::of(self)))
+    }
     pub fn add_vars(&mut self, specs: Vec<Var>) -> VarRefs {
         let vars = self.alloc_vars(specs.len().into_glp());
         vars.iter()
@@ -284,45 +757,251 @@ impl Prob {
                 glp_add_cols(self.as_ptr(), len)
             }
         };
-        VarRefs { first, len }
+        VarRefs {
+            first,
+            len,
+            generation: Generation::of(self),
+        }
     }
     fn init_var(&mut self, var: VarRef, spec: Var) {
-        let name = spec.name.into_glp();
+        assert!(
+            spec.kind.allows(spec.bounds),
+            "var {} is {:?} but has bounds {:?}",
+            spec.name,
+            spec.kind,
+            spec.bounds
+        );
+        let name = mangle_name(spec.name);
         let kind = spec.kind.into_glp();
         let (bounds, lower, upper) = spec.bounds.into_glp();
         let objective = spec.objective;
+        let col = self.col(var);
         unsafe {
-            glp_set_col_name(self.as_ptr(), var.0, name.as_ptr());
-            #[allow(clippy::float_cmp)]
-            // if kind == GLP_IV as c_int && bounds == GLP_DB as c_int && lower == 0.0 && upper == 1.0
-            // {
-            //     glp_set_col_kind(self.0, var.0, GLP_BV as c_int);
-            // } else {
-            glp_set_col_kind(self.as_ptr(), var.0, kind);
-            glp_set_col_bnds(self.as_ptr(), var.0, bounds, lower, upper);
-            // }
-            glp_set_obj_coef(self.as_ptr(), var.0, objective);
+            glp_set_col_name(self.as_ptr(), col, name.as_ptr());
+            // binary also sets the bounds to 0 and 1, so it needs to be before the actual bounds
+            glp_set_col_kind(self.as_ptr(), col, kind);
+            glp_set_col_bnds(self.as_ptr(), col, bounds, lower, upper);
+            glp_set_obj_coef(self.as_ptr(), col, objective);
         }
     }
+    pub fn set_objective(&mut self, var: VarRef, coef: f64) {
+        unsafe { glp_set_obj_coef(self.as_ptr(), self.col(var), coef) };
+    }
+    /// sets the objective coefficient of each var in `terms`, leaving every other var alone
+    pub fn set_objectives(&mut self, terms: impl IntoIterator<Item = Term>) {
+        terms
+            .into_iter()
+            .for_each(|Term(var, coef)| self.set_objective(var, coef));
+    }
+    pub fn get_objective(&self, var: VarRef) -> f64 {
+        unsafe { glp_get_obj_coef(self.as_ptr(), self.col(var)) }
+    }
+    /// a fixed amount added to the objective, which doesn't change which solution is best
+    pub fn set_objective_constant(&mut self, constant: f64) {
+        unsafe { glp_set_obj_coef(self.as_ptr(), 0, constant) };
+    }
+    pub fn get_objective_constant(&self) -> f64 {
+        unsafe { glp_get_obj_coef(self.as_ptr(), 0) }
+    }
+    /// of the best integer solution, including the constant
+    pub fn get_mip_objective(&self) -> f64 {
+        unsafe { glp_mip_obj_val(self.as_ptr()) }
+    }
+    pub fn set_bounds(&mut self, var: VarRef, bounds: Bounds) {
+        let kind = self.get_kind(var);
+        assert!(
+            kind.allows(bounds),
+            "var {} is {:?} but was given bounds {:?}",
+            var.0,
+            kind,
+            bounds
+        );
+        let (bounds, lower, upper) = bounds.into_glp();
+        unsafe { glp_set_col_bnds(self.as_ptr(), self.col(var), bounds, lower, upper) };
+    }
+    /// as mangled when it was added, so a long name may be cut short
+    pub fn get_var_name(&self, var: VarRef) -> String {
+        name_from_glp(unsafe { glp_get_col_name(self.as_ptr(), self.col(var)) })
+    }
+    /// glpk only remembers binary while the bounds are exactly 0 and 1, otherwise it is just int
+    pub fn get_kind(&self, var: VarRef) -> Kind {
+        let kind = unsafe { glp_get_col_kind(self.as_ptr(), self.col(var)) };
+        match kind as c_uint {
+            GLP_CV => Kind::Float,
+            GLP_IV => Kind::Int,
+            GLP_BV => Kind::Binary,
+            _ => panic!("unknown glpk var kind {}", kind),
+        }
+    }
+    pub fn get_bounds(&self, var: VarRef) -> Bounds {
+        unsafe {
+            (
+                glp_get_col_type(self.as_ptr(), self.col(var)),
+                glp_get_col_lb(self.as_ptr(), self.col(var)),
+                glp_get_col_ub(self.as_ptr(), self.col(var)),
+            )
+        }
+        .from_glp()
+    }
+    /// returns the bounds it had before, to later pass to `unfix_var`
+    pub fn fix_var(&mut self, var: VarRef, value: f64) -> Bounds {
+        let bounds = self.get_bounds(var);
+        self.set_bounds(var, Bounds::Fixed(value));
+        bounds
+    }
+    pub fn unfix_var(&mut self, var: VarRef, bounds: Bounds) {
+        debug_assert!(
+            matches!(self.get_bounds(var), Bounds::Fixed(_)),
+            "var {} is not fixed",
+            var.0
+        );
+        self.set_bounds(var, bounds);
+    }
+    /// every other var moves down to fill the gaps, so any refs that are kept must go through the returned renumbering
+    pub fn del_vars(&mut self, vars: impl IntoIterator<Item = VarRef>) -> Renumbering<VarRef> {
+        let deleted = sorted_indices(vars.into_iter().map(|var| self.col(var)));
+        let from = Generation::of(self);
+        if deleted.len() > 1 {
+            unsafe { glp_del_cols(self.as_ptr(), deleted.len() as c_int - 1, deleted.as_ptr()) };
+            Generation::set(self, Generation::fresh());
+        }
+        Renumbering::new(
+            deleted.into_iter().skip(1).collect(),
+            from,
+            Generation::of(self),
+        )
+    }
+    pub fn get_var_status(&self, var: VarRef) -> Status {
+        Status::from_glp(unsafe { glp_get_col_stat(self.as_ptr(), self.col(var)) })
+    }
+    pub fn set_var_status(&mut self, var: VarRef, status: Status) {
+        unsafe { glp_set_col_stat(self.as_ptr(), self.col(var), status.into_glp()) };
+    }
+    pub fn get_expr_bounds(&self, expr: ExprRef) -> Bounds {
+        unsafe {
+            (
+                glp_get_row_type(self.as_ptr(), self.row(expr)),
+                glp_get_row_lb(self.as_ptr(), self.row(expr)),
+                glp_get_row_ub(self.as_ptr(), self.row(expr)),
+            )
+        }
+        .from_glp()
+    }
+    /// as mangled when it was added, so a long name may be cut short
+    pub fn get_expr_name(&self, expr: ExprRef) -> String {
+        name_from_glp(unsafe { glp_get_row_name(self.as_ptr(), self.row(expr)) })
+    }
+    pub fn get_expr_status(&self, expr: ExprRef) -> Status {
+        Status::from_glp(unsafe { glp_get_row_stat(self.as_ptr(), self.row(expr)) })
+    }
+    pub fn set_expr_status(&mut self, expr: ExprRef, status: Status) {
+        unsafe { glp_set_row_stat(self.as_ptr(), self.row(expr), status.into_glp()) };
+    }
+    /// replaces the basis, such as when the one left by the last solve can't be factorized
+    pub fn set_basis(&mut self, basis: Basis) {
+        match basis {
+            Basis::Standard => unsafe { glp_std_basis(self.as_ptr()) },
+            Basis::Advanced => unsafe { glp_adv_basis(self.as_ptr(), 0) },
+            Basis::Bixby => unsafe { glp_cpx_basis(self.as_ptr()) },
+        }
+    }
+    /// factorizes the current basis and computes the values it gives, without solving.
+    /// glpk keeps the basis between solves, and vars and exprs added since are given a status that keeps it valid
+    pub fn warm_up(&mut self) -> Result<(), Error> {
+        let err = unsafe { glp_warm_up(self.as_ptr()) };
+        match err as c_uint {
+            0 => Ok(()),
+            GLP_EBADB => Err(Error::InvalidBasis),
+            GLP_ESING | GLP_ECOND => Err(Error::SingularBasis),
+            _ => {
+                warn!("Unknown warm up error {}", err);
+                Err(Error::Unknown)
+            }
+        }
+    }
+    /// the relaxation with the simplex method, starting from the current basis if it can be factorized
+    /// and from an advanced basis otherwise. the solution is left in the problem, even if it isn't optimal
+    fn solve_relaxation(&mut self) -> Result<(), Error> {
+        if self.warm_up().is_err() {
+            self.set_basis(Basis::Advanced);
+        }
+        let mut parm = MaybeUninit::uninit();
+        unsafe { glp_init_smcp(parm.as_mut_ptr()) };
+        let mut parm = unsafe { parm.assume_init() };
+        // the mip solve logs its own progress, and branch and bound solves far too many relaxations to log them all
+        parm.msg_lev = LoggingLevel::Error.into_glp();
+        let err = unsafe { glp_simplex(self.as_ptr(), &parm as *const glp_smcp) };
+        match err as c_uint {
+            0 => Ok(()),
+            GLP_EBADB => Err(Error::InvalidBasis),
+            GLP_ESING | GLP_ECOND => Err(Error::SingularBasis),
+            GLP_EBOUND => Err(Error::InvalidBounds),
+            GLP_EFAIL => Err(Error::SolverFailed),
+            GLP_EITLIM | GLP_ETMLIM => Err(Error::Timeout),
+            _ => {
+                warn!("Unknown simplex error {}", err);
+                Err(Error::Unknown)
+            }
+        }
+    }
+
     pub fn get_value(&self, var: VarRef) -> f64 {
-        unsafe { glp_get_col_prim(self.as_ptr(), var.0) }
+        unsafe { glp_get_col_prim(self.as_ptr(), self.col(var)) }
     }
     pub fn get_int_value(&self, var: VarRef) -> f64 {
-        unsafe { glp_mip_col_val(self.as_ptr(), var.0) }
+        unsafe { glp_mip_col_val(self.as_ptr(), self.col(var)) }
+    }
+    pub fn get_expr_value(&self, expr: ExprRef) -> f64 {
+        unsafe { glp_get_row_prim(self.as_ptr(), self.row(expr)) }
+    }
+    pub fn get_int_expr_value(&self, expr: ExprRef) -> f64 {
+        unsafe { glp_mip_row_val(self.as_ptr(), self.row(expr)) }
+    }
+    /// the value of every var in the last simplex solve
+    pub fn get_solution(&self) -> Solution {
+        let mut solution = Solution::zeros(self);
+        self.vars()
+            .iter()
+            .for_each(|var| solution[var] = self.get_value(var));
+        solution
+    }
+    /// the value of every var in the best integer solution
+    pub fn get_int_solution(&self) -> Solution {
+        let mut solution = Solution::zeros(self);
+        self.vars()
+            .iter()
+            .for_each(|var| solution[var] = self.get_int_value(var));
+        solution
     }
 
-    pub fn add_exprs(&mut self, specs: Vec<Expr>) {
+    pub fn add_exprs<T: IntoIterator<Item = Term>>(&mut self, specs: Vec<Expr<T>>) -> ExprRefs {
         let exprs = self.alloc_exprs(specs.len().into_glp());
         exprs
             .iter()
             .zip(specs.into_iter())
-            .for_each(|(var, spec)| self.init_expr(var, spec));
+            .for_each(|(expr, spec)| self.init_expr(expr, spec));
+        exprs
     }
-    pub fn add_expr(&mut self, spec: Expr) {
-        let var = self.alloc_exprs(1).get(0);
-        self.init_expr(var, spec);
+    pub fn add_expr<T: IntoIterator<Item = Term>>(&mut self, spec: Expr<T>) -> ExprRef {
+        let expr = self.alloc_exprs(1).get(0);
+        self.init_expr(expr, spec);
+        expr
     }
-    fn alloc_exprs(&mut self, len: c_int) -> VarRefs {
+    /// every other expr moves down to fill the gaps, so any refs that are kept must go through the returned renumbering
+    pub fn del_exprs(&mut self, exprs: impl IntoIterator<Item = ExprRef>) -> Renumbering<ExprRef> {
+        let deleted = sorted_indices(exprs.into_iter().map(|expr| self.row(expr)));
+        let from = Generation::of(self);
+        if deleted.len() > 1 {
+            unsafe { glp_del_rows(self.as_ptr(), deleted.len() as c_int - 1, deleted.as_ptr()) };
+            Generation::set(self, Generation::fresh());
+        }
+        Renumbering::new(
+            deleted.into_iter().skip(1).collect(),
+            from,
+            Generation::of(self),
+        )
+    }
+    fn alloc_exprs(&mut self, len: c_int) -> ExprRefs {
         let first = unsafe {
             if len == 0 {
                 // TODO add 1?
@@ -331,86 +1010,164 @@ impl Prob {
                 glp_add_rows(self.as_ptr(), len)
             }
         };
-        VarRefs { first, len }
+        ExprRefs {
+            first,
+            len,
+            generation: Generation::of(self),
+        }
     }
-    fn init_expr(&mut self, var: VarRef, spec: Expr) {
-        let name = spec.name.into_glp();
+    fn init_expr<T: IntoIterator<Item = Term>>(&mut self, expr: ExprRef, spec: Expr<T>) {
+        let name = mangle_name(spec.name);
         let (bounds, lower, upper) = spec.bounds.into_glp();
-        let (terms_len, vars, coeffs) = spec.terms.into_glp();
-        unsafe {
-            glp_set_row_name(self.as_ptr(), var.0, name.as_ptr());
-            glp_set_row_bnds(self.as_ptr(), var.0, bounds, lower, upper);
-            glp_set_mat_row(
-                self.as_ptr(),
-                var.0,
-                terms_len,
-                vars.as_ptr(),
-                coeffs.as_ptr(),
-            );
-        }
+        let terms = spec.terms;
+        let row = self.row(expr);
+        let generation = Generation::of(self);
+        SCRATCH.with(|scratch| {
+            let (vars, coeffs) = &mut *scratch.borrow_mut();
+            let terms_len = fill_terms(terms, generation, vars, coeffs);
+            unsafe {
+                glp_set_row_name(self.as_ptr(), row, name.as_ptr());
+                glp_set_row_bnds(self.as_ptr(), row, bounds, lower, upper);
+                glp_set_mat_row(
+                    self.as_ptr(),
+                    row,
+                    terms_len,
+                    vars.as_ptr(),
+                    coeffs.as_ptr(),
+                );
+            }
+        });
     }
 
-    pub fn optimize_mip<T: MipCallback>(&mut self, callback: &mut T) -> Result<(), Error> {
-        let mut options = MaybeUninit::uninit();
-        unsafe { glp_init_iocp(options.as_mut_ptr()) };
-        let mut options = unsafe { options.assume_init() };
-        options.presolve = GLP_ON as c_int;
-        options.binarize = GLP_ON as c_int;
+    pub fn optimize_mip<T: MipCallback>(
+        &mut self,
+        options: &OptimizeOptions,
+        callback: &mut T,
+    ) -> Result<(), Error> {
+        if let Some(free) = self.small_mip_vars(options.small_mip_vars) {
+            return self.branch_and_bound(free, callback);
+        }
+        let mut parm = MaybeUninit::uninit();
+        unsafe { glp_init_iocp(parm.as_mut_ptr()) };
+        let mut parm = unsafe { parm.assume_init() };
+        let presolve = !options.reuse_basis && !options.no_presolve;
+        if !presolve {
+            if !options.reuse_basis {
+                self.set_basis(Basis::Advanced);
+            }
+            // without the presolver glpk starts from an optimal basis of the relaxation, which it leaves behind
+            self.solve_relaxation()?;
+        }
+        parm.presolve = presolve.into_glp();
+        if let Some(limit) = options.time_limit {
+            parm.tm_lim = limit.as_millis().min(c_int::MAX as u128) as c_int;
+        }
+        parm.mip_gap = options.mip_gap;
+        parm.br_tech = options.branching.into_glp();
+        parm.bt_tech = options.backtracking.into_glp();
+        parm.binarize = GLP_ON as c_int;
         // disabling default heuristics since it doesn't respect lazy exprs that haven't been added yet
-        options.sr_heur = GLP_OFF as c_int;
+        parm.sr_heur = GLP_OFF as c_int;
+        parm.gmi_cuts = options.gomory.into_glp();
+        parm.mir_cuts = options.mir.into_glp();
+        parm.cov_cuts = options.cover.into_glp();
+        parm.clq_cuts = options.clique.into_glp();
 
-        assert_eq!(
-            std::mem::size_of::<*mut c_void>(),
-            std::mem::size_of::<&mut T>(),
-            "cannot cast callback to a C pointer"
-        );
+        /// what glpk is handed as the callback's info, so a panic can be remembered for after glpk returns
+        struct Guarded<'c, T> {
+            callback: &'c mut T,
+            panic: Option<String>,
+        }
 
         #[deny(unsafe_op_in_unsafe_fn)]
-        unsafe extern "C" fn c_callback<T: MipCallback>(
-            tree: *mut glp_tree,
-            callback: *mut c_void,
-        ) {
-            let callback = unsafe { &mut *(callback as *mut T) };
-            // although glpk might complain about mutating the problem, there are no other mutable references in rust
-            let problem = unsafe { &mut *(glp_ios_get_prob(tree) as *mut Prob) };
-            match unsafe { glp_ios_reason(tree) } as c_uint {
-                // GLP_ISELECT => {
-                // more flexibility around what sub problem to work on other then which branch to take
-                // }
-                GLP_IROWGEN => {
-                    if let Some(expr) = callback.get_lazy_expr(problem) {
-                        problem.add_expr(expr);
-                    }
+        unsafe extern "C" fn c_callback<T: MipCallback>(tree: *mut glp_tree, info: *mut c_void) {
+            let guarded = unsafe { &mut *(info as *mut Guarded<T>) };
+            if guarded.panic.is_some() {
+                unsafe { glp_ios_terminate(tree) };
+                return;
+            }
+            // unwinding into glpk would abort, so a panic stops the search instead and is returned once glpk has
+            let callback = &mut *guarded.callback;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                // although glpk might complain about mutating the problem, there are no other mutable references in rust
+                let problem = unsafe { &mut *(glp_ios_get_prob(tree) as *mut Prob) };
+                let mut status = TreeStatus {
+                    gap: unsafe { glp_ios_mip_gap(tree) },
+                    ..TreeStatus::default()
+                };
+                unsafe {
+                    glp_ios_tree_size(
+                        tree,
+                        &mut status.active,
+                        &mut status.current,
+                        &mut status.total,
+                    )
+                };
+                let current = unsafe { glp_ios_curr_node(tree) };
+                if current != 0 {
+                    let parent = unsafe { glp_ios_up_node(tree, current) };
+                    status.node = Some(TreeNode {
+                        id: current,
+                        parent: (parent != 0).then_some(parent),
+                        level: unsafe { glp_ios_node_level(tree, current) },
+                        bound: unsafe { glp_ios_node_bound(tree, current) },
+                    });
                 }
-                // GLP_ICUTGEN => {
-                // remember that cuts cannot remove integral solutions
-                // they are instead for cutting a fractional corner into multiple (hopefully) integral corners
-                // }
-                GLP_IHEUR => {
-                    if let Some(solution) = callback.get_heuristic_solution(problem) {
-                        assert_eq!(
-                            problem.num_vars(),
-                            solution.len(),
-                            "heuristic solution must have the correct number of vars"
-                        );
-                        unsafe { glp_ios_heur_sol(tree, &solution.0[0] as *const f64) };
-                    }
+                callback.tree_status(status);
+                if callback.terminate() {
+                    unsafe { glp_ios_terminate(tree) };
+                    return;
                 }
-                GLP_IBRANCH => {
-                    if let Some((var, dir)) = callback.get_branch(problem) {
-                        unsafe { glp_ios_branch_upon(tree, var.0, dir.into_glp()) };
+                match unsafe { glp_ios_reason(tree) } as c_uint {
+                    // GLP_ISELECT => {
+                    // more flexibility around what sub problem to work on other then which branch to take
+                    // }
+                    GLP_IROWGEN => {
+                        if let Some(expr) = callback.get_lazy_expr(problem) {
+                            problem.add_expr(expr);
+                        }
                     }
+                    // GLP_ICUTGEN => {
+                    // remember that cuts cannot remove integral solutions
+                    // they are instead for cutting a fractional corner into multiple (hopefully) integral corners
+                    // }
+                    GLP_IHEUR => {
+                        if let Some(solution) = callback.get_heuristic_solution(problem) {
+                            assert_eq!(
+                                problem.num_vars(),
+                                solution.len(),
+                                "heuristic solution must have the correct number of vars"
+                            );
+                            unsafe { glp_ios_heur_sol(tree, solution.values.as_ptr()) };
+                        }
+                    }
+                    GLP_IBRANCH => {
+                        if let Some((var, dir)) = callback.get_branch(problem) {
+                            unsafe { glp_ios_branch_upon(tree, problem.col(var), dir.into_glp()) };
+                        }
+                    }
+                    GLP_IBINGO => {
+                        callback.new_best_solution(problem);
+                    }
+                    _ => {}
                 }
-                GLP_IBINGO => {
-                    callback.new_best_solution(problem);
-                }
-                _ => {}
+            }));
+            if let Err(payload) = result {
+                guarded.panic = Some(panic_message(payload));
+                unsafe { glp_ios_terminate(tree) };
             }
         }
-        options.cb_func = Some(c_callback::<T>);
-        options.cb_info = callback as *mut T as *mut c_void;
+        let mut guarded = Guarded {
+            callback,
+            panic: None,
+        };
+        parm.cb_func = Some(c_callback::<T>);
+        parm.cb_info = &mut guarded as *mut Guarded<T> as *mut c_void;
 
-        let err = unsafe { glp_intopt(self.as_ptr(), &options as *const glp_iocp) };
+        let err = unsafe { glp_intopt(self.as_ptr(), &parm as *const glp_iocp) };
+        if let Some(message) = guarded.panic {
+            return Err(Error::CallbackPanicked(message));
+        }
         match err as c_uint {
             0 => Ok(()),
             GLP_EBOUND => Err(Error::InvalidBounds),
@@ -427,6 +1184,314 @@ impl Prob {
             }
         }
     }
+
+    /// the int vars that aren't fixed, if there are at most `limit` of them and every int var has both bounds.
+    /// anything else is left to glpk's own search
+    fn small_mip_vars(&self, limit: usize) -> Option<Vec<VarRef>> {
+        if limit == 0 {
+            return None;
+        }
+        let mut free = Vec::new();
+        for var in self
+            .vars()
+            .iter()
+            .filter(|var| !matches!(self.get_kind(*var), Kind::Float))
+        {
+            match self.get_bounds(var) {
+                Bounds::Fixed(_) => {}
+                Bounds::Double(..) if free.len() < limit => free.push(var),
+                _ => return None,
+            }
+        }
+        Some(free)
+    }
+
+    /// depth first branch and bound over the simplex relaxation, for problems small enough that setting up
+    /// glpk's own search takes longer than the search does. the callback is asked for lazy exprs after every
+    /// relaxation, sees the tree status and can terminate, but is never asked for heuristic solutions or branches.
+    /// the best solution is recorded by glpk with every int var fixed to it, so it is read the same way as
+    /// after glpk's search, and `new_best_solution` is only called for it once the search is done
+    fn branch_and_bound<T: MipCallback>(
+        &mut self,
+        free: Vec<VarRef>,
+        callback: &mut T,
+    ) -> Result<(), Error> {
+        // objectives are compared as if minimizing
+        let sense = match unsafe { glp_get_obj_dir(self.as_ptr()) } as c_uint {
+            GLP_MAX => -1.0,
+            _ => 1.0,
+        };
+        let bounds: Vec<Bounds> = free.iter().map(|var| self.get_bounds(*var)).collect();
+        let mut open = vec![Subproblem {
+            changes: Vec::new(),
+            bound: f64::NEG_INFINITY,
+            id: 1,
+            parent: None,
+        }];
+        let mut total = 1;
+        let mut best: Option<(f64, Vec<f64>)> = None;
+        let result = loop {
+            let bound = open
+                .iter()
+                .map(|sub| sub.bound)
+                .fold(f64::INFINITY, f64::min);
+            let sub = match open.pop() {
+                Some(sub) => sub,
+                None => break Ok(()),
+            };
+            free.iter()
+                .zip(&bounds)
+                .for_each(|(var, bounds)| self.set_bounds(*var, *bounds));
+            sub.changes
+                .iter()
+                .for_each(|(i, bounds)| self.set_bounds(free[*i], *bounds));
+            callback.tree_status(TreeStatus {
+                gap: best.as_ref().map_or(f64::MAX, |(incumbent, _)| {
+                    (incumbent - bound).abs() / (incumbent.abs() + f64::EPSILON)
+                }),
+                active: open.len() as c_int + 1,
+                current: open.len() as c_int + 1,
+                total,
+                node: Some(TreeNode {
+                    id: sub.id,
+                    parent: sub.parent,
+                    level: sub.changes.len() as c_int,
+                    bound: sub.bound * sense,
+                }),
+            });
+            if callback.terminate() {
+                break Err(Error::Stopped);
+            }
+            match self.solve_with_lazy_exprs(callback) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => break Err(e),
+            }
+            let objective = unsafe { glp_get_obj_val(self.as_ptr()) } * sense;
+            if best.as_ref().is_some_and(|(incumbent, _)| {
+                objective >= incumbent - BOUND_TOLERANCE * (1.0 + incumbent.abs())
+            }) {
+                continue;
+            }
+            let fractional = free
+                .iter()
+                .enumerate()
+                .map(|(i, var)| (i, self.get_value(*var)))
+                .filter(|(_, value)| (value - value.round()).abs() > INT_TOLERANCE)
+                .max_by(|(_, l), (_, r)| {
+                    let closeness = |value: f64| (value.fract().abs() - 0.5).abs();
+                    closeness(*r).total_cmp(&closeness(*l))
+                });
+            match fractional {
+                None => {
+                    best = Some((
+                        objective,
+                        free.iter()
+                            .map(|var| self.get_value(*var).round())
+                            .collect(),
+                    ))
+                }
+                Some((i, value)) => {
+                    let (lower, upper) = match self.get_bounds(free[i]) {
+                        Bounds::Double(lower, upper) => (lower, upper),
+                        bounds => unreachable!("fractional var with bounds {:?}", bounds),
+                    };
+                    // the branch up is pushed last, so it is searched first
+                    IntoIterator::into_iter([(lower, value.floor()), (value.ceil(), upper)])
+                        .for_each(|(lower, upper)| {
+                            let mut changes = sub.changes.clone();
+                            changes.push((i, range(lower, upper)));
+                            total += 1;
+                            open.push(Subproblem {
+                                changes,
+                                bound: objective,
+                                id: total,
+                                parent: Some(sub.id),
+                            });
+                        });
+                }
+            }
+        };
+
+        let recorded = match &best {
+            Some((_, values)) => {
+                free.iter()
+                    .zip(values)
+                    .for_each(|(var, value)| self.set_bounds(*var, Bounds::Fixed(*value)));
+                self.record_fixed()
+            }
+            None => Ok(()),
+        };
+        free.iter()
+            .zip(&bounds)
+            .for_each(|(var, bounds)| self.set_bounds(*var, *bounds));
+        result.and(recorded)?;
+        match best {
+            Some(_) => {
+                callback.new_best_solution(self);
+                Ok(())
+            }
+            None => Err(Error::NotPrimalFeasible),
+        }
+    }
+
+    /// solves the relaxation again until the callback has no more lazy exprs for it.
+    /// false if it is infeasible
+    fn solve_with_lazy_exprs<T: MipCallback>(&mut self, callback: &mut T) -> Result<bool, Error> {
+        loop {
+            self.solve_relaxation()?;
+            match unsafe { glp_get_status(self.as_ptr()) } as c_uint {
+                GLP_OPT => {}
+                GLP_NOFEAS => return Ok(false),
+                GLP_UNBND => return Err(Error::NotDualFeasible),
+                status => {
+                    warn!("Unknown simplex status {}", status);
+                    return Err(Error::Unknown);
+                }
+            }
+            match callback.get_lazy_expr(self) {
+                Some(expr) => {
+                    self.add_expr(expr);
+                }
+                None => return Ok(true),
+            }
+        }
+    }
+
+    /// glpk's search with every int var fixed, which only has the root to solve
+    fn record_fixed(&mut self) -> Result<(), Error> {
+        self.solve_relaxation()?;
+        let mut parm = MaybeUninit::uninit();
+        unsafe { glp_init_iocp(parm.as_mut_ptr()) };
+        let mut parm = unsafe { parm.assume_init() };
+        parm.msg_lev = LoggingLevel::Error.into_glp();
+        let err = unsafe { glp_intopt(self.as_ptr(), &parm as *const glp_iocp) };
+        match err {
+            0 => Ok(()),
+            _ => {
+                warn!("Unknown intopt error {} recording a solution", err);
+                Err(Error::Unknown)
+            }
+        }
+    }
+}
+
+/// what was passed to `panic!`, which is always a string unless `panic_any` was used instead
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "a panic without a message".to_owned(),
+        },
+    }
+}
+
+/// a node of the built-in branch and bound, as the bounds it changes from the root
+struct Subproblem {
+    /// applied in order, so a later change to the same var wins
+    changes: Vec<(usize, Bounds)>,
+    /// the objective of the parent's relaxation, as if minimizing
+    bound: f64,
+    id: c_int,
+    parent: Option<c_int>,
+}
+
+/// how far from an integer a value can be and still count as one, like glpk's default
+const INT_TOLERANCE: f64 = 1e-5;
+/// how much better than the incumbent, relative to it, a relaxation has to be to be worth branching on
+const BOUND_TOLERANCE: f64 = 1e-9;
+
+/// an int var's bounds after branching, which are fixed once they meet
+#[allow(clippy::float_cmp)]
+fn range(lower: f64, upper: f64) -> Bounds {
+    if lower == upper {
+        Bounds::Fixed(lower)
+    } else {
+        Bounds::Double(lower, upper)
+    }
+}
+
+/// how glpk chooses the var to branch on, which defaults to glpk's own default
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Branching {
+    FirstFractional,
+    LastFractional,
+    MostFractional,
+    /// Driebeck and Tomlin's heuristic
+    #[default]
+    DriebeckTomlin,
+    /// hybrid pseudocost, which is slow to start but learns from earlier branches
+    Pseudocost,
+}
+impl IntoGlp for Branching {
+    type Output = c_int;
+    fn into_glp(self) -> Self::Output {
+        match self {
+            Self::FirstFractional => GLP_BR_FFV as c_int,
+            Self::LastFractional => GLP_BR_LFV as c_int,
+            Self::MostFractional => GLP_BR_MFV as c_int,
+            Self::DriebeckTomlin => GLP_BR_DTH as c_int,
+            Self::Pseudocost => GLP_BR_PCH as c_int,
+        }
+    }
+}
+
+/// how glpk chooses the subproblem to solve next, which defaults to glpk's own default
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backtracking {
+    DepthFirst,
+    BreadthFirst,
+    /// the subproblem with the best local bound
+    #[default]
+    BestBound,
+    /// the subproblem with the best projection heuristic
+    BestProjection,
+}
+impl IntoGlp for Backtracking {
+    type Output = c_int;
+    fn into_glp(self) -> Self::Output {
+        match self {
+            Self::DepthFirst => GLP_BT_DFS as c_int,
+            Self::BreadthFirst => GLP_BT_BFS as c_int,
+            Self::BestBound => GLP_BT_BLB as c_int,
+            Self::BestProjection => GLP_BT_BPH as c_int,
+        }
+    }
+}
+
+/// generic cuts glpk can add to the relaxation of each subproblem, which are all off by default.
+/// they only cut off fractional solutions, so they are still valid before every lazy expr has been added
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OptimizeOptions {
+    /// gomory mixed integer cuts, from rows of the optimal simplex tableau
+    pub gomory: bool,
+    /// mixed integer rounding cuts, from aggregated exprs
+    pub mir: bool,
+    /// mixed cover cuts, from knapsack-like exprs
+    pub cover: bool,
+    /// clique cuts, from binary vars that can't be set together
+    pub clique: bool,
+    /// solve the root relaxation from the basis the last solve left, instead of presolving from scratch.
+    /// for solving the same problem again after changing a few bounds or objectives
+    pub reuse_basis: bool,
+    /// solve the root relaxation with the simplex method instead of presolving, which `reuse_basis` implies.
+    /// the presolver hands the callback a new problem with its own numbering, so callbacks that use refs need this
+    pub no_presolve: bool,
+    /// which fractional var glpk branches on when the callback doesn't choose one
+    pub branching: Branching,
+    /// which subproblem glpk solves next
+    pub backtracking: Backtracking,
+    /// problems with at most this many int vars that aren't fixed are solved with a plain branch and bound
+    /// over the relaxation instead of glpk's search, which takes longer to set up than such a search takes.
+    /// every int var has to have both bounds. 0 always uses glpk's search.
+    /// the plain branch and bound has no time limit or mip gap
+    pub small_mip_vars: usize,
+    /// stops glpk's search with `Error::Timeout` once it has run this long, keeping its incumbent
+    pub time_limit: Option<Duration>,
+    /// stops glpk's search with `Error::HitMipGapLimit` once the incumbent is within this relative gap of the bound.
+    /// 0 only stops once the incumbent is proven optimal
+    pub mip_gap: f64,
 }
 
 pub trait MipCallback {
@@ -448,28 +1513,122 @@ pub trait MipCallback {
     fn new_best_solution(&mut self, problem: &Prob) {
         let _ = problem;
     }
+
+    /// called before every other callback, so it sees the tree grow
+    fn tree_status(&mut self, status: TreeStatus) {
+        let _ = status;
+    }
+
+    /// checked after `tree_status`, stops the solve with `Error::Stopped`
+    fn terminate(&mut self) -> bool {
+        false
+    }
+}
+
+/// numbers of nodes in the branch and bound tree, and how far the incumbent may be from optimal
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TreeStatus {
+    /// relative gap between the incumbent and the best bound, or `f64::MAX` without an incumbent
+    pub gap: f64,
+    /// leaves that still need to be solved
+    pub active: c_int,
+    /// all nodes currently kept in memory
+    pub current: c_int,
+    /// all nodes ever created
+    pub total: c_int,
+    /// the subproblem being solved, if there is one
+    pub node: Option<TreeNode>,
+}
+
+/// a subproblem in the branch and bound tree.
+/// glpk reuses the ids of subproblems it has removed, so an id only names one subproblem at a time
+#[derive(Copy, Clone, Debug)]
+pub struct TreeNode {
+    pub id: c_int,
+    /// none for the root
+    pub parent: Option<c_int>,
+    /// depth in the tree, where the root is 0
+    pub level: c_int,
+    /// the best objective any solution in this subproblem could have
+    pub bound: f64,
+}
+
+/// memory glpk has allocated, over every problem
+#[derive(Copy, Clone, Debug)]
+pub struct MemUsage {
+    pub blocks: c_int,
+    pub peak_blocks: c_int,
+    pub bytes: usize,
+    pub peak_bytes: usize,
+}
+
+pub fn mem_usage() -> MemUsage {
+    let mut usage = MemUsage {
+        blocks: 0,
+        peak_blocks: 0,
+        bytes: 0,
+        peak_bytes: 0,
+    };
+    unsafe {
+        glp_mem_usage(
+            &mut usage.blocks,
+            &mut usage.peak_blocks,
+            &mut usage.bytes,
+            &mut usage.peak_bytes,
+        )
+    };
+    usage
+}
+
+/// glpk aborts the whole process once it needs more than this many megabytes,
+/// which is still better than taking down the rest of the machine
+pub fn set_mem_limit(megabytes: c_int) {
+    assert!(megabytes >= 1, "memory limit must be at least 1 megabyte");
+    unsafe { glp_mem_limit(megabytes) };
 }
 
+/// a value for every var of a problem, such as a heuristic solution offered to the solver
 #[derive(Debug)]
-pub struct Solution(Vec<f64>);
+pub struct Solution {
+    vars: VarRefs,
+    /// by glpk index, so index 0 is ignored
+    values: Vec<f64>,
+}
 impl Solution {
-    pub fn zeros(len: usize) -> Self {
-        // index 0 is ignored by glpk
-        Self(vec![0.0; len + 1])
+    /// every var of the problem at 0
+    pub fn zeros(problem: &Prob) -> Self {
+        let vars = problem.vars();
+        Self {
+            vars,
+            values: vec![0.0; vars.len() + 1],
+        }
     }
     pub fn len(&self) -> usize {
-        self.0.len() - 1
+        self.values.len() - 1
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// every var with its value, in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (VarRef, f64)> + '_ {
+        self.vars.iter().map(move |var| (var, self[var]))
+    }
+    /// the vars whose value isn't 0
+    pub fn nonzero(&self) -> impl Iterator<Item = (VarRef, f64)> + '_ {
+        self.iter().filter(|(_, value)| *value != 0.0)
     }
 }
 impl Index<VarRef> for Solution {
     type Output = f64;
     fn index(&self, index: VarRef) -> &Self::Output {
-        &self.0[index.0.from_glp()]
+        index.1.check(self.vars.generation, "var", index.0);
+        &self.values[index.0.from_glp()]
     }
 }
 impl IndexMut<VarRef> for Solution {
     fn index_mut(&mut self, index: VarRef) -> &mut Self::Output {
-        &mut self.0[index.0.from_glp()]
+        index.1.check(self.vars.generation, "var", index.0);
+        &mut self.values[index.0.from_glp()]
     }
 }
 
@@ -490,14 +1649,373 @@ impl IntoGlp for Branch {
     }
 }
 
+/// the version of the linked glpk library, such as "4.65"
+pub fn version() -> String {
+    unsafe { CStr::from_ptr(glp_version()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// a glpk major and minor version, to check what the linked library supports
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+}
+impl Version {
+    /// the glpk.h the bindings were generated from, which the struct layouts come from
+    pub const BINDINGS: Version = Version {
+        major: GLP_MAJOR_VERSION,
+        minor: GLP_MINOR_VERSION,
+    };
+
+    /// such as "4.65"
+    pub fn parse(version: &str) -> Option<Self> {
+        let (major, minor) = version.trim().split_once('.')?;
+        Some(Version {
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+        })
+    }
+
+    /// the linked library, if its version can be parsed
+    pub fn linked() -> Option<Self> {
+        Self::parse(&version())
+    }
+
+    /// glpk pads its option structs so they keep their size within a major version,
+    /// so the bindings can only be trusted with a library of the same major version
+    pub fn matches_bindings(self) -> bool {
+        self.major == Self::BINDINGS.major
+    }
+
+    /// the proximity search heuristic, `ps_heur` in the MIP options, was added in 4.50
+    pub fn has_proximity_search(self) -> bool {
+        self >= Version {
+            major: 4,
+            minor: 50,
+        }
+    }
+}
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn version_parses() {
+        assert_eq!(
+            Version::parse("4.65"),
+            Some(Version {
+                major: 4,
+                minor: 65
+            })
+        );
+        assert_eq!(
+            Version::parse("5.0\n").map(|v| v.to_string()),
+            Some("5.0".to_owned())
+        );
+        assert_eq!(Version::parse("4"), None);
+        assert!(Version::parse("4.65").unwrap().has_proximity_search());
+        assert!(!Version::parse("4.45").unwrap().has_proximity_search());
+    }
+
+    #[test]
+    fn lp_file_round_trips() {
+        let mut problem = Problem::new();
+        let vars = problem.add_vars(vec![
+            Var {
+                name: "x".to_owned(),
+                kind: Kind::Binary,
+                bounds: Bounds::Double(0.0, 1.0),
+                objective: 2.0,
+            },
+            Var {
+                name: "y".to_owned(),
+                kind: Kind::Float,
+                bounds: Bounds::Lower(0.0),
+                objective: 1.0,
+            },
+        ]);
+        problem.add_expr(Expr {
+            name: "xy".to_owned(),
+            bounds: Bounds::Lower(1.0),
+            terms: vec![vars.get(0) * 1.0, vars.get(1) * 1.0],
+        });
+        problem.set_objective_constant(5.0);
+        let path = std::env::temp_dir().join(format!("glpk-{}.lp", std::process::id()));
+        problem.write_lp(&path).unwrap();
+
+        let mut read = Problem::from_lp_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.num_vars(), 2);
+        assert_eq!(read.num_binary_vars(), 1);
+        assert_eq!(read.num_exprs(), 1);
+        assert_eq!(read.num_terms(), 2);
+        let x = read.find_var("x").unwrap();
+        assert_eq!(read.get_objective(x), 2.0);
+        assert_eq!(read.get_objective_constant(), 5.0);
+        assert!(read.find_var("z").is_none());
+        assert!(Problem::from_lp_file(&path).is_err());
+    }
+
+    #[test]
+    fn reads_free_mps() {
+        let path = std::env::temp_dir().join(format!("glpk-{}.mps", std::process::id()));
+        std::fs::write(
+            &path,
+            "NAME test\n\
+             ROWS\n N cost\n G xy\n\
+             COLUMNS\n x cost 2 xy 1\n y cost 1 xy 1\n\
+             RHS\n rhs xy 1\n\
+             ENDATA\n",
+        )
+        .unwrap();
+        let read = Problem::from_mps_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read.num_vars(), 2);
+        assert_eq!(read.vars().iter().count(), 2);
+        assert_eq!(read.get_objective(read.vars().get(0)), 2.0);
+    }
+
+    #[test]
+    fn solutions_iterate_by_var() {
+        let mut problem = Problem::new();
+        let spec = |name: &str| Var {
+            name: name.to_owned(),
+            kind: Kind::Binary,
+            bounds: Bounds::Double(0.0, 1.0),
+            objective: 1.0,
+        };
+        let first = problem.add_vars(vec![spec("a"), spec("b")]);
+        let second = problem.add_vars(vec![spec("c"), spec("d")]);
+        let mut solution = Solution::zeros(&problem);
+        solution[second.get(1)] = 1.0;
+        assert_eq!(solution.len(), 4);
+        assert_eq!(solution.iter().count(), 4);
+        let nonzero: Vec<_> = solution.nonzero().collect();
+        assert_eq!(nonzero.len(), 1);
+        assert_eq!(second.position(nonzero[0].0), Some(1));
+        assert_eq!(first.position(nonzero[0].0), None);
+    }
+
+    #[test]
+    fn var_blocks_slice_and_map() {
+        let mut problem = Problem::new();
+        let vars = problem.add_vars(
+            (0..4)
+                .map(|i| Var {
+                    name: format!("x{}", i),
+                    kind: Kind::Float,
+                    bounds: Bounds::Lower(0.0),
+                    objective: i as f64,
+                })
+                .collect(),
+        );
+        let middle = vars.slice(1..3);
+        assert_eq!(middle.len(), 2);
+        assert_eq!(problem.get_objective(middle.get(0)), 1.0);
+        assert_eq!(vars.position(middle.get(1)), Some(2));
+        assert_eq!(middle.position(vars.get(3)), None);
+        assert!(vars.slice(4..4).is_empty());
+
+        // indexed from the end, to show the map translates both ways
+        let reversed = VarMap::new(vars, |i| 3 - i, |i| 3 - i);
+        assert_eq!(problem.get_objective(reversed.get(0)), 3.0);
+        assert_eq!(reversed.position(vars.get(1)), Some(2));
+        assert_eq!(reversed.iter().next().map(|(i, _)| i), Some(3));
+    }
+
     #[test]
     fn can_set_name() {
         let mut problem = Problem::new();
         problem.set_name("abc".to_owned());
         // TODO verify name
     }
+
+    #[test]
+    fn exprs_can_be_added_from_iterators() {
+        let mut problem = Problem::new();
+        let vars = problem.add_vars(
+            ["x", "y", "z"]
+                .iter()
+                .map(|name| Var {
+                    name: name.to_string(),
+                    kind: Kind::Float,
+                    bounds: Bounds::Double(0.0, 1.0),
+                    objective: 1.0,
+                })
+                .collect(),
+        );
+        // the terms are mapped straight into glpk's buffers, without a Vec in between
+        let xy = problem.add_expr(Expr::from_terms(
+            "xy".to_owned(),
+            Bounds::Lower(1.0),
+            vars.iter().take(2).map(|var| var * 2.0),
+        ));
+        assert_eq!(problem.num_terms(), 2);
+        assert_eq!(problem.get_expr_name(xy), "xy");
+        assert!(matches!(problem.get_expr_bounds(xy), Bounds::Lower(l) if l == 1.0));
+    }
+
+    #[test]
+    fn basis_can_be_set_and_warmed_up() {
+        let mut problem = Problem::new();
+        let vars = problem.add_vars(
+            ["x", "y"]
+                .iter()
+                .map(|name| Var {
+                    name: name.to_string(),
+                    kind: Kind::Float,
+                    bounds: Bounds::Double(0.0, 1.0),
+                    objective: 1.0,
+                })
+                .collect(),
+        );
+        let xy = problem.add_expr(Expr {
+            name: "xy".to_owned(),
+            bounds: Bounds::Lower(1.0),
+            terms: vec![vars.get(0) * 1.0, vars.get(1) * 1.0],
+        });
+        problem.set_basis(Basis::Standard);
+        assert_eq!(problem.get_expr_status(xy), Status::Basic);
+        assert_eq!(problem.get_var_status(vars.get(0)), Status::AtLower);
+        assert_eq!(problem.warm_up(), Ok(()));
+
+        // a basic var and a basic expr for only one expr
+        problem.set_var_status(vars.get(0), Status::Basic);
+        assert_eq!(problem.warm_up(), Err(Error::InvalidBasis));
+        // x makes up whatever y leaves of the expr's lower bound
+        problem.set_expr_status(xy, Status::AtLower);
+        assert_eq!(problem.warm_up(), Ok(()));
+        assert_eq!(problem.get_value(vars.get(0)), 1.0);
+    }
+
+    #[test]
+    fn callback_panics_are_returned() {
+        struct Panics;
+        impl MipCallback for Panics {
+            fn tree_status(&mut self, _: TreeStatus) {
+                panic!("lost track of the tree");
+            }
+        }
+        let mut problem = Problem::new();
+        problem.add_var(Var {
+            name: "x".to_owned(),
+            kind: Kind::Binary,
+            bounds: Bounds::Double(0.0, 1.0),
+            objective: 1.0,
+        });
+        assert_eq!(
+            problem.optimize_mip(&OptimizeOptions::default(), &mut Panics),
+            Err(Error::CallbackPanicked("lost track of the tree".to_owned()))
+        );
+        assert_eq!(panic_message(Box::new(format!("{} frames", 3))), "3 frames");
+    }
+
+    #[test]
+    fn small_mips_are_branched_on_without_glpk() {
+        let knapsack = || {
+            let mut problem = Problem::new();
+            problem.set_direction(Direction::Maximize);
+            let vars = problem.add_vars(
+                [(5.0, 4.0), (4.0, 3.0), (3.0, 2.0), (2.0, 1.0)]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (value, _))| Var {
+                        name: format!("x{}", i),
+                        kind: Kind::Binary,
+                        bounds: Bounds::Double(0.0, 1.0),
+                        objective: *value,
+                    })
+                    .collect(),
+            );
+            problem.add_expr(Expr::from_terms(
+                "weight".to_owned(),
+                Bounds::Upper(6.5),
+                vars.iter()
+                    .zip([4.0, 3.0, 2.0, 1.0])
+                    .map(|(var, weight)| var * weight),
+            ));
+            (problem, vars)
+        };
+        struct NoCallback;
+        impl MipCallback for NoCallback {}
+
+        let (mut glpk, vars) = knapsack();
+        glpk.optimize_mip(&OptimizeOptions::default(), &mut NoCallback)
+            .unwrap();
+        let (mut small, _) = knapsack();
+        let options = OptimizeOptions {
+            small_mip_vars: 4,
+            ..OptimizeOptions::default()
+        };
+        assert_eq!(small.optimize_mip(&options, &mut NoCallback), Ok(()));
+        assert_eq!(small.get_mip_objective(), 9.0);
+        assert_eq!(small.get_mip_objective(), glpk.get_mip_objective());
+        vars.iter()
+            .for_each(|var| assert_eq!(small.get_int_value(var), glpk.get_int_value(var)));
+        // the bounds are left as they were
+        assert!(matches!(small.get_bounds(vars.get(0)), Bounds::Double(..)));
+        assert!(small.small_mip_vars(3).is_none());
+    }
+
+    #[test]
+    fn long_names_are_mangled() {
+        assert_eq!(mangle_name("x".to_owned()).as_bytes(), b"x");
+        let long = |c: &str| c.repeat(300);
+        let a = mangle_name(long("a"));
+        assert_eq!(a.as_bytes().len(), MAX_NAME_LEN);
+        assert_eq!(a, mangle_name(long("a")));
+        assert_ne!(a, mangle_name(long("a") + "b"));
+        // cut short on a char boundary
+        let snowmen = mangle_name(long("\u{2603}"));
+        assert!(snowmen.as_bytes().len() <= MAX_NAME_LEN);
+        assert!(snowmen.to_str().is_ok());
+
+        let mut problem = Problem::new();
+        problem.add_var(Var {
+            name: long("x"),
+            kind: Kind::Float,
+            bounds: Bounds::Free,
+            objective: 0.0,
+        });
+        assert!(problem.find_var(&long("x")).is_some());
+        assert!(problem.find_var(&long("y")).is_none());
+    }
+
+    #[test]
+    fn kind_checks_bounds() {
+        assert!(Kind::Float.allows(Bounds::Double(0.5, 1.5)));
+        assert!(!Kind::Float.allows(Bounds::Double(1.0, 0.0)));
+        assert!(Kind::Int.allows(Bounds::Lower(3.0)));
+        assert!(!Kind::Int.allows(Bounds::Upper(0.5)));
+        assert!(Kind::Binary.allows(Bounds::Double(0.0, 1.0)));
+        assert!(Kind::Binary.allows(Bounds::Fixed(1.0)));
+        assert!(!Kind::Binary.allows(Bounds::Double(0.0, 2.0)));
+        assert!(!Kind::Binary.allows(Bounds::Lower(0.0)));
+    }
+
+    #[test]
+    fn renumbering_moves_later_refs_down() {
+        let deleted = sorted_indices(vec![5, 2, 5].into_iter());
+        assert_eq!(deleted, vec![0, 2, 5]);
+        let generation = Generation::fresh();
+        let renumbering = Renumbering::<VarRef>::new(
+            deleted.into_iter().skip(1).collect(),
+            generation,
+            generation,
+        );
+        assert_eq!(renumbering.get(VarRef(1, generation)).map(|v| v.0), Some(1));
+        assert!(renumbering.get(VarRef(2, generation)).is_none());
+        assert_eq!(renumbering.get(VarRef(4, generation)).map(|v| v.0), Some(3));
+        assert!(renumbering.get(VarRef(5, generation)).is_none());
+        assert_eq!(renumbering.get(VarRef(6, generation)).map(|v| v.0), Some(4));
+    }
 }